@@ -1,8 +1,54 @@
 import test from 'ava'
 
-import { plus100 } from '../index'
+import { DeviceManager, EventType, listen, PortType, scan, scanSync, waitForDevice } from '../index'
 
-test('sync function from native code', (t) => {
-  const fixture = 42
-  t.is(plus100(fixture), fixture + 100)
+test('scanSync returns the devices keyed by port', (t) => {
+  for (const [port, device] of Object.entries(scanSync())) {
+    t.is(device.port, port)
+  }
+})
+
+test('enums are available at runtime', (t) => {
+  t.is(EventType.Change, 'Change')
+  t.is(PortType.Usb, 'Usb')
+})
+
+test('scan resolves to the same devices as scanSync', async (t) => {
+  t.deepEqual(Object.keys(await scan()).sort(), Object.keys(scanSync()).sort())
+})
+
+test('listen ends after abort', async (t) => {
+  const events = listen()
+  setTimeout(() => events.abort(), 100)
+  for await (const event of events) {
+    t.truthy(event.device.port)
+  }
+  t.pass()
+})
+
+test('listen ends when the signal fires', async (t) => {
+  const controller = new AbortController()
+  setTimeout(() => controller.abort(), 100)
+  for await (const event of listen({ signal: controller.signal })) {
+    t.truthy(event.device.port)
+  }
+  t.pass()
+})
+
+test('waitForDevice rejects when no device shows up in time', async (t) => {
+  await t.throwsAsync(waitForDevice({ vid: 0xffff, pid: 0xffff, timeoutMs: 100 }), {
+    message: 'timed out waiting for a device',
+  })
+})
+
+test('DeviceManager tracks the devices of a registration', (t) => {
+  const manager = new DeviceManager()
+  const id = manager.register(
+    {},
+    () => {},
+    () => {},
+  )
+  t.deepEqual(Object.keys(manager.connected(id)).sort(), Object.keys(manager.devices()).sort())
+  manager.unregister(id)
+  t.deepEqual(manager.connected(id), {})
 })
@@ -1,6 +1,28 @@
 /* auto-generated by NAPI-RS */
 /* eslint-disable */
-/** Information about the serial port */
+/** Milliseconds since the unix epoch, like `Date.now()` */
+export type Timestamp = number
+
+/** Selects the devices of a registration. Every device matches an empty filter */
+export interface DeviceFilter {
+  /** Vendor ID */
+  vid?: number
+  /** Product ID */
+  pid?: number
+  /** Serial number */
+  serial?: string
+  /** Manufacturer string */
+  manufacturer?: string
+  /** Product string */
+  product?: string
+}
+
+/**
+ * Information about the serial port
+ *
+ * With the `serde` feature the field names are the wire format, and are kept stable. Missing
+ * fields deserialize to their default
+ */
 export interface DeviceInfo {
   /** The port name. IE: COM3 */
   port: string
@@ -8,12 +30,113 @@ export interface DeviceInfo {
   vid?: string
   /** Product ID */
   pid?: string
+  /** Vendor ID as a number */
+  vidU16?: number
+  /** Product ID as a number */
+  pidU16?: number
+  /**
+   * The device release number of the USB device descriptor (bcdDevice), in binary coded
+   * decimal (IE: 0x0600). Tells apart hardware or firmware revisions of otherwise identical
+   * adapters. See [`DeviceInfo::release`]
+   */
+  bcdDevice?: number
   /** Serial number */
   serial?: string
   /** Manufacturer string (arbitrary string) */
   manufacturer?: string
   /** Product string (arbitrary string) */
   product?: string
+  /** The kind of hardware behind the port. See [`PortType`] */
+  portType: PortType
+  /**
+   * Whether the current user may open the port, as of when it was reported. False tells a
+   * device which we are not allowed to use (IE: we are not in the dialout group) from one which
+   * is absent. None when it could not be checked, and in Remove events
+   */
+  accessible?: boolean
+  /**
+   * Whether another program has the port open, as of when it was reported, IE: to grey it out
+   * in a port picker. On Linux we see the programs of other users only when they hold a lock
+   * file, or when we run as root. Elsewhere only a lock file tells, and on Windows a port which
+   * we are not allowed to open is in use as well. None when it could not be checked, and in
+   * Remove events. See [`crate::who_has`]
+   */
+  inUse?: boolean
+  /**
+   * The speed the USB device negotiated with its hub, IE: to spot an adapter which fell back
+   * to full speed behind a bad cable or hub. See [`UsbSpeed`]. None when the port is not a USB
+   * device, or when the backend cannot tell
+   */
+  usbSpeed?: UsbSpeed
+  /**
+   * The class codes of the USB interface the port belongs to, IE: to open CDC-ACM consoles and
+   * skip vendor specific debug interfaces. See [`UsbClass`]. Only populated on Linux, Android,
+   * macOS, Windows and FreeBSD
+   */
+  interfaceClass?: UsbClass
+  /**
+   * Identifies the same physical device across unplug and replug, even when the port name
+   * changes. On Linux this is the name of the /dev/serial/by-id link and on Windows the device
+   * instance ID. Elsewhere it is built from the USB ids and serial number, and is None when the
+   * device has no serial number
+   */
+  stableId?: string
+  /**
+   * The physical USB port the device is plugged into, as the bus number and the chain of hub
+   * ports (IE: 1-3.2). Tells apart identical devices which have no serial number. On Windows
+   * it is the hub and port instead (IE: Port_#0002.Hub_#0003) when the driver stack reports no
+   * location path, and on macOS the IOKit locationID (IE: 0x14200000)
+   */
+  location?: string
+  /**
+   * The systemd-logind seat the device is assigned to (IE: seat0), from the ID_SEAT udev
+   * property of the device or of a hub it is plugged into. A device which is not assigned to a
+   * seat belongs to seat0. Only populated on Linux, when udev is running
+   */
+  seat?: string
+  /**
+   * The kernel driver bound to the port (IE: ftdi_sio, cdc_acm, usbser). Helps to diagnose a
+   * device which is present but bound to the wrong driver
+   */
+  driver?: string
+  /**
+   * The name Device Manager shows for the port (IE: USB Serial Device (COM7)). Only populated
+   * on Windows
+   */
+  friendlyName?: string
+  /**
+   * The device instance ID of the port (IE: FTDIBUS\VID_0403+PID_6001+A50285BIA\0000), which
+   * Device Manager and vendor tools identify it by. Only populated on Windows
+   */
+  instanceId?: string
+  /**
+   * The sysfs path of the tty (IE: /sys/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/
+   * ttyUSB0/tty/ttyUSB0), for matching udev rules and finding the other interfaces of the
+   * device. Only populated on Linux and Android
+   */
+  syspath?: string
+  /**
+   * The udev ID_PATH of the port (IE: pci-0000:00:14.0-usb-0:3:1.0), which names the physical
+   * connection as udev rules and /dev/serial/by-path do. Only populated on Linux
+   */
+  idPath?: string
+  /**
+   * The symlinks udev made to the port (IE: /dev/serial/by-id/usb-FTDI_FT232R_A50285BI-if00-
+   * port0), which survive the renumbering of the device node. See [`DeviceInfo::by_id`]. Only
+   * populated on Linux
+   */
+  links: Array<string>
+  /**
+   * The dial-in device of the port (IE: /dev/tty.usbserial-A50285BI), which blocks on open
+   * until the carrier is detected. [`DeviceInfo::port`] is the callout device (/dev/cu.*),
+   * which is the one to open. Only populated on macOS
+   */
+  dialin?: string
+  /**
+   * Every udev property of the device (IE: ID_USB_INTERFACE_NUM, ID_PATH). Only populated on
+   * Linux with the `udev-properties` feature
+   */
+  udevProperties: Record<string, string>
 }
 
 /** Extra data appended to the event */
@@ -22,28 +145,159 @@ export interface EventInfo {
   device: DeviceInfo
   /** See [`EventType`] */
   event: EventType
+  /** When the listener received the notification from the OS. See [`Timestamp`] */
+  timestamp: Timestamp
+  /**
+   * Counts the events of a listener, starting from 0. Each event is numbered one more than the
+   * last, so a gap means that events were lost
+   */
+  seq: bigint
+  /**
+   * The port the device was at before a [`EventType::Moved`] event. [`DeviceInfo::port`] is the
+   * port it moved to. None for every other event
+   */
+  oldPort?: string
 }
 
 /** A USB Add or Remove event has occured */
-export declare const enum EventType {
+export declare enum EventType {
   /** A USB serial port device has been plugged into the system */
   Add = 'Add',
   /** A USB serial port device has been unplugged from the system */
-  Remove = 'Remove'
+  Remove = 'Remove',
+  /**
+   * The properties of a connected device have changed, IE: its ownership or permissions, or
+   * after ModemManager probed it. Only reported when enabled with
+   * [`crate::ListenBuilder::changes`], and only by the Linux backend
+   */
+  Change = 'Change',
+  /**
+   * A device came back at another port soon after it was removed, IE: ttyUSB0 became ttyUSB1
+   * when it dropped off the bus for a moment. Replaces the Remove of the old port and the Add of
+   * the new one, which [`EventInfo::old_port`] names. Only reported when enabled with
+   * [`crate::ListenBuilder::moves`]
+   */
+  Moved = 'Moved'
 }
+
+/** The kind of hardware behind a serial port */
+export declare enum PortType {
+  /** A USB serial adapter or a USB CDC device */
+  Usb = 'Usb',
+  /** A PCI serial card */
+  Pci = 'Pci',
+  /** A Bluetooth serial link (IE: rfcomm) */
+  Bluetooth = 'Bluetooth',
+  /** Anything else, including built in UARTs and virtual ports */
+  Unknown = 'Unknown'
+}
+
+/**
+ * The class, subclass and protocol codes of a USB interface, IE: 02/02/01 for a CDC-ACM modem or
+ * ff/ff/ff for a vendor specific interface
+ */
+export interface UsbClass {
+  /** bInterfaceClass */
+  class: number
+  /** bInterfaceSubClass */
+  subclass: number
+  /** bInterfaceProtocol */
+  protocol: number
+}
+
+/** The signalling rate a USB device negotiated with its hub */
+export declare enum UsbSpeed {
+  /** 1.5 Mbps, USB 1.0 */
+  Low = 'Low',
+  /** 12 Mbps, USB 1.1 */
+  Full = 'Full',
+  /** 480 Mbps, USB 2.0 */
+  High = 'High',
+  /** 5 Gbps, USB 3.0 */
+  Super = 'Super',
+  /** 10 or 20 Gbps, USB 3.1 and 3.2 */
+  SuperPlus = 'SuperPlus'
+}
+
+/**
+ * Tracks which devices are present, and tells each registration when its devices connect and
+ * disconnect. A registration is told about the devices which are already connected when it is
+ * made
+ */
+export declare class DeviceManager {
+  /** Start tracking devices */
+  constructor()
+  /**
+   * Call `connected` with every device matching `filter` as it connects, starting with the ones
+   * which are already connected, and `disconnected` as they go away. Returns the id to
+   * unregister with. Throws once the context of the manager was torn down
+   */
+  register(filter: DeviceFilter, connected: ((err: Error | null, arg: DeviceInfo) => any), disconnected: ((err: Error | null, arg: DeviceInfo) => any)): number
+  /** Stop notifying a registration, and release its callbacks */
+  unregister(id: number): void
+  /** Every connected device, keyed by port name */
+  devices(): Record<string, DeviceInfo>
+  /** The connected devices of a registration, keyed by port name */
+  connected(id: number): Record<string, DeviceInfo>
+}
+
+/**
+ * The events of a listener, iterated with `for await`. Breaking out of the loop, calling
+ * `abort()` or firing the signal of [`ListenOptions`] stops the listener
+ */
+export declare class EventStream {
+  /** Stop the listener. Iterating ends once the events already received are drained */
+  abort(): void
+  [Symbol.asyncIterator](): AsyncGenerator<EventInfo, void, void>
+}
+
 export declare class JsAbortHandle {
   abort(): void
 }
 
 /** Handle to a log event transmitter */
 export declare class Logger {
+  /** Stop sending logs to the callback, and release it. Calling it again does nothing */
   abort(): void
+  /** Change which logs are sent to the callback. See [`configure_logger`] */
+  setFilter(filter: string): void
 }
 
-/** Provide event logs to a callback */
-export declare function configureLogger(tsfn: ((err: Error | null, arg: LogInfo) => any)): [Logger, Promise<unknown>]
+/**
+ * Provide event logs to a callback
+ *
+ * `filter` selects the logs by level, and by level per target like RUST_LOG (IE: `warn` or
+ * `warn,serialport_detect=trace`). Every log is sent by default
+ *
+ * Each context (IE: Electron window or worker) has its own logger. Configuring the logger of a
+ * context again replaces it, and the previous task resolves. So does tearing down the context
+ */
+export declare function configureLogger(tsfn: ((err: Error | null, arg: LogInfo) => any), filter?: string | undefined | null): [Logger, Promise<unknown>]
 
-export declare function listen(tsfn: ((err: Error | null, arg: EventInfo) => any)): [JsAbortHandle, Promise<undefined>]
+/**
+ * Listen for events
+ *
+ * ```js
+ * const controller = new AbortController()
+ * for await (const event of listen({ signal: controller.signal })) {
+ *   console.log(event.event, event.device.port)
+ * }
+ * ```
+ */
+export declare function listen(options?: ListenOptions | undefined | null): EventStream
+
+export interface ListenOptions {
+  /** Stops the listener when it fires, like `abort()` on the stream */
+  signal?: AbortSignal
+  /**
+   * Drop the events over a rate limit, IE: of a device caught in a connect and disconnect loop.
+   * Iterating throws once for the events dropped in a row, and carries on
+   */
+  rateLimit?: RateLimitOptions
+}
+
+/** Listen for events, calling `tsfn` for each of them until the returned handle is aborted */
+export declare function listenWithCallback(tsfn: ((err: Error | null, arg: EventInfo) => any)): [JsAbortHandle, Promise<undefined>]
 
 export interface LogInfo {
   mesg: string
@@ -53,3 +307,37 @@ export interface LogInfo {
   file?: string
   modulePath?: string
 }
+
+export interface RateLimitOptions {
+  /** At most this many events of each port per window */
+  perPort?: number
+  /** At most this many events of all the ports together per window */
+  global?: number
+  /** The window of the limits. One second by default */
+  windowMs?: number
+}
+
+/** Scan for connected devices on the libuv thread pool, keyed by port name */
+export declare function scan(): Promise<Record<string, DeviceInfo>>
+
+/** Scan for connected devices, keyed by port name */
+export declare function scanSync(): Record<string, DeviceInfo>
+
+/**
+ * Resolve with the first device matching `options`, as soon as it is present
+ *
+ * A device which is already connected resolves immediately, otherwise we listen until one is
+ * plugged in. There is no window between the two in which a device could be missed
+ */
+export declare function waitForDevice(options: WaitForDeviceOptions): Promise<DeviceInfo>
+
+export interface WaitForDeviceOptions {
+  /** Vendor ID */
+  vid?: number
+  /** Product ID */
+  pid?: number
+  /** Serial number */
+  serial?: string
+  /** Reject when no device shows up in time. Waits forever by default */
+  timeoutMs?: number
+}
@@ -393,8 +393,16 @@ if (!nativeBinding) {
 }
 
 module.exports = nativeBinding
+module.exports.DeviceManager = nativeBinding.DeviceManager
+module.exports.EventStream = nativeBinding.EventStream
 module.exports.EventType = nativeBinding.EventType
 module.exports.JsAbortHandle = nativeBinding.JsAbortHandle
 module.exports.Logger = nativeBinding.Logger
+module.exports.PortType = nativeBinding.PortType
+module.exports.UsbSpeed = nativeBinding.UsbSpeed
 module.exports.configureLogger = nativeBinding.configureLogger
 module.exports.listen = nativeBinding.listen
+module.exports.listenWithCallback = nativeBinding.listenWithCallback
+module.exports.scan = nativeBinding.scan
+module.exports.scanSync = nativeBinding.scanSync
+module.exports.waitForDevice = nativeBinding.waitForDevice
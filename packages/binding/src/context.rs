@@ -0,0 +1,83 @@
+//! The module can be loaded by several contexts of one process, IE: the windows and workers of an
+//! Electron app. Each context keeps track of what it started, which is released when the context
+//! is torn down (IE: when its window is reloaded or closed) instead of outliving it
+use crate::logger;
+use napi::{Env, Result};
+use std::{
+  collections::BTreeMap,
+  sync::{Arc, Mutex, PoisonError, Weak},
+};
+
+/// A value shared by its javascript object and its context, released by whichever lets go first
+pub type Shared<T> = Arc<Mutex<Option<T>>>;
+
+pub fn shared<T>(value: T) -> Shared<T> {
+  Arc::new(Mutex::new(Some(value)))
+}
+
+/// Release a shared value, IE: drop an abort handle to stop its listener. Does nothing when it
+/// is already released
+pub fn release<T>(shared: &Mutex<Option<T>>) {
+  let _value = shared.lock().unwrap_or_else(PoisonError::into_inner).take();
+}
+
+trait Release: Send + Sync {
+  fn release(&self);
+}
+
+impl<T: Send> Release for Mutex<Option<T>> {
+  fn release(&self) {
+    release(self)
+  }
+}
+
+#[derive(Default)]
+struct Context {
+  owned: Vec<Weak<dyn Release>>,
+}
+
+/// Keyed by the `napi_env` of the context
+static CONTEXTS: Mutex<BTreeMap<usize, Context>> = Mutex::new(BTreeMap::new());
+
+pub fn key(env: &Env) -> usize {
+  env.raw() as usize
+}
+
+/// The state of the context of `env`, created on first use along with its teardown hook
+fn with_context<R>(env: &Env, f: impl FnOnce(&mut Context) -> R) -> Result<R> {
+  let key = key(env);
+  let mut contexts = CONTEXTS.lock().unwrap_or_else(PoisonError::into_inner);
+  if !contexts.contains_key(&key) {
+    let mut env = *env;
+    env.add_env_cleanup_hook(key, teardown)?;
+  }
+  Ok(f(contexts.entry(key).or_default()))
+}
+
+/// Make sure the context of `env` is torn down with it
+pub fn track(env: &Env) -> Result<()> {
+  with_context(env, |_| ())
+}
+
+/// Release `shared` when the context of `env` is torn down, unless its javascript object
+/// released it first
+pub fn own<T: Send + 'static>(env: &Env, shared: &Shared<T>) -> Result<()> {
+  let shared: Arc<dyn Release> = Arc::clone(shared) as _;
+  with_context(env, |context| {
+    context.owned.retain(|owned| owned.strong_count() > 0);
+    context.owned.push(Arc::downgrade(&shared));
+  })
+}
+
+fn teardown(key: usize) {
+  let context = CONTEXTS
+    .lock()
+    .unwrap_or_else(PoisonError::into_inner)
+    .remove(&key);
+  for owned in context.into_iter().flat_map(|context| context.owned) {
+    if let Some(owned) = owned.upgrade() {
+      owned.release();
+    }
+  }
+  logger::detach(key);
+}
@@ -1,32 +1,38 @@
 #![deny(clippy::all)]
+pub mod context;
 pub mod logger;
+pub mod manager;
 
+use context::Shared;
 use futures::prelude::*;
+use manager::DeviceFilter;
 use napi::{
   bindgen_prelude::*,
   threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
-  Error, Result,
+  Error, Result, Task,
 };
 use napi_derive::napi;
-use serialport_detect::{AbortHandle, EventInfo};
+use serialport_detect::{AbortHandle, DeviceInfo, EventInfo, EventIter, RateLimit};
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
 use tracing::{trace, warn};
 
 #[napi]
 pub struct JsAbortHandle {
-  inner: Option<AbortHandle>,
+  inner: Shared<AbortHandle>,
 }
 
 #[napi]
 impl JsAbortHandle {
   #[napi]
-  pub fn abort(&mut self) {
+  pub fn abort(&self) {
     // Drop abort handle, cause abort
-    let _abort = self.inner.take();
+    context::release(&self.inner);
   }
 }
 
+/// Listen for events, calling `tsfn` for each of them until the returned handle is aborted
 #[napi]
-pub fn listen<'env>(
+pub fn listen_with_callback<'env>(
   env: &'env Env,
   tsfn: ThreadsafeFunction<EventInfo>,
 ) -> Result<(JsAbortHandle, PromiseRaw<'env, ()>)> {
@@ -50,5 +56,177 @@ pub fn listen<'env>(
     }
     Ok(())
   })?;
-  Ok((JsAbortHandle { inner: Some(abort) }, future))
+  let inner = context::shared(abort);
+  context::own(env, &inner)?;
+  Ok((JsAbortHandle { inner }, future))
+}
+
+/// The events of a listener, iterated with `for await`. Breaking out of the loop, calling
+/// `abort()` or firing the signal of [`ListenOptions`] stops the listener
+#[napi(async_iterator)]
+pub struct EventStream {
+  events: Arc<futures::lock::Mutex<EventIter>>,
+  abort: Shared<AbortHandle>,
+}
+
+#[napi]
+impl EventStream {
+  /// Stop the listener. Iterating ends once the events already received are drained
+  #[napi]
+  pub fn abort(&self) {
+    context::release(&self.abort);
+  }
+}
+
+impl AsyncGenerator for EventStream {
+  type Yield = EventInfo;
+  type Next = ();
+  type Return = ();
+
+  fn next(
+    &mut self,
+    _value: Option<Self::Next>,
+  ) -> impl Future<Output = Result<Option<Self::Yield>>> + Send + 'static {
+    let events = Arc::clone(&self.events);
+    async move {
+      match events.lock().await.next().await {
+        None => Ok(None),
+        Some(Ok(event)) => Ok(Some(event)),
+        Some(Err(e)) => Err(Error::from_reason(e.to_string())),
+      }
+    }
+  }
+
+  fn complete(
+    &mut self,
+    _value: Option<Self::Return>,
+  ) -> impl Future<Output = Result<Option<Self::Yield>>> + Send + 'static {
+    self.abort();
+    async { Ok(None) }
+  }
+}
+
+#[napi(object, object_to_js = false)]
+pub struct ListenOptions {
+  /// Stops the listener when it fires, like `abort()` on the stream
+  pub signal: Option<AbortSignal>,
+  /// Drop the events over a rate limit, IE: of a device caught in a connect and disconnect loop.
+  /// Iterating throws once for the events dropped in a row, and carries on
+  pub rate_limit: Option<RateLimitOptions>,
+}
+
+#[napi(object, object_to_js = false)]
+pub struct RateLimitOptions {
+  /// At most this many events of each port per window
+  pub per_port: Option<u32>,
+  /// At most this many events of all the ports together per window
+  pub global: Option<u32>,
+  /// The window of the limits. One second by default
+  pub window_ms: Option<u32>,
+}
+
+impl From<RateLimitOptions> for RateLimit {
+  fn from(options: RateLimitOptions) -> RateLimit {
+    let window = Duration::from_millis(options.window_ms.unwrap_or(1000).into());
+    let mut limit = RateLimit::new();
+    if let Some(events) = options.per_port {
+      limit = limit.per_port(events, window);
+    }
+    if let Some(events) = options.global {
+      limit = limit.global(events, window);
+    }
+    limit
+  }
+}
+
+/// Listen for events
+///
+/// ```js
+/// const controller = new AbortController()
+/// for await (const event of listen({ signal: controller.signal })) {
+///   console.log(event.event, event.device.port)
+/// }
+/// ```
+#[napi]
+pub fn listen(env: &Env, options: Option<ListenOptions>) -> Result<EventStream> {
+  let (signal, rate_limit) = match options {
+    Some(options) => (options.signal, options.rate_limit),
+    None => (None, None),
+  };
+  let mut builder = serialport_detect::builder();
+  if let Some(limit) = rate_limit {
+    builder = builder.rate_limit(limit.into());
+  }
+  let (abort, events) = builder
+    .listen()
+    .map_err(|e| Error::from_reason(e.to_string()))?;
+  let stream = EventStream {
+    events: Arc::new(futures::lock::Mutex::new(events)),
+    abort: context::shared(abort),
+  };
+  context::own(env, &stream.abort)?;
+  if let Some(signal) = signal {
+    let abort = Arc::clone(&stream.abort);
+    signal.on_abort(move || context::release(&abort));
+  }
+  Ok(stream)
+}
+
+#[napi(object, object_to_js = false)]
+pub struct WaitForDeviceOptions {
+  /// Vendor ID
+  pub vid: Option<u16>,
+  /// Product ID
+  pub pid: Option<u16>,
+  /// Serial number
+  pub serial: Option<String>,
+  /// Reject when no device shows up in time. Waits forever by default
+  pub timeout_ms: Option<u32>,
+}
+
+/// Resolve with the first device matching `options`, as soon as it is present
+///
+/// A device which is already connected resolves immediately, otherwise we listen until one is
+/// plugged in. There is no window between the two in which a device could be missed
+#[napi]
+pub async fn wait_for_device(options: WaitForDeviceOptions) -> Result<DeviceInfo> {
+  let filter = DeviceFilter {
+    vid: options.vid,
+    pid: options.pid,
+    serial: options.serial,
+    ..Default::default()
+  };
+  let timeout = options.timeout_ms.map_or(Duration::MAX, |timeout| {
+    Duration::from_millis(timeout.into())
+  });
+  serialport_detect::wait_for_device(filter.into(), timeout)
+    .await
+    .map_err(|e| Error::from_reason(e.to_string()))
+}
+
+/// Scan for connected devices, keyed by port name
+#[napi]
+pub fn scan_sync() -> Result<HashMap<String, DeviceInfo>> {
+  serialport_detect::scan().map_err(|e| Error::from_reason(e.to_string()))
+}
+
+pub struct ScanTask;
+
+impl Task for ScanTask {
+  type Output = HashMap<String, DeviceInfo>;
+  type JsValue = HashMap<String, DeviceInfo>;
+
+  fn compute(&mut self) -> Result<Self::Output> {
+    scan_sync()
+  }
+
+  fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+    Ok(output)
+  }
+}
+
+/// Scan for connected devices on the libuv thread pool, keyed by port name
+#[napi]
+pub fn scan() -> AsyncTask<ScanTask> {
+  AsyncTask::new(ScanTask)
 }
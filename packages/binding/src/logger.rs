@@ -1,3 +1,4 @@
+use crate::context;
 use napi::{
   bindgen_prelude::*,
   threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
@@ -7,10 +8,17 @@ use napi_derive::napi;
 use serde_json::Value;
 use std::{
   collections::HashMap,
-  sync::mpsc::{Receiver, Sender},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    mpsc::{Receiver, Sender},
+    Mutex, OnceLock, PoisonError,
+  },
 };
-use tracing::{error, field::Visit, level_filters::LevelFilter, warn, Subscriber};
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+use tracing::{
+  callsite, field::Visit, level_filters::LevelFilter, subscriber::Interest, warn, Metadata,
+  Subscriber,
+};
+use tracing_subscriber::{filter::Targets, layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
 struct VisitJsonLike {
   meta: HashMap<String, Value>,
@@ -79,6 +87,7 @@ impl Visit for VisitJsonLike {
 }
 
 #[napi(object)]
+#[derive(Clone)]
 pub struct LogInfo {
   pub mesg: String,
   pub meta: HashMap<String, Value>,
@@ -88,14 +97,41 @@ pub struct LogInfo {
   pub module_path: Option<String>,
 }
 
-pub struct JsTrace {
-  tx: Sender<LogMsg>,
+/// Forwards logs to the [`Sink`] of every context which configured a logger, filtered by each
+pub struct JsTrace;
+
+impl JsTrace {
+  fn wanted(metadata: &Metadata<'_>) -> bool {
+    let sinks = SINKS.lock().unwrap_or_else(PoisonError::into_inner);
+    sinks.iter().any(|sink| {
+      sink
+        .filter
+        .would_enable(metadata.target(), metadata.level())
+    })
+  }
 }
+
 impl<S> Layer<S> for JsTrace
 where
   S: Subscriber,
 {
+  fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+    match JsTrace::wanted(metadata) {
+      true => Interest::always(),
+      false => Interest::never(),
+    }
+  }
+
+  fn enabled(
+    &self,
+    metadata: &Metadata<'_>,
+    _ctx: tracing_subscriber::layer::Context<'_, S>,
+  ) -> bool {
+    JsTrace::wanted(metadata)
+  }
+
   fn on_event(&self, event: &tracing::Event<'_>, _ctx: tracing_subscriber::layer::Context<'_, S>) {
+    // Recording may log, so we must not hold the sinks meanwhile
     let mut visitor = VisitJsonLike {
       meta: HashMap::new(),
     };
@@ -105,20 +141,71 @@ where
       .remove("message")
       .and_then(|value| value.as_str().map(|s| s.to_string()))
       .unwrap_or_default();
-    let result = self.tx.send(LogMsg::Log(LogInfo {
+    let metadata = event.metadata();
+    let info = LogInfo {
       mesg,
       meta,
-      file: event.metadata().file().map(|s| s.to_string()),
-      line: event.metadata().line(),
-      target: event.metadata().target().to_string(),
-      module_path: event.metadata().module_path().map(|s| s.to_string()),
-    }));
-    if let Err(error) = result {
-      error!(?error, "ipc failed to send log event");
-    }
+      file: metadata.file().map(|s| s.to_string()),
+      line: metadata.line(),
+      target: metadata.target().to_string(),
+      module_path: metadata.module_path().map(|s| s.to_string()),
+    };
+    let mut sinks = SINKS.lock().unwrap_or_else(PoisonError::into_inner);
+    // A sink whose log task is gone has nobody listening anymore
+    sinks.retain(|sink| {
+      !sink
+        .filter
+        .would_enable(metadata.target(), metadata.level())
+        || sink.tx.send(LogMsg::Log(info.clone())).is_ok()
+    });
   }
 }
 
+/// Where the logs of a context go to, and which logger it belongs to
+struct Sink {
+  id: u64,
+  context: usize,
+  tx: Sender<LogMsg>,
+  filter: Targets,
+}
+
+/// The subscriber can only be installed once per process, so it stays installed and forwards
+/// to the logger of each context
+static SINKS: Mutex<Vec<Sink>> = Mutex::new(Vec::new());
+
+/// Install the subscriber unless it already is. Fails when another global subscriber was
+/// installed
+fn install() -> Result<()> {
+  static INSTALLED: OnceLock<()> = OnceLock::new();
+  if INSTALLED.get().is_none() {
+    tracing_subscriber::registry()
+      .with(JsTrace)
+      .try_init()
+      .map_err(|error| Error::from_reason(error.to_string()))?;
+    INSTALLED.get_or_init(|| ());
+  }
+  Ok(())
+}
+
+/// Update the sinks, then have the callsites ask [`JsTrace`] again whether they are wanted
+fn update_sinks<R>(f: impl FnOnce(&mut Vec<Sink>) -> R) -> R {
+  let result = f(&mut SINKS.lock().unwrap_or_else(PoisonError::into_inner));
+  callsite::rebuild_interest_cache();
+  result
+}
+
+/// Stop sending logs to the logger of a context which is torn down
+pub(crate) fn detach(context: usize) {
+  update_sinks(|sinks| {
+    sinks.retain(|sink| {
+      if sink.context == context {
+        let _ = sink.tx.send(LogMsg::Abort);
+      }
+      sink.context != context
+    })
+  });
+}
+
 enum LogMsg {
   Log(LogInfo),
   Abort,
@@ -163,30 +250,79 @@ impl Task for LogTask {
 /// Handle to a log event transmitter
 #[napi]
 pub struct Logger {
+  id: u64,
   /// Used to cancel the remote thread listening to log events
   tx: Sender<LogMsg>,
 }
 
 #[napi]
 impl Logger {
+  /// Stop sending logs to the callback, and release it. Calling it again does nothing
   #[napi]
-  pub fn abort(&self) -> Result<()> {
-    self
-      .tx
-      .send(LogMsg::Abort)
-      .map_err(|error| Error::from_reason(error.to_string()))
+  pub fn abort(&self) {
+    update_sinks(|sinks| sinks.retain(|sink| sink.id != self.id));
+    // The task is already gone when the logger was replaced, or aborted before
+    let _ = self.tx.send(LogMsg::Abort);
+  }
+
+  /// Change which logs are sent to the callback. See [`configure_logger`]
+  #[napi]
+  pub fn set_filter(&self, filter: String) -> Result<()> {
+    let filter = parse_filter(&filter)?;
+    update_sinks(
+      |sinks| match sinks.iter_mut().find(|sink| sink.id == self.id) {
+        Some(sink) => {
+          sink.filter = filter;
+          Ok(())
+        }
+        None => Err(Error::from_reason("the logger was aborted or replaced")),
+      },
+    )
   }
 }
 
+fn parse_filter(filter: &str) -> Result<Targets> {
+  filter
+    .parse()
+    .map_err(|error| Error::from_reason(format!("invalid log filter {filter:?}: {error}")))
+}
+
 /// Provide event logs to a callback
+///
+/// `filter` selects the logs by level, and by level per target like RUST_LOG (IE: `warn` or
+/// `warn,serialport_detect=trace`). Every log is sent by default
+///
+/// Each context (IE: Electron window or worker) has its own logger. Configuring the logger of a
+/// context again replaces it, and the previous task resolves. So does tearing down the context
 #[napi]
-pub fn configure_logger(tsfn: ThreadsafeFunction<LogInfo>) -> (Logger, AsyncTask<LogTask>) {
+pub fn configure_logger(
+  env: &Env,
+  tsfn: ThreadsafeFunction<LogInfo>,
+  filter: Option<String>,
+) -> Result<(Logger, AsyncTask<LogTask>)> {
+  static IDS: AtomicU64 = AtomicU64::new(0);
+  let filter = match filter {
+    Some(filter) => parse_filter(&filter)?,
+    None => Targets::new().with_default(LevelFilter::TRACE),
+  };
+  install()?;
+  context::track(env)?;
+  let context = context::key(env);
   let (tx, rx) = std::sync::mpsc::channel();
+  let id = IDS.fetch_add(1, Ordering::Relaxed);
+  let sink = Sink {
+    id,
+    context,
+    tx: tx.clone(),
+    filter,
+  };
+  update_sinks(|sinks| {
+    for previous in sinks.iter().filter(|sink| sink.context == context) {
+      let _ = previous.tx.send(LogMsg::Abort);
+    }
+    sinks.retain(|sink| sink.context != context);
+    sinks.push(sink);
+  });
   let task = LogTask { rx, tsfn };
-  let abort = Logger { tx: tx.clone() };
-  tracing_subscriber::registry()
-    .with(JsTrace { tx })
-    .with(LevelFilter::TRACE)
-    .init();
-  (abort, AsyncTask::new(task))
+  Ok((Logger { id, tx }, AsyncTask::new(task)))
 }
@@ -0,0 +1,132 @@
+use crate::context::{self, Shared};
+use napi::{
+  bindgen_prelude::*,
+  threadsafe_function::{ThreadsafeFunction, ThreadsafeFunctionCallMode},
+};
+use napi_derive::napi;
+use serialport_detect::{DeviceInfo, DeviceManager, ListenBuilder, RegistrationId};
+use std::{collections::HashMap, sync::PoisonError};
+
+/// Selects the devices of a registration. Every device matches an empty filter
+#[napi(object, object_to_js = false)]
+#[derive(Default)]
+pub struct DeviceFilter {
+  /// Vendor ID
+  pub vid: Option<u16>,
+  /// Product ID
+  pub pid: Option<u16>,
+  /// Serial number
+  pub serial: Option<String>,
+  /// Manufacturer string
+  pub manufacturer: Option<String>,
+  /// Product string
+  pub product: Option<String>,
+}
+
+impl From<DeviceFilter> for ListenBuilder {
+  fn from(filter: DeviceFilter) -> ListenBuilder {
+    let mut builder = serialport_detect::builder();
+    if let Some(vid) = filter.vid {
+      builder = builder.vid(vid);
+    }
+    if let Some(pid) = filter.pid {
+      builder = builder.pid(pid);
+    }
+    if let Some(serial) = filter.serial {
+      builder = builder.serial(serial);
+    }
+    if let Some(manufacturer) = filter.manufacturer {
+      builder = builder.manufacturer(manufacturer);
+    }
+    if let Some(product) = filter.product {
+      builder = builder.product(product);
+    }
+    builder
+  }
+}
+
+/// Tracks which devices are present, and tells each registration when its devices connect and
+/// disconnect. A registration is told about the devices which are already connected when it is
+/// made
+#[napi(js_name = "DeviceManager")]
+pub struct JsDeviceManager {
+  /// Released when the context is torn down
+  inner: Shared<DeviceManager>,
+  /// Registration ids are opaque in Rust, so we number them for javascript
+  registrations: HashMap<u32, RegistrationId>,
+  next_id: u32,
+}
+
+#[napi]
+impl JsDeviceManager {
+  /// Start tracking devices
+  #[napi(constructor)]
+  pub fn new(env: Env) -> Result<Self> {
+    let inner = DeviceManager::new().map_err(|e| Error::from_reason(e.to_string()))?;
+    let inner = context::shared(inner);
+    context::own(&env, &inner)?;
+    Ok(JsDeviceManager {
+      inner,
+      registrations: HashMap::new(),
+      next_id: 0,
+    })
+  }
+
+  /// Call `connected` with every device matching `filter` as it connects, starting with the ones
+  /// which are already connected, and `disconnected` as they go away. Returns the id to
+  /// unregister with
+  #[napi]
+  pub fn register(
+    &mut self,
+    filter: DeviceFilter,
+    connected: ThreadsafeFunction<DeviceInfo>,
+    disconnected: ThreadsafeFunction<DeviceInfo>,
+  ) -> Result<u32> {
+    let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+    let manager = inner
+      .as_ref()
+      .ok_or_else(|| Error::from_reason("the device manager was released"))?;
+    let id = manager.register(
+      filter.into(),
+      move |device| {
+        connected.call(Ok(device.clone()), ThreadsafeFunctionCallMode::Blocking);
+      },
+      move |device| {
+        disconnected.call(Ok(device.clone()), ThreadsafeFunctionCallMode::Blocking);
+      },
+    );
+    let js_id = self.next_id;
+    self.next_id += 1;
+    self.registrations.insert(js_id, id);
+    Ok(js_id)
+  }
+
+  /// Stop notifying a registration, and release its callbacks
+  #[napi]
+  pub fn unregister(&mut self, id: u32) {
+    let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+    if let (Some(manager), Some(id)) = (inner.as_ref(), self.registrations.remove(&id)) {
+      manager.unregister(id);
+    }
+  }
+
+  /// Every connected device, keyed by port name
+  #[napi]
+  pub fn devices(&self) -> HashMap<String, DeviceInfo> {
+    let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+    inner
+      .as_ref()
+      .map(DeviceManager::devices)
+      .unwrap_or_default()
+  }
+
+  /// The connected devices of a registration, keyed by port name
+  #[napi]
+  pub fn connected(&self, id: u32) -> HashMap<String, DeviceInfo> {
+    let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+    match (inner.as_ref(), self.registrations.get(&id)) {
+      (Some(manager), Some(id)) => manager.connected(*id),
+      _ => HashMap::new(),
+    }
+  }
+}
@@ -0,0 +1,94 @@
+/* C bindings of serialport-detect. Link against libserialport_detect_ffi */
+#ifndef SERIALPORT_DETECT_H
+#define SERIALPORT_DETECT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The result of every function. Anything other than SPD_OK sets spd_last_error() */
+typedef enum SpdStatus {
+    SPD_OK = 0,
+    SPD_INVALID_ARGUMENT,
+    SPD_BACKEND,
+    SPD_REGISTRATION,
+    SPD_PERMISSION_DENIED,
+    SPD_DECODE,
+    SPD_OVERFLOW,
+    SPD_TIMEOUT,
+    SPD_IO,
+} SpdStatus;
+
+typedef enum SpdEventType {
+    SPD_EVENT_ADD = 0,
+    SPD_EVENT_REMOVE,
+    SPD_EVENT_CHANGE,
+    /* The device came back at another port, named by old_port */
+    SPD_EVENT_MOVED,
+} SpdEventType;
+
+typedef enum SpdPortType {
+    SPD_PORT_USB = 0,
+    SPD_PORT_PCI,
+    SPD_PORT_BLUETOOTH,
+    SPD_PORT_UNKNOWN,
+} SpdPortType;
+
+/* A device. The strings are NULL when the platform did not report them, and the ids are 0 */
+typedef struct SpdDeviceInfo {
+    char *port;
+    uint16_t vid;
+    uint16_t pid;
+    char *serial;
+    char *manufacturer;
+    char *product;
+    SpdPortType port_type;
+} SpdDeviceInfo;
+
+/* An event, only valid for the duration of the callback it is passed to */
+typedef struct SpdEvent {
+    SpdEventType event;
+    SpdDeviceInfo device;
+    uint64_t seq;
+    /* The port the device was at before a SPD_EVENT_MOVED event. NULL for every other event */
+    char *old_port;
+} SpdEvent;
+
+/* The devices returned by spd_scan(). Release with spd_device_list_free() */
+typedef struct SpdDeviceList {
+    SpdDeviceInfo *devices;
+    size_t len;
+} SpdDeviceList;
+
+typedef struct SpdListener SpdListener;
+
+/* Called from the listener thread for every event. On error `event` is NULL and `status` tells
+ * what went wrong, the listener keeps running */
+typedef void (*SpdCallback)(void *user_data, const SpdEvent *event, SpdStatus status);
+
+/* The message of the last error of the calling thread, or NULL. Valid until the next call which
+ * fails on this thread */
+const char *spd_last_error(void);
+
+/* Scan for connected devices, sorted by port name */
+SpdStatus spd_scan(SpdDeviceList *out);
+
+/* Release a list returned by spd_scan(). The list is left empty */
+void spd_device_list_free(SpdDeviceList *list);
+
+/* Listen for events, calling `callback` with `user_data` from a thread of our own until the
+ * listener is released with spd_abort() */
+SpdStatus spd_listen(SpdCallback callback, void *user_data, SpdListener **out);
+
+/* Stop a listener and wait for its thread to exit, after which the callback is not called again.
+ * Must not be called from within the callback */
+void spd_abort(SpdListener *listener);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,346 @@
+//! C bindings of serialport-detect
+//!
+//! Every function returns an [`SpdStatus`], and the message of the last error of the calling
+//! thread is available from [`spd_last_error`]. Strings handed to C are owned by this library and
+//! are only valid until the list or event holding them is released. See include/serialport_detect.h
+
+#![allow(clippy::missing_safety_doc)]
+
+use serialport_detect::{AbortHandle, DeviceInfo, Error, EventInfo, EventType, PortType};
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_void, CString},
+    ptr,
+    thread::JoinHandle,
+};
+
+/// The result of every function. Anything other than [`SpdStatus::Ok`] sets [`spd_last_error`]
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpdStatus {
+    Ok = 0,
+    /// A pointer argument was NULL
+    InvalidArgument,
+    /// The native backend could not be initialized
+    Backend,
+    /// The backend could not register for device notifications
+    Registration,
+    /// Access to the device or to the notification mechanism was denied
+    PermissionDenied,
+    /// A notification or a device property could not be decoded
+    Decode,
+    /// The event queue was full and events were dropped
+    Overflow,
+    /// A device did not show up in time
+    Timeout,
+    /// Any other I/O error
+    Io,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpdEventType {
+    Add = 0,
+    Remove,
+    Change,
+    /// The device came back at another port, named by [`SpdEvent::old_port`]
+    Moved,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpdPortType {
+    Usb = 0,
+    Pci,
+    Bluetooth,
+    Unknown,
+}
+
+/// A device. The strings are NULL when the platform did not report them, and the ids are 0
+#[repr(C)]
+#[derive(Debug)]
+pub struct SpdDeviceInfo {
+    pub port: *mut c_char,
+    pub vid: u16,
+    pub pid: u16,
+    pub serial: *mut c_char,
+    pub manufacturer: *mut c_char,
+    pub product: *mut c_char,
+    pub port_type: SpdPortType,
+}
+
+/// An event, only valid for the duration of the callback it is passed to
+#[repr(C)]
+#[derive(Debug)]
+pub struct SpdEvent {
+    pub event: SpdEventType,
+    pub device: SpdDeviceInfo,
+    pub seq: u64,
+    /// The port the device was at before a [`SpdEventType::Moved`] event. NULL for every other
+    /// event
+    pub old_port: *mut c_char,
+}
+
+/// The devices returned by [`spd_scan`]. Release with [`spd_device_list_free`]
+#[repr(C)]
+#[derive(Debug)]
+pub struct SpdDeviceList {
+    pub devices: *mut SpdDeviceInfo,
+    pub len: usize,
+}
+
+/// Called from the listener thread for every event. On error `event` is NULL and `status` tells
+/// what went wrong, the listener keeps running
+pub type SpdCallback =
+    Option<extern "C" fn(user_data: *mut c_void, event: *const SpdEvent, status: SpdStatus)>;
+
+/// A running listener, returned by [`spd_listen`] and released by [`spd_abort`]
+#[derive(Debug)]
+pub struct SpdListener {
+    abort: AbortHandle,
+    thread: JoinHandle<()>,
+}
+
+/// The caller of [`spd_listen`] promises that the user data may be used from the listener thread
+struct UserData(*mut c_void);
+unsafe impl Send for UserData {}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl ToString) {
+    let message = CString::new(message.to_string().replace('\0', "")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+fn status(error: &Error) -> SpdStatus {
+    set_last_error(error);
+    match error {
+        Error::Backend(_) => SpdStatus::Backend,
+        Error::Registration(_) => SpdStatus::Registration,
+        Error::PermissionDenied(_) => SpdStatus::PermissionDenied,
+        Error::Decode(_) => SpdStatus::Decode,
+        Error::Overflow { .. } => SpdStatus::Overflow,
+        Error::Timeout => SpdStatus::Timeout,
+        _ => SpdStatus::Io,
+    }
+}
+
+fn invalid_argument(name: &str) -> SpdStatus {
+    set_last_error(format!("{name} is NULL"));
+    SpdStatus::InvalidArgument
+}
+
+fn into_c_string(value: String) -> *mut c_char {
+    CString::new(value.replace('\0', "")).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+fn into_c_option(value: Option<String>) -> *mut c_char {
+    value.map_or(ptr::null_mut(), into_c_string)
+}
+
+unsafe fn free_c_string(value: *mut c_char) {
+    if !value.is_null() {
+        drop(CString::from_raw(value));
+    }
+}
+
+impl From<DeviceInfo> for SpdDeviceInfo {
+    fn from(device: DeviceInfo) -> SpdDeviceInfo {
+        SpdDeviceInfo {
+            port: into_c_string(device.port),
+            vid: device.vid_u16.unwrap_or(0),
+            pid: device.pid_u16.unwrap_or(0),
+            serial: into_c_option(device.serial),
+            manufacturer: into_c_option(device.manufacturer),
+            product: into_c_option(device.product),
+            port_type: match device.port_type {
+                PortType::Usb => SpdPortType::Usb,
+                PortType::Pci => SpdPortType::Pci,
+                PortType::Bluetooth => SpdPortType::Bluetooth,
+                PortType::Unknown => SpdPortType::Unknown,
+            },
+        }
+    }
+}
+
+impl From<EventInfo> for SpdEvent {
+    fn from(event: EventInfo) -> SpdEvent {
+        SpdEvent {
+            event: match event.event {
+                EventType::Add => SpdEventType::Add,
+                EventType::Remove => SpdEventType::Remove,
+                EventType::Change => SpdEventType::Change,
+                EventType::Moved => SpdEventType::Moved,
+            },
+            device: event.device.into(),
+            seq: event.seq,
+            old_port: into_c_option(event.old_port),
+        }
+    }
+}
+
+impl Drop for SpdDeviceInfo {
+    fn drop(&mut self) {
+        unsafe {
+            free_c_string(self.port);
+            free_c_string(self.serial);
+            free_c_string(self.manufacturer);
+            free_c_string(self.product);
+        }
+    }
+}
+
+impl Drop for SpdEvent {
+    fn drop(&mut self) {
+        unsafe { free_c_string(self.old_port) };
+    }
+}
+
+/// The message of the last error of the calling thread, or NULL. Valid until the next call which
+/// fails on this thread
+#[no_mangle]
+pub extern "C" fn spd_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |last| last.as_ptr())
+    })
+}
+
+/// Scan for connected devices, sorted by port name. Release the list with [`spd_device_list_free`]
+#[no_mangle]
+pub unsafe extern "C" fn spd_scan(out: *mut SpdDeviceList) -> SpdStatus {
+    let Some(out) = out.as_mut() else {
+        return invalid_argument("out");
+    };
+    match serialport_detect::scan_sorted() {
+        Ok(devices) => {
+            let devices: Box<[SpdDeviceInfo]> = devices.into_iter().map(Into::into).collect();
+            out.len = devices.len();
+            out.devices = Box::into_raw(devices).cast();
+            SpdStatus::Ok
+        }
+        Err(error) => status(&error),
+    }
+}
+
+/// Release a list returned by [`spd_scan`]. The list is left empty, so freeing it twice is harmless
+#[no_mangle]
+pub unsafe extern "C" fn spd_device_list_free(list: *mut SpdDeviceList) {
+    let Some(list) = list.as_mut() else { return };
+    if !list.devices.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            list.devices,
+            list.len,
+        )));
+    }
+    list.devices = ptr::null_mut();
+    list.len = 0;
+}
+
+/// Listen for events, calling `callback` with `user_data` from a thread of our own until the
+/// listener is released with [`spd_abort`]
+#[no_mangle]
+pub unsafe extern "C" fn spd_listen(
+    callback: SpdCallback,
+    user_data: *mut c_void,
+    out: *mut *mut SpdListener,
+) -> SpdStatus {
+    let Some(callback) = callback else {
+        return invalid_argument("callback");
+    };
+    let Some(out) = out.as_mut() else {
+        return invalid_argument("out");
+    };
+    let (abort, events) = match serialport_detect::listen() {
+        Ok(listener) => listener,
+        Err(error) => return status(&error),
+    };
+    let user_data = UserData(user_data);
+    let spawned = std::thread::Builder::new()
+        .name("serialport-detect-ffi".into())
+        .spawn(move || {
+            let user_data = user_data;
+            for event in events {
+                match event {
+                    Ok(event) => {
+                        let event = SpdEvent::from(event);
+                        callback(user_data.0, &event, SpdStatus::Ok);
+                    }
+                    Err(error) => callback(user_data.0, ptr::null(), status(&error)),
+                }
+            }
+        });
+    match spawned {
+        Ok(thread) => {
+            *out = Box::into_raw(Box::new(SpdListener { abort, thread }));
+            SpdStatus::Ok
+        }
+        Err(error) => status(&error.into()),
+    }
+}
+
+/// Stop a listener and wait for its thread to exit, after which the callback is not called again.
+/// Must not be called from within the callback, which would wait for itself
+#[no_mangle]
+pub unsafe extern "C" fn spd_abort(listener: *mut SpdListener) {
+    if listener.is_null() {
+        return;
+    }
+    let listener = Box::from_raw(listener);
+    listener.abort.shutdown();
+    if listener.thread.join().is_err() {
+        set_last_error("the listener thread panicked");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_device_info() {
+        let device = DeviceInfo {
+            port: "/dev/ttyUSB0".into(),
+            vid_u16: Some(0x0403),
+            serial: Some("A50285BI".into()),
+            port_type: PortType::Usb,
+            ..Default::default()
+        };
+        let event = SpdEvent::from(EventInfo {
+            device,
+            event: EventType::Add,
+            timestamp: serialport_detect::Timestamp::now(),
+            seq: 7,
+            old_port: None,
+        });
+        let port = unsafe { CStr::from_ptr(event.device.port) };
+        let serial = unsafe { CStr::from_ptr(event.device.serial) };
+        assert_eq!(port.to_str().unwrap(), "/dev/ttyUSB0");
+        assert_eq!(serial.to_str().unwrap(), "A50285BI");
+        assert_eq!((event.device.vid, event.device.pid), (0x0403, 0));
+        assert!(event.device.product.is_null());
+        assert_eq!(event.device.port_type, SpdPortType::Usb);
+        assert_eq!((event.event, event.seq), (SpdEventType::Add, 7));
+        assert!(event.old_port.is_null());
+    }
+
+    #[test]
+    fn test_invalid_argument() {
+        let status = unsafe { spd_scan(ptr::null_mut()) };
+        assert_eq!(status, SpdStatus::InvalidArgument);
+        let message = unsafe { CStr::from_ptr(spd_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "out is NULL");
+        let mut list = SpdDeviceList {
+            devices: ptr::null_mut(),
+            len: 0,
+        };
+        if unsafe { spd_scan(&mut list) } == SpdStatus::Ok {
+            unsafe { spd_device_list_free(&mut list) };
+            assert!(list.devices.is_null());
+        }
+    }
+}
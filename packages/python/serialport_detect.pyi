@@ -0,0 +1,31 @@
+from typing import AsyncIterator, Iterator, List, Literal, Optional, Tuple
+
+PortType = Literal["usb", "pci", "bluetooth", "unknown"]
+EventType = Literal["add", "remove", "change", "move"]
+
+class DeviceInfo:
+    port: str
+    vid: Optional[int]
+    pid: Optional[int]
+    serial: Optional[str]
+    manufacturer: Optional[str]
+    product: Optional[str]
+    port_type: PortType
+
+class EventInfo:
+    event: EventType
+    device: DeviceInfo
+    seq: int
+    old_port: Optional[str]
+
+class AbortHandle:
+    def abort(self) -> None: ...
+
+class Events(Iterator[EventInfo], AsyncIterator[EventInfo]):
+    def __iter__(self) -> "Events": ...
+    def __next__(self) -> EventInfo: ...
+    def __aiter__(self) -> "Events": ...
+    async def __anext__(self) -> EventInfo: ...
+
+def scan() -> List[DeviceInfo]: ...
+def listen() -> Tuple[AbortHandle, Events]: ...
@@ -0,0 +1,189 @@
+//! Python bindings of serialport-detect
+//!
+//! Blocking calls release the GIL, so other Python threads keep running while we scan or wait for
+//! an event
+use futures::StreamExt;
+use pyo3::{exceptions::PyStopAsyncIteration, prelude::*};
+use std::{
+    io,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+/// How often a blocking iteration wakes up to let Python handle signals, IE: Ctrl-C
+const SIGNAL_INTERVAL: Duration = Duration::from_millis(100);
+
+fn into_py_err(error: detect::Error) -> PyErr {
+    io::Error::from(error).into()
+}
+
+/// Meta data about a port
+#[pyclass(module = "serialport_detect", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+struct DeviceInfo {
+    port: String,
+    vid: Option<u16>,
+    pid: Option<u16>,
+    serial: Option<String>,
+    manufacturer: Option<String>,
+    product: Option<String>,
+    port_type: &'static str,
+}
+
+#[pymethods]
+impl DeviceInfo {
+    fn __repr__(&self) -> String {
+        format!(
+            "DeviceInfo(port={:?}, vid={:?}, pid={:?}, serial={:?})",
+            self.port, self.vid, self.pid, self.serial
+        )
+    }
+}
+
+impl From<detect::DeviceInfo> for DeviceInfo {
+    fn from(device: detect::DeviceInfo) -> DeviceInfo {
+        DeviceInfo {
+            port: device.port,
+            vid: device.vid_u16,
+            pid: device.pid_u16,
+            serial: device.serial,
+            manufacturer: device.manufacturer,
+            product: device.product,
+            port_type: match device.port_type {
+                detect::PortType::Usb => "usb",
+                detect::PortType::Pci => "pci",
+                detect::PortType::Bluetooth => "bluetooth",
+                detect::PortType::Unknown => "unknown",
+            },
+        }
+    }
+}
+
+/// A device was added, removed, changed or moved to another port
+#[pyclass(module = "serialport_detect", get_all, frozen, skip_from_py_object)]
+#[derive(Debug, Clone)]
+struct EventInfo {
+    event: &'static str,
+    device: DeviceInfo,
+    seq: u64,
+    /// The port the device was at before a "move" event. None for every other event
+    old_port: Option<String>,
+}
+
+#[pymethods]
+impl EventInfo {
+    fn __repr__(&self) -> String {
+        format!(
+            "EventInfo(event={:?}, port={:?}, seq={})",
+            self.event, self.device.port, self.seq
+        )
+    }
+}
+
+impl From<detect::EventInfo> for EventInfo {
+    fn from(event: detect::EventInfo) -> EventInfo {
+        EventInfo {
+            event: match event.event {
+                detect::EventType::Add => "add",
+                detect::EventType::Remove => "remove",
+                detect::EventType::Change => "change",
+                detect::EventType::Moved => "move",
+            },
+            device: event.device.into(),
+            seq: event.seq,
+            old_port: event.old_port,
+        }
+    }
+}
+
+/// Stops the listener. Iterating the events ends once they are drained
+#[pyclass(module = "serialport_detect", frozen)]
+#[derive(Debug)]
+struct AbortHandle {
+    inner: Mutex<Option<detect::AbortHandle>>,
+}
+
+#[pymethods]
+impl AbortHandle {
+    /// Stop the listener and wait for its thread to exit. Calling it again does nothing
+    fn abort(&self, py: Python<'_>) {
+        let abort = self
+            .inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(abort) = abort {
+            py.detach(|| abort.shutdown());
+        }
+    }
+}
+
+/// The events of a listener, with both `for` and `async for`
+#[pyclass(module = "serialport_detect")]
+#[derive(Debug)]
+struct Events {
+    inner: detect::EventIter,
+}
+
+#[pymethods]
+impl Events {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<EventInfo>> {
+        loop {
+            match py.detach(|| self.inner.next_timeout(SIGNAL_INTERVAL)) {
+                Ok(event) => return Ok(event.map(Into::into)),
+                Err(detect::Error::Timeout) => py.check_signals()?,
+                Err(error) => return Err(into_py_err(error)),
+            }
+        }
+    }
+
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Async magic methods can not be `async fn`, so return the coroutine of one instead
+    fn __anext__<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        slf.call_method0("_anext")
+    }
+
+    #[pyo3(name = "_anext")]
+    async fn anext(&mut self) -> PyResult<EventInfo> {
+        match self.inner.next().await {
+            Some(Ok(event)) => Ok(event.into()),
+            Some(Err(error)) => Err(into_py_err(error)),
+            None => Err(PyStopAsyncIteration::new_err(())),
+        }
+    }
+}
+
+/// Scan for connected devices, sorted by port name
+#[pyfunction]
+fn scan(py: Python<'_>) -> PyResult<Vec<DeviceInfo>> {
+    let devices = py.detach(detect::scan_sorted).map_err(into_py_err)?;
+    Ok(devices.into_iter().map(Into::into).collect())
+}
+
+/// Listen for events. Returns the abort handle and the events
+#[pyfunction]
+fn listen() -> PyResult<(AbortHandle, Events)> {
+    let (abort, events) = detect::listen().map_err(into_py_err)?;
+    let abort = AbortHandle {
+        inner: Mutex::new(Some(abort)),
+    };
+    Ok((abort, Events { inner: events }))
+}
+
+#[pymodule]
+fn serialport_detect(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<DeviceInfo>()?;
+    m.add_class::<EventInfo>()?;
+    m.add_class::<AbortHandle>()?;
+    m.add_class::<Events>()?;
+    m.add_function(wrap_pyfunction!(scan, m)?)?;
+    m.add_function(wrap_pyfunction!(listen, m)?)?;
+    Ok(())
+}
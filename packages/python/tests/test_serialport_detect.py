@@ -0,0 +1,29 @@
+import asyncio
+import threading
+import unittest
+
+import serialport_detect
+
+
+class TestSerialportDetect(unittest.TestCase):
+    def test_scan(self):
+        for device in serialport_detect.scan():
+            self.assertIsInstance(device.port, str)
+
+    def test_listen_abort(self):
+        abort, events = serialport_detect.listen()
+        threading.Timer(0.1, abort.abort).start()
+        self.assertEqual([event for event in events if event.event != "add"], [])
+        abort.abort()
+
+    def test_listen_async(self):
+        async def drain():
+            abort, events = serialport_detect.listen()
+            asyncio.get_running_loop().call_later(0.1, abort.abort)
+            return [event async for event in events]
+
+        self.assertEqual(asyncio.run(drain()), [])
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,233 @@
+//! Kotlin and Swift bindings of serialport-detect, generated by UniFFI
+//!
+//! Events are delivered to an [`EventListener`] implemented in the foreign language, from a
+//! thread of our own, until the [`ListenHandle`] returned by [`listen`] is cancelled
+use std::{
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+    thread::JoinHandle,
+};
+
+uniffi::setup_scaffolding!();
+
+/// The errors of [`scan`] and [`listen`], and of the event stream. The message describes the
+/// underlying error
+#[derive(Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum DetectError {
+    /// The native backend could not be initialized
+    Backend(String),
+    /// The backend could not register for device notifications
+    Registration(String),
+    /// Access to the device or to the notification mechanism was denied
+    PermissionDenied(String),
+    /// A notification or a device property could not be decoded
+    Decode(String),
+    /// The event queue was full and events were dropped
+    Overflow(String),
+    /// Any other error
+    Io(String),
+}
+
+impl fmt::Display for DetectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DetectError::Backend(message)
+            | DetectError::Registration(message)
+            | DetectError::PermissionDenied(message)
+            | DetectError::Decode(message)
+            | DetectError::Overflow(message)
+            | DetectError::Io(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for DetectError {}
+
+impl From<detect::Error> for DetectError {
+    fn from(error: detect::Error) -> DetectError {
+        let message = error.to_string();
+        match error {
+            detect::Error::Backend(_) => DetectError::Backend(message),
+            detect::Error::Registration(_) => DetectError::Registration(message),
+            detect::Error::PermissionDenied(_) => DetectError::PermissionDenied(message),
+            detect::Error::Decode(_) => DetectError::Decode(message),
+            detect::Error::Overflow { .. } => DetectError::Overflow(message),
+            _ => DetectError::Io(message),
+        }
+    }
+}
+
+/// The kind of hardware behind a serial port
+#[derive(Debug, Copy, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum PortType {
+    Usb,
+    Pci,
+    Bluetooth,
+    Unknown,
+}
+
+/// Whether a device was added, removed, changed or moved to another port
+#[derive(Debug, Copy, Clone, PartialEq, Eq, uniffi::Enum)]
+pub enum EventType {
+    Add,
+    Remove,
+    Change,
+    /// The device came back at another port, named by [`EventInfo::old_port`]
+    Moved,
+}
+
+/// Meta data about a port
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct DeviceInfo {
+    pub port: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub port_type: PortType,
+}
+
+/// A device was added, removed, changed or moved to another port
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct EventInfo {
+    pub event: EventType,
+    pub device: DeviceInfo,
+    pub seq: u64,
+    /// The port the device was at before a [`EventType::Moved`] event. None for every other event
+    pub old_port: Option<String>,
+}
+
+impl From<detect::DeviceInfo> for DeviceInfo {
+    fn from(device: detect::DeviceInfo) -> DeviceInfo {
+        DeviceInfo {
+            port: device.port,
+            vid: device.vid_u16,
+            pid: device.pid_u16,
+            serial: device.serial,
+            manufacturer: device.manufacturer,
+            product: device.product,
+            port_type: match device.port_type {
+                detect::PortType::Usb => PortType::Usb,
+                detect::PortType::Pci => PortType::Pci,
+                detect::PortType::Bluetooth => PortType::Bluetooth,
+                detect::PortType::Unknown => PortType::Unknown,
+            },
+        }
+    }
+}
+
+impl From<detect::EventInfo> for EventInfo {
+    fn from(event: detect::EventInfo) -> EventInfo {
+        EventInfo {
+            event: match event.event {
+                detect::EventType::Add => EventType::Add,
+                detect::EventType::Remove => EventType::Remove,
+                detect::EventType::Change => EventType::Change,
+                detect::EventType::Moved => EventType::Moved,
+            },
+            device: event.device.into(),
+            seq: event.seq,
+            old_port: event.old_port,
+        }
+    }
+}
+
+/// Implemented in the foreign language to receive the events of [`listen`]. Called from the
+/// listener thread
+#[uniffi::export(callback_interface)]
+pub trait EventListener: Send + Sync {
+    fn on_event(&self, event: EventInfo);
+
+    /// The listener keeps running after an error
+    fn on_error(&self, error: DetectError);
+
+    /// The listener was cancelled, no more events follow
+    fn on_done(&self);
+}
+
+/// Stops a listener started by [`listen`]
+#[derive(Debug, uniffi::Object)]
+pub struct ListenHandle {
+    inner: Mutex<Option<(detect::AbortHandle, JoinHandle<()>)>>,
+}
+
+#[uniffi::export]
+impl ListenHandle {
+    /// Stop the listener and wait for its thread to exit, after which the listener is not called
+    /// again. Must not be called from within the listener. Calling it again does nothing
+    pub fn cancel(&self) {
+        let inner = self
+            .inner
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some((abort, thread)) = inner {
+            abort.shutdown();
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ListenHandle {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Scan for connected devices, sorted by port name
+#[uniffi::export]
+pub fn scan() -> Result<Vec<DeviceInfo>, DetectError> {
+    let devices = detect::scan_sorted()?;
+    Ok(devices.into_iter().map(Into::into).collect())
+}
+
+/// Listen for events, calling `listener` until the returned handle is cancelled
+#[uniffi::export]
+pub fn listen(listener: Box<dyn EventListener>) -> Result<Arc<ListenHandle>, DetectError> {
+    let (abort, events) = detect::listen()?;
+    let thread = std::thread::Builder::new()
+        .name("serialport-detect-uniffi".into())
+        .spawn(move || {
+            for event in events {
+                match event {
+                    Ok(event) => listener.on_event(event.into()),
+                    Err(error) => listener.on_error(error.into()),
+                }
+            }
+            listener.on_done();
+        })
+        .map_err(detect::Error::from)?;
+    Ok(Arc::new(ListenHandle {
+        inner: Mutex::new(Some((abort, thread))),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[derive(Debug, Default)]
+    struct Done(Arc<AtomicBool>);
+
+    impl EventListener for Done {
+        fn on_event(&self, _event: EventInfo) {}
+
+        fn on_error(&self, _error: DetectError) {}
+
+        fn on_done(&self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_listen_cancel() {
+        let done = Arc::new(AtomicBool::new(false));
+        let handle = listen(Box::new(Done(Arc::clone(&done)))).unwrap();
+        handle.cancel();
+        assert!(done.load(Ordering::SeqCst));
+        handle.cancel();
+    }
+}
@@ -0,0 +1,252 @@
+// access.rs
+//
+// Whether the current process may open a port, so that a device which is present but which we are
+// not allowed to use is not mistaken for one which is absent. And whether another program already
+// has it open
+
+use crate::detect::DeviceInfo;
+use std::collections::HashMap;
+
+/// A process which has a port open. See [`crate::who_has`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortHolder {
+    /// The process id
+    pub pid: u32,
+    /// The name of the program (IE: minicom), when we can read it. Only on Linux and Android
+    pub name: Option<String>,
+}
+
+/// Check whether a device is accessible, and in use when asked to. See
+/// [`crate::ListenBuilder::check_in_use`]
+pub(crate) fn check(device: &mut DeviceInfo, check_in_use: bool) {
+    let open = match check_in_use {
+        true => open_by(&[&device.port]),
+        false => HashMap::new(),
+    };
+    probe(device, check_in_use, &open);
+}
+
+/// Check whether the scanned devices are accessible, and in use when asked to, looking for the
+/// processes which have them open once for all of them
+pub(crate) fn check_all(devices: &mut HashMap<String, DeviceInfo>, check_in_use: bool) {
+    let open = match check_in_use {
+        true => open_by(&devices.keys().map(String::as_str).collect::<Vec<_>>()),
+        false => HashMap::new(),
+    };
+    for device in devices.values_mut() {
+        probe(device, check_in_use, &open);
+    }
+}
+
+#[cfg(not(windows))]
+fn probe(device: &mut DeviceInfo, check_in_use: bool, open: &HashMap<String, Vec<u32>>) {
+    device.accessible = accessible(&device.port);
+    device.in_use = check_in_use.then(|| in_use(&device.port, open)).flatten();
+}
+
+/// Both are told by opening the port once, which we only do when asked to
+#[cfg(windows)]
+fn probe(device: &mut DeviceInfo, check_in_use: bool, _open: &HashMap<String, Vec<u32>>) {
+    (device.accessible, device.in_use) = match check_in_use {
+        true => crate::native::probe(&device.port),
+        false => (None, None),
+    };
+}
+
+/// The processes which have `port` open, other than ours
+#[cfg(not(windows))]
+pub(crate) fn holders(port: &str) -> std::io::Result<Vec<PortHolder>> {
+    let mut pids = open_by(&[port]).remove(port).unwrap_or_default();
+    pids.extend(locked_by(port).filter(|pid| !pids.contains(pid)));
+    Ok(pids
+        .into_iter()
+        .map(|pid| PortHolder {
+            pid,
+            name: process_name(pid),
+        })
+        .collect())
+}
+
+/// Windows only tells which process has a handle open by enumerating the handles of the system
+#[cfg(windows)]
+pub(crate) fn holders(_port: &str) -> std::io::Result<Vec<PortHolder>> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Whether the current user may read and write the device node. access(2) checks the mode of the
+/// node against our user and groups, IE: whether we are in the dialout group. The port is not
+/// opened
+#[cfg(unix)]
+pub(crate) fn accessible(port: &str) -> Option<bool> {
+    let path = std::ffi::CString::new(port).ok()?;
+    // SAFETY: path is a valid nul terminated string
+    Some(unsafe { libc::access(path.as_ptr(), libc::R_OK | libc::W_OK) } == 0)
+}
+
+#[cfg(windows)]
+pub(crate) use crate::native::accessible;
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn accessible(_port: &str) -> Option<bool> {
+    None
+}
+
+/// We see the processes of other users with an open port only when we run as root, but a lock
+/// file tells of them as well
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn in_use(port: &str, open: &HashMap<String, Vec<u32>>) -> Option<bool> {
+    Some(open.contains_key(port) || locked_by(port).is_some())
+}
+
+/// Without procfs only a lock file tells, and most programs don't take one
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn in_use(port: &str, _open: &HashMap<String, Vec<u32>>) -> Option<bool> {
+    locked_by(port).map(|_| true)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn in_use(_port: &str, _open: &HashMap<String, Vec<u32>>) -> Option<bool> {
+    None
+}
+
+/// The processes other than ours with a file descriptor open on one of `ports`, by port. We can
+/// only read the file descriptors of the processes of our user, unless we are root
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn open_by(ports: &[&str]) -> HashMap<String, Vec<u32>> {
+    let own = std::process::id();
+    let mut open: HashMap<String, Vec<u32>> = HashMap::new();
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return open;
+    };
+    for process in processes.flatten() {
+        let pid = process
+            .file_name()
+            .to_str()
+            .and_then(|pid| pid.parse().ok());
+        let Some(pid) = pid.filter(|pid| *pid != own) else {
+            continue;
+        };
+        let Ok(fds) = std::fs::read_dir(process.path().join("fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else {
+                continue;
+            };
+            if let Some(port) = ports.iter().find(|port| target.as_os_str() == **port) {
+                let pids = open.entry(port.to_string()).or_default();
+                if !pids.contains(&pid) {
+                    pids.push(pid);
+                }
+            }
+        }
+    }
+    open
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn open_by(_ports: &[&str]) -> HashMap<String, Vec<u32>> {
+    HashMap::new()
+}
+
+/// Where programs following the UUCP convention take the lock of a port, IE: minicom and picocom
+#[cfg(unix)]
+const LOCK_DIRS: [&str; 4] = [
+    "/run/lock",
+    "/var/lock",
+    "/var/spool/lock",
+    "/var/spool/uucp",
+];
+
+/// The live process other than ours which holds the lock file of `port`. A lock file left behind
+/// by a process which exited does not count
+#[cfg(unix)]
+fn locked_by(port: &str) -> Option<u32> {
+    let name = std::path::Path::new(port).file_name()?.to_str()?;
+    LOCK_DIRS.iter().find_map(|dir| {
+        let contents = std::fs::read(format!("{dir}/LCK..{name}")).ok()?;
+        lock_pid(&contents).filter(|pid| *pid != std::process::id() && alive(*pid))
+    })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn locked_by(_port: &str) -> Option<u32> {
+    None
+}
+
+/// The pid of a lock file, in ASCII as usual or as a binary int as some older programs write it
+#[cfg(unix)]
+fn lock_pid(contents: &[u8]) -> Option<u32> {
+    let ascii = std::str::from_utf8(contents)
+        .ok()
+        .and_then(|pid| pid.trim().parse().ok());
+    let binary = || contents.try_into().ok().map(u32::from_ne_bytes);
+    ascii.or_else(binary).filter(|pid| *pid > 0)
+}
+
+#[cfg(unix)]
+fn alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks that the process exists
+    let result = unsafe { libc::kill(pid, 0) };
+    // We may not be allowed to signal the process of another user, which exists all the same
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn process_name(pid: u32) -> Option<String> {
+    let name = std::fs::read_to_string(format!("/proc/{pid}/comm")).ok()?;
+    Some(name.trim_end().to_string())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+fn process_name(_pid: u32) -> Option<String> {
+    None
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_accessible() {
+        let file = std::env::temp_dir().join(format!("serialport-detect-{}", std::process::id()));
+        std::fs::write(&file, b"").unwrap();
+        let path = file.to_str().unwrap();
+        assert_eq!(accessible(path), Some(true));
+        assert_eq!(accessible("/dev/serialport-detect-absent"), Some(false));
+        std::fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn test_lock_pid() {
+        assert_eq!(lock_pid(b"      1234\n"), Some(1234));
+        assert_eq!(lock_pid(&1234u32.to_ne_bytes()), Some(1234));
+        assert_eq!(lock_pid(b"0\n"), None);
+        assert_eq!(lock_pid(b"garbage"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_open_by() {
+        let file =
+            std::env::temp_dir().join(format!("serialport-detect-open-{}", std::process::id()));
+        std::fs::write(&file, b"").unwrap();
+        let path = file.to_str().unwrap();
+        // Our own process is never reported
+        let _open = std::fs::File::open(&file).unwrap();
+        assert!(open_by(&[path]).is_empty());
+        let mut child = std::process::Command::new("sleep")
+            .arg("10")
+            .stdin(std::fs::File::open(&file).unwrap())
+            .spawn()
+            .unwrap();
+        let open = open_by(&[path]);
+        child.kill().unwrap();
+        child.wait().unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(open.get(path), Some(&vec![child.id()]));
+    }
+}
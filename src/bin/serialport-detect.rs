@@ -0,0 +1,301 @@
+//! Scan for serial ports, or listen for them to be plugged and unplugged
+
+use serialport_detect::{
+    DeviceInfo, EventInfo, EventType, Journal, ListenBuilder, ListenMode, Pid, Rules, Vid,
+};
+use std::{
+    env,
+    process::{self, ExitCode},
+    str::FromStr,
+    time::Duration,
+};
+
+const USAGE: &str = "\
+Usage: serialport-detect <COMMAND> [OPTIONS]
+
+Commands:
+  scan      Print the connected serial ports
+  listen    Print serial ports as they are plugged and unplugged
+
+Options:
+  --json        Print JSON instead of text. listen prints one event per line
+  --all         Include PCI, Bluetooth, built in and virtual ports
+  --initial     listen: start with an Add event for every connected port
+  --changes     listen: also print Change events (Linux only)
+  --moves       listen: print a port which comes back under another name within 3 seconds as
+                moved, instead of removed and added
+  --vid VID     Only ports with this vendor id, in hex (IE: 0403)
+  --pid PID     Only ports with this product id, in hex (IE: 6001)
+  --serial SN   Only ports with this serial number
+  --seat SEAT   Only ports assigned to this logind seat (IE: seat1), Linux only
+  --rules FILE  Only ports which the allow and deny rules of a TOML or JSON file admit
+  --exec CMD    listen: run CMD with the shell for every event, with PORT, VID, PID, SERIAL,
+                EVENT (add, remove, change or move) and OLD_PORT in its environment
+  --journal FILE
+                listen: append every event to FILE, rotating it once it grows past 10 MiB
+  -h, --help    Print this help
+";
+
+/// How long --moves waits for a removed port to come back under another name
+const MOVES: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Scan,
+    Listen,
+}
+
+#[derive(Debug)]
+struct Options {
+    command: Command,
+    json: bool,
+    all: bool,
+    initial: bool,
+    changes: bool,
+    moves: bool,
+    vid: Option<Vid>,
+    pid: Option<Pid>,
+    serial: Option<String>,
+    seat: Option<String>,
+    rules: Option<String>,
+    exec: Option<String>,
+    journal: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let options = match parse(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("error: {error}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let result = match options.command {
+        Command::Scan => scan(&options),
+        Command::Listen => listen(&options),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Parse the command line. None when help was asked for
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut command = None;
+    let mut options = Options {
+        command: Command::Scan,
+        json: false,
+        all: false,
+        initial: false,
+        changes: false,
+        moves: false,
+        vid: None,
+        pid: None,
+        serial: None,
+        seat: None,
+        rules: None,
+        exec: None,
+        journal: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for '{arg}'"));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--json" => options.json = true,
+            "--all" => options.all = true,
+            "--initial" => options.initial = true,
+            "--changes" => options.changes = true,
+            "--moves" => options.moves = true,
+            "--vid" => options.vid = Some(id(&value()?)?),
+            "--pid" => options.pid = Some(id(&value()?)?),
+            "--serial" => options.serial = Some(value()?),
+            "--seat" => options.seat = Some(value()?),
+            "--rules" => options.rules = Some(value()?),
+            "--exec" => options.exec = Some(value()?),
+            "--journal" => options.journal = Some(value()?),
+            "scan" if command.is_none() => command = Some(Command::Scan),
+            "listen" if command.is_none() => command = Some(Command::Listen),
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+    options.command = command.ok_or("missing command")?;
+    match options.command {
+        Command::Scan
+            if options.initial
+                || options.changes
+                || options.moves
+                || options.exec.is_some()
+                || options.journal.is_some() =>
+        {
+            Err("--initial, --changes, --moves, --exec and --journal only apply to listen".into())
+        }
+        _ => Ok(Some(options)),
+    }
+}
+
+/// A USB id in hex, with or without the 0x prefix
+fn id<T: FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid USB id '{value}'"))
+}
+
+/// A builder with the filters of the command line
+fn builder(options: &Options) -> serialport_detect::Result<ListenBuilder> {
+    let mut builder = serialport_detect::builder();
+    if let Some(vid) = options.vid {
+        builder = builder.vid(vid.into());
+    }
+    if let Some(pid) = options.pid {
+        builder = builder.pid(pid.into());
+    }
+    if let Some(serial) = &options.serial {
+        builder = builder.serial(serial.as_str());
+    }
+    if let Some(seat) = &options.seat {
+        builder = builder.seat(seat.as_str());
+    }
+    if let Some(path) = &options.rules {
+        builder = builder.rules(Rules::load(path)?);
+    }
+    Ok(builder.all_ports(options.all))
+}
+
+fn scan(options: &Options) -> serialport_detect::Result<()> {
+    let devices = builder(options)?.scan_sorted()?;
+    match options.json {
+        true => println!("{}", json(&devices)),
+        false => devices
+            .iter()
+            .for_each(|device| println!("{}", text(device))),
+    }
+    Ok(())
+}
+
+fn listen(options: &Options) -> serialport_detect::Result<()> {
+    let mut builder = builder(options)?
+        .initial(options.initial)
+        .changes(options.changes);
+    if options.moves {
+        builder = builder.moves(MOVES);
+    }
+    if let Some(path) = &options.journal {
+        builder = builder.journal(Journal::open(path)?);
+    }
+    let (abort, events) = builder.listen()?;
+    if abort.mode() != ListenMode::Native {
+        eprintln!("listening with {:?}", abort.mode());
+    }
+    for event in events {
+        let event = match event {
+            Ok(event) => event,
+            Err(error) => {
+                eprintln!("error: {error}");
+                continue;
+            }
+        };
+        match options.json {
+            true => println!("{}", json(&event)),
+            false => match &event.old_port {
+                Some(old_port) => {
+                    println!("{} {}  (was {old_port})", sign(&event), text(&event.device))
+                }
+                None => println!("{} {}", sign(&event), text(&event.device)),
+            },
+        }
+        if let Some(command) = &options.exec {
+            exec(command, &event);
+        }
+    }
+    Ok(())
+}
+
+/// Run `command` with the shell for an event, and wait for it to finish
+fn exec(command: &str, event: &EventInfo) {
+    #[cfg(windows)]
+    let mut shell = process::Command::new("cmd");
+    #[cfg(windows)]
+    shell.arg("/C");
+    #[cfg(not(windows))]
+    let mut shell = process::Command::new("sh");
+    #[cfg(not(windows))]
+    shell.arg("-c");
+    let device = &event.device;
+    let event_name = match event.event {
+        EventType::Add => "add",
+        EventType::Remove => "remove",
+        EventType::Change => "change",
+        EventType::Moved => "move",
+    };
+    let status = shell
+        .arg(command)
+        .env("PORT", &device.port)
+        .env(
+            "VID",
+            device
+                .vendor_id()
+                .map(|vid| vid.to_string())
+                .unwrap_or_default(),
+        )
+        .env(
+            "PID",
+            device
+                .product_id()
+                .map(|pid| pid.to_string())
+                .unwrap_or_default(),
+        )
+        .env("SERIAL", device.serial.as_deref().unwrap_or_default())
+        .env("EVENT", event_name)
+        .env("OLD_PORT", event.old_port.as_deref().unwrap_or_default())
+        .status();
+    match status {
+        Ok(status) if !status.success() => eprintln!("error: '{command}' exited with {status}"),
+        Ok(_) => {}
+        Err(error) => eprintln!("error: failed to run '{command}': {error}"),
+    }
+}
+
+fn json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("devices serialize to JSON")
+}
+
+fn sign(event: &EventInfo) -> char {
+    match event.event {
+        EventType::Add => '+',
+        EventType::Remove => '-',
+        EventType::Change => '~',
+        EventType::Moved => '>',
+    }
+}
+
+/// One line per port, IE: /dev/ttyUSB0  0403:6001  FTDI FT232R USB UART  A50285BI
+fn text(device: &DeviceInfo) -> String {
+    let ids = match (device.vendor_id(), device.product_id()) {
+        (Some(vid), Some(pid)) => format!("{vid}:{pid}"),
+        _ => format!("{:?}", device.port_type).to_lowercase(),
+    };
+    let mut line = format!("{:<16}  {ids:<9}", device.port);
+    let name = [&device.manufacturer, &device.product]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if !name.is_empty() {
+        line.push_str("  ");
+        line.push_str(&name);
+    }
+    if let Some(serial) = &device.serial {
+        line.push_str("  ");
+        line.push_str(serial);
+    }
+    line.trim_end().to_string()
+}
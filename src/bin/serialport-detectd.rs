@@ -0,0 +1,128 @@
+//! Serve the serial ports to the local processes over a unix socket, sharing one listener
+
+#[cfg(unix)]
+use std::{env, path::PathBuf, process::ExitCode};
+
+#[cfg(unix)]
+const USAGE: &str = "\
+Usage: serialport-detectd [OPTIONS]
+
+Serves scan and subscribe requests over a unix socket, with frames of a big endian u32 length
+followed by JSON. See ListenBuilder::serve_unix of the serialport-detect crate
+
+Options:
+  --socket PATH  Listen at PATH instead of serialport-detect.sock in $XDG_RUNTIME_DIR or /run
+  --http ADDR    Serve GET /scan and GET /listen (server-sent events) over HTTP at ADDR
+                 (IE: 0.0.0.0:7878) instead of the unix socket. Needs the server feature
+  --all          Include PCI, Bluetooth, built in and virtual ports
+  -h, --help     Print this help
+";
+
+#[cfg(unix)]
+#[derive(Debug)]
+struct Options {
+    socket: PathBuf,
+    http: Option<String>,
+    all: bool,
+}
+
+#[cfg(unix)]
+fn main() -> ExitCode {
+    let options = match parse(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("error: {error}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    // Every thread we start inherits the mask, so the signals are left for us to wait for
+    let signals = block_signals();
+    let abort = match serve(&options) {
+        Ok(abort) => abort,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    wait(&signals);
+    abort.shutdown();
+    // The thread accepting the clients removes the socket as well once it wakes up, but we exit
+    // without waiting for it
+    if options.http.is_none() {
+        let _ = std::fs::remove_file(&options.socket);
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(not(unix))]
+fn main() -> std::process::ExitCode {
+    eprintln!("error: serialport-detectd serves over a unix socket, which needs a unix system");
+    std::process::ExitCode::FAILURE
+}
+
+/// Serve on the unix socket, or over HTTP when asked to
+#[cfg(unix)]
+fn serve(options: &Options) -> serialport_detect::Result<serialport_detect::AbortHandle> {
+    let builder = serialport_detect::builder().all_ports(options.all);
+    #[cfg(feature = "server")]
+    if let Some(addr) = &options.http {
+        let abort = builder.serve_http(std::net::TcpListener::bind(addr)?)?;
+        eprintln!("serving on http://{addr}");
+        return Ok(abort);
+    }
+    let abort = builder.serve_unix(&options.socket)?;
+    eprintln!("serving on {}", options.socket.display());
+    Ok(abort)
+}
+
+/// Parse the command line. None when help was asked for
+#[cfg(unix)]
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options {
+        socket: serialport_detect::daemon_socket(),
+        http: None,
+        all: false,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--all" => options.all = true,
+            "--socket" => {
+                let value = args.next().ok_or(format!("missing value for '{arg}'"))?;
+                options.socket = PathBuf::from(value);
+            }
+            "--http" if cfg!(feature = "server") => {
+                let value = args.next().ok_or(format!("missing value for '{arg}'"))?;
+                options.http = Some(value);
+            }
+            _ => return Err(format!("unexpected argument '{arg}'")),
+        }
+    }
+    Ok(Some(options))
+}
+
+/// Block SIGINT and SIGTERM, returning the set to wait for them with
+#[cfg(unix)]
+fn block_signals() -> libc::sigset_t {
+    // Safety: the set is initialized by sigemptyset before it is used
+    unsafe {
+        let mut set = std::mem::zeroed::<libc::sigset_t>();
+        libc::sigemptyset(&mut set);
+        libc::sigaddset(&mut set, libc::SIGINT);
+        libc::sigaddset(&mut set, libc::SIGTERM);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+        set
+    }
+}
+
+/// Wait for one of the blocked signals
+#[cfg(unix)]
+fn wait(signals: &libc::sigset_t) {
+    let mut signal = 0;
+    // Safety: the set is initialized, and sigwait only writes the signal number
+    unsafe { libc::sigwait(signals, &mut signal) };
+}
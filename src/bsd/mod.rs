@@ -0,0 +1,157 @@
+// OpenBSD and NetBSD support
+//
+// There is no hotplug notification available to unprivileged processes, so we poll the attached
+// ucom(4) units
+
+use crate::{
+    detect::{DeviceInfo, PortType, Queue},
+    poll,
+};
+use std::{collections::HashMap, io, sync::Arc, time::Duration};
+
+pub use crate::poll::AbortHandle;
+
+/// How often we rescan the attached ucom(4) units
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Scan for connected devices
+///
+/// NOTE the USB descriptors of the device are not available, only the port is populated
+pub fn scan() -> io::Result<HashMap<String, DeviceInfo>> {
+    let items = ucom_units()?
+        .into_iter()
+        .map(|unit| {
+            let port = callout_device(unit);
+            let device = DeviceInfo {
+                port: port.clone(),
+                vid: None,
+                pid: None,
+                vid_u16: None,
+                pid_u16: None,
+                serial: None,
+                manufacturer: None,
+                product: None,
+                port_type: PortType::Usb,
+                ..Default::default()
+            };
+            (port, device)
+        })
+        .collect();
+    Ok(items)
+}
+
+/// Look up the device at `port`. There is no cheaper way than a scan
+pub fn port_info(port: &str) -> io::Result<Option<DeviceInfo>> {
+    Ok(scan()?.remove(port))
+}
+
+/// Listen for connected devices
+pub fn listen(queue: Arc<Queue>) -> io::Result<AbortHandle> {
+    poll::listen(POLL_INTERVAL, scan, queue)
+}
+
+/// OpenBSD names the callout device of ucom unit N /dev/cuaUN
+#[cfg(target_os = "openbsd")]
+fn callout_device(unit: u32) -> String {
+    format!("/dev/cuaU{unit}")
+}
+
+/// NetBSD names the callout device of ucom unit N /dev/dtyUN
+#[cfg(target_os = "netbsd")]
+fn callout_device(unit: u32) -> String {
+    format!("/dev/dtyU{unit}")
+}
+
+/// List the attached ucom units with the `hw.ucomnames` sysctl, IE: "U0:usb0.1.00003.0,U1:..."
+#[cfg(target_os = "openbsd")]
+fn ucom_units() -> io::Result<Vec<u32>> {
+    use std::ffi::{c_int, c_void};
+    /// See sys/sys/sysctl.h
+    const HW_UCOMNAMES: c_int = 28;
+    let mib = [libc::CTL_HW, HW_UCOMNAMES];
+    let mut len = 0;
+    // Safety: a null buffer asks for the required length
+    let result = unsafe {
+        libc::sysctl(
+            mib.as_ptr(),
+            mib.len() as _,
+            std::ptr::null_mut(),
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buffer = vec![0u8; len];
+    // Safety: the buffer is len bytes long
+    let result = unsafe {
+        libc::sysctl(
+            mib.as_ptr(),
+            mib.len() as _,
+            buffer.as_mut_ptr() as *mut c_void,
+            &mut len,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let names = String::from_utf8_lossy(&buffer[..len]);
+    let units = names
+        .trim_end_matches('\0')
+        .split(',')
+        .filter_map(|name| name.split(':').next()?.strip_prefix('U')?.parse().ok())
+        .collect();
+    Ok(units)
+}
+
+/// List the attached ucom units by walking the autoconf device tree with drvctl(4)
+#[cfg(target_os = "netbsd")]
+fn ucom_units() -> io::Result<Vec<u32>> {
+    use std::{
+        ffi::{c_char, CStr},
+        os::fd::AsRawFd,
+    };
+
+    /// See sys/sys/drvctlio.h
+    #[repr(C)]
+    struct DevListArgs {
+        l_devname: [c_char; 16],
+        l_childname: *mut [c_char; 16],
+        l_children: usize,
+    }
+    nix::ioctl_readwrite!(drvlistdev, b'D', 127, DevListArgs);
+
+    let drvctl = std::fs::File::open("/dev/drvctl")?;
+    let children = |parent: &[c_char; 16]| -> io::Result<Vec<[c_char; 16]>> {
+        let mut args = DevListArgs {
+            l_devname: *parent,
+            l_childname: std::ptr::null_mut(),
+            l_children: 0,
+        };
+        // Safety: the first call only counts the children, the second fills our buffer
+        unsafe { drvlistdev(drvctl.as_raw_fd(), &mut args) }?;
+        let mut names = vec![[0 as c_char; 16]; args.l_children];
+        args.l_childname = names.as_mut_ptr();
+        unsafe { drvlistdev(drvctl.as_raw_fd(), &mut args) }?;
+        names.truncate(args.l_children);
+        Ok(names)
+    };
+    let mut units = Vec::new();
+    // An empty name lists the roots of the device tree
+    let mut pending = vec![[0 as c_char; 16]];
+    while let Some(parent) = pending.pop() {
+        for child in children(&parent)? {
+            // Safety: drvctl returns null terminated names
+            let name = unsafe { CStr::from_ptr(child.as_ptr()) }.to_string_lossy();
+            if let Some(unit) = name.strip_prefix("ucom").and_then(|n| n.parse().ok()) {
+                units.push(unit);
+            }
+            pending.push(child);
+        }
+    }
+    Ok(units)
+}
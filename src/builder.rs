@@ -0,0 +1,485 @@
+// builder.rs
+#[cfg(feature = "journal")]
+use crate::journal::Journal;
+#[cfg(feature = "mock")]
+use crate::mock::MockBackend;
+#[cfg(feature = "replay")]
+use crate::replay;
+use crate::{
+    access,
+    detect::{
+        cmp_ports, AbortHandle, DeviceInfo, EventIter, Overflow, PortType, Queue, Scan, UdevMatch,
+        WindowsBackend,
+    },
+    error::{Error, Result},
+    filter::{Filter, Match},
+    metrics::Metrics,
+    monitor, native, poll,
+    retry::Retry,
+    rules::Rules,
+    throttle::RateLimit,
+};
+use serialport::{SerialPortInfo, SerialPortType};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{debug, warn};
+
+/// Create a [`ListenBuilder`]
+pub fn builder() -> ListenBuilder {
+    ListenBuilder::default()
+}
+
+/// Configure which devices we are interested in before listening or scanning
+///
+/// ```no_run
+/// let (abort, events) = serialport_detect::builder()
+///     .vid(0x0403)
+///     .pid(0x6001)
+///     .listen()?;
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ListenBuilder {
+    filter: Filter,
+    initial: bool,
+    changes: bool,
+    capacity: Option<usize>,
+    overflow: Overflow,
+    rate_limit: Option<RateLimit>,
+    retry: Option<Retry>,
+    ready: Option<Duration>,
+    debounce: Option<Duration>,
+    moves: Option<Duration>,
+    all_ports: bool,
+    windows_backend: WindowsBackend,
+    rescan_on_resume: bool,
+    check_in_use: bool,
+    udev: UdevMatch,
+    metrics: Option<Arc<dyn Metrics>>,
+    #[cfg(feature = "journal")]
+    journal: Option<Arc<parking_lot::Mutex<Journal>>>,
+}
+
+impl ListenBuilder {
+    /// Only report the device at this port name. IE: `.port("COM3")`
+    pub fn port(mut self, port: impl Into<Match>) -> Self {
+        self.filter.port = Some(port.into());
+        self
+    }
+
+    /// Only report devices with this USB vendor id
+    pub fn vid(mut self, vid: u16) -> Self {
+        self.filter.vid = Some(vid);
+        self
+    }
+
+    /// Only report devices with this USB product id
+    pub fn pid(mut self, pid: u16) -> Self {
+        self.filter.pid = Some(pid);
+        self
+    }
+
+    /// Only report devices whose serial number matches. IE: `.serial(Match::prefix("FIX-"))`
+    pub fn serial(mut self, serial: impl Into<Match>) -> Self {
+        self.filter.serial = Some(serial.into());
+        self
+    }
+
+    /// Only report devices whose manufacturer string matches
+    pub fn manufacturer(mut self, manufacturer: impl Into<Match>) -> Self {
+        self.filter.manufacturer = Some(manufacturer.into());
+        self
+    }
+
+    /// Only report devices whose product string matches
+    pub fn product(mut self, product: impl Into<Match>) -> Self {
+        self.filter.product = Some(product.into());
+        self
+    }
+
+    /// Only report the devices assigned to this systemd-logind seat, IE: for a multi-seat kiosk
+    /// where each session must only see the serial devices plugged into its own seat. See
+    /// [`crate::current_seat`]. Seats are only known on Linux, so no device matches elsewhere
+    pub fn seat(mut self, seat: impl Into<Match>) -> Self {
+        self.filter.seat = Some(seat.into());
+        self
+    }
+
+    /// Only report the devices which `rules` admit, IE: the allow and deny rules of a config file
+    /// loaded with `Rules::load`. See [`Rules`]
+    ///
+    /// ```no_run
+    /// use serialport_detect::{Filter, Rules};
+    ///
+    /// let rules = Rules::new().deny(Filter::new().vid(0x1519));
+    /// let devices = serialport_detect::builder().rules(rules).scan()?;
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    pub fn rules(mut self, rules: Rules) -> Self {
+        self.filter.rules = Some(Arc::new(rules));
+        self
+    }
+
+    /// Start the stream with an Add event for every matching device which is already connected.
+    /// The devices are scanned after the listener has started, so nothing is missed in between
+    pub fn initial(mut self, initial: bool) -> Self {
+        self.initial = initial;
+        self
+    }
+
+    /// Report [`crate::EventType::Change`] events, when the properties of a connected device
+    /// change. Only the Linux backend reports them
+    pub fn changes(mut self, changes: bool) -> Self {
+        self.changes = changes;
+        self
+    }
+
+    /// Hold at most `capacity` events for a slow consumer. Once full, events are dropped as
+    /// `overflow` says. By default the queue is unbounded
+    pub fn capacity(mut self, capacity: usize, overflow: Overflow) -> Self {
+        self.capacity = Some(capacity);
+        self.overflow = overflow;
+        self
+    }
+
+    /// Drop the events over `limit`, IE: of a device caught in a connect and disconnect loop. The
+    /// stream yields an [`Error::Throttled`] in their place. See [`RateLimit`]
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Report the counters of the event queue to `metrics`, IE: to export them to a telemetry
+    /// system. See [`Metrics`]
+    pub fn metrics(mut self, metrics: Arc<dyn Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Append every event the consumer is given to `journal`, IE: for an audit trail of device
+    /// connectivity. Failing to write is logged, and does not end the stream. Enabled with the
+    /// `journal` feature. See [`Journal`]
+    #[cfg(feature = "journal")]
+    pub fn journal(mut self, journal: Journal) -> Self {
+        self.journal = Some(Arc::new(parking_lot::Mutex::new(journal)));
+        self
+    }
+
+    /// Restart the listener when the OS notification mechanism fails, instead of ending the
+    /// stream. See [`Retry`]
+    pub fn retry(mut self, retry: Retry) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Hold back the Add of a device until the current user can open it, IE: once udev has set
+    /// its permissions, for at most `grace`. The Add is reported anyway when the grace period runs
+    /// out, and not at all if the device is removed before. Only the Linux and Android listeners
+    /// wait, the option is ignored elsewhere
+    pub fn ready(mut self, grace: Duration) -> Self {
+        self.ready = Some(grace);
+        self
+    }
+
+    /// Only report an Add or a Remove once its port has been stable for `window`. A device which
+    /// is removed and added back within the window (IE: a flaky cable) is not reported at all,
+    /// nor is one which is added and removed again. The events are delayed by `window`
+    ///
+    /// The device is the same when its USB ids, serial number and stable id are
+    pub fn debounce(mut self, window: Duration) -> Self {
+        self.debounce = Some(window);
+        self
+    }
+
+    /// Report a device which is removed and comes back at another port within `window` (IE:
+    /// ttyUSB0 came back as ttyUSB1 after dropping off the bus) as a single
+    /// [`crate::EventType::Moved`], instead of a Remove and an Add. Removes are delayed by
+    /// `window`, to see whether their device comes back
+    ///
+    /// The device is the same when its USB ids, serial number and stable id are. Devices with
+    /// neither a serial number nor a stable id are never reported as Moved
+    pub fn moves(mut self, window: Duration) -> Self {
+        self.moves = Some(window);
+        self
+    }
+
+    /// Report serial ports of every kind, including PCI and built in UARTs, Bluetooth and virtual
+    /// ports, as [`ListenBuilder::scan_all`] does. See [`PortType`]
+    ///
+    /// The Windows listener is limited to USB devices otherwise, and reports every serial port with
+    /// it. The udev and macOS listeners report every serial port already. The netlink and Android
+    /// listeners, the FreeBSD, OpenBSD, NetBSD and illumos listeners, and
+    /// [`ListenBuilder::listen_polling`] on those platforms only see USB serial ports, and ignore
+    /// the option. With [`ListenBuilder::initial`] they report the other ports found by the
+    /// initial scan, but never their removal
+    pub fn all_ports(mut self, all_ports: bool) -> Self {
+        self.all_ports = all_ports;
+        self
+    }
+
+    /// Choose how the Windows listener is notified of devices. The default window needs an
+    /// interactive session, [`WindowsBackend::ConfigManager`] also works from a service. The option
+    /// is ignored elsewhere
+    pub fn windows_backend(mut self, backend: WindowsBackend) -> Self {
+        self.windows_backend = backend;
+        self
+    }
+
+    /// Rescan when the system resumes from suspend, and report the devices which came and went
+    /// while it slept, IE: a laptop docked or undocked while asleep. On Linux we are told of the
+    /// resume by systemd-logind over the system D-Bus, which needs the `dbus` feature, and carry on
+    /// without it when the bus is not available. The Windows listener always rescans on resume, the others ignore the option
+    pub fn rescan_on_resume(mut self, rescan: bool) -> Self {
+        self.rescan_on_resume = rescan;
+        self
+    }
+
+    /// Check whether another program has each scanned or reported port open, and fill in
+    /// [`DeviceInfo::in_use`], which is None otherwise. Off by default, as on Linux and Android it
+    /// walks the file descriptors of every process, and on Windows it opens the port. On Windows
+    /// [`DeviceInfo::accessible`] is told by the same attempt to open the port, and is None as well
+    /// without it. See [`crate::who_has`] to ask about one port instead
+    pub fn check_in_use(mut self, check: bool) -> Self {
+        self.check_in_use = check;
+        self
+    }
+
+    /// Report the devices of these udev subsystems instead of `tty`. IE: `.subsystems(["tty",
+    /// "usbmisc"])` for the tty ports and the character devices of proprietary USB drivers. A
+    /// device of any of the subsystems is reported. The option is only used on Linux
+    pub fn subsystems<I>(mut self, subsystems: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.udev.subsystems = subsystems.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Report the devices of this udev subsystem as well. IE: `.subsystem("usb")`
+    pub fn subsystem(mut self, subsystem: impl Into<String>) -> Self {
+        self.udev.subsystems.push(subsystem.into());
+        self
+    }
+
+    /// Only report devices whose udev property `name` is `value`. IE: `.udev_property("ID_BUS",
+    /// "usb")`. A device must have every property given. The option is only used on Linux
+    pub fn udev_property(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.udev.properties.push((name.into(), value.into()));
+        self
+    }
+
+    /// Listen for events of the matching devices. Events of other devices are dropped by the
+    /// listener thread
+    ///
+    /// If the native backend fails to start we fall back to polling
+    pub fn listen(self) -> Result<(AbortHandle, EventIter)> {
+        let queue = Arc::new(self.live_queue());
+        match native::listen(Arc::clone(&queue)) {
+            Ok(handle) => self.start(AbortHandle::native(handle, &queue), queue),
+            Err(error) => {
+                warn!(
+                    ?error,
+                    "failed to start native listener, falling back to polling"
+                );
+                self.listen_polling(poll::DEFAULT_INTERVAL)
+                    .map_err(Error::backend)
+            }
+        }
+    }
+
+    /// Listen for events of the matching devices by scanning every `interval`
+    pub fn listen_polling(self, interval: Duration) -> Result<(AbortHandle, EventIter)> {
+        let queue = Arc::new(self.live_queue());
+        #[cfg(target_os = "linux")]
+        let scan = {
+            let udev = self.udev.clone();
+            move || native::scan_matching(&udev)
+        };
+        #[cfg(not(target_os = "linux"))]
+        let scan = native::scan;
+        let handle = poll::listen(interval, scan, Arc::clone(&queue)).map_err(Error::backend)?;
+        self.start(AbortHandle::polling(handle, &queue), queue)
+    }
+
+    /// Like [`ListenBuilder::listen`], sharing one listener between every subscriber in the
+    /// process instead of starting a thread and an OS subscription per call
+    ///
+    /// Each subscriber receives every event of the matching devices independently. The shared
+    /// listener starts with the first subscriber and stops once they have all been dropped. It
+    /// restarts on failure with the default [`Retry`] policy, so [`ListenBuilder::retry`] is
+    /// ignored, and it reports tty devices, so [`ListenBuilder::subsystems`] is ignored as well
+    ///
+    /// ```no_run
+    /// let ftdi = serialport_detect::builder().vid(0x0403).subscribe()?;
+    /// let everything = serialport_detect::subscribe()?;
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    pub fn subscribe(self) -> Result<EventIter> {
+        let queue = Arc::new(self.live_queue());
+        monitor::subscribe(&queue)?;
+        if self.initial {
+            queue.seed(self.scan()?);
+        }
+        Ok(EventIter::new(queue))
+    }
+
+    /// Play back a recording made by a [`crate::Recorder`] through an [`EventIter`], to reproduce
+    /// a hotplug sequence without the hardware. Enabled with the `replay` feature
+    ///
+    /// The events keep their recorded timestamps and are as far apart as they were when recorded,
+    /// divided by `speed`. A `speed` of 1.0 plays in real time, 10.0 ten times faster and
+    /// `f64::INFINITY` without waiting. The stream ends after the last event.
+    /// [`ListenBuilder::initial`] is ignored
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` is not positive
+    ///
+    /// ```no_run
+    /// let (abort, events) = serialport_detect::builder()
+    ///     .vid(0x0403)
+    ///     .replay("hotplug.jsonl", 10.0)?;
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    #[cfg(feature = "replay")]
+    pub fn replay(
+        self,
+        path: impl AsRef<std::path::Path>,
+        speed: f64,
+    ) -> Result<(AbortHandle, EventIter)> {
+        assert!(speed > 0.0, "replay speed must be positive");
+        let events = replay::load(path.as_ref())?;
+        let queue = Arc::new(self.queue());
+        let handle = replay::listen(events, speed, Arc::clone(&queue));
+        Ok((AbortHandle::replay(handle, &queue), EventIter::new(queue)))
+    }
+
+    /// Like [`ListenBuilder::listen`], receiving the events pushed to `mock` instead of the ones
+    /// of the OS. Enabled with the `mock` feature
+    #[cfg(feature = "mock")]
+    pub fn listen_mock(self, mock: &MockBackend) -> Result<(AbortHandle, EventIter)> {
+        let queue = Arc::new(self.queue());
+        let (handle, mut devices) = mock.attach(&queue);
+        if self.initial {
+            devices.retain(|_, device| self.filter.matches(device));
+            queue.seed(devices);
+        }
+        Ok((AbortHandle::mock(handle, &queue), EventIter::new(queue)))
+    }
+
+    pub(crate) fn matches(&self, device: &DeviceInfo) -> bool {
+        self.filter.matches(device)
+    }
+
+    fn queue(&self) -> Queue {
+        let queue = Queue::with_filter(self.filter.clone())
+            .with_changes(self.changes)
+            .with_capacity(self.capacity, self.overflow)
+            .with_rate_limit(self.rate_limit)
+            .with_retry(self.retry)
+            .with_ready(self.ready)
+            .with_debounce(self.debounce)
+            .with_moves(self.moves)
+            .with_all_ports(self.all_ports)
+            .with_windows_backend(self.windows_backend)
+            .with_rescan_on_resume(self.rescan_on_resume)
+            .with_udev(self.udev.clone())
+            .with_metrics(self.metrics.clone());
+        #[cfg(feature = "journal")]
+        let queue = queue.with_journal(self.journal.clone());
+        queue
+    }
+
+    /// The queue of a listener of the devices of the OS
+    fn live_queue(&self) -> Queue {
+        self.queue()
+            .with_scan(self.scanner())
+            .with_access(true)
+            .with_in_use(self.check_in_use)
+    }
+
+    /// Scan for the devices of a live listener, for its first snapshot. Every device is kept, the
+    /// queue filters them as its filter may change
+    fn scanner(&self) -> Scan {
+        let builder = ListenBuilder {
+            filter: Filter::default(),
+            ..self.clone()
+        };
+        Box::new(move || builder.scan())
+    }
+
+    /// Seed the stream of a running listener with the initial devices if we were asked to
+    fn start(&self, handle: AbortHandle, queue: Arc<Queue>) -> Result<(AbortHandle, EventIter)> {
+        if self.initial {
+            queue.seed(self.scan()?);
+        }
+        Ok((handle, EventIter::new(queue)))
+    }
+
+    /// Scan for the matching devices. Ports of every kind are scanned with
+    /// [`ListenBuilder::all_ports`]
+    pub fn scan(&self) -> Result<HashMap<String, DeviceInfo>> {
+        if self.all_ports {
+            return self.scan_all();
+        }
+        #[cfg(target_os = "linux")]
+        let mut items = native::scan_matching(&self.udev)?;
+        #[cfg(not(target_os = "linux"))]
+        let mut items = native::scan()?;
+        items.retain(|_, device| self.filter.matches(device));
+        access::check_all(&mut items, self.check_in_use);
+        Ok(items)
+    }
+
+    /// Like [`ListenBuilder::scan`], sorted by port name with the numbers in the names compared by
+    /// value. IE: COM2 comes before COM10
+    pub fn scan_sorted(&self) -> Result<Vec<DeviceInfo>> {
+        let mut devices = self.scan()?.into_values().collect::<Vec<_>>();
+        devices.sort_by(|a, b| cmp_ports(&a.port, &b.port));
+        Ok(devices)
+    }
+
+    /// Scan for the matching serial ports of every kind, including PCI and built in UARTs,
+    /// Bluetooth and virtual ports. See [`PortType`]
+    pub fn scan_all(&self) -> Result<HashMap<String, DeviceInfo>> {
+        #[cfg(windows)]
+        let mut items = native::scan_all()?;
+        #[cfg(not(windows))]
+        let mut items = native::scan()?;
+        // The native backends know USB devices best, serialport fills in everything else
+        match serialport::available_ports() {
+            Ok(ports) => {
+                for port in ports {
+                    if let Entry::Vacant(entry) = items.entry(port.port_name.clone()) {
+                        if let Some(device) = other_port(port) {
+                            entry.insert(device);
+                        }
+                    }
+                }
+            }
+            Err(error) => debug!(?error, "failed to list serial ports"),
+        }
+        items.retain(|_, device| self.filter.matches(device));
+        access::check_all(&mut items, self.check_in_use);
+        Ok(items)
+    }
+}
+
+/// Convert a serial port which is not a USB device
+fn other_port(info: SerialPortInfo) -> Option<DeviceInfo> {
+    let port_type = match info.port_type {
+        SerialPortType::UsbPort(_) => return None,
+        SerialPortType::PciPort => PortType::Pci,
+        SerialPortType::BluetoothPort => PortType::Bluetooth,
+        SerialPortType::Unknown => PortType::Unknown,
+    };
+    Some(DeviceInfo {
+        port: info.port_name,
+        port_type,
+        ..Default::default()
+    })
+}
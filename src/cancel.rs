@@ -0,0 +1,37 @@
+// cancel.rs
+use crate::{error::Result, EventIter, ListenBuilder};
+use tokio_util::sync::CancellationToken;
+use tracing::trace;
+
+impl ListenBuilder {
+    /// Like [`ListenBuilder::listen`], stopping the listener when `token` is cancelled instead of
+    /// when an [`crate::AbortHandle`] is dropped
+    ///
+    /// The AbortHandle is held by a task spawned on the current tokio runtime, so the listener
+    /// keeps running until the token is cancelled, even if the [`EventIter`] is dropped
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime
+    ///
+    /// ```no_run
+    /// # async fn run() -> serialport_detect::Result<()> {
+    /// use tokio_util::sync::CancellationToken;
+    ///
+    /// let shutdown = CancellationToken::new();
+    /// let events = serialport_detect::builder().listen_until(shutdown.child_token())?;
+    /// // ...
+    /// shutdown.cancel();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn listen_until(self, token: CancellationToken) -> Result<EventIter> {
+        let (abort, events) = self.listen()?;
+        tokio::spawn(async move {
+            token.cancelled().await;
+            trace!("listener cancelled");
+            abort.abort_async().await;
+        });
+        Ok(events)
+    }
+}
@@ -0,0 +1,311 @@
+// daemon.rs
+//
+// One listener shared by every local process: the daemon owns the subscription to the OS, and
+// serves scans and event streams over a unix socket
+
+use crate::{
+    detect::{DeviceInfo, EventInfo},
+    error::{Error, Result},
+    hub::{Hub, Publisher, Response},
+    AbortHandle, ListenBuilder,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+use tracing::{debug, trace, warn};
+
+/// The largest frame we accept, so that a confused peer can't make us allocate without bounds
+const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+/// The socket of `serialport-detectd` when it is given none: `serialport-detect.sock` in
+/// XDG_RUNTIME_DIR, else in /run. Enabled with the `daemon` feature on unix
+pub fn daemon_socket() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/run"))
+        .join("serialport-detect.sock")
+}
+
+/// A request of a client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+enum Request {
+    Scan,
+    Subscribe {
+        #[serde(default)]
+        initial: bool,
+    },
+}
+
+/// Write `value` as a frame: its length as a big endian u32, followed by it as JSON
+fn write_frame<T: Serialize>(stream: &mut impl Write, value: &T) -> io::Result<()> {
+    let json = serde_json::to_vec(value).map_err(io::Error::from)?;
+    stream.write_all(&(json.len() as u32).to_be_bytes())?;
+    stream.write_all(&json)?;
+    stream.flush()
+}
+
+/// Read a frame. None when the peer hung up between frames
+fn read_frame<T: for<'de> Deserialize<'de>>(stream: &mut impl Read) -> Result<Option<T>> {
+    let mut len = [0; 4];
+    match stream.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error.into()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME {
+        return Err(Error::Decode(format!("frame of {len} bytes is too large")));
+    }
+    let mut json = vec![0; len];
+    stream.read_exact(&mut json)?;
+    serde_json::from_slice(&json)
+        .map(Some)
+        .map_err(|error| Error::Decode(error.to_string()))
+}
+
+impl ListenBuilder {
+    /// Listen, and serve the devices and the events to the local processes which connect to the
+    /// unix socket at `path`, instead of returning an [`EventIter`]. Enabled with the `daemon`
+    /// feature on unix. See [`DaemonClient`] for a client, and the `serialport-detectd` binary
+    ///
+    /// Many processes then share one listener. Each message is a frame: its length as a big endian
+    /// u32, followed by it as JSON. A client sends `{"request":"scan"}` to receive the connected
+    /// devices as `{"devices":[..]}`, or `{"request":"subscribe","initial":true}` to receive every
+    /// event as `{"event":{..}}` until it hangs up, starting with an Add event for the connected
+    /// devices when `initial`. Errors of the listener are sent as `{"error":".."}`. A subscriber
+    /// which falls 1024 events behind is hung up on
+    ///
+    /// The listener reports the connected devices when it starts, so [`ListenBuilder::initial`]
+    /// is always on. A stale socket at `path` is replaced. The socket is removed by the thread
+    /// accepting the clients once the listener is gone, which may be after the [`AbortHandle`] is
+    /// dropped, so a process about to exit removes it itself
+    ///
+    /// ```no_run
+    /// let abort = serialport_detect::builder().serve_unix("/run/serialport-detect.sock")?;
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    ///
+    /// [`EventIter`]: crate::EventIter
+    pub fn serve_unix(self, path: impl AsRef<Path>) -> Result<AbortHandle> {
+        let path = path.as_ref().to_path_buf();
+        let listener = bind(&path)?;
+        let hub = Arc::new(Mutex::new(Hub::default()));
+        let closed = Arc::new(AtomicBool::new(false));
+        let wake = path.clone();
+        let publisher = Publisher::new(&hub, &closed, move || {
+            let _ = UnixStream::connect(&wake);
+        });
+        let abort = self.initial(true).listen_into(publisher)?;
+        debug!(path = %path.display(), "serving on unix socket");
+        thread::Builder::new()
+            .name("serialport-detect-daemon".into())
+            .spawn(move || accept(listener, &path, &hub, &closed))?;
+        Ok(abort)
+    }
+}
+
+/// Bind to `path`, replacing a socket nobody listens on anymore
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    match UnixListener::bind(path) {
+        Err(error) if error.kind() == io::ErrorKind::AddrInUse => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(error);
+            }
+            debug!(path = %path.display(), "replacing stale socket");
+            std::fs::remove_file(path)?;
+            UnixListener::bind(path)
+        }
+        bound => bound,
+    }
+}
+
+/// Serve every client with a thread of its own, until the listener is gone
+fn accept(listener: UnixListener, path: &Path, hub: &Arc<Mutex<Hub>>, closed: &AtomicBool) {
+    for stream in listener.incoming() {
+        if closed.load(Ordering::Acquire) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!(?error, "failed to accept client");
+                continue;
+            }
+        };
+        let hub = Arc::clone(hub);
+        let spawned = thread::Builder::new()
+            .name("serialport-detect-client".into())
+            .spawn(move || serve(stream, &hub));
+        if let Err(error) = spawned {
+            warn!(?error, "failed to serve client");
+        }
+    }
+    trace!("closing unix socket");
+    let _ = std::fs::remove_file(path);
+}
+
+/// Answer the requests of a client until it hangs up or subscribes. A subscriber is sent events
+/// until it hangs up or the listener is gone
+fn serve(mut stream: UnixStream, hub: &Mutex<Hub>) {
+    loop {
+        let request = match read_frame(&mut stream) {
+            Ok(Some(request)) => request,
+            Ok(None) => return,
+            Err(error) => {
+                let _ = write_frame(&mut stream, &Response::Error(error.to_string()));
+                return;
+            }
+        };
+        trace!(?request, "request");
+        match request {
+            Request::Scan => {
+                let devices = hub.lock().scan();
+                if write_frame(&mut stream, &Response::Devices(devices)).is_err() {
+                    return;
+                }
+            }
+            Request::Subscribe { initial } => {
+                let events = hub.lock().subscribe(initial);
+                for response in events {
+                    if write_frame(&mut stream, &response).is_err() {
+                        return;
+                    }
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// A connection to a daemon serving with [`ListenBuilder::serve_unix`]. Enabled with the `daemon`
+/// feature on unix
+///
+/// ```no_run
+/// use serialport_detect::DaemonClient;
+///
+/// let mut client = DaemonClient::connect("/run/serialport-detect.sock")?;
+/// for device in client.scan()? {
+///     println!("{}", device.port);
+/// }
+/// for event in client.subscribe(false)? {
+///     println!("{:?}", event?);
+/// }
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct DaemonClient {
+    stream: UnixStream,
+}
+
+impl DaemonClient {
+    /// Connect to the daemon at `path`
+    pub fn connect(path: impl AsRef<Path>) -> Result<DaemonClient> {
+        Ok(DaemonClient {
+            stream: UnixStream::connect(path)?,
+        })
+    }
+
+    /// The devices connected now, sorted by port
+    pub fn scan(&mut self) -> Result<Vec<DeviceInfo>> {
+        write_frame(&mut self.stream, &Request::Scan)?;
+        match read_frame(&mut self.stream)? {
+            Some(Response::Devices(devices)) => Ok(devices),
+            Some(Response::Error(error)) => Err(Error::Io(io::Error::other(error))),
+            Some(Response::Event(_)) => Err(Error::Decode("unexpected event".into())),
+            None => Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+        }
+    }
+
+    /// Receive the events of the daemon, starting with an Add event for every connected device
+    /// when `initial`. The connection only carries events from now on. The initial events are
+    /// numbered 0, the others are numbered by the listener of the daemon
+    pub fn subscribe(mut self, initial: bool) -> Result<Subscription> {
+        write_frame(&mut self.stream, &Request::Subscribe { initial })?;
+        Ok(Subscription {
+            stream: self.stream,
+        })
+    }
+}
+
+/// The events of a daemon. See [`DaemonClient::subscribe`]
+///
+/// Ends when the daemon stops. Errors of the listener of the daemon are yielded as
+/// [`Error::Io`]
+#[derive(Debug)]
+pub struct Subscription {
+    stream: UnixStream,
+}
+
+impl Iterator for Subscription {
+    type Item = Result<EventInfo>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match read_frame(&mut self.stream) {
+            Ok(Some(Response::Event(event))) => Some(Ok(*event)),
+            Ok(Some(Response::Error(error))) => Some(Err(Error::Io(io::Error::other(error)))),
+            Ok(Some(Response::Devices(_))) => Some(Err(Error::Decode("unexpected devices".into()))),
+            Ok(None) => None,
+            Err(error) => Some(Err(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::detect::{test::event, EventType};
+
+    #[test]
+    fn test_daemon_protocol() {
+        let hub = Arc::new(Mutex::new(Hub::default()));
+        hub.lock()
+            .publish(Ok(event("/dev/ttyUSB1", EventType::Add)));
+        hub.lock()
+            .publish(Ok(event("/dev/ttyUSB0", EventType::Add)));
+        let client = |hub: &Arc<Mutex<Hub>>| {
+            let (client, server) = UnixStream::pair().unwrap();
+            let hub = Arc::clone(hub);
+            thread::spawn(move || serve(server, &hub));
+            DaemonClient { stream: client }
+        };
+        let ports = |devices: Vec<DeviceInfo>| {
+            devices
+                .into_iter()
+                .map(|device| device.port)
+                .collect::<Vec<_>>()
+        };
+        let mut scanner = client(&hub);
+        assert_eq!(
+            ports(scanner.scan().unwrap()),
+            ["/dev/ttyUSB0", "/dev/ttyUSB1"]
+        );
+        let mut events = client(&hub).subscribe(true).unwrap();
+        let initial = (0..2).map(|_| events.next().unwrap().unwrap().device);
+        assert_eq!(ports(initial.collect()), ["/dev/ttyUSB0", "/dev/ttyUSB1"]);
+        hub.lock()
+            .publish(Ok(event("/dev/ttyUSB1", EventType::Remove)));
+        let removed = events.next().unwrap().unwrap();
+        assert!(matches!(removed.event, EventType::Remove));
+        assert_eq!(ports(scanner.scan().unwrap()), ["/dev/ttyUSB0"]);
+        // The stream ends with the daemon
+        hub.lock().close();
+        assert!(events.next().is_none());
+
+        let mut garbage = UnixStream::pair().unwrap();
+        garbage.0.write_all(&2u32.to_be_bytes()).unwrap();
+        garbage.0.write_all(b"{}").unwrap();
+        thread::spawn(move || serve(garbage.1, &hub));
+        let response = read_frame::<Response>(&mut garbage.0).unwrap();
+        assert!(matches!(response, Some(Response::Error(_))));
+    }
+}
@@ -0,0 +1,752 @@
+// dbus.rs
+//
+// Just enough of the D-Bus wire protocol to talk to a bus over its unix socket: authenticate,
+// call methods, receive the signals we subscribed to, and answer the method calls and emit the
+// signals of an object of our own
+
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    os::{
+        fd::{AsFd, AsRawFd},
+        unix::net::UnixStream,
+    },
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// Where the system bus listens when DBUS_SYSTEM_BUS_ADDRESS is not set
+const SYSTEM_BUS: &str = "/run/dbus/system_bus_socket";
+
+/// How long the bus may take to authenticate us, or to reply to a call we wait for
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The reply of RequestName when we own the name
+const PRIMARY_OWNER: u32 = 1;
+
+/// Fail RequestName when another connection owns the name, instead of waiting for it in line
+const DO_NOT_QUEUE: u32 = 0x4;
+
+/// The part of a message before its header fields
+const FIXED_HEADER: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum MessageType {
+    MethodCall = 1,
+    MethodReturn = 2,
+    Error = 3,
+    Signal = 4,
+}
+
+impl MessageType {
+    fn from_u8(kind: u8) -> Option<MessageType> {
+        match kind {
+            1 => Some(MessageType::MethodCall),
+            2 => Some(MessageType::MethodReturn),
+            3 => Some(MessageType::Error),
+            4 => Some(MessageType::Signal),
+            _ => None,
+        }
+    }
+}
+
+/// A message we received. Only the header fields we use are kept
+#[derive(Debug)]
+pub(crate) struct Message {
+    pub(crate) kind: MessageType,
+    pub(crate) serial: u32,
+    pub(crate) reply_serial: Option<u32>,
+    pub(crate) sender: Option<String>,
+    pub(crate) path: Option<String>,
+    pub(crate) interface: Option<String>,
+    pub(crate) member: Option<String>,
+    pub(crate) error_name: Option<String>,
+    pub(crate) signature: String,
+    big_endian: bool,
+    body: Vec<u8>,
+}
+
+impl Message {
+    /// Parse the message at the start of `buf`. Returns the message and its length, or None when
+    /// `buf` does not hold all of it yet. A message we cannot parse is an error
+    fn parse(buf: &[u8]) -> io::Result<Option<(Message, usize)>> {
+        if buf.len() < FIXED_HEADER {
+            return Ok(None);
+        }
+        let big_endian = match buf[0] {
+            b'l' => false,
+            b'B' => true,
+            _ => return Err(invalid("message endianness")),
+        };
+        let mut reader = Reader::new(&buf[..FIXED_HEADER], big_endian);
+        reader.pos = 4;
+        let body_len = reader.u32()? as usize;
+        let serial = reader.u32()?;
+        let fields_len = reader.u32()? as usize;
+        let header_len = align(FIXED_HEADER + fields_len, 8);
+        let len = header_len + body_len;
+        if buf.len() < len {
+            return Ok(None);
+        }
+        let kind = MessageType::from_u8(buf[1]).ok_or_else(|| invalid("message type"))?;
+        let mut message = Message {
+            kind,
+            serial,
+            reply_serial: None,
+            sender: None,
+            path: None,
+            interface: None,
+            member: None,
+            error_name: None,
+            signature: String::new(),
+            big_endian,
+            body: buf[header_len..len].to_vec(),
+        };
+        let mut fields = Reader::new(&buf[..FIXED_HEADER + fields_len], big_endian);
+        fields.pos = FIXED_HEADER;
+        while fields.pos < fields.buf.len() {
+            fields.align(8)?;
+            let code = fields.u8()?;
+            let value = match fields.signature()?.as_str() {
+                "s" | "o" => fields.string()?,
+                "g" => fields.signature()?,
+                "u" => {
+                    let value = fields.u32()?;
+                    if code == 5 {
+                        message.reply_serial = Some(value);
+                    }
+                    continue;
+                }
+                _ => return Err(invalid("header field")),
+            };
+            match code {
+                1 => message.path = Some(value),
+                2 => message.interface = Some(value),
+                3 => message.member = Some(value),
+                4 => message.error_name = Some(value),
+                7 => message.sender = Some(value),
+                8 => message.signature = value,
+                _ => {}
+            }
+        }
+        Ok(Some((message, len)))
+    }
+
+    /// Whether this is the signal `member` of `interface`
+    pub(crate) fn is_signal(&self, interface: &str, member: &str) -> bool {
+        self.kind == MessageType::Signal
+            && self.interface.as_deref() == Some(interface)
+            && self.member.as_deref() == Some(member)
+    }
+
+    /// Whether this is a call of the method `member` of `interface`. A call which names no
+    /// interface may mean any
+    pub(crate) fn is_call(&self, interface: &str, member: &str) -> bool {
+        self.kind == MessageType::MethodCall
+            && self
+                .interface
+                .as_deref()
+                .is_none_or(|name| name == interface)
+            && self.member.as_deref() == Some(member)
+    }
+
+    /// Read the arguments of the message
+    pub(crate) fn body(&self) -> Reader<'_> {
+        Reader::new(&self.body, self.big_endian)
+    }
+}
+
+/// A method call to send. The body is written with [`Writer`]
+pub(crate) struct Call<'a> {
+    pub(crate) destination: &'a str,
+    pub(crate) path: &'a str,
+    pub(crate) interface: &'a str,
+    pub(crate) member: &'a str,
+    pub(crate) signature: &'a str,
+    pub(crate) body: Vec<u8>,
+}
+
+impl Call<'_> {
+    /// With `reply`, the bus and the callee know that we wait for a reply
+    fn encode(&self, serial: u32, reply: bool) -> Vec<u8> {
+        let fields = [
+            Field::Path(self.path),
+            Field::Interface(self.interface),
+            Field::Member(self.member),
+            Field::Destination(self.destination),
+            Field::Signature(self.signature),
+        ];
+        // NO_REPLY_EXPECTED
+        let flags = if reply { 0 } else { 0x1 };
+        encode(MessageType::MethodCall, flags, serial, &fields, &self.body)
+    }
+}
+
+/// A header field of a message we send
+#[derive(Debug, Copy, Clone)]
+enum Field<'a> {
+    Path(&'a str),
+    Interface(&'a str),
+    Member(&'a str),
+    ErrorName(&'a str),
+    ReplySerial(u32),
+    Destination(&'a str),
+    Signature(&'a str),
+}
+
+impl Field<'_> {
+    fn write(&self, header: &mut Writer) {
+        let (code, signature) = match self {
+            Field::Path(_) => (1, "o"),
+            Field::Interface(_) => (2, "s"),
+            Field::Member(_) => (3, "s"),
+            Field::ErrorName(_) => (4, "s"),
+            Field::ReplySerial(_) => (5, "u"),
+            Field::Destination(_) => (6, "s"),
+            Field::Signature(_) => (8, "g"),
+        };
+        header.align(8);
+        header.u8(code);
+        header.signature(signature);
+        match *self {
+            Field::ReplySerial(serial) => header.u32(serial),
+            Field::Signature(value) => header.signature(value),
+            Field::Path(value)
+            | Field::Interface(value)
+            | Field::Member(value)
+            | Field::ErrorName(value)
+            | Field::Destination(value) => header.string(value),
+        }
+    }
+
+    /// Fields without a value are left out, IE: the signature of a message without a body
+    fn is_empty(&self) -> bool {
+        match self {
+            Field::ReplySerial(_) => false,
+            Field::Path(value)
+            | Field::Interface(value)
+            | Field::Member(value)
+            | Field::ErrorName(value)
+            | Field::Destination(value)
+            | Field::Signature(value) => value.is_empty(),
+        }
+    }
+}
+
+/// A message of `kind` with the header `fields`, followed by `body`
+fn encode(kind: MessageType, flags: u8, serial: u32, fields: &[Field<'_>], body: &[u8]) -> Vec<u8> {
+    let mut header = Writer::new();
+    header.u8(b'l');
+    header.u8(kind as u8);
+    header.u8(flags);
+    header.u8(1);
+    header.u32(body.len() as u32);
+    header.u32(serial);
+    header.array(8, |header| {
+        for field in fields.iter().filter(|field| !field.is_empty()) {
+            field.write(header);
+        }
+    });
+    header.align(8);
+    let mut message = header.into_inner();
+    message.extend_from_slice(body);
+    message
+}
+
+/// Writes values with the alignment of the D-Bus marshalling, relative to the start of the buffer.
+/// A body starts 8 aligned in its message, so it is written on its own
+#[derive(Debug, Default)]
+pub(crate) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn new() -> Writer {
+        Writer::default()
+    }
+
+    pub(crate) fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub(crate) fn align(&mut self, to: usize) {
+        self.buf.resize(align(self.buf.len(), to), 0);
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    pub(crate) fn signature(&mut self, value: &str) {
+        self.u8(value.len() as u8);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    /// An array of elements aligned to `alignment`, written by `elements`
+    pub(crate) fn array(&mut self, alignment: usize, elements: impl FnOnce(&mut Writer)) {
+        self.u32(0);
+        let len_at = self.buf.len() - 4;
+        // The padding before the first element is not counted in the length
+        self.align(alignment);
+        let start = self.buf.len();
+        elements(self);
+        let len = (self.buf.len() - start) as u32;
+        self.buf[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+    }
+}
+
+/// Reads values with the alignment of the D-Bus marshalling, relative to the start of the buffer
+#[derive(Debug)]
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8], big_endian: bool) -> Reader<'a> {
+        Reader {
+            buf,
+            pos: 0,
+            big_endian,
+        }
+    }
+
+    fn align(&mut self, to: usize) -> io::Result<()> {
+        self.pos = align(self.pos, to);
+        match self.pos <= self.buf.len() {
+            true => Ok(()),
+            false => Err(invalid("message length")),
+        }
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| invalid("message length"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> io::Result<u32> {
+        self.align(4)?;
+        let bytes = self.take(4)?.try_into().expect("4 bytes");
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    pub(crate) fn bool(&mut self) -> io::Result<bool> {
+        match self.u32()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("boolean")),
+        }
+    }
+
+    pub(crate) fn string(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+        let value = self.take(len + 1)?;
+        String::from_utf8(value[..len].to_vec()).map_err(|_| invalid("string"))
+    }
+
+    fn signature(&mut self) -> io::Result<String> {
+        let len = self.u8()? as usize;
+        let value = self.take(len + 1)?;
+        String::from_utf8(value[..len].to_vec()).map_err(|_| invalid("signature"))
+    }
+}
+
+fn align(pos: usize, to: usize) -> usize {
+    pos.div_ceil(to) * to
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid D-Bus {what}"))
+}
+
+/// A connection to a bus
+#[derive(Debug)]
+pub(crate) struct Connection {
+    stream: UnixStream,
+    serial: u32,
+    /// What we read of a message which has not been received in full
+    buf: Vec<u8>,
+    /// The messages received while we waited for a reply
+    pending: Vec<Message>,
+}
+
+impl Connection {
+    /// Connect to the system bus, at DBUS_SYSTEM_BUS_ADDRESS when it is set
+    pub(crate) fn system() -> io::Result<Connection> {
+        let path = match std::env::var("DBUS_SYSTEM_BUS_ADDRESS") {
+            Ok(address) => address_path(&address)
+                .ok_or_else(|| io::Error::other(format!("unsupported bus address {address}")))?,
+            Err(_) => PathBuf::from(SYSTEM_BUS),
+        };
+        Connection::open(path)
+    }
+
+    /// Connect to the session bus of our user, at DBUS_SESSION_BUS_ADDRESS
+    pub(crate) fn session() -> io::Result<Connection> {
+        let address = std::env::var("DBUS_SESSION_BUS_ADDRESS").map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "DBUS_SESSION_BUS_ADDRESS is not set",
+            )
+        })?;
+        let path = address_path(&address)
+            .ok_or_else(|| io::Error::other(format!("unsupported bus address {address}")))?;
+        Connection::open(path)
+    }
+
+    /// Authenticate as our user, and say Hello to the bus as every client must
+    fn open(path: PathBuf) -> io::Result<Connection> {
+        let mut stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        // Safety: always succeeds
+        let uid = unsafe { libc::geteuid() }.to_string();
+        let hex = uid.bytes().map(|b| format!("{b:02x}")).collect::<String>();
+        stream.write_all(format!("\0AUTH EXTERNAL {hex}\r\n").as_bytes())?;
+        // The bus sends nothing more until we BEGIN, so the reader buffers nothing of the messages
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        if !line.starts_with("OK ") {
+            let error = format!("D-Bus authentication failed: {}", line.trim_end());
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, error));
+        }
+        stream.write_all(b"BEGIN\r\n")?;
+        stream.set_read_timeout(None)?;
+        stream.set_nonblocking(true)?;
+        let mut connection = Connection {
+            stream,
+            serial: 0,
+            buf: Vec::new(),
+            pending: Vec::new(),
+        };
+        connection.call_bus("Hello", "", Vec::new())?;
+        Ok(connection)
+    }
+
+    fn next_serial(&mut self) -> u32 {
+        self.serial += 1;
+        self.serial
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        // The messages we send are small, and the socket buffer is empty unless the bus is stuck
+        self.stream.set_nonblocking(false)?;
+        let written = self.stream.write_all(message);
+        self.stream.set_nonblocking(true)?;
+        written
+    }
+
+    /// Send a method call, without waiting for its reply
+    pub(crate) fn call(&mut self, call: &Call<'_>) -> io::Result<()> {
+        let serial = self.next_serial();
+        self.send(&call.encode(serial, false))
+    }
+
+    /// Send a method call and wait for its reply. An error reply is an error. The other messages
+    /// received meanwhile are kept for [`Connection::receive`]
+    fn call_wait(&mut self, call: &Call<'_>) -> io::Result<Message> {
+        let serial = self.next_serial();
+        self.send(&call.encode(serial, true))?;
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let mut reply = None;
+            for message in self.read()? {
+                match message.reply_serial == Some(serial) {
+                    true => reply = Some(message),
+                    false => self.pending.push(message),
+                }
+            }
+            match reply {
+                Some(reply) if reply.kind == MessageType::Error => {
+                    let name = reply.error_name.unwrap_or_default();
+                    let error = format!("{} failed: {name}", call.member);
+                    return Err(io::Error::other(error));
+                }
+                Some(reply) => return Ok(reply),
+                None => {}
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            let timeout = PollTimeout::try_from(left).unwrap_or(PollTimeout::MAX);
+            let mut fds = [PollFd::new(self.stream.as_fd(), PollFlags::POLLIN)];
+            if poll(&mut fds, timeout)? == 0 {
+                let error = format!("no reply to {}", call.member);
+                return Err(io::Error::new(io::ErrorKind::TimedOut, error));
+            }
+        }
+    }
+
+    fn call_bus(&mut self, member: &str, signature: &str, body: Vec<u8>) -> io::Result<()> {
+        self.call(&Call {
+            destination: "org.freedesktop.DBus",
+            path: "/org/freedesktop/DBus",
+            interface: "org.freedesktop.DBus",
+            member,
+            signature,
+            body,
+        })
+    }
+
+    /// Own the well known `name`, failing when another connection owns it
+    pub(crate) fn request_name(&mut self, name: &str) -> io::Result<()> {
+        let mut body = Writer::new();
+        body.string(name);
+        body.u32(DO_NOT_QUEUE);
+        let reply = self.call_wait(&Call {
+            destination: "org.freedesktop.DBus",
+            path: "/org/freedesktop/DBus",
+            interface: "org.freedesktop.DBus",
+            member: "RequestName",
+            signature: "su",
+            body: body.into_inner(),
+        })?;
+        match reply.body().u32()? {
+            PRIMARY_OWNER => Ok(()),
+            _ => {
+                let error = format!("the bus name {name} is owned by another connection");
+                Err(io::Error::new(io::ErrorKind::AddrInUse, error))
+            }
+        }
+    }
+
+    /// Emit the signal `member` of `interface` from the object at `path`
+    pub(crate) fn signal(
+        &mut self,
+        path: &str,
+        interface: &str,
+        member: &str,
+        signature: &str,
+        body: &[u8],
+    ) -> io::Result<()> {
+        let fields = [
+            Field::Path(path),
+            Field::Interface(interface),
+            Field::Member(member),
+            Field::Signature(signature),
+        ];
+        let serial = self.next_serial();
+        self.send(&encode(MessageType::Signal, 0, serial, &fields, body))
+    }
+
+    /// Return from the method `call`
+    pub(crate) fn reply(&mut self, call: &Message, signature: &str, body: &[u8]) -> io::Result<()> {
+        let fields = [
+            Field::ReplySerial(call.serial),
+            Field::Destination(call.sender.as_deref().unwrap_or_default()),
+            Field::Signature(signature),
+        ];
+        let serial = self.next_serial();
+        self.send(&encode(MessageType::MethodReturn, 0, serial, &fields, body))
+    }
+
+    /// Fail the method `call` with the error `name` and a message for humans
+    pub(crate) fn error(&mut self, call: &Message, name: &str, text: &str) -> io::Result<()> {
+        let mut body = Writer::new();
+        body.string(text);
+        let fields = [
+            Field::ErrorName(name),
+            Field::ReplySerial(call.serial),
+            Field::Destination(call.sender.as_deref().unwrap_or_default()),
+            Field::Signature("s"),
+        ];
+        let serial = self.next_serial();
+        let message = encode(MessageType::Error, 0, serial, &fields, &body.into_inner());
+        self.send(&message)
+    }
+
+    /// Receive the messages matching `rule` (IE: `type='signal',member='...'`)
+    pub(crate) fn add_match(&mut self, rule: &str) -> io::Result<()> {
+        let mut body = Writer::new();
+        body.string(rule);
+        self.call_bus("AddMatch", "s", body.into_inner())
+    }
+
+    /// The messages received since we last looked. The socket is non blocking and read until it
+    /// is drained. The bus closing the connection is an error
+    pub(crate) fn receive(&mut self) -> io::Result<Vec<Message>> {
+        let mut messages = std::mem::take(&mut self.pending);
+        messages.extend(self.read()?);
+        Ok(messages)
+    }
+
+    /// The messages we can read without blocking
+    fn read(&mut self) -> io::Result<Vec<Message>> {
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(len) => self.buf.extend_from_slice(&chunk[..len]),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+        }
+        let mut messages = Vec::new();
+        let mut start = 0;
+        while let Some((message, len)) = Message::parse(&self.buf[start..])? {
+            messages.push(message);
+            start += len;
+        }
+        self.buf.drain(..start);
+        Ok(messages)
+    }
+}
+
+impl AsRawFd for Connection {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+/// The socket of a bus address (IE: `unix:path=/run/dbus/system_bus_socket`). Only unix paths
+/// are supported. An address may list several, separated by `;`
+fn address_path(address: &str) -> Option<PathBuf> {
+    address.split(';').find_map(|address| {
+        let options = address.strip_prefix("unix:")?;
+        options
+            .split(',')
+            .find_map(|option| option.strip_prefix("path="))
+            .map(PathBuf::from)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_address_path() {
+        assert_eq!(
+            address_path("unix:path=/run/dbus/system_bus_socket"),
+            Some(PathBuf::from("/run/dbus/system_bus_socket"))
+        );
+        assert_eq!(
+            address_path("tcp:host=localhost,port=1;unix:guid=1234,path=/run/user/1000/bus"),
+            Some(PathBuf::from("/run/user/1000/bus"))
+        );
+        assert_eq!(address_path("unix:abstract=/tmp/dbus-1234"), None);
+    }
+
+    #[test]
+    fn test_message_roundtrip() {
+        let mut body = Writer::new();
+        body.string("type='signal'");
+        let call = Call {
+            destination: "org.freedesktop.DBus",
+            path: "/org/freedesktop/DBus",
+            interface: "org.freedesktop.DBus",
+            member: "AddMatch",
+            signature: "s",
+            body: body.into_inner(),
+        };
+        let mut encoded = call.encode(7, false);
+        let len = encoded.len();
+        // A partial message waits for the rest
+        assert!(Message::parse(&encoded[..len - 1]).unwrap().is_none());
+        encoded.extend_from_slice(b"next");
+        let (message, parsed) = Message::parse(&encoded).unwrap().unwrap();
+        assert_eq!(parsed, len);
+        assert_eq!(message.kind, MessageType::MethodCall);
+        assert_eq!(message.interface.as_deref(), Some("org.freedesktop.DBus"));
+        assert_eq!(message.member.as_deref(), Some("AddMatch"));
+        assert_eq!(message.signature, "s");
+        assert_eq!(message.body().string().unwrap(), "type='signal'");
+    }
+
+    #[test]
+    fn test_parse_signal() {
+        // PrepareForSleep(false) of logind, as the bus sends it
+        let mut header = Writer::new();
+        header.u8(b'l');
+        header.u8(MessageType::Signal as u8);
+        header.u8(0);
+        header.u8(1);
+        header.u32(4);
+        header.u32(1);
+        header.array(8, |header| {
+            for (code, signature, value) in [
+                (1, "o", "/org/freedesktop/login1"),
+                (2, "s", "org.freedesktop.login1.Manager"),
+                (3, "s", "PrepareForSleep"),
+                (8, "g", "b"),
+            ] {
+                header.align(8);
+                header.u8(code);
+                header.signature(signature);
+                match signature {
+                    "g" => header.signature(value),
+                    _ => header.string(value),
+                }
+            }
+            header.align(8);
+            header.u8(5);
+            header.signature("u");
+            header.u32(9);
+        });
+        header.align(8);
+        let mut message = header.into_inner();
+        message.extend_from_slice(&0u32.to_le_bytes());
+        let (message, _) = Message::parse(&message).unwrap().unwrap();
+        assert!(message.is_signal("org.freedesktop.login1.Manager", "PrepareForSleep"));
+        assert!(!message.body().bool().unwrap());
+        assert!(Message::parse(b"xxxxxxxxxxxxxxxxxxxx").is_err());
+    }
+
+    #[test]
+    fn test_parse_error_reply() {
+        let call = Call {
+            destination: "io.github.tomzbench.SerialportDetect",
+            path: "/io/github/tomzbench/SerialportDetect",
+            interface: "",
+            member: "Scan",
+            signature: "",
+            body: Vec::new(),
+        };
+        let (call, _) = Message::parse(&call.encode(3, true)).unwrap().unwrap();
+        assert_eq!(call.serial, 3);
+        assert_eq!(
+            call.path.as_deref(),
+            Some("/io/github/tomzbench/SerialportDetect")
+        );
+        // A call which names no interface is a call of any
+        assert!(call.is_call("io.github.tomzbench.SerialportDetect", "Scan"));
+        let mut body = Writer::new();
+        body.string("no such method");
+        let fields = [
+            Field::ErrorName("org.freedesktop.DBus.Error.UnknownMethod"),
+            Field::ReplySerial(call.serial),
+            Field::Destination(":1.42"),
+            Field::Signature("s"),
+        ];
+        let reply = encode(MessageType::Error, 0, 9, &fields, &body.into_inner());
+        let (reply, _) = Message::parse(&reply).unwrap().unwrap();
+        assert_eq!(reply.kind, MessageType::Error);
+        assert_eq!(reply.serial, 9);
+        assert_eq!(reply.reply_serial, Some(3));
+        let name = reply.error_name.as_deref();
+        assert_eq!(name, Some("org.freedesktop.DBus.Error.UnknownMethod"));
+        assert_eq!(reply.body().string().unwrap(), "no such method");
+    }
+}
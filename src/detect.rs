@@ -1,14 +1,44 @@
 // io.rs
+#[cfg(feature = "journal")]
+use crate::journal::Journal;
+use crate::{
+    access,
+    error::{Error, Result},
+    filter::{Filter, Match},
+    id::{Pid, Vid},
+    metrics::Metrics,
+    retry::Retry,
+    throttle::{RateLimit, Throttle},
+    timer::{self, Timer},
+};
 use crossbeam::queue::SegQueue;
+#[cfg(feature = "futures")]
+use futures::{stream::FusedStream, Stream};
 use parking_lot::Mutex;
 use std::{
+    cmp,
+    collections::{HashMap, VecDeque},
+    fmt::{self, Debug},
+    future::{self, Future},
     io,
-    task::{Context, Poll, Waker},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 /// Information about the serial port
-#[derive(Debug, Clone)]
+///
+/// With the `serde` feature the field names are the wire format, and are kept stable. Missing
+/// fields deserialize to their default
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "napi", napi_derive::napi(object))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 pub struct DeviceInfo {
     /// The port name. IE: COM3
     pub port: String,
@@ -16,65 +46,1372 @@ pub struct DeviceInfo {
     pub vid: Option<String>,
     /// Product ID
     pub pid: Option<String>,
+    /// Vendor ID as a number
+    pub vid_u16: Option<u16>,
+    /// Product ID as a number
+    pub pid_u16: Option<u16>,
+    /// The device release number of the USB device descriptor (bcdDevice), in binary coded
+    /// decimal (IE: 0x0600). Tells apart hardware or firmware revisions of otherwise identical
+    /// adapters. See [`DeviceInfo::release`]
+    pub bcd_device: Option<u16>,
     /// Serial number
     pub serial: Option<String>,
     /// Manufacturer string (arbitrary string)
     pub manufacturer: Option<String>,
     /// Product string (arbitrary string)
     pub product: Option<String>,
+    /// The kind of hardware behind the port. See [`PortType`]
+    pub port_type: PortType,
+    /// Whether the current user may open the port, as of when it was reported. False tells a
+    /// device which we are not allowed to use (IE: we are not in the dialout group) from one which
+    /// is absent. On Windows this is told by opening the port, so it is only checked with
+    /// [`crate::ListenBuilder::check_in_use`]. None when it could not be checked, and in Remove
+    /// events
+    pub accessible: Option<bool>,
+    /// Whether another program has the port open, as of when it was reported, IE: to grey it out
+    /// in a port picker. On Linux we see the programs of other users only when they hold a lock
+    /// file, or when we run as root. Elsewhere only a lock file tells, and on Windows a port which
+    /// we are not allowed to open is in use as well. Only checked with
+    /// [`crate::ListenBuilder::check_in_use`]. None otherwise, when it could not be checked, and in
+    /// Remove events. See [`crate::who_has`]
+    pub in_use: Option<bool>,
+    /// The speed the USB device negotiated with its hub, IE: to spot an adapter which fell back
+    /// to full speed behind a bad cable or hub. See [`UsbSpeed`]. None when the port is not a USB
+    /// device, or when the backend cannot tell
+    pub usb_speed: Option<UsbSpeed>,
+    /// The class codes of the USB interface the port belongs to, IE: to open CDC-ACM consoles and
+    /// skip vendor specific debug interfaces. See [`UsbClass`]. Only populated on Linux, Android,
+    /// macOS, Windows and FreeBSD
+    pub interface_class: Option<UsbClass>,
+    /// Identifies the same physical device across unplug and replug, even when the port name
+    /// changes. On Linux this is the name of the /dev/serial/by-id link and on Windows the device
+    /// instance ID. Elsewhere it is built from the USB ids and serial number, and is None when the
+    /// device has no serial number
+    pub stable_id: Option<String>,
+    /// The physical USB port the device is plugged into, as the bus number and the chain of hub
+    /// ports (IE: 1-3.2). Tells apart identical devices which have no serial number. On Windows
+    /// it is the hub and port instead (IE: Port_#0002.Hub_#0003) when the driver stack reports no
+    /// location path, and on macOS the IOKit locationID (IE: 0x14200000)
+    pub location: Option<String>,
+    /// The systemd-logind seat the device is assigned to (IE: seat0), from the ID_SEAT udev
+    /// property of the device or of a hub it is plugged into. A device which is not assigned to a
+    /// seat belongs to seat0. Only populated on Linux, when udev is running. See
+    /// [`crate::ListenBuilder::seat`]
+    pub seat: Option<String>,
+    /// The kernel driver bound to the port (IE: ftdi_sio, cdc_acm, usbser). Helps to diagnose a
+    /// device which is present but bound to the wrong driver
+    pub driver: Option<String>,
+    /// The name Device Manager shows for the port (IE: USB Serial Device (COM7)). Only populated
+    /// on Windows
+    pub friendly_name: Option<String>,
+    /// The device instance ID of the port (IE: FTDIBUS\VID_0403+PID_6001+A50285BIA\0000), which
+    /// Device Manager and vendor tools identify it by. Only populated on Windows
+    pub instance_id: Option<String>,
+    /// The sysfs path of the tty (IE: /sys/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/
+    /// ttyUSB0/tty/ttyUSB0), for matching udev rules and finding the other interfaces of the
+    /// device. Only populated on Linux and Android
+    pub syspath: Option<String>,
+    /// The udev ID_PATH of the port (IE: pci-0000:00:14.0-usb-0:3:1.0), which names the physical
+    /// connection as udev rules and /dev/serial/by-path do. Only populated on Linux
+    pub id_path: Option<String>,
+    /// The symlinks udev made to the port (IE: /dev/serial/by-id/usb-FTDI_FT232R_A50285BI-if00-
+    /// port0), which survive the renumbering of the device node. See [`DeviceInfo::by_id`]. Only
+    /// populated on Linux
+    pub links: Vec<String>,
+    /// The dial-in device of the port (IE: /dev/tty.usbserial-A50285BI), which blocks on open
+    /// until the carrier is detected. [`DeviceInfo::port`] is the callout device (/dev/cu.*),
+    /// which is the one to open. Only populated on macOS
+    pub dialin: Option<String>,
+    /// Every udev property of the device (IE: ID_USB_INTERFACE_NUM, ID_PATH). Only populated on
+    /// Linux with the `udev-properties` feature
+    pub udev_properties: HashMap<String, String>,
+}
+
+impl DeviceInfo {
+    /// The USB vendor id. See [`Vid`]
+    pub fn vendor_id(&self) -> Option<Vid> {
+        self.vid_u16.map(Vid)
+    }
+
+    /// The USB product id. See [`Pid`]
+    pub fn product_id(&self) -> Option<Pid> {
+        self.pid_u16.map(Pid)
+    }
+
+    /// The device release number as the USB descriptor means it, IE: 6.00 for a bcdDevice of
+    /// 0x0600. See [`DeviceInfo::bcd_device`]
+    pub fn release(&self) -> Option<String> {
+        self.bcd_device
+            .map(|bcd| format!("{:x}.{:02x}", bcd >> 8, bcd & 0xff))
+    }
+
+    /// The /dev/serial/by-id link to the port, named after the device and its serial number. Open
+    /// it instead of [`DeviceInfo::port`] to find the same device after it is plugged back in
+    pub fn by_id(&self) -> Option<&str> {
+        self.link("/dev/serial/by-id/")
+    }
+
+    /// The /dev/serial/by-path link to the port, named after the physical connection
+    pub fn by_path(&self) -> Option<&str> {
+        self.link("/dev/serial/by-path/")
+    }
+
+    fn link(&self, dir: &str) -> Option<&str> {
+        self.links
+            .iter()
+            .find(|link| link.starts_with(dir))
+            .map(String::as_str)
+    }
+}
+
+/// The stable id of a USB device for backends without a native one. Ports on the same device are
+/// told apart by their `interface`
+#[cfg(any(
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "freebsd",
+    target_os = "android",
+    target_os = "illumos",
+    target_os = "solaris"
+))]
+pub(crate) fn usb_stable_id(
+    vid: Option<u16>,
+    pid: Option<u16>,
+    serial: Option<&str>,
+    interface: Option<&str>,
+) -> Option<String> {
+    let id = format!("usb-{:04x}:{:04x}-{}", vid?, pid?, serial?);
+    match interface {
+        Some(interface) => Some(format!("{id}-if{interface}")),
+        None => Some(id),
+    }
+}
+
+/// Order port names with their numbers compared by value, IE: COM2 before COM10 and ttyUSB9
+/// before ttyUSB10
+pub(crate) fn cmp_ports(mut a: &str, mut b: &str) -> cmp::Ordering {
+    loop {
+        if a.is_empty() || b.is_empty() {
+            break a.len().cmp(&b.len());
+        }
+        let ((x, next_a), (y, next_b)) = (split_digits(a), split_digits(b));
+        let order = match (
+            x.as_bytes()[0].is_ascii_digit(),
+            y.as_bytes()[0].is_ascii_digit(),
+        ) {
+            (true, true) => {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            _ => x.cmp(y),
+        };
+        if order.is_ne() {
+            break order;
+        }
+        (a, b) = (next_a, next_b);
+    }
+}
+
+/// Split a non empty string after its leading run of digits, or of anything but digits
+fn split_digits(s: &str) -> (&str, &str) {
+    let digit = s.as_bytes()[0].is_ascii_digit();
+    let end = s.bytes().position(|byte| byte.is_ascii_digit() != digit);
+    s.split_at(end.unwrap_or(s.len()))
+}
+
+/// The kind of hardware behind a serial port
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "napi", napi_derive::napi(string_enum))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PortType {
+    /// A USB serial adapter or a USB CDC device
+    Usb,
+    /// A PCI serial card
+    Pci,
+    /// A Bluetooth serial link (IE: rfcomm)
+    Bluetooth,
+    /// Anything else, including built in UARTs and virtual ports
+    #[default]
+    Unknown,
+}
+
+/// The signalling rate a USB device negotiated with its hub
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "napi", napi_derive::napi(string_enum))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UsbSpeed {
+    /// 1.5 Mbps, USB 1.0
+    Low,
+    /// 12 Mbps, USB 1.1
+    Full,
+    /// 480 Mbps, USB 2.0
+    High,
+    /// 5 Gbps, USB 3.0
+    Super,
+    /// 10 or 20 Gbps, USB 3.1 and 3.2
+    SuperPlus,
+}
+
+impl UsbSpeed {
+    /// Parse the speed attribute of a USB device in sysfs, which is in Mbps
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn from_sysfs(speed: &str) -> Option<UsbSpeed> {
+        match speed.trim() {
+            "1.5" => Some(UsbSpeed::Low),
+            "12" => Some(UsbSpeed::Full),
+            "480" => Some(UsbSpeed::High),
+            "5000" => Some(UsbSpeed::Super),
+            "10000" | "20000" => Some(UsbSpeed::SuperPlus),
+            _ => None,
+        }
+    }
+}
+
+/// The class, subclass and protocol codes of a USB interface, IE: 02/02/01 for a CDC-ACM modem or
+/// ff/ff/ff for a vendor specific interface
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "napi", napi_derive::napi(object))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UsbClass {
+    /// bInterfaceClass
+    pub class: u8,
+    /// bInterfaceSubClass
+    pub subclass: u8,
+    /// bInterfaceProtocol
+    pub protocol: u8,
+}
+
+impl UsbClass {
+    /// A CDC Abstract Control Model interface, which the usual USB modems and the serial consoles
+    /// of microcontrollers are
+    pub fn is_cdc_acm(&self) -> bool {
+        self.class == 0x02 && self.subclass == 0x02
+    }
+
+    /// An interface the vendor defines, which only its own driver knows how to use
+    pub fn is_vendor_specific(&self) -> bool {
+        self.class == 0xff
+    }
+
+    /// Parse the codes from hex, with or without a 0x prefix
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        windows
+    ))]
+    pub(crate) fn from_hex(class: &str, subclass: &str, protocol: &str) -> Option<UsbClass> {
+        let code = |hex: &str| crate::id::parse_hex(hex).and_then(|code| u8::try_from(code).ok());
+        Some(UsbClass {
+            class: code(class)?,
+            subclass: code(subclass)?,
+            protocol: code(protocol)?,
+        })
+    }
 }
 
 /// A USB Add or Remove event has occured
 #[derive(Debug, Copy, Clone)]
 #[cfg_attr(feature = "napi", napi_derive::napi(string_enum))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EventType {
     /// A USB serial port device has been plugged into the system
     Add,
     /// A USB serial port device has been unplugged from the system
     Remove,
+    /// The properties of a connected device have changed, IE: its ownership or permissions, or
+    /// after ModemManager probed it. Only reported when enabled with
+    /// [`crate::ListenBuilder::changes`], and only by the Linux backend
+    Change,
+    /// A device came back at another port soon after it was removed, IE: ttyUSB0 became ttyUSB1
+    /// when it dropped off the bus for a moment. Replaces the Remove of the old port and the Add of
+    /// the new one, which [`EventInfo::old_port`] names. Only reported when enabled with
+    /// [`crate::ListenBuilder::moves`]
+    Moved,
 }
 
 /// Extra data appended to the event
 #[derive(Debug, Clone)]
-#[cfg_attr(feature = "napi", napi_derive::napi(object))]
+#[cfg_attr(feature = "napi", napi_derive::napi(object, object_from_js = false))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EventInfo {
     /// Meta data about the port. See [`DeviceInfo`]
     pub device: DeviceInfo,
     /// See [`EventType`]
     pub event: EventType,
+    /// When the listener received the notification from the OS. See [`Timestamp`]
+    pub timestamp: Timestamp,
+    /// Counts the events of a listener, starting from 0. Each event is numbered one more than the
+    /// last, so a gap means that events were lost
+    pub seq: u64,
+    /// The port the device was at before a [`EventType::Moved`] event. [`DeviceInfo::port`] is the
+    /// port it moved to. None for every other event
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub old_port: Option<String>,
+}
+
+impl EventInfo {
+    /// An event received now. The sequence number is assigned when the event is yielded
+    pub(crate) fn new(device: DeviceInfo, event: EventType) -> EventInfo {
+        EventInfo {
+            device,
+            event,
+            timestamp: Timestamp::now(),
+            seq: 0,
+            old_port: None,
+        }
+    }
+
+    /// A Moved event as the Remove of the old port and the Add of the new one, for the consumers
+    /// which follow ports rather than devices. Any other event is returned as it is
+    pub(crate) fn split_move(self) -> Vec<EventInfo> {
+        match (self.event, &self.old_port) {
+            (EventType::Moved, Some(old_port)) => {
+                let removed = DeviceInfo {
+                    port: old_port.clone(),
+                    ..self.device.clone()
+                };
+                vec![
+                    EventInfo {
+                        device: removed,
+                        event: EventType::Remove,
+                        old_port: None,
+                        ..self.clone()
+                    },
+                    EventInfo {
+                        event: EventType::Add,
+                        old_port: None,
+                        ..self
+                    },
+                ]
+            }
+            _ => vec![self],
+        }
+    }
+}
+
+/// When an event was received, on both the monotonic and the wall clock. The monotonic clock is
+/// for measuring intervals between events, the wall clock is for logging them
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Timestamp {
+    /// Monotonic time, unaffected by changes to the system clock
+    pub instant: Instant,
+    /// Wall clock time
+    pub system: SystemTime,
+}
+
+impl Timestamp {
+    /// The current time
+    pub fn now() -> Timestamp {
+        Timestamp {
+            instant: Instant::now(),
+            system: SystemTime::now(),
+        }
+    }
+}
+
+/// A monotonic clock cannot be sent to another process, so we serialize the wall clock as
+/// milliseconds since the unix epoch, like the napi binding. A deserialized timestamp is placed on
+/// the monotonic clock by how long ago it was
+#[cfg(feature = "serde")]
+mod serde_timestamp {
+    use super::Timestamp;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::{Duration, Instant, UNIX_EPOCH};
+
+    impl Serialize for Timestamp {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let millis = self
+                .system
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |since| since.as_secs_f64() * 1000.0);
+            serializer.serialize_f64(millis)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Timestamp {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let millis = f64::deserialize(deserializer)?;
+            let since = Duration::try_from_secs_f64(millis / 1000.0).map_err(D::Error::custom)?;
+            let system = UNIX_EPOCH + since;
+            let ago = system.elapsed().unwrap_or_default();
+            let instant = Instant::now().checked_sub(ago).unwrap_or_else(Instant::now);
+            Ok(Timestamp { instant, system })
+        }
+    }
+}
+
+/// Javascript has no monotonic clock to convert to, so we pass the wall clock as milliseconds since
+/// the unix epoch, like `Date.now()`
+#[cfg(feature = "napi")]
+mod napi_timestamp {
+    use super::Timestamp;
+    use napi::{bindgen_prelude::*, sys};
+    use std::time::UNIX_EPOCH;
+
+    impl TypeName for Timestamp {
+        fn type_name() -> &'static str {
+            "number"
+        }
+
+        fn value_type() -> ValueType {
+            ValueType::Number
+        }
+    }
+
+    impl ToNapiValue for Timestamp {
+        unsafe fn to_napi_value(env: sys::napi_env, val: Self) -> Result<sys::napi_value> {
+            let millis = val
+                .system
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |since| since.as_secs_f64() * 1000.0);
+            f64::to_napi_value(env, millis)
+        }
+    }
 }
 
+/// The AbortHandle will cause the [`EventIter`] to stop emitting events when dropped
+///
+/// Dropping signals the listener thread to stop and returns right away, the thread is joined in the
+/// background. Use [`AbortHandle::shutdown`] to wait for it
+pub struct AbortHandle {
+    inner: Option<Backend>,
+    queue: Arc<Queue>,
+}
+
+/// The listener which is stopped when the [`AbortHandle`] is dropped
+#[derive(Debug)]
+enum Backend {
+    Native(crate::native::AbortHandle),
+    Polling(crate::poll::AbortHandle),
+    #[cfg(feature = "replay")]
+    Replay(crate::replay::AbortHandle),
+    // Only held so that the mock stream ends when we are dropped
+    #[cfg(feature = "mock")]
+    #[allow(dead_code)]
+    Mock(crate::mock::AbortHandle),
+}
+
+impl AbortHandle {
+    pub(crate) fn native(handle: crate::native::AbortHandle, queue: &Arc<Queue>) -> AbortHandle {
+        AbortHandle {
+            inner: Some(Backend::Native(handle)),
+            queue: Arc::clone(queue),
+        }
+    }
+
+    pub(crate) fn polling(handle: crate::poll::AbortHandle, queue: &Arc<Queue>) -> AbortHandle {
+        AbortHandle {
+            inner: Some(Backend::Polling(handle)),
+            queue: Arc::clone(queue),
+        }
+    }
+
+    #[cfg(feature = "replay")]
+    pub(crate) fn replay(handle: crate::replay::AbortHandle, queue: &Arc<Queue>) -> AbortHandle {
+        AbortHandle {
+            inner: Some(Backend::Replay(handle)),
+            queue: Arc::clone(queue),
+        }
+    }
+
+    #[cfg(feature = "mock")]
+    pub(crate) fn mock(handle: crate::mock::AbortHandle, queue: &Arc<Queue>) -> AbortHandle {
+        AbortHandle {
+            inner: Some(Backend::Mock(handle)),
+            queue: Arc::clone(queue),
+        }
+    }
+
+    /// Stop receiving events without stopping the listener. Events which happen while paused are
+    /// discarded, the ones already queued are still yielded
+    ///
+    /// Call [`crate::scan`] after [`AbortHandle::resume`] to learn what changed in between
+    ///
+    /// ```no_run
+    /// let (abort, events) = serialport_detect::listen()?;
+    /// abort.pause();
+    /// // flash the device, which re-enumerates a few times
+    /// abort.resume();
+    /// let devices = serialport_detect::scan()?;
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    pub fn pause(&self) {
+        self.queue.pause(true);
+    }
+
+    /// Receive events again after [`AbortHandle::pause`]
+    pub fn resume(&self) {
+        self.queue.pause(false);
+    }
+
+    /// Change which devices are reported, without restarting the listener. IE: when the user
+    /// edits the list of devices an application works with
+    ///
+    /// The events still queued are filtered again, the ones of devices which no longer match are
+    /// dropped. A device which is already connected is not reported when it starts matching,
+    /// [`EventIter::snapshot`] returns it
+    ///
+    /// ```no_run
+    /// use serialport_detect::Filter;
+    ///
+    /// let (abort, events) = serialport_detect::builder().vid(0x0403).listen()?;
+    /// abort.set_filter(Filter::new().vid(0x0403).pid(0x6015));
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    pub fn set_filter(&self, filter: Filter) {
+        self.queue.set_filter(filter);
+    }
+
+    /// True between [`AbortHandle::pause`] and [`AbortHandle::resume`]
+    pub fn is_paused(&self) -> bool {
+        self.queue.paused()
+    }
+
+    /// False once the listener thread has exited, IE: it failed and gave up, or it panicked. The
+    /// [`EventIter`] then ends after the events already queued
+    ///
+    /// ```no_run
+    /// let (abort, events) = serialport_detect::listen()?;
+    /// if !abort.is_alive() {
+    ///     eprintln!("device listener died");
+    /// }
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    pub fn is_alive(&self) -> bool {
+        match &self.inner {
+            Some(Backend::Native(handle)) => handle.is_alive(),
+            Some(Backend::Polling(handle)) => handle.is_alive(),
+            #[cfg(feature = "replay")]
+            Some(Backend::Replay(handle)) => handle.is_alive(),
+            #[cfg(feature = "mock")]
+            Some(Backend::Mock(_)) => true,
+            None => false,
+        }
+    }
+
+    /// How the listener detects devices. IE: [`ListenMode::Sysfs`] when udev is not running, or
+    /// [`ListenMode::Polling`] when the native backend failed to start
+    ///
+    /// ```no_run
+    /// let (abort, events) = serialport_detect::listen()?;
+    /// println!("listening with {:?}", abort.mode());
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    pub fn mode(&self) -> ListenMode {
+        match &self.inner {
+            #[cfg(target_os = "linux")]
+            Some(Backend::Native(handle)) => handle.mode(),
+            #[cfg(target_os = "android")]
+            Some(Backend::Native(_)) => ListenMode::Sysfs,
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Some(Backend::Native(_)) => ListenMode::Native,
+            Some(Backend::Polling(_)) => ListenMode::Polling,
+            // Only while we are dropped
+            None => ListenMode::Native,
+            #[cfg(feature = "replay")]
+            Some(Backend::Replay(_)) => ListenMode::Replay,
+            #[cfg(feature = "mock")]
+            Some(Backend::Mock(_)) => ListenMode::Mock,
+        }
+    }
+
+    /// Cancel [`EventIter`] and no longer listen to Device Connect and Disconnect events. Does not
+    /// wait for the listener thread to exit
+    pub fn abort(self) {}
+
+    /// Cancel [`EventIter`] and block until the listener thread has exited
+    pub fn shutdown(mut self) {
+        drop(self.inner.take());
+    }
+
+    /// Like [`AbortHandle::shutdown`], without blocking the caller. The returned future resolves
+    /// once the listener thread has exited
+    ///
+    /// ```no_run
+    /// # async fn run() -> serialport_detect::Result<()> {
+    /// let (abort, events) = serialport_detect::listen()?;
+    /// abort.abort_async().await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn abort_async(mut self) -> impl Future<Output = ()> + Send + 'static {
+        let (tx, rx) = timer::notify();
+        let backend = self.inner.take();
+        let spawned = std::thread::Builder::new()
+            .name("serialport-detect-abort".into())
+            .spawn(move || {
+                drop(backend);
+                drop(tx);
+            })
+            .is_ok();
+        async move {
+            // If the thread could not be spawned the listener was shut down here already
+            if spawned {
+                rx.await;
+            }
+        }
+    }
+}
+
+impl Debug for AbortHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AbortHandle")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl Drop for AbortHandle {
+    // Stopping a listener joins its thread, which may take a while (IE: a stuck window message
+    // loop). We hand it to a detached thread instead
+    fn drop(&mut self) {
+        if let Some(backend) = self.inner.take() {
+            let spawned = std::thread::Builder::new()
+                .name("serialport-detect-abort".into())
+                .spawn(move || drop(backend));
+            if let Err(error) = spawned {
+                tracing::error!(?error, "failed to detach listener shutdown");
+            }
+        }
+    }
+}
+
+/// The span of a listener thread, named after its `backend` (IE: udev). Each listener gets an id
+/// of its own, so that the traces of several listeners in one process can be told apart
+pub(crate) fn listener_span(backend: &'static str) -> tracing::Span {
+    static LISTENERS: AtomicU64 = AtomicU64::new(0);
+    let id = LISTENERS.fetch_add(1, Ordering::Relaxed);
+    tracing::info_span!("listener", id, backend)
+}
+
+/// Scans the devices a queue reports
+pub(crate) type Scan = Box<dyn Fn() -> Result<HashMap<String, DeviceInfo>> + Send + Sync>;
+
 #[derive(Default)]
 pub(crate) struct Queue {
-    inner: SegQueue<Option<io::Result<EventInfo>>>,
+    inner: SegQueue<Item>,
     waker: Mutex<Option<Waker>>,
+    /// Changed while listening by [`AbortHandle::set_filter`]
+    filter: Mutex<Filter>,
+    changes: bool,
+    /// Check whether the devices of Add and Change events are accessible
+    access: bool,
+    /// And whether they are in use. See [`crate::ListenBuilder::check_in_use`]
+    in_use: bool,
+    capacity: Option<usize>,
+    overflow: Overflow,
+    retry: Option<Retry>,
+    ready: Option<Duration>,
+    all_ports: bool,
+    windows_backend: WindowsBackend,
+    udev: UdevMatch,
+    /// Rescan when the system resumes from suspend. See [`crate::ListenBuilder::rescan_on_resume`]
+    rescan_on_resume: bool,
+    /// Adds held back until their device is ready, by port name. See
+    /// [`crate::ListenBuilder::ready`]
+    held: Mutex<HashMap<String, u64>>,
+    #[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+    held_id: AtomicU64,
+    paused: AtomicBool,
+    /// Set once the consumer has seen the end of the stream, which is final
+    finished: AtomicBool,
+    debounce: Option<Duration>,
+    /// How long a Remove is held back, waiting for its device to come back at another port. See
+    /// [`crate::ListenBuilder::moves`]
+    moves: Option<Duration>,
+    bounces: Mutex<Bounces>,
+    /// Events dropped since the last [`Error::Overflow`] was yielded
+    lost: AtomicU64,
+    /// See [`crate::ListenBuilder::rate_limit`]
+    throttle: Option<Mutex<Throttle>>,
+    /// Events over the rate limit since the last [`Error::Throttled`] was yielded
+    over_limit: AtomicU64,
+    /// Events dropped with [`Overflow::DropNewest`] since the last event was pushed
+    skipped: AtomicU64,
+    known: Mutex<Option<Known>>,
+    /// The devices present, once a snapshot has been taken, whether they match the filter or not
+    /// as it may change. See [`EventIter::snapshot`]
+    devices: Mutex<Option<HashMap<String, DeviceInfo>>>,
+    /// Finds the devices present when the first snapshot is taken
+    scan: Option<Scan>,
+    seq: AtomicU64,
+    pushed: AtomicU64,
+    dropped: AtomicU64,
+    throttled: AtomicU64,
+    high_water: AtomicUsize,
+    metrics: Option<Arc<dyn Metrics>>,
+    /// Appended every event the consumer is given. See [`crate::ListenBuilder::journal`]
+    #[cfg(feature = "journal")]
+    journal: Option<Arc<Mutex<Journal>>>,
+}
+
+/// Counters of the event queue, to tell when the consumer is falling behind. See
+/// [`EventIter::stats`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Events waiting for the consumer
+    pub len: usize,
+    /// The most events that have been waiting at once
+    pub high_water: usize,
+    /// Events queued since the listener started. Events of filtered out devices are not counted
+    pub pushed: u64,
+    /// Events dropped because the queue was full. See [`Overflow`]
+    pub dropped: u64,
+    /// Events dropped because they were over the rate limit. See [`crate::RateLimit`]
+    pub throttled: u64,
+}
+
+// Nearly every item is an event, so boxing them would only add an allocation
+#[allow(clippy::large_enum_variant)]
+enum Item {
+    Event(Result<EventInfo>),
+    /// Marks where events were dropped with [`Overflow::Error`]
+    Lost,
+    /// Marks where events over the rate limit were dropped
+    Throttled,
+    /// Marks where events were dropped with [`Overflow::DropNewest`], whose sequence numbers are
+    /// skipped
+    Skip(u64),
+    Done,
+}
+
+/// What to do with a new event when the queue of a slow consumer is full. See
+/// [`crate::ListenBuilder::capacity`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Make room by dropping the oldest event in the queue
+    #[default]
+    DropOldest,
+    /// Drop the new event
+    DropNewest,
+    /// Drop the new event, and yield an [`Error::Overflow`] in its place which counts the events
+    /// dropped
+    Error,
+}
+
+/// How the Windows listener is notified of devices. See [`crate::ListenBuilder::windows_backend`]
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum WindowsBackend {
+    /// WM_DEVICECHANGE messages to a message-only window, with a thread to pump its messages
+    #[default]
+    Window,
+    /// CM_Register_Notification callbacks on the thread pool, which need no window or message
+    /// loop. Works from services and non-interactive sessions. Needs Windows 8 or later
+    ConfigManager,
+}
+
+/// How a listener detects devices. See [`AbortHandle::mode`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ListenMode {
+    /// The notifications of the OS, IE: the udev monitor on Linux
+    Native,
+    /// An inotify watch on /dev, reading the devices from sysfs. Used on Android, and on Linux
+    /// when udev is not running (IE: in a container)
+    Sysfs,
+    /// Scanning periodically, see [`crate::listen_polling`]
+    Polling,
+    /// Playing back a recording, see [`crate::ListenBuilder::replay`]
+    #[cfg(feature = "replay")]
+    Replay,
+    /// The events of a [`crate::MockBackend`]
+    #[cfg(feature = "mock")]
+    Mock,
+}
+
+/// Which udev devices the Linux backend reports. See [`crate::ListenBuilder::subsystems`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct UdevMatch {
+    /// A device of any of these subsystems
+    pub(crate) subsystems: Vec<String>,
+    /// With every one of these properties
+    pub(crate) properties: Vec<(String, String)>,
+}
+
+impl Default for UdevMatch {
+    fn default() -> UdevMatch {
+        UdevMatch {
+            subsystems: vec!["tty".to_string()],
+            properties: Vec::new(),
+        }
+    }
+}
+
+/// When the stream starts with the devices already present, we track what the consumer has been
+/// told so that the live events which raced the initial scan are not reported twice
+struct Known {
+    /// Synthetic Add events, yielded before any live event
+    pending: VecDeque<EventInfo>,
+    devices: HashMap<String, DeviceInfo>,
+}
+
+impl Known {
+    /// Returns false if the event repeats what we already reported
+    fn admit(&mut self, event: &EventInfo) -> bool {
+        let port = &event.device.port;
+        match event.event {
+            EventType::Add if self.devices.get(port) == Some(&event.device) => false,
+            EventType::Add => {
+                self.devices.insert(port.clone(), event.device.clone());
+                true
+            }
+            EventType::Remove => self.devices.remove(port).is_some(),
+            EventType::Change => match self.devices.get_mut(port) {
+                Some(device) => {
+                    *device = event.device.clone();
+                    true
+                }
+                None => false,
+            },
+            EventType::Moved => {
+                if let Some(old_port) = &event.old_port {
+                    self.devices.remove(old_port);
+                }
+                self.devices.insert(port.clone(), event.device.clone());
+                true
+            }
+        }
+    }
+}
+
+/// Adds and Removes held back by the consumer until their port has been stable for the debounce
+/// window, and Removes held back until we know that their device did not move to another port.
+/// See [`crate::ListenBuilder::debounce`] and [`crate::ListenBuilder::moves`]
+#[derive(Default)]
+struct Bounces {
+    /// In the order they were received, with when they may be reported
+    held: VecDeque<(Instant, EventInfo)>,
+    /// Wakes the consumer when the oldest event is due
+    timer: Option<Timer>,
+    /// Whether the Add of a device whose Remove is held at another port makes it a Moved
+    moves: bool,
+}
+
+impl Bounces {
+    /// Hold back `event` until `due`. An Add and a Remove of the same device on the same port
+    /// cancel out, along with anything held in between
+    fn hold(&mut self, event: EventInfo, due: Instant) {
+        let port = &event.device.port;
+        // An Add may be followed by Changes of the device before it is removed again
+        let last = self.held.iter().rposition(|(_, held)| {
+            &held.device.port == port && !matches!(held.event, EventType::Change)
+        });
+        if let Some(last) = last {
+            let held = &self.held[last].1;
+            let opposite = matches!(
+                (held.event, event.event),
+                (EventType::Add, EventType::Remove) | (EventType::Remove, EventType::Add)
+            );
+            if opposite && same_device(&held.device, &event.device) {
+                let mut index = 0;
+                self.held.retain(|(_, held)| {
+                    index += 1;
+                    index <= last || &held.device.port != port
+                });
+                return;
+            }
+        }
+        if let Some(index) = self.moved_from(&event) {
+            // The Moved takes the place of the Remove, and is due as soon as either of them
+            let (held_due, removed) = &mut self.held[index];
+            let old_port = std::mem::take(&mut removed.device.port);
+            *held_due = due.min(*held_due);
+            *removed = EventInfo {
+                event: EventType::Moved,
+                old_port: Some(old_port),
+                ..event
+            };
+            return;
+        }
+        self.held.push_back((due, event));
+    }
+
+    fn holds(&self, port: &str) -> bool {
+        self.held.iter().any(|(_, held)| held.device.port == port)
+    }
+
+    /// Where the Remove of the device of an Add is held, when the device was removed from another
+    /// port. Only a device which we can tell apart from others of its kind, by its serial number or
+    /// stable id, is followed. Nothing held for the new port may be reported after the Moved
+    fn moved_from(&self, event: &EventInfo) -> Option<usize> {
+        let device = &event.device;
+        let traceable = device.serial.is_some() || device.stable_id.is_some();
+        if !self.moves || !traceable || !matches!(event.event, EventType::Add) {
+            return None;
+        }
+        let index = self.held.iter().rposition(|(_, held)| {
+            matches!(held.event, EventType::Remove)
+                && held.device.port != device.port
+                && same_device(&held.device, device)
+        })?;
+        let mut behind = self.held.iter().skip(index + 1);
+        match behind.any(|(_, held)| held.device.port == device.port) {
+            true => None,
+            false => Some(index),
+        }
+    }
+
+    /// The oldest held event once it is due
+    fn poll_due(&mut self, cx: &mut Context<'_>) -> Poll<EventInfo> {
+        loop {
+            let Some(&(due, _)) = self.held.front() else {
+                self.timer = None;
+                return Poll::Pending;
+            };
+            let now = Instant::now();
+            if due <= now {
+                self.timer = None;
+                return self
+                    .held
+                    .pop_front()
+                    .map_or(Poll::Pending, |(_, e)| Poll::Ready(e));
+            }
+            // A timer armed for an event which was cancelled fires early, and is armed again
+            let timer = self.timer.get_or_insert_with(|| Timer::after(due - now));
+            match Pin::new(timer).poll(cx) {
+                Poll::Ready(()) => self.timer = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Whether a bounce reported the same device again
+fn same_device(a: &DeviceInfo, b: &DeviceInfo) -> bool {
+    a.vid == b.vid && a.pid == b.pid && a.serial == b.serial && a.stable_id == b.stable_id
 }
 
 impl Queue {
-    pub(crate) fn new() -> Queue {
+    /// A queue which drops the events of devices not matching `filter`
+    pub(crate) fn with_filter(filter: Filter) -> Queue {
         Queue {
             inner: SegQueue::new(),
             waker: Mutex::new(None),
+            filter: Mutex::new(filter),
+            changes: false,
+            access: false,
+            in_use: false,
+            capacity: None,
+            overflow: Overflow::default(),
+            retry: None,
+            ready: None,
+            all_ports: false,
+            windows_backend: WindowsBackend::default(),
+            udev: UdevMatch::default(),
+            rescan_on_resume: false,
+            held: Mutex::new(HashMap::new()),
+            held_id: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            debounce: None,
+            moves: None,
+            bounces: Mutex::new(Bounces::default()),
+            lost: AtomicU64::new(0),
+            throttle: None,
+            over_limit: AtomicU64::new(0),
+            skipped: AtomicU64::new(0),
+            known: Mutex::new(None),
+            devices: Mutex::new(None),
+            scan: None,
+            seq: AtomicU64::new(0),
+            pushed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            throttled: AtomicU64::new(0),
+            high_water: AtomicUsize::new(0),
+            metrics: None,
+            #[cfg(feature = "journal")]
+            journal: None,
+        }
+    }
+
+    /// Check whether the devices reported by the listener are accessible, unlike the made up ones
+    /// of a mock or a replay. See [`DeviceInfo::accessible`]
+    pub(crate) fn with_access(mut self, access: bool) -> Queue {
+        self.access = access;
+        self
+    }
+
+    /// Also check whether the devices reported by the listener are in use, when they are checked
+    /// at all. See [`DeviceInfo::in_use`]
+    pub(crate) fn with_in_use(mut self, in_use: bool) -> Queue {
+        self.in_use = in_use;
+        self
+    }
+
+    /// Report Change events, which are dropped otherwise
+    pub(crate) fn with_changes(mut self, changes: bool) -> Queue {
+        self.changes = changes;
+        self
+    }
+
+    /// Hold at most `capacity` events, and then make room as `overflow` says
+    pub(crate) fn with_capacity(mut self, capacity: Option<usize>, overflow: Overflow) -> Queue {
+        self.capacity = capacity;
+        self.overflow = overflow;
+        self
+    }
+
+    /// Restart the listener as `retry` says when it fails
+    pub(crate) fn with_retry(mut self, retry: Option<Retry>) -> Queue {
+        self.retry = retry;
+        self
+    }
+
+    #[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+    pub(crate) fn retry(&self) -> Option<Retry> {
+        self.retry
+    }
+
+    /// Hold back Adds until the device is ready, for at most `grace`
+    pub(crate) fn with_ready(mut self, grace: Option<Duration>) -> Queue {
+        self.ready = grace;
+        self
+    }
+
+    #[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+    pub(crate) fn ready(&self) -> Option<Duration> {
+        self.ready
+    }
+
+    /// Report serial ports of every kind, not only USB devices
+    pub(crate) fn with_all_ports(mut self, all_ports: bool) -> Queue {
+        self.all_ports = all_ports;
+        self
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub(crate) fn all_ports(&self) -> bool {
+        self.all_ports
+    }
+
+    /// Be notified of devices by `backend` on Windows
+    pub(crate) fn with_windows_backend(mut self, backend: WindowsBackend) -> Queue {
+        self.windows_backend = backend;
+        self
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    pub(crate) fn windows_backend(&self) -> WindowsBackend {
+        self.windows_backend
+    }
+
+    /// Rescan when the system resumes from suspend, where the listener is not told of it otherwise
+    pub(crate) fn with_rescan_on_resume(mut self, rescan: bool) -> Queue {
+        self.rescan_on_resume = rescan;
+        self
+    }
+
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub(crate) fn rescan_on_resume(&self) -> bool {
+        self.rescan_on_resume
+    }
+
+    /// Append every event the consumer is given to `journal`
+    #[cfg(feature = "journal")]
+    pub(crate) fn with_journal(mut self, journal: Option<Arc<Mutex<Journal>>>) -> Queue {
+        self.journal = journal;
+        self
+    }
+
+    /// Drop the events over `limit`
+    pub(crate) fn with_rate_limit(mut self, limit: Option<RateLimit>) -> Queue {
+        self.throttle = limit.map(|limit| Mutex::new(Throttle::new(limit)));
+        self
+    }
+
+    /// Report the counters of the queue to `metrics`
+    pub(crate) fn with_metrics(mut self, metrics: Option<Arc<dyn Metrics>>) -> Queue {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Report the udev devices matching `udev` on Linux
+    pub(crate) fn with_udev(mut self, udev: UdevMatch) -> Queue {
+        self.udev = udev;
+        self
+    }
+
+    #[cfg_attr(
+        not(all(target_os = "linux", feature = "udev", not(feature = "netlink"))),
+        allow(dead_code)
+    )]
+    pub(crate) fn udev(&self) -> &UdevMatch {
+        &self.udev
+    }
+
+    /// Hold back the Add of `port`. A Remove of the port cancels the Add, and is dropped as well
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn hold(&self, port: &str) -> u64 {
+        let id = self.held_id.fetch_add(1, Ordering::Relaxed);
+        self.held.lock().insert(port.to_string(), id);
+        id
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn is_held(&self, port: &str, id: u64) -> bool {
+        self.held.lock().get(port) == Some(&id)
+    }
+
+    /// Push an Add which was held back, unless its device was removed meanwhile
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) fn release(&self, id: u64, event: EventInfo) {
+        let mut held = self.held.lock();
+        if held.get(&event.device.port) == Some(&id) {
+            held.remove(&event.device.port);
+            // Pushed with the lock held, so that a Remove cannot overtake it
+            self.push(Ok(event));
+        }
+    }
+
+    /// Only report an Add or a Remove once its port has been stable for `window`
+    pub(crate) fn with_debounce(mut self, window: Option<Duration>) -> Queue {
+        self.debounce = window;
+        self
+    }
+
+    /// Report a device which is removed and comes back at another port within `window` as Moved
+    pub(crate) fn with_moves(mut self, window: Option<Duration>) -> Queue {
+        self.moves = window;
+        self.bounces.get_mut().moves = window.is_some();
+        self
+    }
+
+    /// Find the devices present with `scan` when the first snapshot is taken. Without it the
+    /// snapshot starts out empty
+    pub(crate) fn with_scan(mut self, scan: Scan) -> Queue {
+        self.scan = Some(scan);
+        self
+    }
+
+    /// The devices present, as the events pushed since the first snapshot tell
+    pub(crate) fn snapshot(&self) -> Result<HashMap<String, DeviceInfo>> {
+        let mut devices = self.devices.lock();
+        let devices = match devices.as_mut() {
+            Some(devices) => devices,
+            // The listener waits for the lock to push, so no event is missed while we scan
+            None => {
+                let scanned = match &self.scan {
+                    Some(scan) => scan()?,
+                    None => HashMap::new(),
+                };
+                devices.insert(scanned)
+            }
+        };
+        let filter = self.filter.lock();
+        Ok(devices
+            .iter()
+            .filter(|(_, device)| filter.matches(device))
+            .map(|(port, device)| (port.clone(), device.clone()))
+            .collect())
+    }
+
+    /// Start the snapshot with `devices` instead of scanning for them
+    #[cfg(feature = "mock")]
+    pub(crate) fn start_snapshot(&self, devices: HashMap<String, DeviceInfo>) {
+        *self.devices.lock() = Some(devices);
+    }
+
+    /// Follow an event in the snapshot. Paused and dropped events are followed as well, as the
+    /// snapshot is what the listener knows rather than what the consumer was told
+    fn track(&self, event: &EventInfo) {
+        let mut devices = self.devices.lock();
+        let Some(devices) = devices.as_mut() else {
+            return;
+        };
+        match event.event {
+            EventType::Add | EventType::Change => {
+                devices.insert(event.device.port.clone(), event.device.clone());
+            }
+            EventType::Remove => {
+                devices.remove(&event.device.port);
+            }
+            EventType::Moved => {
+                if let Some(old_port) = &event.old_port {
+                    devices.remove(old_port);
+                }
+                devices.insert(event.device.port.clone(), event.device.clone());
+            }
         }
     }
 
+    /// Start the stream with an Add for each of `devices`. Must be called after the listener has
+    /// started and before the stream is polled
+    pub(crate) fn seed(&self, devices: HashMap<String, DeviceInfo>) {
+        let pending = devices
+            .values()
+            .map(|device| EventInfo::new(device.clone(), EventType::Add))
+            .collect();
+        *self.known.lock() = Some(Known { pending, devices });
+    }
+
+    pub(crate) fn pause(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub(crate) fn paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn matches(&self, device: &DeviceInfo) -> bool {
+        self.filter.lock().matches(device)
+    }
+
+    pub(crate) fn set_filter(&self, filter: Filter) {
+        *self.filter.lock() = filter;
+    }
+
     fn maybe_wake(&self) {
         if let Some(waker) = &self.waker.lock().as_ref() {
             waker.wake_by_ref();
         }
     }
 
-    pub(crate) fn push(&self, ev: io::Result<EventInfo>) {
-        self.inner.push(Some(ev));
+    /// Whether an event passes the filters, IE: it is not dropped by push
+    pub(crate) fn admits(&self, event: &EventInfo) -> bool {
+        let change = matches!(event.event, EventType::Change);
+        (self.changes || !change) && !self.paused() && self.matches(&event.device)
+    }
+
+    pub(crate) fn push(&self, mut ev: io::Result<EventInfo>) {
+        if let Ok(event) = &mut ev {
+            if self.access && !matches!(event.event, EventType::Remove) {
+                access::check(&mut event.device, self.in_use);
+            }
+            self.track(event);
+            if !self.admits(event) {
+                return;
+            }
+            if self.ready.is_some()
+                && matches!(event.event, EventType::Remove)
+                && self.held.lock().remove(&event.device.port).is_some()
+            {
+                // The consumer never saw the Add
+                return;
+            }
+            if self
+                .throttle
+                .as_ref()
+                .is_some_and(|throttle| !throttle.lock().admit(&event.device.port, Instant::now()))
+            {
+                self.throttled();
+                return;
+            }
+        }
+        if self
+            .capacity
+            .is_some_and(|capacity| self.inner.len() >= capacity)
+        {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            if let Some(metrics) = &self.metrics {
+                metrics.events_dropped(1);
+            }
+            // The sequence number of each event we drop is skipped where it was dropped, so the
+            // consumer sees the gap there
+            match self.overflow {
+                Overflow::DropOldest => {
+                    // The next event yielded is the one after it
+                    self.seq.fetch_add(1, Ordering::Relaxed);
+                    self.drop_oldest();
+                }
+                Overflow::DropNewest => {
+                    self.skipped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Overflow::Error => {
+                    if self.lost.fetch_add(1, Ordering::Relaxed) == 0 {
+                        self.inner.push(Item::Lost);
+                        self.maybe_wake();
+                    }
+                    return;
+                }
+            }
+        }
+        // Errors are not numbered, the gap is left in front of the next event
+        if ev.is_ok() {
+            let skipped = self.skipped.swap(0, Ordering::Relaxed);
+            if skipped > 0 {
+                self.inner.push(Item::Skip(skipped));
+            }
+        }
+        let ev = ev.map_err(Error::from);
+        if let Some(metrics) = &self.metrics {
+            match &ev {
+                Ok(event) => metrics.events_emitted(event),
+                Err(error) => metrics.errors(error),
+            }
+        }
+        self.inner.push(Item::Event(ev));
+        self.pushed.fetch_add(1, Ordering::Relaxed);
+        self.high_water
+            .fetch_max(self.inner.len(), Ordering::Relaxed);
+        self.report_depth();
+        self.maybe_wake();
+    }
+
+    /// Make room by dropping the oldest event. The markers in front of it are kept, behind the
+    /// events left, so that the consumer is still told of the drops and of the end of the stream
+    fn drop_oldest(&self) {
+        let mut markers = Vec::new();
+        while let Some(item) = self.inner.pop() {
+            match item {
+                Item::Event(_) => break,
+                marker => markers.push(marker),
+            }
+        }
+        markers
+            .into_iter()
+            .for_each(|marker| self.inner.push(marker));
+    }
+
+    /// Drop an event over the rate limit, and tell the consumer once for the events dropped in a
+    /// row
+    fn throttled(&self) {
+        self.throttled.fetch_add(1, Ordering::Relaxed);
+        if let Some(metrics) = &self.metrics {
+            metrics.events_throttled(1);
+        }
+        if self.over_limit.fetch_add(1, Ordering::Relaxed) == 0 {
+            self.inner.push(Item::Throttled);
+            self.maybe_wake();
+        }
+    }
+
+    /// Report an error which is not an I/O error
+    #[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+    pub(crate) fn error(&self, error: Error) {
+        if let Some(metrics) = &self.metrics {
+            metrics.errors(&error);
+        }
+        self.inner.push(Item::Event(Err(error)));
+        self.report_depth();
         self.maybe_wake();
     }
 
+    fn report_depth(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.queue_depth(self.inner.len());
+        }
+    }
+
     pub(crate) fn done(&self) {
-        self.inner.push(None);
+        self.inner.push(Item::Done);
         self.maybe_wake();
     }
 
-    pub(crate) fn poll_next(&self, cx: &mut Context<'_>) -> Poll<Option<io::Result<EventInfo>>> {
+    /// Hand the events which are ready to `accept`, until it has accepted `limit` of them. The
+    /// waker is registered and the locks are taken once for the whole batch. Pending when no event
+    /// was accepted, and Ready(0) once the stream has ended
+    pub(crate) fn poll_batch(
+        &self,
+        cx: &mut Context<'_>,
+        limit: usize,
+        mut accept: impl FnMut(Result<EventInfo>) -> bool,
+    ) -> Poll<usize> {
+        // Whatever is pushed after the end, IE: by a listener thread still winding down, is never
+        // yielded
+        if self.is_finished() {
+            return Poll::Ready(0);
+        }
         // Waker accounting
         let new_waker = cx.waker();
         let mut waker = self.waker.lock();
@@ -82,11 +1419,778 @@ impl Queue {
             Some(old_waker) if old_waker.will_wake(new_waker) => Some(old_waker),
             None | Some(_) => Some(new_waker.clone()),
         };
+        drop(waker);
+
+        let mut known = self.known.lock();
+        let mut bounces = self.bounces.lock();
+        let mut accepted = 0;
+        let poll = loop {
+            if accepted == limit {
+                break Poll::Ready(accepted);
+            }
+            match self.pop(cx, &mut known, &mut bounces) {
+                Poll::Ready(Some(event)) => accepted += usize::from(accept(event)),
+                Poll::Pending if accepted == 0 => break Poll::Pending,
+                Poll::Ready(None) | Poll::Pending => break Poll::Ready(accepted),
+            }
+        };
+        self.report_depth();
+        poll
+    }
+
+    fn pop(
+        &self,
+        cx: &mut Context<'_>,
+        known: &mut Option<Known>,
+        bounces: &mut Bounces,
+    ) -> Poll<Option<Result<EventInfo>>> {
+        if let Some(event) = known.as_mut().and_then(|known| known.pending.pop_front()) {
+            return Poll::Ready(Some(Ok(self.number(event))));
+        }
+        loop {
+            break match self.inner.pop() {
+                None => match bounces.poll_due(cx) {
+                    Poll::Ready(event) => Poll::Ready(Some(Ok(self.number(event)))),
+                    Poll::Pending => Poll::Pending,
+                },
+                Some(Item::Event(Ok(event)))
+                    if known.as_mut().is_some_and(|known| !known.admit(&event)) =>
+                {
+                    continue
+                }
+                Some(Item::Event(Ok(event))) if self.holds_back(&event, bounces) => {
+                    let window = match event.event {
+                        EventType::Remove => self.debounce.max(self.moves),
+                        _ => self.debounce,
+                    };
+                    bounces.hold(event, Instant::now() + window.unwrap_or_default());
+                    continue;
+                }
+                Some(Item::Event(Ok(event))) => Poll::Ready(Some(Ok(self.number(event)))),
+                Some(Item::Event(Err(error))) => Poll::Ready(Some(Err(error))),
+                // The sequence numbers of the events dropped are skipped, so the consumer sees
+                // the gap where they were dropped
+                Some(Item::Lost) => {
+                    let dropped = self.lost.swap(0, Ordering::Relaxed);
+                    self.seq.fetch_add(dropped, Ordering::Relaxed);
+                    Poll::Ready(Some(Err(Error::Overflow { dropped })))
+                }
+                Some(Item::Throttled) => {
+                    let dropped = self.over_limit.swap(0, Ordering::Relaxed);
+                    self.seq.fetch_add(dropped, Ordering::Relaxed);
+                    Poll::Ready(Some(Err(Error::Throttled { dropped })))
+                }
+                Some(Item::Skip(dropped)) => {
+                    self.seq.fetch_add(dropped, Ordering::Relaxed);
+                    continue;
+                }
+                // Whatever is still held is reported before the stream ends
+                Some(Item::Done) => match bounces.held.pop_front() {
+                    Some((_, event)) => {
+                        self.inner.push(Item::Done);
+                        Poll::Ready(Some(Ok(self.number(event))))
+                    }
+                    None => {
+                        self.finished.store(true, Ordering::Relaxed);
+                        Poll::Ready(None)
+                    }
+                },
+            };
+        }
+    }
+
+    /// Whether the consumer holds back an event, to debounce it or to see whether its device
+    /// moves to another port. An event is held behind the held events of its port as well, to keep
+    /// them in order
+    fn holds_back(&self, event: &EventInfo, bounces: &Bounces) -> bool {
+        let debounced = self.debounce.is_some() && !matches!(event.event, EventType::Change);
+        let moving = self.moves.is_some() && matches!(event.event, EventType::Remove);
+        debounced
+            || moving
+            || bounces.holds(&event.device.port)
+            || bounces.moved_from(event).is_some()
+    }
+
+    /// Whether the stream has ended
+    pub(crate) fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn stats(&self) -> Stats {
+        Stats {
+            len: self.inner.len(),
+            high_water: self.high_water.load(Ordering::Relaxed),
+            pushed: self.pushed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Events are numbered in the order the consumer sees them
+    fn number(&self, mut event: EventInfo) -> EventInfo {
+        event.seq = self.seq.fetch_add(1, Ordering::Relaxed);
+        #[cfg(feature = "journal")]
+        if let Some(journal) = &self.journal {
+            if let Err(error) = journal.lock().append(&event) {
+                tracing::warn!(?error, "failed to append to the journal");
+            }
+        }
+        event
+    }
+}
 
-        match self.inner.pop() {
-            None => Poll::Pending,
-            Some(Some(inner)) => Poll::Ready(Some(inner)),
-            Some(None) => Poll::Ready(None),
+/// An event emitter to listen for Usb Add Remove events
+pub struct EventIter {
+    pub(crate) queue: Arc<Queue>,
+    /// The filters of the stream adapters, on top of the filter of the queue
+    filters: Vec<Filter>,
+}
+
+impl EventIter {
+    pub(crate) fn new(queue: Arc<Queue>) -> EventIter {
+        EventIter {
+            queue,
+            filters: Vec::new(),
         }
     }
+
+    /// Only yield the events of the devices matching `filter`, on top of the filters of the
+    /// builder and of the other adapters. Errors are still yielded. For when the builder cannot be
+    /// used, IE: the stream of a shared listener handed out to several consumers
+    ///
+    /// ```no_run
+    /// use serialport_detect::{Filter, Match};
+    ///
+    /// let events = serialport_detect::subscribe()?
+    ///     .matching(Filter::new().product(Match::prefix("Arduino")));
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    pub fn matching(mut self, filter: Filter) -> EventIter {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Only yield the events of the devices with this USB vendor and product id. See
+    /// [`EventIter::matching`]
+    pub fn filter_vid_pid(self, vid: u16, pid: u16) -> EventIter {
+        self.matching(Filter::new().vid(vid).pid(pid))
+    }
+
+    /// Only yield the events of the devices whose serial number matches. See
+    /// [`EventIter::matching`]
+    pub fn filter_serial(self, serial: impl Into<Match>) -> EventIter {
+        self.matching(Filter::new().serial(serial))
+    }
+
+    /// Whether the events of the device are yielded
+    pub(crate) fn matches(&self, device: &DeviceInfo) -> bool {
+        self.queue.matches(device) && self.filters.iter().all(|filter| filter.matches(device))
+    }
+
+    /// Hand an event to `yield_event` unless its device is filtered out. Errors are never filtered
+    fn accept(
+        &self,
+        event: Result<EventInfo>,
+        yield_event: impl FnOnce(Result<EventInfo>),
+    ) -> bool {
+        match &event {
+            Ok(info) if !self.matches(&info.device) => false,
+            _ => {
+                yield_event(event);
+                true
+            }
+        }
+    }
+
+    /// Move the events which are ready into `buf`, at most `limit` of them, in one poll. The waker
+    /// is registered and the queue is locked once for the whole batch instead of once per event,
+    /// which helps when a storm of events arrives at once, IE: when a hub with many ports is
+    /// plugged in
+    ///
+    /// Pending until an event is ready. Ready with the number of events moved, which is 0 only
+    /// once the stream has ended or when `limit` is 0
+    pub fn poll_next_many(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut Vec<Result<EventInfo>>,
+        limit: usize,
+    ) -> Poll<usize> {
+        if limit == 0 {
+            return Poll::Ready(0);
+        }
+        self.queue.poll_batch(cx, limit, |event| {
+            self.accept(event, |event| buf.push(event))
+        })
+    }
+
+    /// Wait for events, and then move the ones which are ready into `buf`, at most `limit` of
+    /// them. Returns 0 once the stream has ended. See [`EventIter::poll_next_many`]
+    ///
+    /// ```no_run
+    /// # async fn run() -> serialport_detect::Result<()> {
+    /// let (abort, mut events) = serialport_detect::listen()?;
+    /// let mut batch = Vec::new();
+    /// while events.recv_many(&mut batch, 64).await > 0 {
+    ///     for event in batch.drain(..) {
+    ///         println!("{:?}", event?);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn recv_many(&mut self, buf: &mut Vec<Result<EventInfo>>, limit: usize) -> usize {
+        future::poll_fn(|cx| self.poll_next_many(cx, buf, limit)).await
+    }
+
+    /// Block the calling thread until the next event, for scripts and tests without an async
+    /// runtime. None once the stream has ended, and [`Error::Timeout`] when no event arrived
+    /// within `timeout`
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// let (abort, mut events) = serialport_detect::listen()?;
+    /// match events.next_timeout(Duration::from_secs(5)) {
+    ///     Ok(Some(event)) => println!("{:?} {}", event.event, event.device.port),
+    ///     Ok(None) => println!("the listener stopped"),
+    ///     Err(serialport_detect::Error::Timeout) => println!("nothing was plugged in"),
+    ///     Err(error) => return Err(error),
+    /// }
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    pub fn next_timeout(&mut self, timeout: Duration) -> Result<Option<EventInfo>> {
+        match self.wait(Some(Instant::now() + timeout)) {
+            Poll::Ready(next) => next.transpose(),
+            Poll::Pending => Err(Error::Timeout),
+        }
+    }
+
+    /// Block the calling thread until the next event, or until `deadline`. Pending once the
+    /// deadline has passed
+    fn wait(&mut self, deadline: Option<Instant>) -> Poll<Option<Result<EventInfo>>> {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(next) = self.poll_event(&mut cx) {
+                return Poll::Ready(next);
+            }
+            // The waker unparks us when an event is pushed. Wakeups may also be spurious
+            match deadline.map(|deadline| deadline.checked_duration_since(Instant::now())) {
+                None => thread::park(),
+                Some(Some(left)) if !left.is_zero() => thread::park_timeout(left),
+                Some(_) => return Poll::Pending,
+            }
+        }
+    }
+
+    fn poll_event(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<EventInfo>>> {
+        let mut next = None;
+        self.queue
+            .poll_batch(cx, 1, |event| {
+                self.accept(event, |event| next = Some(event))
+            })
+            .map(|_| next)
+    }
+
+    /// A snapshot of the counters of the event queue
+    pub fn stats(&self) -> Stats {
+        self.queue.stats()
+    }
+
+    /// The matching devices which are present, as far as the listener knows. The first call scans
+    /// for them, and from then on the listener keeps them up to date as events arrive, including
+    /// the events not yet yielded by the stream. Later calls do not scan again
+    ///
+    /// The devices of a replay are the ones added by the events played so far
+    ///
+    /// ```no_run
+    /// let (abort, events) = serialport_detect::listen()?;
+    /// for (port, device) in events.snapshot()? {
+    ///     println!("{port} {:?}", device.product);
+    /// }
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    pub fn snapshot(&self) -> Result<HashMap<String, DeviceInfo>> {
+        let mut devices = self.queue.snapshot()?;
+        devices.retain(|_, device| self.matches(device));
+        Ok(devices)
+    }
+}
+
+/// Wakes a thread blocked in [`EventIter::next_timeout`]
+struct Unpark(thread::Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+impl Debug for EventIter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventIter").finish()
+    }
+}
+
+/// The stream keeps yielding None once it has ended, so it is safe to poll in a `select!` loop
+#[cfg(feature = "futures")]
+impl FusedStream for EventIter {
+    fn is_terminated(&self) -> bool {
+        self.queue.is_finished()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl Stream for EventIter {
+    type Item = Result<EventInfo>;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_event(cx)
+    }
+}
+
+/// Block the calling thread for each event, without an async runtime
+///
+/// ```no_run
+/// let (abort, events) = serialport_detect::listen()?;
+/// for event in events {
+///     println!("{:?}", event?);
+/// }
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+impl IntoIterator for EventIter {
+    type Item = Result<EventInfo>;
+    type IntoIter = BlockingIter;
+    fn into_iter(self) -> BlockingIter {
+        BlockingIter(self)
+    }
+}
+
+/// An iterator which blocks the calling thread until each event. See [`EventIter::into_iter`]
+#[derive(Debug)]
+pub struct BlockingIter(EventIter);
+
+impl Iterator for BlockingIter {
+    type Item = Result<EventInfo>;
+    fn next(&mut self) -> Option<Result<EventInfo>> {
+        match self.0.wait(None) {
+            Poll::Ready(next) => next,
+            Poll::Pending => None,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// An event of a device which only has a port
+    pub(crate) fn event(port: &str, kind: EventType) -> EventInfo {
+        let device = DeviceInfo {
+            port: port.to_string(),
+            ..Default::default()
+        };
+        EventInfo::new(device, kind)
+    }
+
+    #[test]
+    fn test_cmp_ports() {
+        let mut ports = [
+            "COM10",
+            "COM2",
+            "/dev/ttyUSB10",
+            "/dev/ttyACM0",
+            "/dev/ttyUSB9",
+            "COM1",
+        ];
+        ports.sort_by(|a, b| cmp_ports(a, b));
+        let expect = [
+            "/dev/ttyACM0",
+            "/dev/ttyUSB9",
+            "/dev/ttyUSB10",
+            "COM1",
+            "COM2",
+            "COM10",
+        ];
+        assert_eq!(expect, ports);
+        assert!(cmp_ports("COM01", "COM2").is_lt());
+        assert!(cmp_ports("COM1", "COM1.1").is_lt());
+        assert!(cmp_ports("COM1", "COM1").is_eq());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_usb_class_from_hex() {
+        let acm = UsbClass::from_hex("02", "02", "0x01").unwrap();
+        assert!(acm.is_cdc_acm() && !acm.is_vendor_specific());
+        let vendor = UsbClass::from_hex("ff", "ff", "ff").unwrap();
+        assert!(vendor.is_vendor_specific() && !vendor.is_cdc_acm());
+        assert_eq!(UsbClass::from_hex("100", "00", "00"), None);
+        assert_eq!(UsbClass::from_hex("02", "", "00"), None);
+    }
+
+    #[test]
+    fn test_release() {
+        let device = |bcd_device| DeviceInfo {
+            bcd_device,
+            ..Default::default()
+        };
+        assert_eq!(device(Some(0x0600)).release().as_deref(), Some("6.00"));
+        assert_eq!(device(Some(0x1012)).release().as_deref(), Some("10.12"));
+        assert_eq!(device(None).release(), None);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn test_usb_speed_from_sysfs() {
+        assert_eq!(UsbSpeed::from_sysfs("1.5"), Some(UsbSpeed::Low));
+        assert_eq!(UsbSpeed::from_sysfs("480\n"), Some(UsbSpeed::High));
+        assert_eq!(UsbSpeed::from_sysfs("20000"), Some(UsbSpeed::SuperPlus));
+        assert_eq!(UsbSpeed::from_sysfs("53.3"), None);
+    }
+
+    #[test]
+    fn test_known_drops_repeats() {
+        let device = |port: &str| DeviceInfo {
+            port: port.to_string(),
+            ..Default::default()
+        };
+        let mut known = Known {
+            pending: VecDeque::new(),
+            devices: HashMap::from([("a".to_string(), device("a"))]),
+        };
+        // Raced the initial scan
+        assert!(!known.admit(&event("a", EventType::Add)));
+        // Removed before the initial scan
+        assert!(!known.admit(&event("b", EventType::Remove)));
+        assert!(known.admit(&event("a", EventType::Remove)));
+        assert!(known.admit(&event("a", EventType::Add)));
+        assert!(known.admit(&event("c", EventType::Add)));
+        assert!(known.admit(&event("c", EventType::Remove)));
+        // Changes are reported for connected devices only
+        assert!(known.admit(&event("a", EventType::Change)));
+        assert!(!known.admit(&event("c", EventType::Change)));
+    }
+
+    #[test]
+    fn test_queue_overflow() {
+        let waker = Waker::noop();
+        let mut cx = Context::from_waker(waker);
+        let drain = |queue: &Queue, cx: &mut Context<'_>| {
+            let mut items = Vec::new();
+            let _ = queue.poll_batch(cx, usize::MAX, |item| {
+                items.push(match item {
+                    Ok(event) => Ok((event.device.port, event.seq)),
+                    Err(error) => Err(error.to_string()),
+                });
+                true
+            });
+            items
+        };
+        let push = |queue: &Queue, port: &str| queue.push(Ok(event(port, EventType::Add)));
+        let queue =
+            |overflow| Queue::with_filter(Filter::default()).with_capacity(Some(2), overflow);
+
+        let oldest = queue(Overflow::DropOldest);
+        ["a", "b", "c"].iter().for_each(|port| push(&oldest, port));
+        let expect = vec![Ok(("b".to_string(), 1)), Ok(("c".to_string(), 2))];
+        assert_eq!(drain(&oldest, &mut cx), expect);
+
+        // The end of the stream is not dropped to make room
+        let done = queue(Overflow::DropOldest);
+        push(&done, "a");
+        done.done();
+        ["b", "c"].iter().for_each(|port| push(&done, port));
+        assert_eq!(drain(&done, &mut cx), vec![]);
+        assert!(done.is_finished());
+
+        let newest = queue(Overflow::DropNewest);
+        ["a", "b", "c"].iter().for_each(|port| push(&newest, port));
+        let expect = vec![Ok(("a".to_string(), 0)), Ok(("b".to_string(), 1))];
+        assert_eq!(drain(&newest, &mut cx), expect);
+        // The gap is where the event was dropped
+        push(&newest, "d");
+        assert_eq!(drain(&newest, &mut cx), vec![Ok(("d".to_string(), 3))]);
+
+        let error = queue(Overflow::Error);
+        ["a", "b", "c", "d"]
+            .iter()
+            .for_each(|port| push(&error, port));
+        let expect = vec![
+            Ok(("a".to_string(), 0)),
+            Ok(("b".to_string(), 1)),
+            Err("event queue overflowed, dropped 2 events".to_string()),
+        ];
+        assert_eq!(drain(&error, &mut cx), expect);
+        let stats = Stats {
+            len: 0,
+            high_water: 2,
+            pushed: 2,
+            dropped: 2,
+            throttled: 0,
+        };
+        assert_eq!(error.stats(), stats);
+        push(&error, "e");
+        assert_eq!(drain(&error, &mut cx), vec![Ok(("e".to_string(), 4))]);
+    }
+
+    #[test]
+    fn test_queue_rate_limit() {
+        let waker = Waker::noop();
+        let mut cx = Context::from_waker(waker);
+        let limit = RateLimit::new().per_port(1, Duration::from_secs(3600));
+        let queue = Queue::with_filter(Filter::default()).with_rate_limit(Some(limit));
+        for port in ["a", "a", "a", "b"] {
+            queue.push(Ok(event(port, EventType::Add)));
+        }
+        let mut items = Vec::new();
+        let _ = queue.poll_batch(&mut cx, usize::MAX, |item| {
+            items.push(match item {
+                Ok(event) => Ok((event.device.port, event.seq)),
+                Err(error) => Err(error.to_string()),
+            });
+            true
+        });
+        let expect = vec![
+            Ok(("a".to_string(), 0)),
+            Err("events over the rate limit, dropped 2 events".to_string()),
+            Ok(("b".to_string(), 3)),
+        ];
+        assert_eq!(items, expect);
+        assert_eq!(queue.stats().throttled, 2);
+    }
+
+    #[test]
+    fn test_queue_debounce() {
+        let serial = |port: &str, serial: &str, kind| {
+            let mut event = event(port, kind);
+            event.device.serial = Some(serial.to_string());
+            Ok(event)
+        };
+        let queue = Arc::new(
+            Queue::with_filter(Filter::default()).with_debounce(Some(Duration::from_millis(50))),
+        );
+        // A bounce, a flap, and a different device replacing the first
+        queue.push(serial("a", "1", EventType::Remove));
+        queue.push(serial("a", "1", EventType::Add));
+        queue.push(serial("b", "2", EventType::Add));
+        queue.push(serial("b", "2", EventType::Remove));
+        queue.push(serial("c", "3", EventType::Remove));
+        queue.push(serial("c", "4", EventType::Add));
+        queue.done();
+        let start = Instant::now();
+        let events = EventIter::new(Arc::clone(&queue))
+            .into_iter()
+            .map(|event| event.map(|event| (event.device.serial.unwrap(), event.event)))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert!(matches!(&events[..], [
+            (a, EventType::Remove),
+            (b, EventType::Add)
+        ] if a == "3" && b == "4"));
+
+        // Held events are due after the window
+        let queue = Arc::new(
+            Queue::with_filter(Filter::default()).with_debounce(Some(Duration::from_millis(50))),
+        );
+        queue.push(serial("a", "1", EventType::Add));
+        let mut events = EventIter::new(queue).into_iter();
+        assert!(matches!(events.next(), Some(Ok(event)) if event.device.port == "a"));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_queue_moves() {
+        let serial = |port: &str, serial: &str, kind| {
+            let mut event = event(port, kind);
+            event.device.serial = Some(serial.to_string());
+            Ok(event)
+        };
+        let queue = Arc::new(
+            Queue::with_filter(Filter::default()).with_moves(Some(Duration::from_millis(50))),
+        );
+        // A move, a device which does not come back, and a new device at the port it left
+        queue.push(serial("a", "1", EventType::Remove));
+        queue.push(serial("b", "2", EventType::Remove));
+        queue.push(serial("c", "1", EventType::Add));
+        queue.push(serial("b", "3", EventType::Add));
+        queue.done();
+        let start = Instant::now();
+        let events = EventIter::new(Arc::clone(&queue))
+            .into_iter()
+            .map(|event| event.map(|e| (e.device.port, e.event, e.old_port)))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert!(matches!(&events[..], [
+            (c, EventType::Moved, Some(a)),
+            (b, EventType::Remove, None),
+            (b2, EventType::Add, None),
+        ] if c == "c" && a == "a" && b == "b" && b2 == "b"));
+
+        // A Remove is reported once the window has passed without its device coming back, but an
+        // Add is not held back
+        let queue = Arc::new(
+            Queue::with_filter(Filter::default()).with_moves(Some(Duration::from_millis(50))),
+        );
+        queue.push(serial("a", "1", EventType::Add));
+        queue.push(serial("b", "2", EventType::Remove));
+        let mut events = EventIter::new(queue).into_iter();
+        assert!(matches!(events.next(), Some(Ok(event)) if event.device.port == "a"));
+        assert!(start.elapsed() < Duration::from_millis(50));
+        assert!(matches!(events.next(), Some(Ok(event)) if event.device.port == "b"));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let moved = EventInfo {
+            old_port: Some("a".to_string()),
+            ..event("c", EventType::Moved)
+        };
+        let split = moved.split_move();
+        assert!(matches!(&split[..], [
+            EventInfo { event: EventType::Remove, old_port: None, .. },
+            EventInfo { event: EventType::Add, old_port: None, .. },
+        ] if split[0].device.port == "a" && split[1].device.port == "c"));
+    }
+
+    #[test]
+    fn test_event_iter_matching() {
+        let queue = Arc::new(Queue::with_filter(Filter::default()));
+        for (port, vid, serial) in [("a", 0x0403, "1"), ("b", 0x1a86, "2"), ("c", 0x0403, "3")] {
+            let mut event = event(port, EventType::Add);
+            event.device.vid_u16 = Some(vid);
+            event.device.pid_u16 = Some(0x6001);
+            event.device.serial = Some(serial.to_string());
+            queue.push(Ok(event));
+        }
+        queue.push(Err(io::ErrorKind::TimedOut.into()));
+        queue.done();
+        let events = EventIter::new(queue)
+            .filter_vid_pid(0x0403, 0x6001)
+            .filter_serial(Match::exact("3"));
+        let events = events
+            .into_iter()
+            .map(|event| event.map(|event| event.device.port))
+            .collect::<Vec<_>>();
+        assert!(matches!(&events[..], [Ok(c), Err(_)] if c == "c"));
+    }
+
+    #[test]
+    fn test_queue_set_filter() {
+        let add = |port: &str, vid: u16| {
+            let mut event = event(port, EventType::Add);
+            event.device.vid_u16 = Some(vid);
+            Ok(event)
+        };
+        let queue = Arc::new(Queue::with_filter(Filter::new().vid(0x0403)));
+        assert!(queue.snapshot().unwrap().is_empty());
+        queue.push(add("a", 0x0403));
+        queue.push(add("b", 0x1a86));
+        assert!(queue.snapshot().unwrap().contains_key("a"));
+        queue.set_filter(Filter::new().vid(0x1a86));
+        let snapshot = queue.snapshot().unwrap();
+        assert_eq!(snapshot.keys().collect::<Vec<_>>(), ["b"]);
+        queue.push(add("c", 0x1a86));
+        queue.push(add("d", 0x0403));
+        queue.done();
+        let ports = EventIter::new(queue)
+            .into_iter()
+            .map(|event| event.unwrap().device.port)
+            .collect::<Vec<_>>();
+        // Queued events are filtered again
+        assert_eq!(ports, ["c"]);
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn test_event_iter_fused() {
+        let add = |port: &str| Ok(event(port, EventType::Add));
+        let queue = Arc::new(Queue::with_filter(Filter::default()));
+        queue.push(add("a"));
+        queue.done();
+        let mut events = futures::executor::block_on_stream(EventIter::new(Arc::clone(&queue)));
+        assert!(matches!(events.next(), Some(Ok(event)) if event.device.port == "a"));
+        assert!(!events.is_terminated());
+        assert!(events.next().is_none());
+        assert!(events.is_terminated());
+        // Late events and a second end are never yielded
+        queue.push(add("b"));
+        queue.done();
+        assert!(events.next().is_none());
+        assert!(events.next().is_none());
+    }
+
+    #[test]
+    fn test_event_iter_many() {
+        let waker = Waker::noop();
+        let mut cx = Context::from_waker(waker);
+        let queue = Arc::new(Queue::with_filter(Filter::default()));
+        let mut events = EventIter::new(Arc::clone(&queue)).filter_serial("1");
+        let mut buf = Vec::new();
+        assert!(events.poll_next_many(&mut cx, &mut buf, 8).is_pending());
+        for (port, serial) in [("a", "1"), ("b", "2"), ("c", "1"), ("d", "1")] {
+            let mut event = event(port, EventType::Add);
+            event.device.serial = Some(serial.to_string());
+            queue.push(Ok(event));
+        }
+        queue.done();
+        assert_eq!(Poll::Ready(2), events.poll_next_many(&mut cx, &mut buf, 2));
+        assert_eq!(Poll::Ready(1), events.poll_next_many(&mut cx, &mut buf, 8));
+        assert_eq!(Poll::Ready(0), events.poll_next_many(&mut cx, &mut buf, 8));
+        let ports = buf
+            .into_iter()
+            .map(|event| event.map(|event| event.device.port))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(ports, ["a", "c", "d"]);
+    }
+
+    #[test]
+    fn test_event_iter_next_timeout() {
+        let queue = Arc::new(Queue::with_filter(Filter::default()));
+        let mut events = EventIter::new(Arc::clone(&queue));
+        let start = Instant::now();
+        let timeout = events.next_timeout(Duration::from_millis(20));
+        assert!(matches!(timeout, Err(Error::Timeout)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        let pusher = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            queue.push(Ok(event("a", EventType::Add)));
+            queue.done();
+        });
+        let next = events.next_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(next, Some(event) if event.device.port == "a"));
+        assert!(matches!(
+            events.next_timeout(Duration::from_secs(5)),
+            Ok(None)
+        ));
+        pusher.join().unwrap();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_wire_format() {
+        use std::time::{Duration, UNIX_EPOCH};
+        let mut event = EventInfo::new(
+            DeviceInfo {
+                port: "/dev/ttyUSB0".to_string(),
+                vid_u16: Some(0x0403),
+                ..Default::default()
+            },
+            EventType::Add,
+        );
+        event.timestamp.system = UNIX_EPOCH + Duration::from_millis(1500);
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["device"]["port"], "/dev/ttyUSB0");
+        assert_eq!(json["device"]["vid_u16"], 0x0403);
+        assert_eq!(json["device"]["port_type"], "Unknown");
+        assert_eq!(json["event"], "Add");
+        assert_eq!(json["timestamp"], 1500.0);
+        assert_eq!(json["seq"], 0);
+        let device: DeviceInfo = serde_json::from_str(r#"{"port":"COM3"}"#).unwrap();
+        assert_eq!(device.port, "COM3");
+        let back: EventInfo = serde_json::from_value(json).unwrap();
+        assert_eq!(back.timestamp.system, event.timestamp.system);
+    }
 }
@@ -0,0 +1,155 @@
+// error.rs
+use std::{fmt, io};
+
+/// The errors of listening for and scanning devices
+///
+/// The variants tell apart the failures worth retrying from the ones that are not. IE: when udev is
+/// unavailable [`listen`](crate::listen) fails with [`Error::Backend`] and there is no point
+/// trying again, while an [`Error::Io`] in the event stream is usually transient
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The native backend could not be initialized (IE: udev or the window manager is unavailable)
+    Backend(io::Error),
+    /// The backend started but could not register for device notifications
+    Registration(io::Error),
+    /// We are not allowed to access the device or the notification mechanism
+    PermissionDenied(io::Error),
+    /// A notification or a device property could not be decoded
+    Decode(String),
+    /// The event queue was full and events were dropped. See [`crate::Overflow::Error`]
+    Overflow {
+        /// How many events were dropped
+        dropped: u64,
+    },
+    /// Events were over the rate limit and dropped. See [`crate::RateLimit`]
+    Throttled {
+        /// How many events were dropped
+        dropped: u64,
+    },
+    /// A device did not show up in time
+    Timeout,
+    /// Any other I/O error
+    Io(io::Error),
+}
+
+/// A `Result` with an [`Error`]
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// I/O errors are not Clone, so an I/O error is replaced by one with the same kind and message
+impl Clone for Error {
+    fn clone(&self) -> Error {
+        let copy = |error: &io::Error| io::Error::new(error.kind(), error.to_string());
+        match self {
+            Error::Backend(error) => Error::Backend(copy(error)),
+            Error::Registration(error) => Error::Registration(copy(error)),
+            Error::PermissionDenied(error) => Error::PermissionDenied(copy(error)),
+            Error::Decode(what) => Error::Decode(what.clone()),
+            Error::Overflow { dropped } => Error::Overflow { dropped: *dropped },
+            Error::Throttled { dropped } => Error::Throttled { dropped: *dropped },
+            Error::Timeout => Error::Timeout,
+            Error::Io(error) => Error::Io(copy(error)),
+        }
+    }
+}
+
+impl Error {
+    /// Classify an error which prevented a listener from starting. Errors which are not already
+    /// more specific are backend initialization failures
+    pub(crate) fn backend(error: impl Into<Error>) -> Error {
+        match error.into() {
+            Error::Io(error) => Error::Backend(error),
+            error => error,
+        }
+    }
+
+    /// The kind of the underlying I/O error
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Error::Backend(error)
+            | Error::Registration(error)
+            | Error::PermissionDenied(error)
+            | Error::Io(error) => error.kind(),
+            Error::Decode(_) => io::ErrorKind::InvalidData,
+            Error::Overflow { .. } | Error::Throttled { .. } => io::ErrorKind::Other,
+            Error::Timeout => io::ErrorKind::TimedOut,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Backend(error) => write!(f, "failed to start the device listener: {error}"),
+            Error::Registration(error) => {
+                write!(f, "failed to register for device notifications: {error}")
+            }
+            Error::PermissionDenied(error) => write!(f, "permission denied: {error}"),
+            Error::Decode(what) => write!(f, "failed to decode {what}"),
+            Error::Overflow { dropped } => {
+                write!(f, "event queue overflowed, dropped {dropped} events")
+            }
+            Error::Throttled { dropped } => {
+                write!(f, "events over the rate limit, dropped {dropped} events")
+            }
+            Error::Timeout => write!(f, "timed out waiting for a device"),
+            Error::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Backend(error) | Error::Registration(error) | Error::PermissionDenied(error) => {
+                Some(error)
+            }
+            Error::Io(error) => error.source(),
+            Error::Decode(_)
+            | Error::Overflow { .. }
+            | Error::Throttled { .. }
+            | Error::Timeout => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied(error),
+            _ => Error::Io(error),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> io::Error {
+        match error {
+            Error::Io(error) => error,
+            error => io::Error::new(error.kind(), error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_classify() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(matches!(Error::from(denied), Error::PermissionDenied(_)));
+        let other = io::Error::from(io::ErrorKind::NotFound);
+        assert!(matches!(Error::backend(other), Error::Backend(_)));
+        let registration = Error::Registration(io::Error::from(io::ErrorKind::Other));
+        assert!(matches!(
+            Error::backend(registration),
+            Error::Registration(_)
+        ));
+        let overflow = io::Error::from(Error::Overflow { dropped: 2 });
+        assert_eq!(
+            overflow.to_string(),
+            "event queue overflowed, dropped 2 events"
+        );
+    }
+}
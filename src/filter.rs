@@ -0,0 +1,221 @@
+// filter.rs
+use crate::{detect::DeviceInfo, rules::Rules};
+use std::sync::Arc;
+
+/// How to match one of the string fields of a [`DeviceInfo`]. A device without the field never
+/// matches
+#[derive(Debug, Clone)]
+pub enum Match {
+    /// The whole string
+    Exact(String),
+    /// The start of the string
+    Prefix(String),
+    /// Anywhere in the string, unless the pattern is anchored
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl Match {
+    /// Match the whole string
+    pub fn exact(value: impl Into<String>) -> Match {
+        Match::Exact(value.into())
+    }
+
+    /// Match the start of the string
+    pub fn prefix(value: impl Into<String>) -> Match {
+        Match::Prefix(value.into())
+    }
+
+    /// Match a regular expression
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str) -> Result<Match, regex::Error> {
+        regex::Regex::new(pattern).map(Match::Regex)
+    }
+
+    fn is_match(&self, value: &str) -> bool {
+        match self {
+            Match::Exact(want) => value == want,
+            Match::Prefix(want) => value.starts_with(want.as_str()),
+            #[cfg(feature = "regex")]
+            Match::Regex(re) => re.is_match(value),
+        }
+    }
+}
+
+impl From<&str> for Match {
+    fn from(value: &str) -> Match {
+        Match::exact(value)
+    }
+}
+
+impl From<String> for Match {
+    fn from(value: String) -> Match {
+        Match::Exact(value)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl From<regex::Regex> for Match {
+    fn from(re: regex::Regex) -> Match {
+        Match::Regex(re)
+    }
+}
+
+/// Select devices by their port name and USB descriptors. An empty filter matches every device
+///
+/// The builder takes the same filters, see [`crate::ListenBuilder`]. A `Filter` is for the events
+/// of a listener which is already running, see [`crate::EventIter::matching`]
+///
+/// ```
+/// use serialport_detect::{DeviceInfo, Filter, Match};
+///
+/// let filter = Filter::new().vid(0x0403).serial(Match::prefix("FIX-"));
+/// let device = DeviceInfo {
+///     vid_u16: Some(0x0403),
+///     serial: Some("FIX-0042".to_string()),
+///     ..Default::default()
+/// };
+/// assert!(filter.matches(&device));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    pub(crate) port: Option<Match>,
+    pub(crate) vid: Option<u16>,
+    pub(crate) pid: Option<u16>,
+    pub(crate) serial: Option<Match>,
+    pub(crate) manufacturer: Option<Match>,
+    pub(crate) product: Option<Match>,
+    pub(crate) seat: Option<Match>,
+    pub(crate) rules: Option<Arc<Rules>>,
+}
+
+impl Filter {
+    /// A filter which matches every device
+    pub fn new() -> Filter {
+        Filter::default()
+    }
+
+    /// Only match the device at this port name
+    pub fn port(mut self, port: impl Into<Match>) -> Self {
+        self.port = Some(port.into());
+        self
+    }
+
+    /// Only match devices with this USB vendor id
+    pub fn vid(mut self, vid: u16) -> Self {
+        self.vid = Some(vid);
+        self
+    }
+
+    /// Only match devices with this USB product id
+    pub fn pid(mut self, pid: u16) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    /// Only match devices whose serial number matches
+    pub fn serial(mut self, serial: impl Into<Match>) -> Self {
+        self.serial = Some(serial.into());
+        self
+    }
+
+    /// Only match devices whose manufacturer string matches
+    pub fn manufacturer(mut self, manufacturer: impl Into<Match>) -> Self {
+        self.manufacturer = Some(manufacturer.into());
+        self
+    }
+
+    /// Only match devices whose product string matches
+    pub fn product(mut self, product: impl Into<Match>) -> Self {
+        self.product = Some(product.into());
+        self
+    }
+
+    /// Only match devices assigned to this logind seat. See [`DeviceInfo::seat`]
+    pub fn seat(mut self, seat: impl Into<Match>) -> Self {
+        self.seat = Some(seat.into());
+        self
+    }
+
+    /// Only match devices which `rules` admit. See [`Rules`]
+    pub fn rules(mut self, rules: Rules) -> Self {
+        self.rules = Some(Arc::new(rules));
+        self
+    }
+
+    /// Whether the device passes every part of the filter
+    pub fn matches(&self, device: &DeviceInfo) -> bool {
+        str_matches(&self.port, Some(&device.port))
+            && id_matches(self.vid, device.vid_u16)
+            && id_matches(self.pid, device.pid_u16)
+            && str_matches(&self.serial, device.serial.as_deref())
+            && str_matches(&self.manufacturer, device.manufacturer.as_deref())
+            && str_matches(&self.product, device.product.as_deref())
+            && str_matches(&self.seat, device.seat.as_deref())
+            && self.rules.as_ref().is_none_or(|rules| rules.admits(device))
+    }
+}
+
+fn str_matches(want: &Option<Match>, have: Option<&str>) -> bool {
+    match want {
+        None => true,
+        Some(want) => have.is_some_and(|value| want.is_match(value)),
+    }
+}
+
+fn id_matches(want: Option<u16>, have: Option<u16>) -> bool {
+    want.is_none() || want == have
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_filter_ids() {
+        let device = |vid: u16, pid: u16| DeviceInfo {
+            port: "/dev/ttyUSB0".to_string(),
+            vid_u16: Some(vid),
+            pid_u16: Some(pid),
+            ..Default::default()
+        };
+        let filter = Filter {
+            vid: Some(0x0403),
+            pid: Some(0x6001),
+            ..Default::default()
+        };
+        assert!(filter.matches(&device(0x0403, 0x6001)));
+        assert!(!filter.matches(&device(0x0403, 0x6015)));
+        assert!(!filter.matches(&DeviceInfo::default()));
+        assert!(Filter::default().matches(&device(0x10c4, 0xea60)));
+    }
+
+    #[test]
+    fn test_filter_strings() {
+        let device = DeviceInfo {
+            port: "/dev/ttyUSB0".to_string(),
+            serial: Some("FIX-0042".to_string()),
+            manufacturer: Some("FTDI".to_string()),
+            ..Default::default()
+        };
+        let filter = |serial: Match| Filter {
+            serial: Some(serial),
+            ..Default::default()
+        };
+        assert!(filter(Match::prefix("FIX-")).matches(&device));
+        assert!(!filter(Match::prefix("RIG-")).matches(&device));
+        assert!(filter("FIX-0042".into()).matches(&device));
+        assert!(!filter("FIX-004".into()).matches(&device));
+        let product = Filter {
+            product: Some(Match::prefix("")),
+            ..Default::default()
+        };
+        assert!(!product.matches(&device));
+        let port = |port: Match| Filter {
+            port: Some(port),
+            ..Default::default()
+        };
+        assert!(port("/dev/ttyUSB0".into()).matches(&device));
+        assert!(!port("/dev/ttyUSB1".into()).matches(&device));
+    }
+}
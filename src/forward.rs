@@ -0,0 +1,245 @@
+// forward.rs
+use crate::{detect::EventInfo, error::Result, AbortHandle, EventIter, ListenBuilder};
+#[cfg(feature = "futures")]
+use futures::SinkExt;
+use std::sync::mpsc;
+use tracing::{error, trace};
+
+/// The sending half of a channel which [`ListenBuilder::listen_into`] forwards events into
+///
+/// Implemented for the senders of [`std::sync::mpsc`], [`crossbeam::channel`], behind the `futures`
+/// feature `futures::channel::mpsc`, and behind the `tokio` feature [`tokio::sync::mpsc`] and
+/// [`tokio::sync::broadcast`]
+pub trait EventSender: Send + 'static {
+    /// Send an event. Returns false when the receiver is gone
+    fn send_event(&self, event: Result<EventInfo>) -> bool;
+}
+
+impl EventSender for mpsc::Sender<Result<EventInfo>> {
+    fn send_event(&self, event: Result<EventInfo>) -> bool {
+        self.send(event).is_ok()
+    }
+}
+
+impl EventSender for mpsc::SyncSender<Result<EventInfo>> {
+    fn send_event(&self, event: Result<EventInfo>) -> bool {
+        self.send(event).is_ok()
+    }
+}
+
+impl EventSender for crossbeam::channel::Sender<Result<EventInfo>> {
+    fn send_event(&self, event: Result<EventInfo>) -> bool {
+        self.send(event).is_ok()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl EventSender for futures::channel::mpsc::Sender<Result<EventInfo>> {
+    // Sending needs the sender mutably, so we send with a clone. Its slot in the channel goes
+    // away with it
+    fn send_event(&self, event: Result<EventInfo>) -> bool {
+        futures::executor::block_on(self.clone().send(event)).is_ok()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl EventSender for futures::channel::mpsc::UnboundedSender<Result<EventInfo>> {
+    fn send_event(&self, event: Result<EventInfo>) -> bool {
+        self.unbounded_send(event).is_ok()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl EventSender for tokio::sync::mpsc::Sender<Result<EventInfo>> {
+    // We are called from our own thread, never from within the runtime
+    fn send_event(&self, event: Result<EventInfo>) -> bool {
+        self.blocking_send(event).is_ok()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl EventSender for tokio::sync::mpsc::UnboundedSender<Result<EventInfo>> {
+    fn send_event(&self, event: Result<EventInfo>) -> bool {
+        self.send(event).is_ok()
+    }
+}
+
+/// Fails, stopping the forwarding, once every receiver is gone
+#[cfg(feature = "tokio")]
+impl EventSender for tokio::sync::broadcast::Sender<Result<EventInfo>> {
+    fn send_event(&self, event: Result<EventInfo>) -> bool {
+        self.send(event).is_ok()
+    }
+}
+
+impl ListenBuilder {
+    /// Like [`ListenBuilder::listen`], sending the events into `sender` instead of returning an
+    /// [`EventIter`]
+    ///
+    /// The events are forwarded by a thread of our own. The sender is dropped, disconnecting the
+    /// channel, when the [`AbortHandle`] is dropped. Forwarding stops when the receiver is dropped,
+    /// but the listener keeps running until the AbortHandle is dropped
+    ///
+    /// ```no_run
+    /// let (tx, rx) = std::sync::mpsc::channel();
+    /// let abort = serialport_detect::builder().vid(0x0403).listen_into(tx)?;
+    /// for event in rx {
+    ///     println!("{:?}", event?);
+    /// }
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    pub fn listen_into(self, sender: impl EventSender) -> Result<AbortHandle> {
+        let (abort, events) = self.listen()?;
+        forward(events, sender)?;
+        Ok(abort)
+    }
+
+    /// Like [`ListenBuilder::listen_into`], into a new bounded `futures::channel::mpsc` channel
+    /// holding `capacity` events. The receiver is a `Stream` which works on any executor (IE:
+    /// smol, async-std or tokio), as the events are sent by a thread of our own
+    ///
+    /// ```no_run
+    /// use futures::StreamExt;
+    ///
+    /// # async fn run() -> serialport_detect::Result<()> {
+    /// let (abort, mut events) = serialport_detect::builder().listen_channel(64)?;
+    /// while let Some(event) = events.next().await {
+    ///     println!("{:?}", event?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "futures")]
+    pub fn listen_channel(
+        self,
+        capacity: usize,
+    ) -> Result<(
+        AbortHandle,
+        futures::channel::mpsc::Receiver<Result<EventInfo>>,
+    )> {
+        let (tx, rx) = futures::channel::mpsc::channel(capacity);
+        Ok((self.listen_into(tx)?, rx))
+    }
+
+    /// Like [`ListenBuilder::listen_into`], into a new bounded tokio channel holding `capacity`
+    /// events. The receiver composes with `tokio::select!` without going through a [`EventIter`]
+    ///
+    /// A slow receiver applies backpressure, the events then wait in the event queue. See
+    /// [`ListenBuilder::capacity`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0
+    ///
+    /// ```no_run
+    /// # async fn run() -> serialport_detect::Result<()> {
+    /// let (abort, mut events) = serialport_detect::builder().listen_tokio(64)?;
+    /// while let Some(event) = events.recv().await {
+    ///     println!("{:?}", event?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub fn listen_tokio(
+        self,
+        capacity: usize,
+    ) -> Result<(AbortHandle, tokio::sync::mpsc::Receiver<Result<EventInfo>>)> {
+        let (tx, rx) = tokio::sync::mpsc::channel(capacity);
+        Ok((self.listen_into(tx)?, rx))
+    }
+
+    /// Like [`ListenBuilder::listen_tokio`], into a tokio broadcast channel. More receivers are
+    /// made with `resubscribe`, and each one gets every event sent after it was made
+    ///
+    /// A receiver which falls more than `capacity` events behind misses the oldest ones, and is
+    /// told how many with `RecvError::Lagged`. Errors are cloned for each receiver, see [`Error`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0
+    ///
+    /// [`Error`]: crate::Error
+    #[cfg(feature = "tokio")]
+    pub fn listen_broadcast(
+        self,
+        capacity: usize,
+    ) -> Result<(
+        AbortHandle,
+        tokio::sync::broadcast::Receiver<Result<EventInfo>>,
+    )> {
+        let (tx, rx) = tokio::sync::broadcast::channel(capacity);
+        Ok((self.listen_into(tx)?, rx))
+    }
+}
+
+/// Drain `events` into `sender` on a new thread
+fn forward(events: EventIter, sender: impl EventSender) -> Result<()> {
+    std::thread::Builder::new()
+        .name("serialport-detect-forward".into())
+        .spawn(move || {
+            for event in events {
+                if !sender.send_event(event) {
+                    trace!("event receiver dropped");
+                    return;
+                }
+            }
+            trace!("event forwarding finished");
+        })
+        .map_err(|error| {
+            error!(?error, "failed to spawn the forwarding thread");
+            error
+        })?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "mock", feature = "futures"))]
+mod test {
+    use super::*;
+    use crate::{DeviceInfo, MockBackend};
+    use futures::StreamExt;
+    use std::{sync::Arc, time::Duration};
+
+    /// Push an Add later from another thread, so that the runtime has to be woken for it
+    fn add_later(mock: &Arc<MockBackend>, port: &str) {
+        let (mock, port) = (Arc::clone(mock), port.to_string());
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            mock.push_add(DeviceInfo {
+                port,
+                ..Default::default()
+            });
+        });
+    }
+
+    /// The stream and the channel of a listener, on whichever runtime drives us
+    async fn listen(mock: Arc<MockBackend>) {
+        let (abort, mut events) = mock.listen().unwrap();
+        add_later(&mock, "a");
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!("a", event.device.port);
+
+        let (channel_abort, channel_events) = mock.listen().unwrap();
+        let (tx, mut rx) = futures::channel::mpsc::channel(1);
+        forward(channel_events, tx).unwrap();
+        add_later(&mock, "b");
+        let event = rx.next().await.unwrap().unwrap();
+        assert_eq!("b", event.device.port);
+        let event = events.next().await.unwrap().unwrap();
+        assert_eq!("b", event.device.port);
+
+        drop((abort, channel_abort));
+        assert!(events.next().await.is_none());
+        assert!(rx.next().await.is_none());
+    }
+
+    #[test]
+    fn test_smol() {
+        smol::block_on(listen(Arc::new(MockBackend::new())));
+    }
+
+    #[test]
+    fn test_async_std() {
+        async_std::task::block_on(listen(Arc::new(MockBackend::new())));
+    }
+}
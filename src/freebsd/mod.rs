@@ -0,0 +1,498 @@
+// FreeBSD support
+
+use crate::{
+    detect::{
+        listener_span, usb_stable_id, DeviceInfo, EventInfo, EventType, PortType, Queue, UsbClass,
+    },
+    id::{parse_hex, Pid, Vid},
+};
+use mio::{unix::SourceFd, Events, Interest, Token};
+use nix::{
+    errno::Errno,
+    sys::{
+        eventfd::{EfdFlags, EventFd},
+        socket::{self, AddressFamily, SockFlag, SockType, UnixAddr},
+    },
+    unistd,
+};
+use std::{
+    collections::HashMap,
+    ffi::{c_int, c_void, CStr, CString},
+    io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    sync::{mpsc, Arc},
+    thread::JoinHandle,
+};
+use tracing::{error, trace};
+
+/// The devd seqpacket socket. Each read returns exactly one notification
+const DEVD_SOCKET: &str = "/var/run/devd.seqpacket.pipe";
+
+/// Devd notifications are a single line of text, this comfortably fits any of them
+const DEVD_MESSAGE_SIZE: usize = 8192;
+
+#[derive(Debug)]
+struct ListenerOptions {
+    capacity: usize,
+    evfd: RawFd,
+    ready: mpsc::Sender<io::Result<()>>,
+}
+
+/// Scan for connected devices
+pub fn scan() -> io::Result<HashMap<String, DeviceInfo>> {
+    let items = scan_drivers()?
+        .into_values()
+        .flatten()
+        .map(|device| (device.port.clone(), device))
+        .collect();
+    Ok(items)
+}
+
+/// Look up the device at `port`. There is no cheaper way than a scan
+pub fn port_info(port: &str) -> io::Result<Option<DeviceInfo>> {
+    Ok(scan()?.remove(port))
+}
+
+/// Listen for connected devices
+pub fn listen(queue: Arc<Queue>) -> io::Result<AbortHandle> {
+    let evfd = EventFd::from_value_and_flags(0, EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_SEMAPHORE)?;
+    let (ready, rx) = mpsc::channel();
+    let opts = ListenerOptions {
+        capacity: 1024,
+        evfd: evfd.as_raw_fd(),
+        ready,
+    };
+    let span = listener_span("devd");
+    let join_handle = std::thread::spawn(move || span.in_scope(|| listener(queue, opts)));
+    // Wait for the listener so that setup errors are returned to the caller
+    match rx.recv() {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            let _ = join_handle.join();
+            return Err(error);
+        }
+        Err(_) => return Err(io::Error::other("listener exited during setup")),
+    }
+    let join_handle = Some(join_handle);
+    Ok(AbortHandle { evfd, join_handle })
+}
+
+fn listener(queue: Arc<Queue>, opts: ListenerOptions) {
+    trace!(capacity = opts.capacity, "listening");
+    // Safety: EventFd is private and when dropped we close, and remains open until join is called.
+    // See AbortHandle drop
+    let evfd = unsafe { BorrowedFd::borrow_raw(opts.evfd) };
+    let (socket, mut poller) = match init_listener(evfd.as_fd()) {
+        Ok(result) => result,
+        Err(error) => {
+            error!(?error, "failed to setup listener");
+            let _ = opts.ready.send(Err(error));
+            return;
+        }
+    };
+    let _ = opts.ready.send(Ok(()));
+    // Devd does not tell us anything about a device when it is detached, so we remember what we
+    // learned about each driver instance when it was attached
+    let mut cache = scan_drivers().unwrap_or_default();
+    let mut events = Events::with_capacity(opts.capacity);
+    let mut buffer = vec![0; DEVD_MESSAGE_SIZE];
+    'main: loop {
+        if let Err(error) = poller.poll(&mut events, None) {
+            error!(?error, "failed to poll devd socket");
+            queue.push(Err(error));
+            return;
+        }
+        for event in &events {
+            if event.token() == Token(0) && event.is_readable() {
+                trace!("closing listener");
+                let mut arr = [0; std::mem::size_of::<u64>()];
+                let _ = unistd::read(evfd.as_fd(), &mut arr);
+                queue.done();
+                break 'main;
+            } else if event.token() == Token(1) && event.is_readable() {
+                loop {
+                    match unistd::read(socket.as_fd(), &mut buffer) {
+                        Ok(0) => {
+                            trace!("devd closed the socket");
+                            queue.done();
+                            break 'main;
+                        }
+                        Ok(len) => {
+                            let message = String::from_utf8_lossy(&buffer[..len]);
+                            on_message(&message, &mut cache, &queue);
+                        }
+                        Err(Errno::EAGAIN) => break,
+                        Err(error) => {
+                            error!(?error, "failed to read devd socket");
+                            queue.push(Err(error.into()));
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    trace!("listener finished");
+}
+
+#[inline]
+fn init_listener(evfd: BorrowedFd<'_>) -> io::Result<(OwnedFd, mio::Poll)> {
+    let socket = socket::socket(
+        AddressFamily::Unix,
+        SockType::SeqPacket,
+        SockFlag::SOCK_NONBLOCK | SockFlag::SOCK_CLOEXEC,
+        None,
+    )?;
+    socket::connect(socket.as_raw_fd(), &UnixAddr::new(DEVD_SOCKET)?)?;
+    let poll = mio::Poll::new()?;
+    poll.registry().register(
+        &mut SourceFd(&evfd.as_raw_fd()),
+        Token(0),
+        Interest::READABLE,
+    )?;
+    poll.registry().register(
+        &mut SourceFd(&socket.as_raw_fd()),
+        Token(1),
+        Interest::READABLE,
+    )?;
+    Ok((socket, poll))
+}
+
+/// Handle a devd notification. We only care about driver attach (`+`) and detach (`-`) events
+///
+/// ```text
+/// +uftdi0 at bus=0 sernum="A50285BI" port=1 vendor=0x0403 product=0x6001 ... on uhub0
+/// -uftdi0 at bus=0 sernum="A50285BI" port=1 vendor=0x0403 product=0x6001 ... on uhub0
+/// ```
+fn on_message(message: &str, cache: &mut HashMap<String, Vec<DeviceInfo>>, queue: &Queue) {
+    for line in message.lines() {
+        trace!(line, "devd event");
+        match parse_event(line) {
+            Some((EventType::Add, instance)) => {
+                // NOTE only ucom(4) drivers (uftdi, umodem, uplcom, ...) have a tty
+                let devices = split_instance(instance)
+                    .and_then(|(driver, unit)| read_devices(driver, unit))
+                    .unwrap_or_default();
+                for device in &devices {
+                    queue.push(Ok(EventInfo::new(device.clone(), EventType::Add)));
+                }
+                if !devices.is_empty() {
+                    cache.insert(instance.to_string(), devices);
+                }
+            }
+            Some((EventType::Remove, instance)) => {
+                for device in cache.remove(instance).unwrap_or_default() {
+                    queue.push(Ok(EventInfo::new(device, EventType::Remove)));
+                }
+            }
+            Some((EventType::Change | EventType::Moved, _)) | None => {}
+        }
+    }
+}
+
+/// Parse a devd attach or detach line into the event and the driver instance name (IE: uftdi0)
+fn parse_event(line: &str) -> Option<(EventType, &str)> {
+    let event = match line.chars().next()? {
+        '+' => EventType::Add,
+        '-' => EventType::Remove,
+        _ => return None,
+    };
+    let instance = line[1..].split_whitespace().next()?;
+    Some((event, instance))
+}
+
+/// Split a driver instance name (IE: uftdi0) into the driver name and the unit number
+fn split_instance(instance: &str) -> Option<(&str, u32)> {
+    // NOTE driver names may contain digits themselves (IE: u3g0)
+    let split = instance
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .len();
+    let (driver, unit) = instance.split_at(split);
+    Some((driver, unit.parse().ok()?))
+}
+
+/// Parse a list of space separated key=value pairs, where values may be quoted
+fn parse_pairs(s: &str) -> HashMap<&str, &str> {
+    let mut pairs = HashMap::new();
+    let mut rest = s.trim_start();
+    while let Some(eq) = rest.find('=') {
+        let key = &rest[..eq];
+        rest = &rest[eq + 1..];
+        let value = match rest.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                rest = quoted.get(end + 1..).unwrap_or("");
+                &quoted[..end]
+            }
+            None => {
+                let end = rest.find(' ').unwrap_or(rest.len());
+                let value = &rest[..end];
+                rest = &rest[end..];
+                value
+            }
+        };
+        pairs.insert(key.trim(), value);
+        rest = rest.trim_start();
+    }
+    pairs
+}
+
+/// Find every ucom(4) driver instance, keyed by its instance name (IE: uftdi0)
+///
+/// ucom(4) publishes a `dev.<driver>.<unit>.ttyname` sysctl for each serial driver instance
+fn scan_drivers() -> io::Result<HashMap<String, Vec<DeviceInfo>>> {
+    let items = sysctl_names("dev")?
+        .iter()
+        .filter_map(|name| {
+            let mut parts = name.split('.');
+            match (parts.next(), parts.next(), parts.next(), parts.next()) {
+                (Some("dev"), Some(driver), Some(unit), Some("ttyname")) => {
+                    let unit = unit.parse().ok()?;
+                    let devices = read_devices(driver, unit)?;
+                    Some((format!("{driver}{unit}"), devices))
+                }
+                _ => None,
+            }
+        })
+        .collect();
+    Ok(items)
+}
+
+/// Read the serial ports of a ucom(4) driver instance. Multi port adapters have one callout device
+/// per port (IE: /dev/cuaU0.0, /dev/cuaU0.1)
+fn read_devices(driver: &str, unit: u32) -> Option<Vec<DeviceInfo>> {
+    let ttyname = sysctl_string(&format!("dev.{driver}.{unit}.ttyname"))?;
+    let ports = sysctl_int(&format!("dev.{driver}.{unit}.ttyports")).unwrap_or(1);
+    let pnpinfo = sysctl_string(&format!("dev.{driver}.{unit}.%pnpinfo")).unwrap_or_default();
+    let pnpinfo = parse_pairs(&pnpinfo);
+    // IE: "FTDI FT232R USB UART, class 0/0, rev 2.00/6.00, addr 2"
+    let desc = sysctl_string(&format!("dev.{driver}.{unit}.%desc"));
+    let vid = pnpinfo.get("vendor").and_then(|s| s.parse::<Vid>().ok());
+    let pid = pnpinfo.get("product").and_then(|s| s.parse::<Pid>().ok());
+    // The driver is attached to the interface, so these are the codes of the interface
+    let code = |key| pnpinfo.get(key).copied();
+    let interface_class = match (code("intclass"), code("intsubclass"), code("intprotocol")) {
+        (Some(class), Some(subclass), Some(protocol)) => {
+            UsbClass::from_hex(class, subclass, protocol)
+        }
+        _ => None,
+    };
+    let info = DeviceInfo {
+        port: String::new(),
+        vid: vid.map(|vid| vid.to_string()),
+        pid: pid.map(|pid| pid.to_string()),
+        vid_u16: vid.map(u16::from),
+        pid_u16: pid.map(u16::from),
+        bcd_device: pnpinfo.get("release").and_then(|s| parse_hex(s)),
+        interface_class,
+        serial: pnpinfo
+            .get("sernum")
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string()),
+        manufacturer: None,
+        product: desc.and_then(|desc| desc.split(',').next().map(|s| s.trim().to_string())),
+        port_type: PortType::Usb,
+        driver: Some(driver.to_string()),
+        ..Default::default()
+    };
+    let devices = (0..ports.max(1))
+        .map(|port| {
+            let (name, interface) = match ports > 1 {
+                true => (
+                    format!("/dev/cua{ttyname}.{port}"),
+                    Some(format!("{port:02}")),
+                ),
+                false => (format!("/dev/cua{ttyname}"), None),
+            };
+            DeviceInfo {
+                port: name,
+                stable_id: usb_stable_id(
+                    info.vid_u16,
+                    info.pid_u16,
+                    info.serial.as_deref(),
+                    interface.as_deref(),
+                ),
+                ..info.clone()
+            }
+        })
+        .collect();
+    Some(devices)
+}
+
+fn sysctl_string(name: &str) -> Option<String> {
+    let name = CString::new(name).ok()?;
+    let mut len = 0;
+    // Safety: a null buffer asks for the required length
+    let result = unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            std::ptr::null_mut(),
+            &mut len,
+            std::ptr::null(),
+            0,
+        )
+    };
+    if result < 0 {
+        return None;
+    }
+    let mut buffer = vec![0u8; len];
+    // Safety: the buffer is len bytes long
+    let result = unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            buffer.as_mut_ptr() as *mut c_void,
+            &mut len,
+            std::ptr::null(),
+            0,
+        )
+    };
+    match result {
+        0 => CStr::from_bytes_until_nul(&buffer[..len])
+            .ok()
+            .and_then(|s| s.to_str().ok())
+            .map(|s| s.to_string()),
+        _ => None,
+    }
+}
+
+fn sysctl_int(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let mut value: c_int = 0;
+    let mut len = std::mem::size_of::<c_int>();
+    // Safety: the buffer is the size of an int
+    let result = unsafe {
+        libc::sysctlbyname(
+            name.as_ptr(),
+            &mut value as *mut c_int as *mut c_void,
+            &mut len,
+            std::ptr::null(),
+            0,
+        )
+    };
+    match result {
+        0 => u32::try_from(value).ok(),
+        _ => None,
+    }
+}
+
+/// List the names of every sysctl below `prefix`
+///
+/// This walks the tree with the undocumented `sysctl.next` ({0, 2}) and `sysctl.name` ({0, 1})
+/// oids, which is how sysctl(8) lists a subtree
+fn sysctl_names(prefix: &str) -> io::Result<Vec<String>> {
+    let name = CString::new(prefix)?;
+    let mut root = [0 as c_int; libc::CTL_MAXNAME as usize];
+    let mut len = root.len();
+    // Safety: root holds CTL_MAXNAME entries
+    if unsafe { libc::sysctlnametomib(name.as_ptr(), root.as_mut_ptr(), &mut len) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let root = &root[..len];
+    let mut names = Vec::new();
+    let mut oid = root.to_vec();
+    loop {
+        let query = [&[0, 2], oid.as_slice()].concat();
+        let mut next = [0 as c_int; libc::CTL_MAXNAME as usize];
+        let mut size = std::mem::size_of_val(&next);
+        // Safety: next holds CTL_MAXNAME entries
+        let result = unsafe {
+            libc::sysctl(
+                query.as_ptr(),
+                query.len() as _,
+                next.as_mut_ptr() as *mut c_void,
+                &mut size,
+                std::ptr::null(),
+                0,
+            )
+        };
+        if result < 0 {
+            match Errno::last() {
+                Errno::ENOENT => break,
+                errno => return Err(errno.into()),
+            }
+        }
+        let next = &next[..size / std::mem::size_of::<c_int>()];
+        if !next.starts_with(root) {
+            break;
+        }
+        let query = [&[0, 1], next].concat();
+        let mut buffer = [0u8; 1024];
+        let mut size = buffer.len();
+        // Safety: the buffer is size bytes long
+        let result = unsafe {
+            libc::sysctl(
+                query.as_ptr(),
+                query.len() as _,
+                buffer.as_mut_ptr() as *mut c_void,
+                &mut size,
+                std::ptr::null(),
+                0,
+            )
+        };
+        if result == 0 {
+            if let Some(name) = CStr::from_bytes_until_nul(&buffer[..size])
+                .ok()
+                .and_then(|s| s.to_str().ok())
+            {
+                names.push(name.to_string());
+            }
+        }
+        oid = next.to_vec();
+    }
+    Ok(names)
+}
+
+/// The AbortHandle will cause the [`crate::EventIter`] to stop emitting events when dropped
+#[derive(Debug)]
+pub struct AbortHandle {
+    evfd: EventFd,
+    join_handle: Option<JoinHandle<()>>,
+}
+
+impl AbortHandle {
+    /// False once the listener thread has exited
+    pub(crate) fn is_alive(&self) -> bool {
+        self.join_handle
+            .as_ref()
+            .is_some_and(|jh| !jh.is_finished())
+    }
+}
+
+impl Drop for AbortHandle {
+    // We signal the remote thread to break its loop with the eventfd, and then we join
+    fn drop(&mut self) {
+        trace!("dropping event iter");
+        if let Some(jh) = self.join_handle.take() {
+            match self.evfd.write(1) {
+                Err(error) => error!(?error, "failed to write evfd"),
+                Ok(_) => {
+                    if let Err(error) = jh.join() {
+                        error!(?error, "event iter join error");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_devd_event() {
+        let line =
+            "+uftdi0 at bus=0 sernum=\"A50285BI\" port=1 vendor=0x0403 product=0x6001 on uhub0";
+        let (event, instance) = parse_event(line).unwrap();
+        assert!(matches!(event, EventType::Add));
+        assert_eq!(instance, "uftdi0");
+        assert_eq!(split_instance(instance), Some(("uftdi", 0)));
+        assert_eq!(split_instance("u3g12"), Some(("u3g", 12)));
+        assert!(parse_event("!system=USB subsystem=DEVICE type=ATTACH").is_none());
+
+        let pairs = parse_pairs("vendor=0x0403 product=0x6001 sernum=\"A5 0285\" release=0x0600");
+        assert_eq!(pairs.get("vendor"), Some(&"0x0403"));
+        assert_eq!(pairs.get("sernum"), Some(&"A5 0285"));
+        assert_eq!(pairs.get("release"), Some(&"0x0600"));
+    }
+}
@@ -0,0 +1,127 @@
+// group.rs
+use crate::detect::{cmp_ports, DeviceInfo};
+use std::collections::BTreeMap;
+
+/// The serial ports of one physical USB device. See [`group_by_device`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceGroup {
+    /// Vendor ID
+    pub vid: Option<u16>,
+    /// Product ID
+    pub pid: Option<u16>,
+    /// Serial number
+    pub serial: Option<String>,
+    /// See [`DeviceInfo::location`]
+    pub location: Option<String>,
+    /// Every port of the device, sorted by port name
+    pub ports: Vec<DeviceInfo>,
+}
+
+/// Cluster the ports of multi port adapters and composite devices, so that a device with N ports
+/// is reported once.
+///
+/// Ports belong to the same device when their vid, pid, serial number and location all match.
+/// A port with neither a serial number nor a location cannot be told apart from an identical
+/// device, and is always in a group of its own. Groups are sorted by the name of their first port
+///
+/// ```no_run
+/// let devices = serialport_detect::scan()?;
+/// for group in serialport_detect::group_by_device(devices.into_values()) {
+///     println!("{:?}: {} ports", group.product(), group.ports.len());
+/// }
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+pub fn group_by_device<I>(devices: I) -> Vec<DeviceGroup>
+where
+    I: IntoIterator<Item = DeviceInfo>,
+{
+    let mut groups: BTreeMap<_, DeviceGroup> = BTreeMap::new();
+    let mut singles = Vec::new();
+    for device in devices {
+        if device.vid_u16.is_none() || (device.serial.is_none() && device.location.is_none()) {
+            singles.push(DeviceGroup::new(device));
+            continue;
+        }
+        let key = (
+            device.vid_u16,
+            device.pid_u16,
+            device.serial.clone(),
+            device.location.clone(),
+        );
+        match groups.get_mut(&key) {
+            Some(group) => group.ports.push(device),
+            None => {
+                groups.insert(key, DeviceGroup::new(device));
+            }
+        }
+    }
+    let mut groups = groups.into_values().chain(singles).collect::<Vec<_>>();
+    for group in &mut groups {
+        group.ports.sort_by(|a, b| cmp_ports(&a.port, &b.port));
+    }
+    groups.sort_by(|a, b| cmp_ports(&a.ports[0].port, &b.ports[0].port));
+    groups
+}
+
+impl DeviceGroup {
+    fn new(device: DeviceInfo) -> DeviceGroup {
+        DeviceGroup {
+            vid: device.vid_u16,
+            pid: device.pid_u16,
+            serial: device.serial.clone(),
+            location: device.location.clone(),
+            ports: vec![device],
+        }
+    }
+
+    /// The product string of the device, taken from its first port
+    pub fn product(&self) -> Option<&str> {
+        self.ports.iter().find_map(|port| port.product.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_group_by_device() {
+        let device = |port: &str, serial: Option<&str>, location: Option<&str>| DeviceInfo {
+            port: port.to_string(),
+            vid_u16: Some(0x0403),
+            pid_u16: Some(0x6011),
+            serial: serial.map(|s| s.to_string()),
+            location: location.map(|s| s.to_string()),
+            ..Default::default()
+        };
+        let groups = group_by_device([
+            device("/dev/ttyUSB3", Some("A"), None),
+            device("/dev/ttyUSB0", Some("A"), None),
+            device("/dev/ttyUSB1", Some("B"), None),
+            device("/dev/ttyUSB4", None, Some("1-2")),
+            device("/dev/ttyUSB5", None, Some("1-2")),
+            device("/dev/ttyUSB6", None, None),
+            device("/dev/ttyUSB7", None, None),
+            DeviceInfo {
+                port: "/dev/ttyS0".to_string(),
+                ..Default::default()
+            },
+        ]);
+        let ports = groups
+            .iter()
+            .map(|group| group.ports.iter().map(|p| p.port.as_str()).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(
+            ports,
+            vec![
+                vec!["/dev/ttyS0"],
+                vec!["/dev/ttyUSB0", "/dev/ttyUSB3"],
+                vec!["/dev/ttyUSB1"],
+                vec!["/dev/ttyUSB4", "/dev/ttyUSB5"],
+                vec!["/dev/ttyUSB6"],
+                vec!["/dev/ttyUSB7"],
+            ]
+        );
+        assert_eq!(groups[1].serial.as_deref(), Some("A"));
+    }
+}
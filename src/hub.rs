@@ -0,0 +1,140 @@
+// hub.rs
+//
+// One listener shared by many clients, which the daemon and the server serve: the hub follows the
+// connected devices through the events, and passes the events on to every subscriber
+
+use crate::{
+    detect::{DeviceInfo, EventInfo, EventType},
+    error::Result,
+    forward::EventSender,
+};
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+use tracing::warn;
+
+/// How many events wait for a subscriber before we hang up on it
+pub(crate) const BACKLOG: usize = 1024;
+
+/// What is sent to a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Response {
+    Devices(Vec<DeviceInfo>),
+    Event(Box<EventInfo>),
+    Error(String),
+}
+
+/// The connected devices and the subscribers, fed by the listener
+#[derive(Debug, Default)]
+pub(crate) struct Hub {
+    devices: HashMap<String, DeviceInfo>,
+    subscribers: Vec<Sender<Response>>,
+}
+
+impl Hub {
+    /// Follow the devices through an event and pass it on. A subscriber which is gone, or which
+    /// does not keep up, is dropped
+    pub(crate) fn publish(&mut self, event: Result<EventInfo>) {
+        let response = match event {
+            Ok(event) => {
+                match event.event {
+                    EventType::Add | EventType::Change => {
+                        let device = event.device.clone();
+                        self.devices.insert(device.port.clone(), device);
+                    }
+                    EventType::Remove => {
+                        self.devices.remove(&event.device.port);
+                    }
+                    EventType::Moved => {
+                        if let Some(old_port) = &event.old_port {
+                            self.devices.remove(old_port);
+                        }
+                        let device = event.device.clone();
+                        self.devices.insert(device.port.clone(), device);
+                    }
+                }
+                Response::Event(Box::new(event))
+            }
+            Err(error) => Response::Error(error.to_string()),
+        };
+        self.subscribers
+            .retain(|subscriber| match subscriber.try_send(response.clone()) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    warn!("subscriber does not keep up, hanging up");
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            });
+    }
+
+    /// The connected devices, sorted by port
+    pub(crate) fn scan(&self) -> Vec<DeviceInfo> {
+        let mut devices = self.devices.values().cloned().collect::<Vec<_>>();
+        devices.sort_by(|a, b| crate::detect::cmp_ports(&a.port, &b.port));
+        devices
+    }
+
+    /// A new subscriber, starting with an Add event for every connected device when `initial`
+    pub(crate) fn subscribe(&mut self, initial: bool) -> Receiver<Response> {
+        let (tx, rx) = channel::bounded(BACKLOG.max(self.devices.len()));
+        if initial {
+            for device in self.scan() {
+                let event = EventInfo::new(device, EventType::Add);
+                let _ = tx.try_send(Response::Event(Box::new(event)));
+            }
+        }
+        self.subscribers.push(tx);
+        rx
+    }
+
+    /// End the stream of every subscriber
+    pub(crate) fn close(&mut self) {
+        self.subscribers.clear();
+    }
+}
+
+/// Feeds the hub with the events of the listener. Once the listener is gone it marks the hub
+/// closed, and wakes up the thread accepting the clients to stop
+pub(crate) struct Publisher {
+    hub: Arc<Mutex<Hub>>,
+    closed: Arc<AtomicBool>,
+    wake: Box<dyn Fn() + Send>,
+}
+
+impl Publisher {
+    pub(crate) fn new(
+        hub: &Arc<Mutex<Hub>>,
+        closed: &Arc<AtomicBool>,
+        wake: impl Fn() + Send + 'static,
+    ) -> Publisher {
+        Publisher {
+            hub: Arc::clone(hub),
+            closed: Arc::clone(closed),
+            wake: Box::new(wake),
+        }
+    }
+}
+
+impl EventSender for Publisher {
+    fn send_event(&self, event: Result<EventInfo>) -> bool {
+        self.hub.lock().publish(event);
+        true
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Release);
+        self.hub.lock().close();
+        (self.wake)();
+    }
+}
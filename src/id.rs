@@ -0,0 +1,93 @@
+// id.rs
+use crate::error::Error;
+use std::{fmt, str::FromStr};
+
+macro_rules! usb_id {
+    ($(#[$doc:meta])* $name:ident, $what:literal) => {
+        $(#[$doc])*
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(pub u16);
+
+        impl FromStr for $name {
+            type Err = Error;
+
+            /// Parse the id in hex, in either case and with or without a 0x prefix. IE: 0x1A86,
+            /// 1a86 or 1A86
+            fn from_str(s: &str) -> Result<$name, Error> {
+                parse_hex(s)
+                    .map($name)
+                    .ok_or_else(|| Error::Decode(format!(concat!($what, " '{}'"), s)))
+            }
+        }
+
+        /// Lowercase hex padded to 4 digits, IE: 1a86
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{:04x}", self.0)
+            }
+        }
+
+        impl From<u16> for $name {
+            fn from(id: u16) -> $name {
+                $name(id)
+            }
+        }
+
+        impl From<$name> for u16 {
+            fn from(id: $name) -> u16 {
+                id.0
+            }
+        }
+    };
+}
+
+usb_id!(
+    /// A USB vendor id. Parses from hex in any case and displays as lowercase hex padded to 4
+    /// digits, so that the ids of every platform compare equal
+    ///
+    /// ```
+    /// use serialport_detect::Vid;
+    ///
+    /// let vid: Vid = "0x1A86".parse()?;
+    /// assert_eq!(Vid(0x1a86), vid);
+    /// assert_eq!("1a86", vid.to_string());
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    Vid,
+    "USB vendor id"
+);
+
+usb_id!(
+    /// A USB product id. Parses and displays as [`Vid`] does
+    Pid,
+    "USB product id"
+);
+
+/// Parse a hex id, with or without padding or a 0x prefix and in either case
+pub(crate) fn parse_hex(s: &str) -> Option<u16> {
+    let hex = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    match hex.is_empty() || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        true => None,
+        false => u16::from_str_radix(hex, 16).ok(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_id() {
+        for s in ["0x1A86", "0X1a86", "1a86", "1A86"] {
+            assert_eq!(Vid(0x1a86), s.parse::<Vid>().unwrap(), "{s}");
+        }
+        assert_eq!(Pid(0x0003), "3".parse::<Pid>().unwrap());
+        assert_eq!("0003", Pid(3).to_string());
+        for s in ["", "0x", "+1a86", "1a86x", "10000", "0x-1"] {
+            assert!(s.parse::<Vid>().is_err(), "{s}");
+        }
+    }
+}
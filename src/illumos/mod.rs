@@ -0,0 +1,247 @@
+// illumos and Solaris support
+//
+// Serial ports are the /dev/term links into the /devices tree. We read the USB descriptors from
+// the device properties with libdevinfo, and rescan when the kernel publishes a devfs sysevent
+
+use crate::{
+    detect::{usb_stable_id, DeviceInfo, PortType, Queue},
+    id::{Pid, Vid},
+    poll::{self, Trigger},
+};
+use parking_lot::{const_mutex, Mutex};
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_int, c_uint, c_void, CStr, CString},
+    fs, io,
+    path::Path,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+use tracing::{debug, trace, warn};
+
+pub use crate::poll::AbortHandle;
+
+/// devfsadm creates the /dev/term links shortly after the kernel attaches the device, so a rescan
+/// triggered by a sysevent can be early. We keep polling to catch up with it
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The dial-in serial ports. Each entry is a link to a minor node under /devices
+const DEV_TERM: &str = "/dev/term";
+
+/// Scan for connected devices
+pub fn scan() -> io::Result<HashMap<String, DeviceInfo>> {
+    let entries = match fs::read_dir(DEV_TERM) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(error) => return Err(error),
+    };
+    let tree = DevInfo::snapshot()?;
+    let mut items = HashMap::new();
+    for entry in entries {
+        let port = entry?.path();
+        let Ok(target) = fs::canonicalize(&port) else {
+            continue;
+        };
+        if let Some(device) = tree.read_device_info(&port, &target) {
+            items.insert(device.port.clone(), device);
+        }
+    }
+    Ok(items)
+}
+
+/// Look up the device at `port`. There is no cheaper way than a scan
+pub fn port_info(port: &str) -> io::Result<Option<DeviceInfo>> {
+    Ok(scan()?.remove(port))
+}
+
+/// Listen for connected devices
+pub fn listen(queue: Arc<Queue>) -> io::Result<AbortHandle> {
+    let abort = poll::listen(POLL_INTERVAL, scan, queue)?;
+    if subscribe() {
+        if let Some(trigger) = abort.trigger() {
+            TRIGGERS.lock().push(trigger);
+        }
+    }
+    Ok(abort)
+}
+
+/// The listeners to wake when a sysevent arrives. Listeners which have finished are removed on the
+/// next event
+static TRIGGERS: Mutex<Vec<Trigger>> = const_mutex(Vec::new());
+
+/// Subscribe to devfs sysevents once for the process. The subscription is never released.
+///
+/// Binding requires privileges, so when it fails we fall back to polling only
+fn subscribe() -> bool {
+    static SUBSCRIBED: OnceLock<bool> = OnceLock::new();
+    *SUBSCRIBED.get_or_init(|| {
+        // Safety: the handler is a static function, and the subclass strings are static
+        unsafe {
+            let handle = sysevent_bind_handle(on_sysevent);
+            if handle.is_null() {
+                let error = io::Error::last_os_error();
+                debug!(?error, "failed to bind sysevent handle, polling only");
+                return false;
+            }
+            let mut subclasses = [ESC_DEVFS_DEVI_ADD.as_ptr(), ESC_DEVFS_DEVI_REMOVE.as_ptr()];
+            let rc = sysevent_subscribe_event(
+                handle,
+                EC_DEVFS.as_ptr(),
+                subclasses.as_mut_ptr(),
+                subclasses.len() as _,
+            );
+            if rc != 0 {
+                let error = io::Error::last_os_error();
+                warn!(
+                    ?error,
+                    "failed to subscribe to devfs sysevents, polling only"
+                );
+                sysevent_unbind_handle(handle);
+                return false;
+            }
+            trace!("subscribed to devfs sysevents");
+            true
+        }
+    })
+}
+
+/// Called on a libsysevent thread for each devfs event. We only need to know that something
+/// changed, the listeners diff their own scans
+extern "C" fn on_sysevent(_event: *mut c_void) {
+    TRIGGERS.lock().retain(Trigger::rescan);
+}
+
+/// A snapshot of the device tree
+struct DevInfo {
+    root: DiNode,
+}
+
+impl DevInfo {
+    fn snapshot() -> io::Result<DevInfo> {
+        // Safety: "/" is a valid physical path, and the snapshot is released on drop
+        let root = unsafe { di_init(c"/".as_ptr(), DINFOCPYALL) };
+        match root.is_null() {
+            true => Err(io::Error::last_os_error()),
+            false => Ok(DevInfo { root }),
+        }
+    }
+
+    /// Read the USB descriptors of the device behind a /dev/term link. `target` is the resolved
+    /// link (IE: /devices/pci@0,0/pci103c,1309@1d/device@1/usbftdi@0:0). Returns None if the port
+    /// is not a USB device
+    fn read_device_info(&self, port: &Path, target: &Path) -> Option<DeviceInfo> {
+        let path = target.to_str()?.strip_prefix("/devices")?;
+        let path = path.rsplit_once(':').map_or(path, |(node, _minor)| node);
+        let path = CString::new(path).ok()?;
+        // Safety: the nodes belong to our snapshot which outlives this function
+        unsafe {
+            let node = di_lookup_node(self.root, path.as_ptr() as _);
+            let usb = usb_device(node)?;
+            let id = |name| int_property(usb, name).and_then(|id| u16::try_from(id).ok());
+            let (vid, pid) = (id(c"usb-vendor-id"), id(c"usb-product-id"));
+            let serial = string_property(usb, c"usb-serialno");
+            Some(DeviceInfo {
+                port: port.to_str()?.to_string(),
+                vid: vid.map(|vid| Vid(vid).to_string()),
+                pid: pid.map(|pid| Pid(pid).to_string()),
+                vid_u16: vid,
+                pid_u16: pid,
+                bcd_device: id(c"usb-revision-id"),
+                stable_id: usb_stable_id(vid, pid, serial.as_deref(), None),
+                serial,
+                manufacturer: string_property(usb, c"usb-vendor-name"),
+                product: string_property(usb, c"usb-product-name"),
+                port_type: PortType::Usb,
+                ..Default::default()
+            })
+        }
+    }
+}
+
+impl Drop for DevInfo {
+    fn drop(&mut self) {
+        // Safety: root was returned by di_init and is released once
+        unsafe { di_fini(self.root) }
+    }
+}
+
+/// Walk up from the serial driver's node to the USB device, which is the first ancestor with a
+/// usb-vendor-id property
+///
+/// Safety: node must be DI_NODE_NIL or belong to a live snapshot
+unsafe fn usb_device(mut node: DiNode) -> Option<DiNode> {
+    while !node.is_null() {
+        if int_property(node, c"usb-vendor-id").is_some() {
+            return Some(node);
+        }
+        node = di_parent_node(node);
+    }
+    None
+}
+
+/// Safety: node must belong to a live snapshot
+unsafe fn int_property(node: DiNode, name: &CStr) -> Option<c_int> {
+    let mut data: *mut c_int = std::ptr::null_mut();
+    match di_prop_lookup_ints(DDI_DEV_T_ANY, node, name.as_ptr(), &mut data) {
+        n if n > 0 && !data.is_null() => Some(*data),
+        _ => None,
+    }
+}
+
+/// Safety: node must belong to a live snapshot
+unsafe fn string_property(node: DiNode, name: &CStr) -> Option<String> {
+    let mut data: *mut c_char = std::ptr::null_mut();
+    match di_prop_lookup_strings(DDI_DEV_T_ANY, node, name.as_ptr(), &mut data) {
+        n if n > 0 && !data.is_null() => CStr::from_ptr(data)
+            .to_str()
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty()),
+        _ => None,
+    }
+}
+
+/// An opaque di_node_t. DI_NODE_NIL is null
+type DiNode = *mut c_void;
+
+/// DINFOSUBTREE | DINFOMINOR | DINFOPROP
+const DINFOCPYALL: c_uint = 0x07;
+
+/// Match properties of any dev_t
+const DDI_DEV_T_ANY: libc::dev_t = -2i64 as _;
+
+const EC_DEVFS: &CStr = c"EC_devfs";
+const ESC_DEVFS_DEVI_ADD: &CStr = c"ESC_devfs_devi_add";
+const ESC_DEVFS_DEVI_REMOVE: &CStr = c"ESC_devfs_devi_remove";
+
+#[link(name = "devinfo")]
+extern "C" {
+    fn di_init(phys_path: *const c_char, flag: c_uint) -> DiNode;
+    fn di_fini(root: DiNode);
+    fn di_lookup_node(root: DiNode, path: *mut c_char) -> DiNode;
+    fn di_parent_node(node: DiNode) -> DiNode;
+    fn di_prop_lookup_ints(
+        dev: libc::dev_t,
+        node: DiNode,
+        prop_name: *const c_char,
+        prop_data: *mut *mut c_int,
+    ) -> c_int;
+    fn di_prop_lookup_strings(
+        dev: libc::dev_t,
+        node: DiNode,
+        prop_name: *const c_char,
+        prop_data: *mut *mut c_char,
+    ) -> c_int;
+}
+
+#[link(name = "sysevent")]
+extern "C" {
+    fn sysevent_bind_handle(event_handler: extern "C" fn(*mut c_void)) -> *mut c_void;
+    fn sysevent_unbind_handle(handle: *mut c_void);
+    fn sysevent_subscribe_event(
+        handle: *mut c_void,
+        event_class: *const c_char,
+        event_subclass_list: *mut *const c_char,
+        num_subclasses: c_uint,
+    ) -> c_int;
+}
@@ -0,0 +1,241 @@
+//! managed
+//!
+//! A serial port which follows its device across unplug and replug
+
+use super::{AsyncSerialPort, Settings, OPEN_ATTEMPTS, OPEN_DELAY};
+use crate::{
+    detect::{AbortHandle, DeviceInfo, EventType},
+    error::{Error, Result},
+    ListenBuilder,
+};
+use futures::{
+    executor::block_on_stream,
+    future::poll_fn,
+    io::{AsyncRead, AsyncWrite},
+};
+use parking_lot::Mutex;
+use std::{
+    fmt, io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+use tracing::{debug, trace, warn};
+
+/// Whether a [`ManagedPort`] is open
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortState {
+    /// The device is present and its port is open
+    Connected(Box<DeviceInfo>),
+    /// No matching device is present, or its port could not be opened
+    Disconnected,
+}
+
+/// A serial port which opens when a matching device is present, and reopens when the device is
+/// plugged back in
+///
+/// Reads and writes fail with [`io::ErrorKind::NotConnected`] while the device is unplugged.
+/// Wait for it with [`ManagedPort::connected`] and carry on
+///
+/// ```no_run
+/// use futures::AsyncReadExt;
+/// use serialport_detect::io::{ManagedPort, Settings};
+///
+/// # async fn run() -> serialport_detect::Result<()> {
+/// let filter = serialport_detect::builder().vid(0x0403).pid(0x6001);
+/// let mut port = ManagedPort::new(filter, Settings::default())?;
+/// let mut buf = [0; 64];
+/// loop {
+///     let device = port.connected().await?;
+///     println!("reading from {}", device.port);
+///     while let Ok(n @ 1..) = port.read(&mut buf).await {
+///         println!("{:?}", &buf[..n]);
+///     }
+/// }
+/// # }
+/// ```
+pub struct ManagedPort {
+    shared: Arc<Mutex<Inner>>,
+    _abort: AbortHandle,
+}
+
+#[derive(Default)]
+struct Inner {
+    device: Option<DeviceInfo>,
+    port: Option<AsyncSerialPort>,
+    /// Woken when the device connects or the listener stops
+    waiters: Vec<Waker>,
+    /// The listener stopped, the device will never connect again
+    finished: bool,
+}
+
+impl ManagedPort {
+    /// Manage the first device matching `filter`, opening it with `settings`
+    pub fn new(filter: ListenBuilder, settings: Settings) -> Result<ManagedPort> {
+        let (abort, events) = filter.initial(true).listen()?;
+        let shared = Arc::new(Mutex::new(Inner::default()));
+        let thread = Arc::clone(&shared);
+        std::thread::Builder::new()
+            .name("serialport-detect-managed".into())
+            .spawn(move || {
+                for event in block_on_stream(events) {
+                    match event {
+                        Ok(event) => {
+                            for event in event.split_move() {
+                                follow(&thread, event.event, event.device, settings);
+                            }
+                        }
+                        Err(error) => debug!(?error, "managed port listener error"),
+                    }
+                }
+                trace!("managed port listener finished");
+                let mut inner = thread.lock();
+                inner.finished = true;
+                inner.disconnect();
+            })?;
+        Ok(ManagedPort {
+            shared,
+            _abort: abort,
+        })
+    }
+
+    /// Whether the port is open right now
+    pub fn state(&self) -> PortState {
+        match &self.shared.lock().device {
+            Some(device) => PortState::Connected(Box::new(device.clone())),
+            None => PortState::Disconnected,
+        }
+    }
+
+    /// Wait until the device is present and its port is open
+    pub async fn connected(&self) -> Result<DeviceInfo> {
+        poll_fn(|cx| {
+            let mut inner = self.shared.lock();
+            match (&inner.device, inner.finished) {
+                (Some(device), _) => Poll::Ready(Ok(device.clone())),
+                (None, true) => Poll::Ready(Err(Error::Io(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "device listener stopped",
+                )))),
+                (None, false) => {
+                    inner.waiters.push(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Run `f` with the open port. A port which failed or closed is dropped, and we wait for its
+    /// device to come back
+    fn with_port<T>(
+        &self,
+        f: impl FnOnce(Pin<&mut AsyncSerialPort>) -> Poll<io::Result<T>>,
+        closed: impl Fn(&T) -> bool,
+    ) -> Poll<io::Result<T>> {
+        let mut inner = self.shared.lock();
+        let Some(port) = inner.port.as_mut() else {
+            return Poll::Ready(Err(io::ErrorKind::NotConnected.into()));
+        };
+        match f(Pin::new(port)) {
+            Poll::Ready(Ok(value)) if closed(&value) => {
+                inner.disconnect();
+                Poll::Ready(Err(io::ErrorKind::NotConnected.into()))
+            }
+            Poll::Ready(Err(error)) => {
+                debug!(?error, "managed port failed");
+                inner.disconnect();
+                Poll::Ready(Err(error))
+            }
+            poll => poll,
+        }
+    }
+}
+
+impl Inner {
+    fn disconnect(&mut self) {
+        self.device = None;
+        self.port = None;
+        self.waiters.drain(..).for_each(Waker::wake);
+    }
+}
+
+/// Open the port of a device which appeared, and close it when the device goes away
+fn follow(shared: &Mutex<Inner>, event: EventType, device: DeviceInfo, settings: Settings) {
+    match event {
+        EventType::Add if shared.lock().device.is_none() => {
+            let Some(port) = open(&device.port, settings) else {
+                return;
+            };
+            let mut inner = shared.lock();
+            trace!(port = device.port, "managed port connected");
+            inner.device = Some(device);
+            inner.port = Some(port);
+            inner.waiters.drain(..).for_each(Waker::wake);
+        }
+        EventType::Remove => {
+            let mut inner = shared.lock();
+            if inner.device.as_ref().is_some_and(|d| d.port == device.port) {
+                trace!(port = device.port, "managed port disconnected");
+                inner.disconnect();
+            }
+        }
+        EventType::Add | EventType::Change | EventType::Moved => {}
+    }
+}
+
+fn open(port: &str, settings: Settings) -> Option<AsyncSerialPort> {
+    for attempt in 1..=OPEN_ATTEMPTS {
+        match super::open(port, settings) {
+            Ok(port) => return Some(port),
+            Err(error) if attempt == OPEN_ATTEMPTS => {
+                warn!(?error, port, "failed to open managed port");
+            }
+            Err(error) => {
+                debug!(
+                    ?error,
+                    port, attempt, "failed to open managed port, retrying"
+                );
+                std::thread::sleep(OPEN_DELAY);
+            }
+        }
+    }
+    None
+}
+
+impl AsyncRead for ManagedPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let empty = buf.is_empty();
+        self.with_port(|port| port.poll_read(cx, buf), |&n| n == 0 && !empty)
+    }
+}
+
+impl AsyncWrite for ManagedPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.with_port(|port| port.poll_write(cx, buf), |_| false)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.with_port(|port| port.poll_flush(cx), |_| false)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.with_port(|port| port.poll_close(cx), |_| false)
+    }
+}
+
+impl fmt::Debug for ManagedPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManagedPort")
+            .field("state", &self.state())
+            .finish()
+    }
+}
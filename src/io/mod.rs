@@ -0,0 +1,385 @@
+// io/mod.rs
+//
+// Non blocking access to a serial port. The port is driven by a thread of its own, which shuttles
+// bytes between the blocking serialport API and buffers shared with the async side
+
+use crate::{error::Result, timer::Timer, ListenBuilder};
+use futures::io::{AsyncRead, AsyncWrite};
+use parking_lot::Mutex;
+use serialport::SerialPort;
+use std::{
+    collections::VecDeque,
+    fmt, io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+use tracing::{debug, error, trace};
+
+mod managed;
+
+pub use managed::{ManagedPort, PortState};
+pub use serialport::{DataBits, FlowControl, Parity, StopBits};
+
+/// How long the port thread blocks on a read before it looks for bytes to write
+const POLL: Duration = Duration::from_millis(10);
+
+/// How many bytes we buffer in each direction before applying back pressure
+const CAPACITY: usize = 64 * 1024;
+
+/// How often we try to open a device which just appeared. The device node may show up before its
+/// permissions are set (IE: by a udev rule)
+const OPEN_ATTEMPTS: u32 = 5;
+const OPEN_DELAY: Duration = Duration::from_millis(100);
+
+/// How to configure the port. Defaults to 115200 baud 8N1 without flow control
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Settings {
+    /// Baud rate
+    pub baud_rate: u32,
+    /// Bits per character
+    pub data_bits: DataBits,
+    /// Parity checking
+    pub parity: Parity,
+    /// Stop bits
+    pub stop_bits: StopBits,
+    /// Flow control
+    pub flow_control: FlowControl,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            baud_rate: 115_200,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+        }
+    }
+}
+
+/// Open `port` for non blocking reads and writes
+///
+/// ```no_run
+/// use futures::{AsyncReadExt, AsyncWriteExt};
+/// use serialport_detect::io::{self, Settings};
+///
+/// # async fn run() -> serialport_detect::Result<()> {
+/// let mut port = io::open("/dev/ttyUSB0", Settings::default())?;
+/// port.write_all(b"AT\r").await?;
+/// let mut reply = [0; 64];
+/// let n = port.read(&mut reply).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn open(port: &str, settings: Settings) -> Result<AsyncSerialPort> {
+    let port = serialport::new(port, settings.baud_rate)
+        .data_bits(settings.data_bits)
+        .parity(settings.parity)
+        .stop_bits(settings.stop_bits)
+        .flow_control(settings.flow_control)
+        .timeout(POLL)
+        .open()
+        .map_err(io::Error::from)?;
+    AsyncSerialPort::new(port)
+}
+
+/// Open the port of the first device matching `filter` as soon as it is present, IE: a device
+/// which reboots into a bootloader on a new port
+///
+/// Fails with [`crate::Error::Timeout`] when no device shows up within `timeout`. A device which
+/// just appeared may not be accessible yet, opening it is retried a few times before giving up
+///
+/// ```no_run
+/// use serialport_detect::io::{self, Settings};
+/// use std::time::Duration;
+///
+/// # async fn run() -> serialport_detect::Result<()> {
+/// let bootloader = serialport_detect::builder().vid(0x2341).pid(0x0036);
+/// let timeout = Some(Duration::from_secs(10));
+/// let port = io::open_when_present(bootloader, Settings::default(), timeout).await?;
+/// let same = serialport_detect::builder().port("/dev/ttyACM0");
+/// let port = io::open_when_present(same, Settings::default(), None).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn open_when_present(
+    filter: ListenBuilder,
+    settings: Settings,
+    timeout: Option<Duration>,
+) -> Result<AsyncSerialPort> {
+    let device = crate::wait::first_device(filter, timeout).await?;
+    let mut attempt = 1;
+    loop {
+        match open(&device.port, settings) {
+            Err(error) if attempt < OPEN_ATTEMPTS => {
+                debug!(
+                    ?error,
+                    port = device.port,
+                    attempt,
+                    "failed to open, retrying"
+                );
+                Timer::after(OPEN_DELAY).await;
+                attempt += 1;
+            }
+            result => break result,
+        }
+    }
+}
+
+/// A serial port implementing [`AsyncRead`] and [`AsyncWrite`]. See [`open`]
+///
+/// Dropping the port, or cancelling it with a [`CancelHandle`], closes it
+pub struct AsyncSerialPort {
+    shared: Arc<Shared>,
+    name: Option<String>,
+}
+
+/// Closes an [`AsyncSerialPort`] from another task. See [`AsyncSerialPort::cancel_handle`]
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Bytes received and not yet read
+    read: VecDeque<u8>,
+    /// Bytes written and not yet sent
+    write: VecDeque<u8>,
+    /// Bytes taken from `write` by the port thread and not yet sent
+    sending: usize,
+    /// Woken when there are bytes to read, or the port failed or closed
+    reader: Option<Waker>,
+    /// Woken when there is room to write or the bytes were sent, or the port failed or closed
+    writer: Option<Waker>,
+    /// Why the port thread stopped. Yielded once, after which the port is closed
+    error: Option<io::Error>,
+    closed: bool,
+}
+
+impl AsyncSerialPort {
+    /// Drive an already open port. Its timeout is lowered so that writes are not held up by reads
+    pub fn new(mut port: Box<dyn SerialPort>) -> Result<AsyncSerialPort> {
+        port.set_timeout(POLL).map_err(io::Error::from)?;
+        let name = port.name();
+        let shared = Arc::new(Shared::default());
+        let thread = Arc::clone(&shared);
+        std::thread::Builder::new()
+            .name("serialport-detect-io".into())
+            .spawn(move || run(port, &thread))?;
+        Ok(AsyncSerialPort { shared, name })
+    }
+
+    /// The name of the port, IE: /dev/ttyUSB0 or COM3
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// A handle to close the port from elsewhere, IE: to cancel a read which is waiting for bytes
+    /// that will never arrive. Pending and later reads then return end of file and writes fail
+    pub fn cancel_handle(&self) -> CancelHandle {
+        CancelHandle {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl CancelHandle {
+    /// Close the port
+    pub fn cancel(&self) {
+        self.shared.close();
+    }
+}
+
+impl Shared {
+    fn close(&self) {
+        let mut state = self.state.lock();
+        state.closed = true;
+        state.wake();
+    }
+}
+
+impl State {
+    fn wake(&mut self) {
+        self.reader.take().into_iter().for_each(Waker::wake);
+        self.writer.take().into_iter().for_each(Waker::wake);
+    }
+}
+
+/// Shuttle bytes until the port fails or is closed
+fn run(mut port: Box<dyn SerialPort>, shared: &Shared) {
+    trace!(name = ?port.name(), "port opened");
+    let mut buffer = [0; 4096];
+    let result = loop {
+        let (pending, full) = {
+            let mut state = shared.state.lock();
+            if state.closed {
+                break Ok(());
+            }
+            let pending = state.write.drain(..).collect::<Vec<_>>();
+            state.sending = pending.len();
+            (pending, state.read.len() >= CAPACITY)
+        };
+        if !pending.is_empty() {
+            if let Err(error) = port.write_all(&pending).and_then(|_| port.flush()) {
+                break Err(error);
+            }
+            let mut state = shared.state.lock();
+            state.sending = 0;
+            state.writer.take().into_iter().for_each(Waker::wake);
+        }
+        if full {
+            // The reader is behind, leave the bytes in the OS buffer for now
+            std::thread::sleep(POLL);
+            continue;
+        }
+        match port.read(&mut buffer) {
+            Ok(n) => {
+                let mut state = shared.state.lock();
+                state.read.extend(&buffer[..n]);
+                state.reader.take().into_iter().for_each(Waker::wake);
+            }
+            Err(error) if error.kind() == io::ErrorKind::TimedOut => {}
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => break Err(error),
+        }
+    };
+    let mut state = shared.state.lock();
+    if let Err(error) = result {
+        error!(?error, name = ?port.name(), "port failed");
+        state.error = Some(error);
+    }
+    trace!(name = ?port.name(), "port closed");
+    state.closed = true;
+    state.wake();
+}
+
+impl AsyncRead for AsyncSerialPort {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.shared.state.lock();
+        if !state.read.is_empty() {
+            let n = buf.len().min(state.read.len());
+            for (dst, src) in buf.iter_mut().zip(state.read.drain(..n)) {
+                *dst = src;
+            }
+            return Poll::Ready(Ok(n));
+        }
+        if let Some(error) = state.error.take() {
+            return Poll::Ready(Err(error));
+        }
+        if state.closed {
+            return Poll::Ready(Ok(0));
+        }
+        state.reader = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for AsyncSerialPort {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.shared.state.lock();
+        if let Some(error) = state.error.take() {
+            return Poll::Ready(Err(error));
+        }
+        if state.closed {
+            return Poll::Ready(Err(io::ErrorKind::NotConnected.into()));
+        }
+        let room = CAPACITY.saturating_sub(state.write.len());
+        if room == 0 {
+            state.writer = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let n = buf.len().min(room);
+        state.write.extend(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.shared.state.lock();
+        if let Some(error) = state.error.take() {
+            return Poll::Ready(Err(error));
+        }
+        if state.closed {
+            return Poll::Ready(Err(io::ErrorKind::NotConnected.into()));
+        }
+        if state.write.is_empty() && state.sending == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        state.writer = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let closed = self.shared.state.lock().closed;
+        if !closed {
+            futures::ready!(self.as_mut().poll_flush(cx))?;
+        }
+        self.shared.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for AsyncSerialPort {
+    // The port thread notices within POLL and closes the port, we do not wait for it
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+impl fmt::Debug for AsyncSerialPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncSerialPort")
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Shared {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared").finish()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use futures::{executor::block_on, AsyncReadExt, AsyncWriteExt};
+    use serialport::TTYPort;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_async_serial_port() {
+        let (mut master, slave) = TTYPort::pair().unwrap();
+        let mut port = AsyncSerialPort::new(Box::new(slave)).unwrap();
+        master.write_all(b"ping").unwrap();
+        let mut buf = [0; 4];
+        block_on(port.read_exact(&mut buf)).unwrap();
+        assert_eq!(&buf, b"ping");
+
+        block_on(port.write_all(b"pong")).unwrap();
+        block_on(port.flush()).unwrap();
+        master.set_timeout(Duration::from_secs(1)).unwrap();
+        master.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"pong");
+
+        port.cancel_handle().cancel();
+        assert_eq!(block_on(port.read(&mut buf)).unwrap(), 0);
+        assert!(block_on(port.write(b"x")).is_err());
+    }
+}
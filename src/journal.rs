@@ -0,0 +1,235 @@
+// journal.rs
+//
+// An audit trail of device connectivity which survives restarts: every event the consumer is given
+// is appended to a file, which is rotated once it grows too large
+
+use crate::{
+    detect::EventInfo,
+    error::{Error, Result},
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use tracing::debug;
+
+/// The size of a journal file past which it is rotated, by default
+const MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// How many rotated files are kept, by default
+const KEEP: usize = 5;
+
+/// Appends events to a file, one JSON object per line with its timestamp, and rotates the file once
+/// it grows past a size. Enabled with the `journal` feature
+///
+/// The journal is opened for appending, so it carries on across restarts of the process. Once the
+/// file is full it is renamed with a `.1` suffix, the older ones shifting to `.2` and so on, and
+/// the oldest beyond [`Journal::keep`] is removed. Read it back with [`Journal::read`]. The lines
+/// are those of a [`crate::Recorder`], so a journal file can be replayed as well
+///
+/// Give it to [`crate::ListenBuilder::journal`] to append every event of a stream, or append the
+/// events yourself
+///
+/// ```no_run
+/// use serialport_detect::Journal;
+///
+/// let journal = Journal::open("/var/log/serialport/events.jsonl")?.max_size(1024 * 1024);
+/// let (abort, events) = serialport_detect::builder().journal(journal).listen()?;
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl Journal {
+    /// Append to `path`, creating it if it does not exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Journal> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Journal {
+            path,
+            writer: BufWriter::new(file),
+            size,
+            max_size: MAX_SIZE,
+            keep: KEEP,
+        })
+    }
+
+    /// Rotate the file once it would grow past `bytes`. 10 MiB by default
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// Keep this many rotated files besides the current one. 5 by default
+    pub fn keep(mut self, files: usize) -> Self {
+        self.keep = files;
+        self
+    }
+
+    /// Append an event. Each event is flushed, so the journal survives a crash
+    pub fn append(&mut self, event: &EventInfo) -> Result<()> {
+        let mut line = serde_json::to_vec(event).map_err(io::Error::from)?;
+        line.push(b'\n');
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.writer.write_all(&line)?;
+        self.writer.flush()?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Shift the rotated files up by one, dropping the oldest, and start a new file
+    fn rotate(&mut self) -> Result<()> {
+        debug!(path = %self.path.display(), "rotating journal");
+        self.writer.flush()?;
+        match self.keep {
+            0 => fs::remove_file(&self.path)?,
+            keep => {
+                remove_if_exists(&rotated(&self.path, keep))?;
+                for n in (1..keep).rev() {
+                    rename_if_exists(&rotated(&self.path, n), &rotated(&self.path, n + 1))?;
+                }
+                fs::rename(&self.path, rotated(&self.path, 1))?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.writer = BufWriter::new(file);
+        self.size = 0;
+        Ok(())
+    }
+
+    /// Read the events of the journal at `path`, oldest first, including the rotated files which
+    /// are still there
+    ///
+    /// A line cut short by a crash while it was written, at the end of a file, is skipped. Any
+    /// other line which is not an event fails with [`Error::Decode`]
+    pub fn read(path: impl AsRef<Path>) -> Result<Vec<EventInfo>> {
+        let path = path.as_ref();
+        let mut files = (1..)
+            .map(|n| rotated(path, n))
+            .take_while(|file| file.exists())
+            .collect::<Vec<_>>();
+        files.reverse();
+        files.push(path.to_path_buf());
+        let mut events = Vec::new();
+        for file in files {
+            let contents = match fs::read_to_string(&file) {
+                Ok(contents) => contents,
+                // The current file is gone while it is being rotated
+                Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+                Err(error) => return Err(error.into()),
+            };
+            events.extend(parse(&contents, &file)?);
+        }
+        Ok(events)
+    }
+}
+
+/// The events of the lines of a journal file
+fn parse(contents: &str, file: &Path) -> Result<Vec<EventInfo>> {
+    let complete = contents.ends_with('\n');
+    let lines = contents.lines().collect::<Vec<_>>();
+    let mut events = Vec::with_capacity(lines.len());
+    for (n, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(event) => events.push(event),
+            Err(_) if !complete && n + 1 == lines.len() => {
+                debug!(file = %file.display(), "skipping a partial line at the end of the journal");
+            }
+            Err(error) => {
+                let what = format!("line {} of {}: {error}", n + 1, file.display());
+                return Err(Error::Decode(what));
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// The name of the `n`th rotated file of the journal at `path` (IE: events.jsonl.2)
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DeviceInfo, EventType};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn test_journal_rotate() {
+        let dir = std::env::temp_dir().join(format!("journal-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("events.jsonl");
+        let event = |port: &str| {
+            let device = DeviceInfo {
+                port: port.to_string(),
+                ..Default::default()
+            };
+            // A fixed timestamp, as the length of the line of a later one may differ
+            let mut event = EventInfo::new(device, EventType::Add);
+            event.timestamp.system = UNIX_EPOCH + Duration::from_millis(1_700_000_000_000);
+            event
+        };
+        let size = serde_json::to_vec(&event("COM1")).unwrap().len() as u64 + 1;
+        // Two events per file, and two rotated files
+        let mut journal = Journal::open(&path).unwrap().max_size(size * 2).keep(2);
+        for n in 1..=5 {
+            journal.append(&event(&format!("COM{n}"))).unwrap();
+        }
+        drop(journal);
+        // Reopening appends to the current file
+        let mut journal = Journal::open(&path).unwrap().max_size(size * 2).keep(2);
+        journal.append(&event("COM6")).unwrap();
+        journal.append(&event("COM7")).unwrap();
+        let ports = |events: Vec<EventInfo>| {
+            events
+                .into_iter()
+                .map(|event| event.device.port)
+                .collect::<Vec<_>>()
+        };
+        let events = Journal::read(&path).unwrap();
+        assert_eq!(ports(events), ["COM3", "COM4", "COM5", "COM6", "COM7"]);
+        assert!(!rotated(&path, 3).exists());
+
+        // A crash in the middle of a line loses that event only
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"device\":").unwrap();
+        let events = Journal::read(&path).unwrap();
+        assert_eq!(ports(events).last().map(String::as_str), Some("COM7"));
+        fs::write(rotated(&path, 1), "garbage\n").unwrap();
+        assert!(matches!(Journal::read(&path), Err(Error::Decode(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -8,7 +8,7 @@
 //! We spawn a thread for each open device to provide an async non blocking API to communicate with
 //! the serialport device. This is considered appropriate because the number of serial ports
 //! connected to a system is considered small. If you prefer a pure async approach, see mio-serial
-//! and tokio-serial crates.
+//! and tokio-serial crates. See [`io::open`].
 
 #![deny(
     clippy::dbg_macro,
@@ -26,33 +26,260 @@
 // doc tests.
 #![doc(test(attr(allow(unused_must_use))))]
 
+mod access;
+mod builder;
+#[cfg(feature = "tokio")]
+mod cancel;
+#[cfg(all(unix, feature = "daemon"))]
+mod daemon;
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+mod dbus;
 mod detect;
+mod error;
+mod filter;
+mod forward;
+mod group;
+#[cfg(any(all(unix, feature = "daemon"), feature = "server"))]
+mod hub;
+mod id;
+#[cfg(feature = "futures")]
+pub mod io;
+#[cfg(feature = "journal")]
+mod journal;
+#[cfg(target_os = "linux")]
+mod logind;
+mod manager;
+mod metrics;
+#[cfg(feature = "mock")]
+mod mock;
+mod monitor;
+mod poll;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod ready;
+#[cfg(feature = "replay")]
+mod replay;
+mod retry;
+mod rules;
+mod seat;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+mod service;
+mod throttle;
+mod timer;
+#[cfg(feature = "futures")]
+mod wait;
+#[cfg(feature = "tokio")]
+mod watch;
 
 #[cfg(windows)]
 mod windows;
 #[cfg(windows)]
-pub use windows::{AbortHandle, EventIter};
+use windows as native;
 
-#[cfg(unix)]
+#[cfg(all(target_os = "linux", feature = "udev", not(feature = "netlink")))]
 mod posix;
-use std::collections::HashMap;
+#[cfg(all(target_os = "linux", feature = "udev", not(feature = "netlink")))]
+use posix as native;
 
-#[cfg(unix)]
-pub use posix::{AbortHandle, EventIter};
+#[cfg(all(target_os = "linux", any(feature = "netlink", not(feature = "udev"))))]
+mod netlink;
+#[cfg(all(target_os = "linux", any(feature = "netlink", not(feature = "udev"))))]
+use netlink as native;
 
-pub use detect::{DeviceInfo, EventInfo, EventType};
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "macos")]
+use macos as native;
+
+#[cfg(target_os = "freebsd")]
+mod freebsd;
+#[cfg(target_os = "freebsd")]
+use freebsd as native;
+
+#[cfg(any(target_os = "openbsd", target_os = "netbsd"))]
+mod bsd;
+#[cfg(any(target_os = "openbsd", target_os = "netbsd"))]
+use bsd as native;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+// The netlink backend only reads devices from sysfs, it has its own listener
+#[cfg_attr(
+    all(target_os = "linux", any(feature = "netlink", not(feature = "udev"))),
+    allow(dead_code)
+)]
+mod sysfs;
+#[cfg(target_os = "android")]
+use sysfs as native;
+
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+mod illumos;
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+use illumos as native;
+
+pub use access::PortHolder;
+pub use builder::{builder, ListenBuilder};
+#[cfg(all(unix, feature = "daemon"))]
+pub use daemon::{daemon_socket, DaemonClient, Subscription};
+pub use detect::{
+    AbortHandle, BlockingIter, DeviceInfo, EventInfo, EventIter, EventType, ListenMode, Overflow,
+    PortType, Stats, Timestamp, UsbClass, UsbSpeed, WindowsBackend,
+};
+pub use error::{Error, Result};
+pub use filter::{Filter, Match};
+pub use forward::EventSender;
+pub use group::{group_by_device, DeviceGroup};
+pub use id::{Pid, Vid};
+#[cfg(feature = "journal")]
+pub use journal::Journal;
+pub use manager::{DeviceManager, RegistrationId};
+pub use metrics::Metrics;
+#[cfg(feature = "metrics")]
+pub use metrics::MetricsFacade;
+#[cfg(feature = "mock")]
+pub use mock::MockBackend;
+#[cfg(feature = "replay")]
+pub use replay::Recorder;
+pub use retry::Retry;
+pub use rules::Rules;
+#[cfg(all(target_os = "linux", feature = "dbus"))]
+pub use service::DbusService;
+pub use throttle::RateLimit;
+#[cfg(feature = "futures")]
+pub use wait::wait_for_device;
+#[cfg(feature = "tokio")]
+pub use watch::DeviceMap;
+
+use std::{collections::HashMap, time::Duration};
 
 /// Listen for events
-pub fn listen() -> std::io::Result<(AbortHandle, EventIter)> {
-    #[cfg(unix)]
-    return posix::listen();
-    #[cfg(windows)]
-    return windows::listen();
-}
-
-pub fn scan() -> std::io::Result<HashMap<String, DeviceInfo>> {
-    #[cfg(unix)]
-    return posix::scan();
-    #[cfg(windows)]
-    return windows::scan();
+///
+/// If the native backend fails to start we fall back to [`listen_polling`]
+pub fn listen() -> Result<(AbortHandle, EventIter)> {
+    builder().listen()
+}
+
+/// Listen for events, starting with an Add event for every device which is already connected
+///
+/// Unlike calling [`scan`] and then [`listen`], there is no window in which an event can be missed
+pub fn listen_with_initial() -> Result<(AbortHandle, EventIter)> {
+    builder().initial(true).listen()
+}
+
+/// Subscribe to the listener shared by the whole process. See [`ListenBuilder::subscribe`]
+pub fn subscribe() -> Result<EventIter> {
+    builder().subscribe()
+}
+
+/// Listen for events, sending them into `sender`. See [`ListenBuilder::listen_into`]
+pub fn listen_into(sender: impl EventSender) -> Result<AbortHandle> {
+    builder().listen_into(sender)
+}
+
+/// Listen for events, receiving them from a bounded `futures` channel which works on any executor.
+/// See [`ListenBuilder::listen_channel`]
+#[cfg(feature = "futures")]
+pub fn listen_channel(
+    capacity: usize,
+) -> Result<(
+    AbortHandle,
+    futures::channel::mpsc::Receiver<Result<EventInfo>>,
+)> {
+    builder().listen_channel(capacity)
+}
+
+/// Listen for events, receiving them from a bounded tokio channel. See
+/// [`ListenBuilder::listen_tokio`]
+#[cfg(feature = "tokio")]
+pub fn listen_tokio(
+    capacity: usize,
+) -> Result<(AbortHandle, tokio::sync::mpsc::Receiver<Result<EventInfo>>)> {
+    builder().listen_tokio(capacity)
+}
+
+/// Listen for events, receiving them from a tokio broadcast channel. See
+/// [`ListenBuilder::listen_broadcast`]
+#[cfg(feature = "tokio")]
+pub fn listen_broadcast(
+    capacity: usize,
+) -> Result<(
+    AbortHandle,
+    tokio::sync::broadcast::Receiver<Result<EventInfo>>,
+)> {
+    builder().listen_broadcast(capacity)
+}
+
+/// Listen for events until `token` is cancelled. See [`ListenBuilder::listen_until`]
+#[cfg(feature = "tokio")]
+pub fn listen_until(token: tokio_util::sync::CancellationToken) -> Result<EventIter> {
+    builder().listen_until(token)
+}
+
+/// Listen for events by calling [`scan`] every `interval` and comparing the results
+///
+/// Works anywhere [`scan`] does, at the cost of latency and some CPU
+pub fn listen_polling(interval: Duration) -> Result<(AbortHandle, EventIter)> {
+    builder().listen_polling(interval)
+}
+
+pub fn scan() -> Result<HashMap<String, DeviceInfo>> {
+    let mut devices = native::scan()?;
+    access::check_all(&mut devices, false);
+    Ok(devices)
+}
+
+/// Look up the device at `port` (IE: /dev/ttyUSB0 or COM3), without enumerating every device
+/// where the platform allows it. None when there is no such port. On Linux and Android links to
+/// the port (IE: /dev/serial/by-id/...) are followed, and the device is reported with its node
+///
+/// ```no_run
+/// if let Some(device) = serialport_detect::port_info("/dev/ttyUSB0")? {
+///     println!("{:?} {:?}", device.manufacturer, device.serial);
+/// }
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+pub fn port_info(port: &str) -> Result<Option<DeviceInfo>> {
+    let mut device = native::port_info(port)?;
+    if let Some(device) = &mut device {
+        access::check(device, false);
+    }
+    Ok(device)
+}
+
+/// The processes other than ours which have `port` open, to tell the user which program to close.
+/// On Linux and Android these are the processes with a file descriptor open on the port, of our
+/// user unless we run as root, and elsewhere the process which holds its UUCP lock file (IE:
+/// /var/lock/LCK..ttyUSB0). Fails with [`std::io::ErrorKind::Unsupported`] on Windows
+///
+/// ```no_run
+/// for holder in serialport_detect::who_has("/dev/ttyUSB0")? {
+///     println!("in use by {} ({:?})", holder.pid, holder.name);
+/// }
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+pub fn who_has(port: &str) -> Result<Vec<PortHolder>> {
+    Ok(access::holders(port)?)
+}
+
+/// The systemd-logind seat of our session (IE: seat1), to listen for the devices of our own seat
+/// on a multi-seat machine with [`ListenBuilder::seat`]. None when the session has no seat (IE:
+/// over ssh), and on other platforms than Linux
+///
+/// ```no_run
+/// let seat = serialport_detect::current_seat().unwrap_or_else(|| "seat0".to_string());
+/// let (abort, events) = serialport_detect::builder().seat(seat).listen()?;
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+pub fn current_seat() -> Option<String> {
+    seat::current()
+}
+
+/// Scan for connected devices, sorted by port name. See [`ListenBuilder::scan_sorted`]
+pub fn scan_sorted() -> Result<Vec<DeviceInfo>> {
+    builder().scan_sorted()
+}
+
+/// Scan for serial ports of every kind, not just USB devices. See [`PortType`]
+pub fn scan_all() -> Result<HashMap<String, DeviceInfo>> {
+    builder().scan_all()
 }
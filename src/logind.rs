@@ -0,0 +1,127 @@
+// logind.rs
+//
+// Devices come and go while the system sleeps and the kernel tells nobody, IE: a laptop which is
+// docked or undocked while asleep. systemd-logind signals PrepareForSleep on the system bus before
+// the system suspends and once it resumed, which is when we rescan. We need the D-Bus client of
+// the dbus feature, without it the listeners never learn of a resume
+
+use crate::detect::Queue;
+use mio::Token;
+use tracing::warn;
+
+#[cfg(feature = "dbus")]
+use crate::dbus::Connection;
+#[cfg(feature = "dbus")]
+use mio::{unix::SourceFd, Interest};
+#[cfg(feature = "dbus")]
+use std::{io, os::fd::AsRawFd};
+#[cfg(feature = "dbus")]
+use tracing::{debug, trace};
+
+#[cfg(feature = "dbus")]
+const MANAGER: &str = "org.freedesktop.login1.Manager";
+#[cfg(feature = "dbus")]
+const PREPARE_FOR_SLEEP: &str = "PrepareForSleep";
+
+/// Our subscription to the sleep signals of logind
+#[cfg(feature = "dbus")]
+#[derive(Debug)]
+pub(crate) struct Sleep {
+    bus: Connection,
+}
+
+#[cfg(feature = "dbus")]
+impl Sleep {
+    /// Subscribe to PrepareForSleep. Fails when the system bus is not available, IE: in a container
+    pub(crate) fn watch() -> io::Result<Sleep> {
+        let mut bus = Connection::system()?;
+        bus.add_match(&format!(
+            "type='signal',interface='{MANAGER}',member='{PREPARE_FOR_SLEEP}'"
+        ))?;
+        debug!("watching for resume from suspend");
+        Ok(Sleep { bus })
+    }
+
+    /// Subscribe when the listener of `queue` rescans on resume. We carry on without it when the
+    /// bus is not available
+    pub(crate) fn watch_for(queue: &Queue) -> Option<Sleep> {
+        if !queue.rescan_on_resume() {
+            return None;
+        }
+        Sleep::watch()
+            .inspect_err(|error| warn!(?error, "failed to watch for resume from suspend"))
+            .ok()
+    }
+
+    /// Whether the system resumed since we last looked. Reads every signal received
+    fn resumed(&mut self) -> io::Result<bool> {
+        let mut resumed = false;
+        for message in self.bus.receive()? {
+            if !message.is_signal(MANAGER, PREPARE_FOR_SLEEP) || message.signature != "b" {
+                continue;
+            }
+            // True before the system sleeps, false once it woke up
+            let sleeping = message.body().bool()?;
+            trace!(sleeping, "prepare for sleep");
+            resumed |= !sleeping;
+        }
+        Ok(resumed)
+    }
+}
+
+/// Wake `poll` with `token` on the signals of logind, or stop watching for them if we can't
+#[cfg(feature = "dbus")]
+pub(crate) fn register(sleep: &mut Option<Sleep>, poll: &mio::Poll, token: Token) {
+    let Some(watching) = sleep else {
+        return;
+    };
+    let fd = watching.bus.as_raw_fd();
+    let registered = poll
+        .registry()
+        .register(&mut SourceFd(&fd), token, Interest::READABLE);
+    if let Err(error) = registered {
+        warn!(?error, "failed to watch for resume from suspend");
+        *sleep = None;
+    }
+}
+
+/// Whether the system resumed since we last looked. We stop watching when we lose the bus
+#[cfg(feature = "dbus")]
+pub(crate) fn resumed(sleep: &mut Option<Sleep>) -> bool {
+    match sleep.as_mut().map(Sleep::resumed) {
+        Some(Ok(resumed)) => resumed,
+        Some(Err(error)) => {
+            warn!(
+                ?error,
+                "lost the system bus, not watching for resume from suspend"
+            );
+            *sleep = None;
+            false
+        }
+        None => false,
+    }
+}
+
+/// Without the dbus feature we can't subscribe, and never hold a subscription
+#[cfg(not(feature = "dbus"))]
+#[derive(Debug)]
+pub(crate) enum Sleep {}
+
+#[cfg(not(feature = "dbus"))]
+impl Sleep {
+    /// Warn when the listener of `queue` asked to rescan on resume, which we can't without the bus
+    pub(crate) fn watch_for(queue: &Queue) -> Option<Sleep> {
+        if queue.rescan_on_resume() {
+            warn!("rescan on resume needs the dbus feature, not watching for resume from suspend");
+        }
+        None
+    }
+}
+
+#[cfg(not(feature = "dbus"))]
+pub(crate) fn register(_sleep: &mut Option<Sleep>, _poll: &mio::Poll, _token: Token) {}
+
+#[cfg(not(feature = "dbus"))]
+pub(crate) fn resumed(_sleep: &mut Option<Sleep>) -> bool {
+    false
+}
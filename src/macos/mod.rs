@@ -0,0 +1,410 @@
+// macOS support
+
+use crate::{
+    detect::{
+        listener_span, usb_stable_id, DeviceInfo, EventInfo, EventType, PortType, Queue, UsbClass,
+        UsbSpeed,
+    },
+    error::{Error, Result},
+    id::{Pid, Vid},
+};
+use core_foundation::{
+    base::{CFType, TCFType},
+    number::CFNumber,
+    runloop::{kCFRunLoopDefaultMode, CFRunLoop, CFRunLoopSource},
+    string::CFString,
+};
+use core_foundation_sys::base::kCFAllocatorDefault;
+use io_kit_sys::{
+    kIOMasterPortDefault,
+    keys::{kIOFirstMatchNotification, kIOServicePlane, kIOTerminatedNotification},
+    serial::keys::{kIOCalloutDeviceKey, kIODialinDeviceKey, kIOSerialBSDServiceValue},
+    types::{io_iterator_t, io_object_t, io_registry_entry_t},
+    IOIteratorNext, IONotificationPortCreate, IONotificationPortDestroy,
+    IONotificationPortGetRunLoopSource, IONotificationPortRef, IOObjectGetClass, IOObjectRelease,
+    IORegistryEntryCreateCFProperty, IORegistryEntryGetParentEntry,
+    IOServiceAddMatchingNotification, IOServiceGetMatchingServices, IOServiceMatching,
+    IOServiceMatchingCallback,
+};
+use mach2::{kern_return::KERN_SUCCESS, port::MACH_PORT_NULL};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_void, CStr},
+    fmt::{self, Debug},
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+use tracing::{error, trace};
+
+/// IOKit classes of the USB device node that owns a serial interface. IOUSBHostDevice is used on
+/// modern systems and IOUSBDevice by the legacy USB stack
+const USB_DEVICE_CLASSES: [&[u8]; 2] = [b"IOUSBHostDevice", b"IOUSBDevice"];
+
+/// IOKit classes of the USB interface a serial port belongs to, on the modern and legacy stacks
+const USB_INTERFACE_CLASSES: [&[u8]; 2] = [b"IOUSBHostInterface", b"IOUSBInterface"];
+
+/// How long the run loop sleeps before checking if it was aborted. [`CFRunLoop::stop`] wakes the
+/// run loop immediately, this only bounds how long an abort racing the startup can be missed
+const RUN_LOOP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Scan for connected devices
+pub fn scan() -> io::Result<HashMap<String, DeviceInfo>> {
+    let mut iter: io_iterator_t = MACH_PORT_NULL;
+    // Safety: IOServiceGetMatchingServices consumes the matching dictionary
+    let result = unsafe {
+        IOServiceGetMatchingServices(
+            kIOMasterPortDefault,
+            IOServiceMatching(kIOSerialBSDServiceValue),
+            &mut iter,
+        )
+    };
+    match result {
+        KERN_SUCCESS => {
+            let iter = Object(iter);
+            let items = drain(iter.0)
+                .filter_map(|service| read_device_info(service.0))
+                .map(|device| (device.port.clone(), device))
+                .collect();
+            Ok(items)
+        }
+        kr => Err(io::Error::other(format!(
+            "IOServiceGetMatchingServices => {kr}"
+        ))),
+    }
+}
+
+/// Look up the device at `port`. There is no cheaper way than a scan
+pub fn port_info(port: &str) -> io::Result<Option<DeviceInfo>> {
+    Ok(scan()?.remove(port))
+}
+
+/// Listen for connected devices
+pub fn listen(queue: Arc<Queue>) -> Result<AbortHandle> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = Arc::clone(&stop);
+    let (tx, rx) = mpsc::channel();
+    let span = listener_span("iokit");
+    let join_handle = std::thread::spawn(move || span.in_scope(|| listener(queue, stopped, tx)));
+    let run_loop = match rx.recv() {
+        Ok(Ok(run_loop)) => run_loop,
+        Ok(Err(error)) => return Err(error),
+        Err(_) => {
+            let error = io::Error::other("listener exited during setup");
+            return Err(Error::Backend(error));
+        }
+    };
+    let abort = AbortHandle {
+        run_loop,
+        stop,
+        join_handle: Some(join_handle),
+    };
+    Ok(abort)
+}
+
+/// State shared with the IOKit matching callbacks
+struct IterState {
+    cache: Mutex<HashMap<String, DeviceInfo>>,
+    queue: Arc<Queue>,
+}
+
+fn listener(queue: Arc<Queue>, stop: Arc<AtomicBool>, ready: mpsc::Sender<Result<CFRunLoop>>) {
+    trace!("listening");
+    let state = IterState {
+        cache: Mutex::new(HashMap::new()),
+        queue,
+    };
+    // Safety: the state outlives the notification port, which is destroyed before we return
+    let (port, iterators) = match unsafe { init_listener(&state) } {
+        Ok(listener) => listener,
+        Err(error) => {
+            error!(?error, "failed to setup listener");
+            let _ = ready.send(Err(error));
+            return;
+        }
+    };
+    let run_loop = CFRunLoop::get_current();
+    // Safety: the run loop source is owned by the notification port
+    let source =
+        unsafe { CFRunLoopSource::wrap_under_get_rule(IONotificationPortGetRunLoopSource(port)) };
+    // Safety: kCFRunLoopDefaultMode is a static CFString
+    let mode = unsafe { kCFRunLoopDefaultMode };
+    run_loop.add_source(&source, mode);
+    if ready.send(Ok(run_loop)).is_ok() {
+        while !stop.load(Ordering::Acquire) {
+            CFRunLoop::run_in_mode(mode, RUN_LOOP_TIMEOUT, false);
+        }
+    }
+    trace!("closing listener");
+    // Safety: we created the port and the callbacks will not fire once it is destroyed
+    unsafe { IONotificationPortDestroy(port) };
+    drop(iterators);
+    state.queue.done();
+    trace!("listener finished");
+}
+
+/// Register for serial device arrival and removal notifications. The iterators of the
+/// notifications are released once the port is destroyed
+///
+/// Safety: state must outlive the returned notification port
+unsafe fn init_listener(state: &IterState) -> Result<(IONotificationPortRef, Vec<Object>)> {
+    let port = IONotificationPortCreate(kIOMasterPortDefault);
+    if port.is_null() {
+        let error = io::Error::other("IONotificationPortCreate failed");
+        return Err(Error::Backend(error));
+    }
+    let refcon = state as *const IterState as *mut c_void;
+    let mut iterators = Vec::with_capacity(2);
+    let notifications: [(*const c_char, IOServiceMatchingCallback); 2] = [
+        (kIOFirstMatchNotification, on_matched),
+        (kIOTerminatedNotification, on_terminated),
+    ];
+    for (kind, callback) in notifications {
+        let mut iter: io_iterator_t = MACH_PORT_NULL;
+        // NOTE IOServiceAddMatchingNotification consumes the matching dictionary, so we need a new
+        //      one for each registration
+        let result = IOServiceAddMatchingNotification(
+            port,
+            kind as *mut c_char,
+            IOServiceMatching(kIOSerialBSDServiceValue),
+            callback,
+            refcon,
+            &mut iter,
+        );
+        if result != KERN_SUCCESS {
+            IONotificationPortDestroy(port);
+            drop(iterators);
+            let error = io::Error::other(format!("IOServiceAddMatchingNotification => {result}"));
+            return Err(Error::Registration(error));
+        }
+        // The notification is only armed once the iterator has been drained. The devices already
+        // present are not events, so we only remember them for their removal
+        for service in drain(iter) {
+            if let Some(device) = read_device_info(service.0) {
+                state.cache.lock().insert(device.port.clone(), device);
+            }
+        }
+        iterators.push(Object(iter));
+    }
+    Ok((port, iterators))
+}
+
+/// Called by the run loop when a serial device has been added
+///
+/// Safety: refcon must be a pointer to IterState
+unsafe extern "C" fn on_matched(refcon: *mut c_void, iter: io_iterator_t) {
+    let state = &*(refcon as *const IterState);
+    for service in drain(iter) {
+        if let Some(device) = read_device_info(service.0) {
+            trace!(port = device.port, "device added");
+            state
+                .cache
+                .lock()
+                .insert(device.port.clone(), device.clone());
+            state.queue.push(Ok(EventInfo::new(device, EventType::Add)));
+        }
+    }
+}
+
+/// Called by the run loop when a serial device has been removed
+///
+/// Safety: refcon must be a pointer to IterState
+unsafe extern "C" fn on_terminated(refcon: *mut c_void, iter: io_iterator_t) {
+    let state = &*(refcon as *const IterState);
+    for service in drain(iter) {
+        // NOTE the USB parent of a terminated service may already be gone, so we prefer what we
+        //      learned about the device when it arrived
+        let device = string_property(service.0, kIOCalloutDeviceKey).and_then(|port| {
+            state
+                .cache
+                .lock()
+                .remove(&port)
+                .or_else(|| read_device_info(service.0))
+        });
+        if let Some(device) = device {
+            trace!(port = device.port, "device removed");
+            state
+                .queue
+                .push(Ok(EventInfo::new(device, EventType::Remove)));
+        }
+    }
+}
+
+/// An owned IOKit object which is released when dropped
+struct Object(io_object_t);
+
+impl Drop for Object {
+    fn drop(&mut self) {
+        // Safety: we own a reference to the object
+        unsafe { IOObjectRelease(self.0) };
+    }
+}
+
+/// Drain an IOKit iterator
+fn drain(iter: io_iterator_t) -> impl Iterator<Item = Object> {
+    // Safety: IOIteratorNext returns an object we own, or MACH_PORT_NULL when exhausted
+    std::iter::from_fn(move || match unsafe { IOIteratorNext(iter) } {
+        MACH_PORT_NULL => None,
+        object => Some(Object(object)),
+    })
+}
+
+fn read_device_info(service: io_registry_entry_t) -> Option<DeviceInfo> {
+    let port = string_property(service, kIOCalloutDeviceKey)?;
+    let usb = usb_device(service);
+    let usb = usb.as_ref().map(|usb| usb.0);
+    let id = |key: &CStr| {
+        usb.and_then(|usb| int_property(usb, key.as_ptr()))
+            .and_then(|id| u16::try_from(id).ok())
+    };
+    let (vid, pid) = (id(c"idVendor"), id(c"idProduct"));
+    let serial = usb.and_then(|usb| string_property(usb, c"USB Serial Number".as_ptr()));
+    let device = DeviceInfo {
+        port,
+        vid: vid.map(|vid| Vid(vid).to_string()),
+        pid: pid.map(|pid| Pid(pid).to_string()),
+        vid_u16: vid,
+        pid_u16: pid,
+        bcd_device: id(c"bcdDevice"),
+        stable_id: usb_stable_id(vid, pid, serial.as_deref(), None),
+        serial,
+        manufacturer: usb.and_then(|usb| string_property(usb, c"USB Vendor Name".as_ptr())),
+        product: usb.and_then(|usb| string_property(usb, c"USB Product Name".as_ptr())),
+        port_type: match usb {
+            Some(_) => PortType::Usb,
+            None => PortType::Unknown,
+        },
+        // The bus number and the chain of hub ports, a nibble each
+        location: usb
+            .and_then(|usb| int_property(usb, c"locationID".as_ptr()))
+            .map(|id| format!("{id:#010x}")),
+        usb_speed: usb
+            .and_then(|usb| int_property(usb, c"Device Speed".as_ptr()))
+            .and_then(usb_speed),
+        interface_class: interface_class(service),
+        dialin: string_property(service, kIODialinDeviceKey),
+        ..Default::default()
+    };
+    Some(device)
+}
+
+/// The Device Speed property of the USB device, an index into the speeds IOKit knows of
+fn usb_speed(speed: i64) -> Option<UsbSpeed> {
+    match speed {
+        0 => Some(UsbSpeed::Low),
+        1 => Some(UsbSpeed::Full),
+        2 => Some(UsbSpeed::High),
+        3 => Some(UsbSpeed::Super),
+        4 | 5 => Some(UsbSpeed::SuperPlus),
+        _ => None,
+    }
+}
+
+/// Walk up the service plane to the USB device which owns this serial interface
+fn usb_device(service: io_registry_entry_t) -> Option<Object> {
+    ancestor(service, &USB_DEVICE_CLASSES)
+}
+
+/// The class codes of the USB interface this serial port belongs to
+fn interface_class(service: io_registry_entry_t) -> Option<UsbClass> {
+    let interface = ancestor(service, &USB_INTERFACE_CLASSES)?;
+    let code = |key: &CStr| {
+        int_property(interface.0, key.as_ptr()).and_then(|code| u8::try_from(code).ok())
+    };
+    Some(UsbClass {
+        class: code(c"bInterfaceClass")?,
+        subclass: code(c"bInterfaceSubClass")?,
+        protocol: code(c"bInterfaceProtocol")?,
+    })
+}
+
+/// Walk up the service plane to the nearest ancestor of one of the IOKit `classes`
+fn ancestor(service: io_registry_entry_t, classes: &[&[u8]]) -> Option<Object> {
+    let mut entry = parent(service)?;
+    loop {
+        let mut class = [0 as c_char; 128];
+        // Safety: io_name_t is a 128 byte buffer
+        unsafe { IOObjectGetClass(entry.0, class.as_mut_ptr()) };
+        // Safety: IOObjectGetClass writes a null terminated string
+        let class = unsafe { CStr::from_ptr(class.as_ptr()) };
+        if classes.contains(&class.to_bytes()) {
+            break Some(entry);
+        }
+        entry = parent(entry.0)?;
+    }
+}
+
+fn parent(entry: io_registry_entry_t) -> Option<Object> {
+    let mut parent: io_registry_entry_t = MACH_PORT_NULL;
+    match unsafe { IORegistryEntryGetParentEntry(entry, kIOServicePlane, &mut parent) } {
+        KERN_SUCCESS => Some(Object(parent)),
+        _ => None,
+    }
+}
+
+fn property(entry: io_registry_entry_t, key: *const c_char) -> Option<CFType> {
+    // Safety: all of our keys are static null terminated strings
+    let key = unsafe { CStr::from_ptr(key) }.to_str().ok()?;
+    let key = CFString::new(key);
+    let value = unsafe {
+        IORegistryEntryCreateCFProperty(entry, key.as_concrete_TypeRef(), kCFAllocatorDefault, 0)
+    };
+    match value.is_null() {
+        true => None,
+        // Safety: IORegistryEntryCreateCFProperty follows the create rule
+        false => Some(unsafe { CFType::wrap_under_create_rule(value) }),
+    }
+}
+
+fn string_property(entry: io_registry_entry_t, key: *const c_char) -> Option<String> {
+    property(entry, key)?
+        .downcast::<CFString>()
+        .map(|s| s.to_string())
+}
+
+fn int_property(entry: io_registry_entry_t, key: *const c_char) -> Option<i64> {
+    property(entry, key)?
+        .downcast::<CFNumber>()
+        .and_then(|n| n.to_i64())
+}
+
+/// The AbortHandle will cause the [`crate::EventIter`] to stop emitting events when dropped
+pub struct AbortHandle {
+    run_loop: CFRunLoop,
+    stop: Arc<AtomicBool>,
+    join_handle: Option<JoinHandle<()>>,
+}
+
+impl Debug for AbortHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AbortHandle").finish()
+    }
+}
+
+impl AbortHandle {
+    /// False once the listener thread has exited
+    pub(crate) fn is_alive(&self) -> bool {
+        self.join_handle
+            .as_ref()
+            .is_some_and(|jh| !jh.is_finished())
+    }
+}
+
+impl Drop for AbortHandle {
+    // We signal the remote run loop to stop, and then we join
+    fn drop(&mut self) {
+        trace!("dropping event iter");
+        if let Some(jh) = self.join_handle.take() {
+            self.stop.store(true, Ordering::Release);
+            self.run_loop.stop();
+            if let Err(error) = jh.join() {
+                error!(?error, "event iter join error");
+            }
+        }
+    }
+}
@@ -0,0 +1,205 @@
+// manager.rs
+use crate::{
+    detect::{AbortHandle, DeviceInfo, EventType},
+    error::Result,
+    ListenBuilder,
+};
+use parking_lot::Mutex;
+use std::{collections::HashMap, fmt, sync::Arc};
+use tracing::{debug, trace};
+
+/// Tracks which devices are present, and tells each registration when its devices connect and
+/// disconnect
+///
+/// Callbacks run on a thread of the manager, one at a time. A registration is told about the
+/// devices which are already connected when it is made
+///
+/// ```no_run
+/// use serialport_detect::DeviceManager;
+///
+/// let manager = DeviceManager::new()?;
+/// let ftdi = manager.register(
+///     serialport_detect::builder().vid(0x0403),
+///     |device| println!("connected {}", device.port),
+///     |device| println!("disconnected {}", device.port),
+/// );
+/// // ...
+/// manager.unregister(ftdi);
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+pub struct DeviceManager {
+    shared: Arc<Mutex<State>>,
+    _abort: AbortHandle,
+}
+
+/// Identifies a registration of a [`DeviceManager`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RegistrationId(u64);
+
+#[derive(Default)]
+struct State {
+    devices: HashMap<String, DeviceInfo>,
+    registrations: HashMap<RegistrationId, Registration>,
+    next_id: u64,
+}
+
+struct Registration {
+    filter: ListenBuilder,
+    /// The connected devices of the registration, keyed by port name
+    present: HashMap<String, DeviceInfo>,
+    callbacks: Arc<Mutex<Callbacks>>,
+}
+
+type Callback = Box<dyn FnMut(&DeviceInfo) + Send>;
+
+struct Callbacks {
+    on_connected: Callback,
+    on_disconnected: Callback,
+}
+
+/// A notification to deliver once the state is unlocked
+enum Notify {
+    Connected(DeviceInfo),
+    Disconnected(DeviceInfo),
+}
+
+impl DeviceManager {
+    /// Start tracking devices
+    pub fn new() -> Result<DeviceManager> {
+        let (abort, events) = crate::builder().initial(true).listen()?;
+        let shared = Arc::new(Mutex::new(State::default()));
+        let thread = Arc::clone(&shared);
+        std::thread::Builder::new()
+            .name("serialport-detect-manager".into())
+            .spawn(move || {
+                for event in events {
+                    match event {
+                        Ok(event) => event
+                            .split_move()
+                            .into_iter()
+                            .for_each(|event| dispatch(&thread, event.event, event.device)),
+                        Err(error) => debug!(?error, "device manager listener error"),
+                    }
+                }
+                trace!("device manager finished");
+            })?;
+        Ok(DeviceManager {
+            shared,
+            _abort: abort,
+        })
+    }
+
+    /// Call `on_connected` with every device matching `filter` as it connects, starting with the
+    /// ones which are already connected, and `on_disconnected` as they go away
+    pub fn register<C, D>(
+        &self,
+        filter: ListenBuilder,
+        on_connected: C,
+        on_disconnected: D,
+    ) -> RegistrationId
+    where
+        C: FnMut(&DeviceInfo) + Send + 'static,
+        D: FnMut(&DeviceInfo) + Send + 'static,
+    {
+        let callbacks = Arc::new(Mutex::new(Callbacks {
+            on_connected: Box::new(on_connected),
+            on_disconnected: Box::new(on_disconnected),
+        }));
+        let mut state = self.shared.lock();
+        let id = RegistrationId(state.next_id);
+        state.next_id += 1;
+        let present = state
+            .devices
+            .iter()
+            .filter(|(_, device)| filter.matches(device))
+            .map(|(port, device)| (port.clone(), device.clone()))
+            .collect::<HashMap<_, _>>();
+        let registration = Registration {
+            filter,
+            present: present.clone(),
+            callbacks: Arc::clone(&callbacks),
+        };
+        state.registrations.insert(id, registration);
+        // Hold the callbacks before unlocking, so that the manager thread can only notify this
+        // registration after we did
+        let mut callbacks = callbacks.lock();
+        drop(state);
+        for device in present.values() {
+            (callbacks.on_connected)(device);
+        }
+        id
+    }
+
+    /// Stop notifying a registration
+    pub fn unregister(&self, id: RegistrationId) {
+        self.shared.lock().registrations.remove(&id);
+    }
+
+    /// Every connected device, keyed by port name
+    pub fn devices(&self) -> HashMap<String, DeviceInfo> {
+        self.shared.lock().devices.clone()
+    }
+
+    /// The connected devices of a registration, keyed by port name
+    pub fn connected(&self, id: RegistrationId) -> HashMap<String, DeviceInfo> {
+        self.shared
+            .lock()
+            .registrations
+            .get(&id)
+            .map(|registration| registration.present.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Update the state with an event, and notify the registrations it concerns
+fn dispatch(shared: &Mutex<State>, event: EventType, device: DeviceInfo) {
+    let mut notify = Vec::new();
+    {
+        let mut state = shared.lock();
+        let State {
+            devices,
+            registrations,
+            ..
+        } = &mut *state;
+        match event {
+            // A Moved was split into a Remove and an Add
+            EventType::Add | EventType::Change | EventType::Moved => {
+                devices.insert(device.port.clone(), device.clone());
+                for registration in registrations.values_mut() {
+                    if registration.filter.matches(&device)
+                        && !registration.present.contains_key(&device.port)
+                    {
+                        registration
+                            .present
+                            .insert(device.port.clone(), device.clone());
+                        let callbacks = Arc::clone(&registration.callbacks);
+                        notify.push((callbacks, Notify::Connected(device.clone())));
+                    }
+                }
+            }
+            EventType::Remove => {
+                devices.remove(&device.port);
+                for registration in registrations.values_mut() {
+                    if let Some(device) = registration.present.remove(&device.port) {
+                        let callbacks = Arc::clone(&registration.callbacks);
+                        notify.push((callbacks, Notify::Disconnected(device)));
+                    }
+                }
+            }
+        }
+    }
+    // Callbacks may call back into the manager, so they run without the state locked
+    for (callbacks, notify) in notify {
+        let mut callbacks = callbacks.lock();
+        match notify {
+            Notify::Connected(device) => (callbacks.on_connected)(&device),
+            Notify::Disconnected(device) => (callbacks.on_disconnected)(&device),
+        }
+    }
+}
+
+impl fmt::Debug for DeviceManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceManager").finish()
+    }
+}
@@ -0,0 +1,257 @@
+// metrics.rs
+use crate::{detect::EventInfo, error::Error};
+use std::fmt::Debug;
+
+/// Receives the counters of a listener, to bridge them into a telemetry system. See
+/// [`crate::ListenBuilder::metrics`]
+///
+/// Every method does nothing by default, so only the ones of interest need implementing. They are
+/// called from the listener thread and from the consumer of the stream, so they should be quick
+///
+/// ```no_run
+/// use serialport_detect::{EventInfo, Metrics};
+/// use std::sync::{
+///     atomic::{AtomicU64, Ordering},
+///     Arc,
+/// };
+///
+/// #[derive(Debug, Default)]
+/// struct Counter(AtomicU64);
+///
+/// impl Metrics for Counter {
+///     fn events_emitted(&self, _event: &EventInfo) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let counter = Arc::new(Counter::default());
+/// let (abort, events) = serialport_detect::builder().metrics(counter.clone()).listen()?;
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+pub trait Metrics: Debug + Send + Sync {
+    /// An event was queued for the consumer. Events of filtered out devices are not counted
+    fn events_emitted(&self, _event: &EventInfo) {}
+
+    /// Events were dropped because the queue was full. See [`crate::Overflow`]
+    fn events_dropped(&self, _count: u64) {}
+
+    /// Events were dropped because they were over the rate limit. See [`crate::RateLimit`]
+    fn events_throttled(&self, _count: u64) {}
+
+    /// The listener reported an error
+    fn errors(&self, _error: &Error) {}
+
+    /// The number of events waiting for the consumer, after it changed
+    fn queue_depth(&self, _depth: usize) {}
+}
+
+/// Reports the counters of a listener to the [`metrics`](https://docs.rs/metrics) facade, from
+/// which any of its exporters publishes them, IE: to Prometheus. Enabled with the `metrics` feature
+///
+/// | Name                                       | Kind    | Labels  |
+/// |--------------------------------------------|---------|---------|
+/// | `serialport_detect_events_total`           | counter | `event` |
+/// | `serialport_detect_events_dropped_total`   | counter |         |
+/// | `serialport_detect_events_throttled_total` | counter |         |
+/// | `serialport_detect_errors_total`           | counter | `error` |
+/// | `serialport_detect_queue_depth`            | gauge   |         |
+/// | `serialport_detect_devices_present`        | gauge   |         |
+/// | `serialport_detect_device_present`         | gauge   | `port`  |
+///
+/// `device_present` is 1 while the device at a port is connected and 0 once it is gone, which is
+/// what to alert on. The devices are counted from the events of the stream, so listen with
+/// [`crate::ListenBuilder::initial`] for the devices connected before it started to count
+///
+/// ```no_run
+/// use serialport_detect::MetricsFacade;
+/// use std::sync::Arc;
+///
+/// // Install an exporter of the metrics crate first, IE: metrics_exporter_prometheus
+/// let (abort, events) = serialport_detect::builder()
+///     .initial(true)
+///     .metrics(Arc::new(MetricsFacade::new()))
+///     .listen()?;
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+pub struct MetricsFacade {
+    present: parking_lot::Mutex<std::collections::HashSet<String>>,
+}
+
+#[cfg(feature = "metrics")]
+impl MetricsFacade {
+    /// Describe the metrics to the installed recorder
+    pub fn new() -> MetricsFacade {
+        use ::metrics::{describe_counter, describe_gauge};
+        describe_counter!(
+            "serialport_detect_events_total",
+            "Events reported to the consumer"
+        );
+        describe_counter!(
+            "serialport_detect_events_dropped_total",
+            "Events dropped because the queue was full"
+        );
+        describe_counter!(
+            "serialport_detect_events_throttled_total",
+            "Events dropped because they were over the rate limit"
+        );
+        describe_counter!(
+            "serialport_detect_errors_total",
+            "Errors reported by the listener"
+        );
+        describe_gauge!(
+            "serialport_detect_queue_depth",
+            "Events waiting for the consumer"
+        );
+        describe_gauge!("serialport_detect_devices_present", "Connected devices");
+        describe_gauge!(
+            "serialport_detect_device_present",
+            "Whether the device at a port is connected"
+        );
+        MetricsFacade::default()
+    }
+
+    /// Mark `port` connected or not, and update the number of connected devices
+    fn present(&self, port: &str, connected: bool) {
+        let mut present = self.present.lock();
+        match connected {
+            true => present.insert(port.to_string()),
+            false => present.remove(port),
+        };
+        ::metrics::gauge!("serialport_detect_devices_present").set(present.len() as f64);
+        ::metrics::gauge!("serialport_detect_device_present", "port" => port.to_string())
+            .set(if connected { 1.0 } else { 0.0 });
+    }
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics for MetricsFacade {
+    fn events_emitted(&self, event: &EventInfo) {
+        use crate::detect::EventType;
+        let label = match event.event {
+            EventType::Add => "add",
+            EventType::Remove => "remove",
+            EventType::Change => "change",
+            EventType::Moved => "move",
+        };
+        ::metrics::counter!("serialport_detect_events_total", "event" => label).increment(1);
+        match event.event {
+            EventType::Add => self.present(&event.device.port, true),
+            EventType::Remove => self.present(&event.device.port, false),
+            EventType::Moved => {
+                if let Some(old_port) = &event.old_port {
+                    self.present(old_port, false);
+                }
+                self.present(&event.device.port, true);
+            }
+            EventType::Change => {}
+        }
+    }
+
+    fn events_dropped(&self, count: u64) {
+        ::metrics::counter!("serialport_detect_events_dropped_total").increment(count);
+    }
+
+    fn events_throttled(&self, count: u64) {
+        ::metrics::counter!("serialport_detect_events_throttled_total").increment(count);
+    }
+
+    fn errors(&self, error: &Error) {
+        let label = match error {
+            Error::Backend(_) => "backend",
+            Error::Registration(_) => "registration",
+            Error::PermissionDenied(_) => "permission_denied",
+            Error::Decode(_) => "decode",
+            Error::Overflow { .. } => "overflow",
+            Error::Throttled { .. } => "throttled",
+            Error::Timeout => "timeout",
+            Error::Io(_) => "io",
+        };
+        ::metrics::counter!("serialport_detect_errors_total", "error" => label).increment(1);
+    }
+
+    fn queue_depth(&self, depth: usize) {
+        ::metrics::gauge!("serialport_detect_queue_depth").set(depth as f64);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        detect::{DeviceInfo, EventIter, EventType, Overflow, Queue},
+        filter::Filter,
+    };
+    use parking_lot::Mutex;
+    use std::{io, sync::Arc};
+
+    #[derive(Debug, Default)]
+    struct Recorder {
+        emitted: Mutex<Vec<String>>,
+        dropped: Mutex<u64>,
+        errors: Mutex<Vec<String>>,
+        depth: Mutex<Vec<usize>>,
+    }
+
+    impl Metrics for Recorder {
+        fn events_emitted(&self, event: &EventInfo) {
+            self.emitted.lock().push(event.device.port.clone());
+        }
+
+        fn events_dropped(&self, count: u64) {
+            *self.dropped.lock() += count;
+        }
+
+        fn errors(&self, error: &Error) {
+            self.errors.lock().push(error.to_string());
+        }
+
+        fn queue_depth(&self, depth: usize) {
+            self.depth.lock().push(depth);
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let recorder = Arc::new(Recorder::default());
+        let queue = Queue::with_filter(Filter::new().vid(0x0403))
+            .with_capacity(Some(2), Overflow::DropNewest)
+            .with_metrics(Some(recorder.clone()));
+        let queue = Arc::new(queue);
+        for (port, vid) in [("a", 0x0403), ("b", 0x1a86), ("c", 0x0403), ("d", 0x0403)] {
+            let device = DeviceInfo {
+                port: port.to_string(),
+                vid_u16: Some(vid),
+                ..Default::default()
+            };
+            queue.push(Ok(EventInfo::new(device, EventType::Add)));
+        }
+        queue.done();
+        let events = EventIter::new(Arc::clone(&queue)).into_iter();
+        assert_eq!(2, events.count());
+        queue.push(Err(io::Error::other("gone")));
+        assert_eq!(*recorder.emitted.lock(), ["a", "c"]);
+        assert_eq!(*recorder.dropped.lock(), 1);
+        assert_eq!(recorder.errors.lock().len(), 1);
+        assert_eq!(*recorder.depth.lock(), [1, 2, 2, 1, 0, 1]);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_metrics_facade_present() {
+        let facade = MetricsFacade::new();
+        let event = |port: &str, kind, old_port: Option<&str>| {
+            let mut event = crate::detect::test::event(port, kind);
+            event.old_port = old_port.map(str::to_string);
+            event
+        };
+        facade.events_emitted(&event("a", EventType::Add, None));
+        facade.events_emitted(&event("b", EventType::Add, None));
+        facade.events_emitted(&event("b", EventType::Change, None));
+        facade.events_emitted(&event("c", EventType::Moved, Some("a")));
+        facade.events_emitted(&event("b", EventType::Remove, None));
+        let present = facade.present.lock().iter().cloned().collect::<Vec<_>>();
+        assert_eq!(present, ["c"]);
+    }
+}
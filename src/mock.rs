@@ -0,0 +1,199 @@
+// mock.rs
+use crate::{
+    detect::{DeviceInfo, EventInfo, EventType, Queue},
+    error::{Error, Result},
+    EventIter,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Weak},
+};
+
+/// A backend driven by the caller instead of the OS, to test hotplug handling without hardware.
+/// Enabled with the `mock` feature
+///
+/// Listeners of the mock receive the events pushed to it after they started, through the same
+/// [`EventIter`] as a real listener. Filters and the other [`crate::ListenBuilder`] options apply
+///
+/// ```no_run
+/// use futures::StreamExt;
+/// use serialport_detect::{DeviceInfo, MockBackend};
+///
+/// # async fn run() -> serialport_detect::Result<()> {
+/// let mock = MockBackend::new();
+/// let (abort, mut events) = mock.listen()?;
+/// mock.push_add(DeviceInfo {
+///     port: "/dev/ttyUSB0".to_string(),
+///     vid_u16: Some(0x0403),
+///     ..Default::default()
+/// });
+/// mock.push_remove("/dev/ttyUSB0");
+/// let added = events.next().await;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    devices: HashMap<String, DeviceInfo>,
+    listeners: Vec<Weak<Queue>>,
+}
+
+impl MockBackend {
+    /// A mock with no devices connected
+    pub fn new() -> MockBackend {
+        MockBackend::default()
+    }
+
+    /// Listen for the events of every device. See [`crate::ListenBuilder::listen_mock`]
+    pub fn listen(&self) -> Result<(crate::AbortHandle, EventIter)> {
+        crate::builder().listen_mock(self)
+    }
+
+    /// The connected devices, keyed by port name
+    pub fn scan(&self) -> HashMap<String, DeviceInfo> {
+        self.inner.lock().devices.clone()
+    }
+
+    /// Connect a device
+    pub fn push_add(&self, device: DeviceInfo) {
+        let mut inner = self.inner.lock();
+        inner.devices.insert(device.port.clone(), device.clone());
+        inner.push(EventType::Add, device);
+    }
+
+    /// Disconnect the device at `port`. The event carries the properties the device was added
+    /// with, like the native backends
+    pub fn push_remove(&self, port: &str) {
+        let mut inner = self.inner.lock();
+        let device = inner.devices.remove(port).unwrap_or_else(|| DeviceInfo {
+            port: port.to_string(),
+            ..Default::default()
+        });
+        inner.push(EventType::Remove, device);
+    }
+
+    /// Change the properties of a connected device
+    pub fn push_change(&self, device: DeviceInfo) {
+        let mut inner = self.inner.lock();
+        inner.devices.insert(device.port.clone(), device.clone());
+        inner.push(EventType::Change, device);
+    }
+
+    /// Yield `error` from the stream of every listener
+    pub fn push_error(&self, error: Error) {
+        self.inner.lock().each(|queue| queue.error(error.clone()));
+    }
+
+    /// Start forwarding events into `queue`. The devices already connected are returned for the
+    /// initial events
+    pub(crate) fn attach(&self, queue: &Arc<Queue>) -> (AbortHandle, HashMap<String, DeviceInfo>) {
+        let mut inner = self.inner.lock();
+        inner.listeners.push(Arc::downgrade(queue));
+        // Events are pushed with the lock held, so the snapshot cannot miss one
+        queue.start_snapshot(inner.devices.clone());
+        let handle = AbortHandle {
+            queue: Arc::clone(queue),
+        };
+        (handle, inner.devices.clone())
+    }
+}
+
+impl Inner {
+    fn push(&mut self, event: EventType, device: DeviceInfo) {
+        let event = EventInfo::new(device, event);
+        self.each(|queue| queue.push(Ok(event.clone())));
+    }
+
+    /// Call `f` with every listener, forgetting the ones which were dropped
+    fn each(&mut self, f: impl Fn(&Queue)) {
+        self.listeners.retain(|listener| match listener.upgrade() {
+            Some(queue) => {
+                f(&queue);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+/// Ends the stream of a mock listener when dropped
+pub(crate) struct AbortHandle {
+    queue: Arc<Queue>,
+}
+
+impl fmt::Debug for AbortHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AbortHandle").finish()
+    }
+}
+
+impl Drop for AbortHandle {
+    fn drop(&mut self) {
+        self.queue.done();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_mock_events() {
+        let device = |port: &str, vid| DeviceInfo {
+            port: port.to_string(),
+            vid_u16: Some(vid),
+            ..Default::default()
+        };
+        let mock = MockBackend::new();
+        mock.push_add(device("/dev/ttyUSB0", 0x0403));
+        let (abort, mut events) = crate::builder()
+            .vid(0x0403)
+            .initial(true)
+            .listen_mock(&mock)
+            .unwrap();
+        mock.push_add(device("/dev/ttyACM0", 0x2341));
+        mock.push_add(device("/dev/ttyUSB1", 0x0403));
+        mock.push_remove("/dev/ttyUSB0");
+        let mut next = || {
+            let event = events.next_timeout(Duration::ZERO).unwrap().unwrap();
+            (event.event, event.device.port, event.seq)
+        };
+        assert!(matches!(next(), (EventType::Add, port, 0) if port == "/dev/ttyUSB0"));
+        assert!(matches!(next(), (EventType::Add, port, 1) if port == "/dev/ttyUSB1"));
+        assert!(matches!(next(), (EventType::Remove, port, 2) if port == "/dev/ttyUSB0"));
+        abort.shutdown();
+        assert!(matches!(events.next_timeout(Duration::ZERO), Ok(None)));
+    }
+
+    #[test]
+    fn test_mock_snapshot() {
+        let device = |port: &str, vid| DeviceInfo {
+            port: port.to_string(),
+            vid_u16: Some(vid),
+            ..Default::default()
+        };
+        let mock = MockBackend::new();
+        mock.push_add(device("/dev/ttyUSB0", 0x0403));
+        mock.push_add(device("/dev/ttyACM0", 0x2341));
+        let (abort, events) = crate::builder().vid(0x0403).listen_mock(&mock).unwrap();
+        let ports = |events: &EventIter| {
+            let mut ports = events.snapshot().unwrap().into_keys().collect::<Vec<_>>();
+            ports.sort();
+            ports
+        };
+        assert_eq!(vec!["/dev/ttyUSB0"], ports(&events));
+        // Followed while paused, and before the stream yields them
+        abort.pause();
+        mock.push_add(device("/dev/ttyUSB1", 0x0403));
+        mock.push_remove("/dev/ttyUSB0");
+        assert_eq!(vec!["/dev/ttyUSB1"], ports(&events));
+    }
+}
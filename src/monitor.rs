@@ -0,0 +1,114 @@
+// monitor.rs
+use crate::{
+    detect::{AbortHandle, EventInfo, Queue},
+    error::Result,
+    retry::Retry,
+};
+use parking_lot::{const_mutex, Mutex};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Weak,
+};
+use tracing::{error, trace};
+
+/// The listener shared by every subscriber in the process. See [`crate::subscribe`]
+struct Monitor {
+    /// Tells a forwarding thread apart from the one of a monitor which replaced it
+    id: u64,
+    abort: AbortHandle,
+    subscribers: Vec<Weak<Queue>>,
+}
+
+static MONITOR: Mutex<Option<Monitor>> = const_mutex(None);
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Forward the events of the shared listener into `queue`, starting the listener if it is not
+/// running. The queue applies its own filter, so the shared listener reports every device
+pub(crate) fn subscribe(queue: &Arc<Queue>) -> Result<()> {
+    let mut monitor = MONITOR.lock();
+    if let Some(monitor) = monitor.as_mut().filter(|monitor| monitor.abort.is_alive()) {
+        monitor.subscribers.push(Arc::downgrade(queue));
+        return Ok(());
+    }
+    // The thread of a listener which exited may still be draining its stream, but it will not
+    // find its subscribers once the monitor is replaced, so their streams are ended here
+    if let Some(stale) = monitor.take() {
+        trace!("replacing stopped shared monitor");
+        stale.end();
+    }
+    trace!("starting shared monitor");
+    let (abort, events) = crate::builder()
+        .changes(true)
+        .retry(Retry::new())
+        .listen()?;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    std::thread::Builder::new()
+        .name("serialport-detect-monitor".into())
+        .spawn(move || {
+            for event in events {
+                if !forward(id, event) {
+                    return;
+                }
+            }
+            finish(id);
+        })
+        .map_err(|error| {
+            error!(?error, "failed to spawn the monitor thread");
+            error
+        })?;
+    *monitor = Some(Monitor {
+        id,
+        abort,
+        subscribers: vec![Arc::downgrade(queue)],
+    });
+    Ok(())
+}
+
+/// Push an event to every subscriber. Returns false when the monitor should stop, because every
+/// subscriber is gone or the monitor was replaced
+fn forward(id: u64, event: Result<EventInfo>) -> bool {
+    let mut guard = MONITOR.lock();
+    let Some(monitor) = guard.as_mut().filter(|monitor| monitor.id == id) else {
+        return false;
+    };
+    monitor
+        .subscribers
+        .retain(|subscriber| match subscriber.upgrade() {
+            Some(queue) => {
+                match &event {
+                    Ok(event) => queue.push(Ok(event.clone())),
+                    Err(error) => queue.error(error.clone()),
+                }
+                true
+            }
+            None => false,
+        });
+    if monitor.subscribers.is_empty() {
+        trace!("no subscribers left, stopping shared monitor");
+        *guard = None;
+        return false;
+    }
+    true
+}
+
+/// The shared listener stopped, end the stream of every subscriber
+fn finish(id: u64) {
+    let mut guard = MONITOR.lock();
+    if guard.as_ref().is_some_and(|monitor| monitor.id == id) {
+        trace!("shared monitor finished");
+        if let Some(monitor) = guard.take() {
+            monitor.end();
+        }
+    }
+}
+
+impl Monitor {
+    /// End the stream of every subscriber still around
+    fn end(self) {
+        for queue in self.subscribers {
+            if let Some(queue) = queue.upgrade() {
+                queue.done();
+            }
+        }
+    }
+}
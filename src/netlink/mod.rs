@@ -0,0 +1,325 @@
+// Netlink support
+//
+// Used on Linux without libudev, with the `netlink` feature or without the `udev` feature. We
+// listen to the uevents the kernel broadcasts on a netlink socket, and read the USB descriptors of
+// the device from sysfs
+
+use crate::{
+    detect::{listener_span, DeviceInfo, EventInfo, EventType, ListenMode, Queue, UdevMatch},
+    error::{Error, Result},
+    logind::{self, Sleep},
+    poll, ready, retry, sysfs,
+};
+use mio::{unix::SourceFd, Events, Interest, Token};
+use nix::{
+    errno::Errno,
+    sys::{
+        eventfd::{EfdFlags, EventFd},
+        socket::{self, AddressFamily, MsgFlags, NetlinkAddr, SockFlag, SockProtocol, SockType},
+    },
+    unistd,
+};
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    sync::{mpsc, Arc},
+    thread::JoinHandle,
+};
+use tracing::{debug, error, trace, warn};
+
+/// The multicast group of the uevents sent by the kernel. udev sends its own to group 2, once it
+/// has processed them
+const KERNEL_GROUP: u32 = 1;
+
+/// The largest uevent the kernel sends, see UEVENT_BUFFER_SIZE
+const UEVENT_BUFFER_SIZE: usize = 2048;
+
+#[derive(Debug)]
+struct ListenerOptions {
+    capacity: usize,
+    evfd: RawFd,
+    ready: mpsc::Sender<Result<()>>,
+}
+
+/// Scan for connected devices
+pub fn scan() -> io::Result<HashMap<String, DeviceInfo>> {
+    sysfs::scan()
+}
+
+/// Like [`scan`]. Matching subsystems and properties needs udev, so `udev` is ignored
+pub(crate) fn scan_matching(_udev: &UdevMatch) -> io::Result<HashMap<String, DeviceInfo>> {
+    scan()
+}
+
+/// Look up the device at `port`, following links to it
+pub fn port_info(port: &str) -> io::Result<Option<DeviceInfo>> {
+    sysfs::port_info(port)
+}
+
+/// Listen for connected devices
+pub fn listen(queue: Arc<Queue>) -> Result<AbortHandle> {
+    let evfd = EventFd::from_value_and_flags(0, EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_SEMAPHORE)
+        .map_err(io::Error::from)?;
+    let (ready, rx) = mpsc::channel();
+    let opts = ListenerOptions {
+        capacity: 1024,
+        evfd: evfd.as_raw_fd(),
+        ready,
+    };
+    let span = listener_span("netlink");
+    let join_handle = std::thread::spawn(move || span.in_scope(|| listener(queue, opts)));
+    // Wait for the listener so that setup errors are returned to the caller
+    match rx.recv() {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            let _ = join_handle.join();
+            return Err(error);
+        }
+        Err(_) => {
+            let error = io::Error::other("listener exited during setup");
+            return Err(Error::Backend(error));
+        }
+    }
+    let join_handle = Some(join_handle);
+    Ok(AbortHandle { evfd, join_handle })
+}
+
+fn listener(queue: Arc<Queue>, opts: ListenerOptions) {
+    trace!(capacity = opts.capacity, "listening");
+    // Safety: EventFd is private and when dropped we close, and remains open until join is called.
+    // See AbortHandle drop
+    let evfd = unsafe { BorrowedFd::borrow_raw(opts.evfd) };
+    let (mut socket, mut poller) = match init_listener(evfd.as_fd()) {
+        Ok(result) => result,
+        Err(error) => {
+            error!(?error, "failed to setup listener");
+            let _ = opts.ready.send(Err(error));
+            return;
+        }
+    };
+    let _ = opts.ready.send(Ok(()));
+    // The sysfs entry is gone by the time the device is removed, so we remember what we learned
+    // about each device when it was added
+    let mut cache = scan().unwrap_or_default();
+    let mut sleep = Sleep::watch_for(&queue);
+    logind::register(&mut sleep, &poller, Token(2));
+    let mut events = Events::with_capacity(opts.capacity);
+    let mut buf = [0; UEVENT_BUFFER_SIZE];
+    let mut attempt = 0;
+    'main: loop {
+        let mut failed = poller.poll(&mut events, None).err();
+        'events: for event in events.iter().filter(|_| failed.is_none()) {
+            if event.token() == Token(0) && event.is_readable() {
+                trace!("closing listener");
+                let mut arr = [0; std::mem::size_of::<u64>()];
+                let _ = unistd::read(evfd.as_fd(), &mut arr);
+                queue.done();
+                break 'main;
+            } else if event.token() == Token(2) {
+                if logind::resumed(&mut sleep) {
+                    debug!("resumed from suspend, rescanning");
+                    resync(&queue, &mut cache);
+                }
+            } else if event.token() == Token(1) && event.is_readable() {
+                // The socket is edge triggered, so we read until it is drained
+                loop {
+                    let len = match socket::recv(socket.as_raw_fd(), &mut buf, MsgFlags::empty()) {
+                        Ok(len) => len,
+                        Err(Errno::EAGAIN) => break,
+                        Err(Errno::ENOBUFS) => {
+                            warn!("uevents were dropped, rescanning");
+                            resync(&queue, &mut cache);
+                            continue;
+                        }
+                        Err(error) => {
+                            failed = Some(error.into());
+                            break 'events;
+                        }
+                    };
+                    if let Some(uevent) = Uevent::parse(&buf[..len]) {
+                        dispatch(&queue, &mut cache, uevent);
+                    }
+                }
+            }
+        }
+        let Some(error) = failed else {
+            attempt = 0;
+            continue;
+        };
+        error!(?error, "failed to read uevents");
+        queue.push(Err(error));
+        match retry::restart(&queue, evfd, &mut attempt, || init_listener(evfd)) {
+            Some(listener) => (socket, poller) = listener,
+            None => return,
+        }
+        logind::register(&mut sleep, &poller, Token(2));
+        // Catch up with whatever happened while we were not listening
+        resync(&queue, &mut cache);
+    }
+    trace!("listener finished");
+}
+
+#[inline]
+fn init_listener(evfd: BorrowedFd<'_>) -> Result<(OwnedFd, mio::Poll)> {
+    let socket = socket::socket(
+        AddressFamily::Netlink,
+        SockType::Datagram,
+        SockFlag::SOCK_NONBLOCK | SockFlag::SOCK_CLOEXEC,
+        SockProtocol::NetlinkKObjectUEvent,
+    )
+    .map_err(io::Error::from)?;
+    socket::bind(socket.as_raw_fd(), &NetlinkAddr::new(0, KERNEL_GROUP))
+        .map_err(|errno| Error::Registration(errno.into()))?;
+    let poll = mio::Poll::new()?;
+    poll.registry().register(
+        &mut SourceFd(&evfd.as_raw_fd()),
+        Token(0),
+        Interest::READABLE,
+    )?;
+    poll.registry().register(
+        &mut SourceFd(&socket.as_raw_fd()),
+        Token(1),
+        Interest::READABLE,
+    )?;
+    Ok((socket, poll))
+}
+
+/// Report the uevent of a tty
+fn dispatch(queue: &Arc<Queue>, cache: &mut HashMap<String, DeviceInfo>, uevent: Uevent<'_>) {
+    let name = match uevent.devname {
+        Some(name) if uevent.subsystem == "tty" => name,
+        _ => return,
+    };
+    trace!(name, action = uevent.action, "device event");
+    match uevent.action {
+        "add" => {
+            if let Some(device) = sysfs::read_device_info(name) {
+                cache.insert(device.port.clone(), device.clone());
+                ready::push(queue, EventInfo::new(device, EventType::Add));
+            }
+        }
+        "change" => {
+            if let Some(device) = sysfs::read_device_info(name) {
+                cache.insert(device.port.clone(), device.clone());
+                queue.push(Ok(EventInfo::new(device, EventType::Change)));
+            }
+        }
+        "remove" => {
+            if let Some(device) = cache.remove(&format!("/dev/{name}")) {
+                queue.push(Ok(EventInfo::new(device, EventType::Remove)));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Report what changed since `cache` was scanned, when we may have missed uevents or the system
+/// slept
+fn resync(queue: &Arc<Queue>, cache: &mut HashMap<String, DeviceInfo>) {
+    let next = scan().unwrap_or_default();
+    for event in poll::diff(cache, &next) {
+        ready::push(queue, event);
+    }
+    *cache = next;
+}
+
+/// The fields we use of a kernel uevent. It is a header (IE: add@/devices/...) followed by
+/// KEY=value pairs, each null terminated
+#[derive(Debug, PartialEq, Eq)]
+struct Uevent<'a> {
+    action: &'a str,
+    subsystem: &'a str,
+    /// The device node, relative to /dev
+    devname: Option<&'a str>,
+}
+
+impl<'a> Uevent<'a> {
+    fn parse(buf: &'a [u8]) -> Option<Uevent<'a>> {
+        let mut fields = buf
+            .split(|byte| *byte == 0)
+            .map(|field| std::str::from_utf8(field).ok());
+        // The messages of udev start with "libudev" instead, though they are not sent to our group
+        fields.next()??.split_once('@')?;
+        let (mut action, mut subsystem, mut devname) = (None, None, None);
+        for field in fields.flatten() {
+            match field.split_once('=') {
+                Some(("ACTION", value)) => action = Some(value),
+                Some(("SUBSYSTEM", value)) => subsystem = Some(value),
+                Some(("DEVNAME", value)) => devname = Some(value),
+                _ => {}
+            }
+        }
+        Some(Uevent {
+            action: action?,
+            subsystem: subsystem?,
+            devname,
+        })
+    }
+}
+
+/// The AbortHandle will cause the [`crate::EventIter`] to stop emitting events when dropped
+#[derive(Debug)]
+pub struct AbortHandle {
+    evfd: EventFd,
+    join_handle: Option<JoinHandle<()>>,
+}
+
+impl AbortHandle {
+    /// False once the listener thread has exited
+    pub(crate) fn is_alive(&self) -> bool {
+        self.join_handle
+            .as_ref()
+            .is_some_and(|jh| !jh.is_finished())
+    }
+
+    pub(crate) fn mode(&self) -> ListenMode {
+        ListenMode::Native
+    }
+}
+
+impl Drop for AbortHandle {
+    // We signal the remote thread to break its loop with the eventfd, and then we join
+    fn drop(&mut self) {
+        trace!("dropping event iter");
+        if let Some(jh) = self.join_handle.take() {
+            match self.evfd.write(1) {
+                Err(error) => error!(?error, "failed to write evfd"),
+                Ok(_) => {
+                    if let Err(error) = jh.join() {
+                        error!(?error, "event iter join error");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_uevent() {
+        let add = b"add@/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/ttyUSB0/tty/ttyUSB0\0\
+            ACTION=add\0DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0/ttyUSB0/tty/\
+            ttyUSB0\0SUBSYSTEM=tty\0MAJOR=188\0MINOR=0\0DEVNAME=ttyUSB0\0SEQNUM=4242\0";
+        let expect = Uevent {
+            action: "add",
+            subsystem: "tty",
+            devname: Some("ttyUSB0"),
+        };
+        assert_eq!(Some(expect), Uevent::parse(add));
+        let interface = b"unbind@/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0\0\
+            ACTION=unbind\0DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-3/1-3:1.0\0\
+            SUBSYSTEM=usb\0DEVTYPE=usb_interface\0SEQNUM=4243\0";
+        let expect = Uevent {
+            action: "unbind",
+            subsystem: "usb",
+            devname: None,
+        };
+        assert_eq!(Some(expect), Uevent::parse(interface));
+        assert_eq!(None, Uevent::parse(b"libudev\0\xfe\xed\xca\xfe"));
+        assert_eq!(None, Uevent::parse(b"add@/devices/virtual\0SEQNUM=1\0"));
+    }
+}
@@ -0,0 +1,169 @@
+// poll.rs
+//
+// Detect devices by periodically scanning the system and diffing the results, for platforms
+// without a hotplug notification mechanism
+
+use crate::detect::{listener_span, DeviceInfo, EventInfo, EventType, Queue};
+use std::{
+    collections::HashMap,
+    io,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+use tracing::{error, trace};
+
+/// How often we scan when the native backend is unavailable
+pub(crate) const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Listen for connected devices by calling `scan` every `interval`
+///
+/// Scan errors are pushed into the stream and polling continues on the next interval
+pub(crate) fn listen<F>(interval: Duration, scan: F, queue: Arc<Queue>) -> io::Result<AbortHandle>
+where
+    F: Fn() -> io::Result<HashMap<String, DeviceInfo>> + Send + 'static,
+{
+    let initial = scan()?;
+    let (tx, rx) = mpsc::channel();
+    let span = listener_span("polling");
+    let join_handle = std::thread::spawn(move || {
+        let _span = span.entered();
+        trace!(?interval, "listening");
+        let mut cache = initial;
+        // We are signalled to stop when the AbortHandle sends Stop or drops its sender
+        while let Ok(Signal::Rescan) | Err(RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+            match scan() {
+                Ok(next) => {
+                    for event in diff(&cache, &next) {
+                        queue.push(Ok(event));
+                    }
+                    cache = next;
+                }
+                Err(error) => {
+                    error!(?error, "failed to scan devices");
+                    queue.push(Err(error));
+                }
+            }
+        }
+        trace!("listener finished");
+        queue.done();
+    });
+    let abort = AbortHandle {
+        tx: Some(tx),
+        join_handle: Some(join_handle),
+    };
+    Ok(abort)
+}
+
+/// Messages to the polling thread
+#[derive(Debug)]
+#[cfg_attr(
+    not(any(target_os = "illumos", target_os = "solaris")),
+    allow(dead_code)
+)]
+enum Signal {
+    Rescan,
+    Stop,
+}
+
+/// Wakes the polling thread to scan immediately, for platforms which can tell us that something
+/// changed but not what
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+#[derive(Debug, Clone)]
+pub(crate) struct Trigger(mpsc::Sender<Signal>);
+
+#[cfg(any(target_os = "illumos", target_os = "solaris"))]
+impl Trigger {
+    /// Request a rescan. Returns false if the listener has finished
+    pub(crate) fn rescan(&self) -> bool {
+        self.0.send(Signal::Rescan).is_ok()
+    }
+}
+
+/// Synthesize the events which take us from the `prev` scan to the `next` scan. A port whose
+/// device changed between scans is reported as a Remove followed by an Add
+pub(crate) fn diff(
+    prev: &HashMap<String, DeviceInfo>,
+    next: &HashMap<String, DeviceInfo>,
+) -> Vec<EventInfo> {
+    let removed = prev
+        .iter()
+        .filter(|(port, device)| next.get(*port) != Some(device))
+        .map(|(_, device)| EventInfo::new(device.clone(), EventType::Remove));
+    let added = next
+        .iter()
+        .filter(|(port, device)| prev.get(*port) != Some(device))
+        .map(|(_, device)| EventInfo::new(device.clone(), EventType::Add));
+    removed.chain(added).collect()
+}
+
+/// The AbortHandle will cause the [`crate::EventIter`] to stop emitting events when dropped
+#[derive(Debug)]
+pub struct AbortHandle {
+    tx: Option<mpsc::Sender<Signal>>,
+    join_handle: Option<JoinHandle<()>>,
+}
+
+impl AbortHandle {
+    /// A handle to wake the polling thread early
+    #[cfg(any(target_os = "illumos", target_os = "solaris"))]
+    pub(crate) fn trigger(&self) -> Option<Trigger> {
+        self.tx.clone().map(Trigger)
+    }
+
+    /// False once the listener thread has exited
+    pub(crate) fn is_alive(&self) -> bool {
+        self.join_handle
+            .as_ref()
+            .is_some_and(|jh| !jh.is_finished())
+    }
+}
+
+impl Drop for AbortHandle {
+    // We signal the polling thread to stop, and then we join. A Trigger may still hold a sender so
+    // dropping ours is not enough
+    fn drop(&mut self) {
+        trace!("dropping event iter");
+        if let Some(tx) = self.tx.take() {
+            let _ = tx.send(Signal::Stop);
+        }
+        if let Some(jh) = self.join_handle.take() {
+            if let Err(error) = jh.join() {
+                error!(?error, "event iter join error");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn device(port: &str, serial: &str) -> (String, DeviceInfo) {
+        let info = DeviceInfo {
+            port: port.to_string(),
+            serial: Some(serial.to_string()),
+            ..Default::default()
+        };
+        (port.to_string(), info)
+    }
+
+    #[test]
+    fn test_diff() {
+        let prev = HashMap::from([device("a", "1"), device("b", "2"), device("c", "3")]);
+        let next = HashMap::from([device("a", "1"), device("c", "4"), device("d", "5")]);
+        let mut events = diff(&prev, &next)
+            .into_iter()
+            .map(|ev| (ev.device.port, ev.device.serial.unwrap(), ev.event))
+            .collect::<Vec<_>>();
+        events.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        assert_eq!(events.len(), 4);
+        assert!(matches!(&events[0], (p, s, EventType::Remove) if p == "b" && s == "2"));
+        assert!(matches!(&events[1], (p, s, EventType::Remove) if p == "c" && s == "3"));
+        assert!(matches!(&events[2], (p, s, EventType::Add) if p == "c" && s == "4"));
+        assert!(matches!(&events[3], (p, s, EventType::Add) if p == "d" && s == "5"));
+    }
+}
@@ -1,7 +1,17 @@
 // Posix support
+//
+// Where udev is not running (IE: in a container) we fall back to the inotify and sysfs backend
 
-use crate::detect::{DeviceInfo, EventInfo, EventType, Queue};
-use futures::Stream;
+use crate::{
+    detect::{
+        listener_span, DeviceInfo, EventInfo, EventType, ListenMode, PortType, Queue, UdevMatch,
+        UsbClass, UsbSpeed,
+    },
+    error::{Error, Result},
+    id::{parse_hex, Pid, Vid},
+    logind::{self, Sleep},
+    poll, ready, retry, sysfs,
+};
 use mio::{unix::SourceFd, Events, Interest, Token};
 use nix::{
     sys::eventfd::{EfdFlags, EventFd},
@@ -10,29 +20,52 @@ use nix::{
 use std::{
     collections::HashMap,
     ffi::OsStr,
-    fmt::{self, Debug},
-    io,
-    os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
-    pin::Pin,
-    sync::Arc,
-    task::{Context, Poll},
+    fs, io,
+    os::{
+        fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+        unix::fs::{FileTypeExt, MetadataExt},
+    },
+    path::Path,
+    sync::{mpsc, Arc},
     thread::JoinHandle,
 };
-use tracing::{error, trace};
+use tracing::{debug, error, trace, warn};
 use udev::Device;
 
 #[derive(Debug)]
 struct ListenerOptions {
     capacity: usize,
     evfd: RawFd,
+    ready: mpsc::Sender<Result<()>>,
+}
+
+/// Present while the udev daemon runs. Without it the database is empty and the monitor never
+/// receives an event, as is the case in most containers
+const UDEV_CONTROL: &str = "/run/udev/control";
+
+fn udev_running() -> bool {
+    Path::new(UDEV_CONTROL).exists()
 }
 
 /// Scan for connected devices
 pub fn scan() -> io::Result<HashMap<String, DeviceInfo>> {
+    scan_matching(&UdevMatch::default())
+}
+
+/// Scan for the connected devices of the subsystems and with the properties of `udev`. Without
+/// udev we read the USB ttys from sysfs, and `udev` is ignored
+pub(crate) fn scan_matching(udev: &UdevMatch) -> io::Result<HashMap<String, DeviceInfo>> {
+    if !udev_running() {
+        return sysfs::scan();
+    }
     let mut enumerator = udev::Enumerator::new()?;
-    enumerator.match_subsystem("tty")?;
+    for subsystem in &udev.subsystems {
+        enumerator.match_subsystem(subsystem)?;
+    }
     let items = enumerator
         .scan_devices()?
+        // The interfaces of a USB device have no node of their own
+        .filter(|dev| dev.devnode().is_some() && has_properties(dev, udev))
         .map(|dev| {
             let port = match dev.devnode() {
                 Some(path) => path.to_str().unwrap_or("").to_string(),
@@ -44,42 +77,104 @@ pub fn scan() -> io::Result<HashMap<String, DeviceInfo>> {
     Ok(items)
 }
 
-/// Listen for connected devices
-pub fn listen() -> io::Result<(AbortHandle, EventIter)> {
-    let queue = Arc::new(Queue::new());
-    let theirs = Arc::clone(&queue);
-    let evfd = EventFd::from_value_and_flags(0, EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_SEMAPHORE)?;
+/// Look up the device at `port` by its device number, following links to it (IE:
+/// /dev/serial/by-id/...). Without udev we read it from sysfs
+pub fn port_info(port: &str) -> io::Result<Option<DeviceInfo>> {
+    if !udev_running() {
+        return sysfs::port_info(port);
+    }
+    let metadata = match fs::metadata(port) {
+        Ok(metadata) => metadata,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    if !metadata.file_type().is_char_device() {
+        return Ok(None);
+    }
+    let dev = Device::from_devnum(udev::DeviceType::Character, metadata.rdev())?;
+    if dev.subsystem().and_then(OsStr::to_str) != Some("tty") {
+        return Ok(None);
+    }
+    let port = match dev.devnode().and_then(Path::to_str) {
+        Some(devnode) => devnode.to_string(),
+        None => port.to_string(),
+    };
+    Ok(Some(read_device_info(port, &dev)))
+}
+
+/// Listen for connected devices, with the udev monitor or, when udev is not running, by watching
+/// /dev with inotify
+pub fn listen(queue: Arc<Queue>) -> Result<AbortHandle> {
+    if !udev_running() {
+        warn!("udev is not running, falling back to inotify and sysfs");
+        let inner = Listener::Sysfs(sysfs::listen(queue)?);
+        return Ok(AbortHandle { inner });
+    }
+    let evfd = EventFd::from_value_and_flags(0, EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_SEMAPHORE)
+        .map_err(io::Error::from)?;
+    let (ready, rx) = mpsc::channel();
     let opts = ListenerOptions {
         capacity: 1024,
         evfd: evfd.as_raw_fd(),
+        ready,
     };
-    let join_handle = Some(std::thread::spawn(move || listener(theirs, opts)));
-    Ok((AbortHandle { evfd, join_handle }, EventIter { queue }))
+    let span = listener_span("udev");
+    let join_handle = std::thread::spawn(move || span.in_scope(|| listener(queue, opts)));
+    // Wait for the listener so that setup errors are returned to the caller
+    match rx.recv() {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            let _ = join_handle.join();
+            return Err(error);
+        }
+        Err(_) => {
+            let error = io::Error::other("listener exited during setup");
+            return Err(Error::Backend(error));
+        }
+    }
+    let join_handle = Some(join_handle);
+    let inner = Listener::Udev { evfd, join_handle };
+    Ok(AbortHandle { inner })
 }
 
 fn listener(queue: Arc<Queue>, opts: ListenerOptions) {
     // Get a udev socket
     trace!(capacity = opts.capacity, "listening");
     // Safety: EventFd is private and when dropped we close, and remains open until join is called.
-    // See EventIter drop
+    // See AbortHandle drop
     let evfd = unsafe { BorrowedFd::borrow_raw(opts.evfd) };
-    let (socket, mut poller) = match init_listener(evfd.as_fd()) {
+    let udev = queue.udev().clone();
+    let (mut socket, mut poller) = match init_listener(evfd.as_fd(), &udev) {
         Ok(result) => result,
         Err(error) => {
             error!(?error, "failed to setup listener");
-            queue.push(Err(error));
+            let _ = opts.ready.send(Err(error));
             return;
         }
     };
+    let _ = opts.ready.send(Ok(()));
+    let mut sleep = Sleep::watch_for(&queue);
+    // What we reported, to diff with a rescan when the system resumes
+    let mut cache = match &sleep {
+        Some(_) => scan_matching(&udev).unwrap_or_default(),
+        None => HashMap::new(),
+    };
+    logind::register(&mut sleep, &poller, Token(2));
     let mut events = Events::with_capacity(opts.capacity);
+    let mut attempt = 0;
     'main: loop {
         match poller.poll(&mut events, None) {
             Err(error) => {
                 error!(?error, "failed to poll udev monitor");
                 queue.push(Err(error));
-                return;
+                match retry::restart(&queue, evfd, &mut attempt, || init_listener(evfd, &udev)) {
+                    Some(listener) => (socket, poller) = listener,
+                    None => return,
+                }
+                logind::register(&mut sleep, &poller, Token(2));
             }
             Ok(_) => {
+                attempt = 0;
                 for event in &events {
                     if event.token() == Token(0) && event.is_readable() {
                         trace!("closing listener");
@@ -87,6 +182,11 @@ fn listener(queue: Arc<Queue>, opts: ListenerOptions) {
                         let _ = unistd::read(evfd.as_fd(), &mut arr);
                         queue.done();
                         break 'main;
+                    } else if event.token() == Token(2) {
+                        if logind::resumed(&mut sleep) {
+                            debug!("resumed from suspend, rescanning");
+                            resync(&queue, &udev, &mut cache);
+                        }
                     } else if event.token() == Token(1) && event.is_read_closed() {
                         trace!("closing listener");
                         queue.done();
@@ -95,20 +195,31 @@ fn listener(queue: Arc<Queue>, opts: ListenerOptions) {
                         for event in socket.iter() {
                             trace!(event = ?event.event_type(), "device event");
                             let dev = event.device();
-                            let port = match dev.devnode() {
-                                Some(path) => path.to_str().unwrap_or("").to_string(),
-                                _ => "".to_string(),
+                            if !has_properties(&dev, &udev) {
+                                continue;
+                            }
+                            let port = match dev.devnode().map(|path| path.to_str()) {
+                                Some(Some(path)) => path.to_string(),
+                                Some(None) => {
+                                    let path = dev.devnode().unwrap_or(dev.syspath());
+                                    let what = format!("device node {}", path.display());
+                                    queue.error(Error::Decode(what));
+                                    continue;
+                                }
+                                None => "".to_string(),
                             };
                             let item = match event.event_type() {
                                 udev::EventType::Add => Some(EventType::Add),
                                 udev::EventType::Remove => Some(EventType::Remove),
+                                udev::EventType::Change => Some(EventType::Change),
                                 _ => None,
                             };
                             if let Some(item) = item {
-                                queue.push(Ok(EventInfo {
-                                    device: read_device_info(port, &dev),
-                                    event: item,
-                                }));
+                                let event = EventInfo::new(read_device_info(port, &dev), item);
+                                if sleep.is_some() {
+                                    track(&mut cache, &event);
+                                }
+                                ready::push(&queue, event);
                             }
                         }
                     }
@@ -119,11 +230,42 @@ fn listener(queue: Arc<Queue>, opts: ListenerOptions) {
     trace!("listener finished");
 }
 
+/// Follow an event in the devices we reported
+fn track(cache: &mut HashMap<String, DeviceInfo>, event: &EventInfo) {
+    match event.event {
+        EventType::Remove => cache.remove(&event.device.port),
+        _ => cache.insert(event.device.port.clone(), event.device.clone()),
+    };
+}
+
+/// Report what changed since `cache` was scanned, when the system slept
+fn resync(queue: &Arc<Queue>, udev: &UdevMatch, cache: &mut HashMap<String, DeviceInfo>) {
+    let next = match scan_matching(udev) {
+        Ok(next) => next,
+        Err(error) => {
+            error!(?error, "failed to rescan devices");
+            return queue.push(Err(error));
+        }
+    };
+    for event in poll::diff(cache, &next) {
+        ready::push(queue, event);
+    }
+    *cache = next;
+}
+
 #[inline]
-fn init_listener(evfd: BorrowedFd<'_>) -> io::Result<(udev::MonitorSocket, mio::Poll)> {
-    let mut socket = udev::MonitorBuilder::new()?
-        .match_subsystem("tty")?
-        .listen()?;
+fn init_listener(
+    evfd: BorrowedFd<'_>,
+    udev: &UdevMatch,
+) -> Result<(udev::MonitorSocket, mio::Poll)> {
+    let mut socket = udev
+        .subsystems
+        .iter()
+        .try_fold(udev::MonitorBuilder::new()?, |builder, subsystem| {
+            builder.match_subsystem(subsystem)
+        })
+        .and_then(|builder| builder.listen())
+        .map_err(Error::Registration)?;
     let poll = mio::Poll::new()?;
     poll.registry().register(
         &mut SourceFd(&evfd.as_raw_fd()),
@@ -135,6 +277,15 @@ fn init_listener(evfd: BorrowedFd<'_>) -> io::Result<(udev::MonitorSocket, mio::
     Ok((socket, poll))
 }
 
+/// Whether the device has every property of `udev`. The monitor cannot match properties, and
+/// the enumerator reports a device with any of them, so we match them ourselves
+fn has_properties(dev: &Device, udev: &UdevMatch) -> bool {
+    udev.properties.iter().all(|(name, value)| {
+        dev.property_value(name)
+            .is_some_and(|v| v == value.as_str())
+    })
+}
+
 fn read_device_info(port: String, dev: &Device) -> DeviceInfo {
     let serial = dev
         .property_value("ID_SERIAL_SHORT")
@@ -171,57 +322,219 @@ fn read_device_info(port: String, dev: &Device) -> DeviceInfo {
     let vid = dev
         .property_value("ID_VENDOR_ID")
         .and_then(OsStr::to_str)
-        .map(|s| s.to_string());
+        .and_then(|s| s.parse::<Vid>().ok());
     let pid = dev
         .property_value("ID_MODEL_ID")
         .and_then(OsStr::to_str)
-        .map(|s| s.to_string());
+        .and_then(|s| s.parse::<Pid>().ok());
+    let port_type = match dev.property_value("ID_BUS").and_then(OsStr::to_str) {
+        Some("usb") => PortType::Usb,
+        Some("pci") => PortType::Pci,
+        _ if port.starts_with("/dev/rfcomm") => PortType::Bluetooth,
+        _ => PortType::Unknown,
+    };
     DeviceInfo {
         port,
         serial,
         manufacturer,
         product,
-        vid,
-        pid,
+        vid: vid.map(|vid| vid.to_string()),
+        pid: pid.map(|pid| pid.to_string()),
+        vid_u16: vid.map(u16::from),
+        pid_u16: pid.map(u16::from),
+        bcd_device: read_bcd_device(dev),
+        port_type,
+        accessible: None,
+        in_use: None,
+        usb_speed: read_usb_speed(dev),
+        interface_class: read_interface_class(dev),
+        stable_id: read_stable_id(dev),
+        location: read_location(dev),
+        seat: read_seat(dev),
+        driver: read_driver(dev),
+        friendly_name: None,
+        instance_id: None,
+        syspath: dev.syspath().to_str().map(|s| s.to_string()),
+        id_path: dev
+            .property_value("ID_PATH")
+            .and_then(OsStr::to_str)
+            .map(|s| s.to_string()),
+        links: read_links(dev),
+        dialin: None,
+        udev_properties: read_properties(dev),
     }
 }
 
-/// An event emitter to listen for Usb Add Remove events
-pub struct EventIter {
-    queue: Arc<Queue>,
+/// The symlinks to the device node, as udev lists them in DEVLINKS
+fn read_links(dev: &Device) -> Vec<String> {
+    dev.property_value("DEVLINKS")
+        .and_then(OsStr::to_str)
+        .map(|links| links.split_whitespace().map(|s| s.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// The name of the /dev/serial/by-id link, which udev derives from the serial number and
+/// interface of the device
+fn read_stable_id(dev: &Device) -> Option<String> {
+    dev.property_value("DEVLINKS")
+        .and_then(OsStr::to_str)
+        .and_then(|links| {
+            links
+                .split_whitespace()
+                .find_map(|link| link.strip_prefix("/dev/serial/by-id/"))
+        })
+        .map(|id| id.to_string())
+        .or_else(|| {
+            let bus = dev.property_value("ID_BUS").and_then(OsStr::to_str)?;
+            let serial = dev.property_value("ID_SERIAL").and_then(OsStr::to_str)?;
+            let interface = dev
+                .property_value("ID_USB_INTERFACE_NUM")
+                .and_then(OsStr::to_str)?;
+            Some(format!("{bus}-{serial}-if{interface}"))
+        })
+}
+
+/// The sysname of the USB device (IE: 1-3.2), which the kernel derives from the bus number and
+/// the hub ports
+fn read_location(dev: &Device) -> Option<String> {
+    let usb = dev
+        .parent_with_subsystem_devtype("usb", "usb_device")
+        .ok()
+        .flatten()?;
+    usb.sysname().to_str().map(|s| s.to_string())
 }
 
-impl Debug for EventIter {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("EventIter").finish()
+/// The seat of the device. logind tags a device or a hub with ID_SEAT, and udev copies it down to
+/// the devices behind it, but we look up the parents too in case the rules did not run
+fn read_seat(dev: &Device) -> Option<String> {
+    let mut next = Some(dev.clone());
+    while let Some(dev) = next {
+        if let Some(seat) = dev.property_value("ID_SEAT").and_then(OsStr::to_str) {
+            return Some(seat.to_string());
+        }
+        next = dev.parent();
     }
+    Some("seat0".to_string())
 }
 
-impl Stream for EventIter {
-    type Item = io::Result<EventInfo>;
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.queue.poll_next(cx)
+/// The bcdDevice of the USB device, which udev copies to ID_REVISION
+fn read_bcd_device(dev: &Device) -> Option<u16> {
+    let revision = dev
+        .property_value("ID_REVISION")
+        .and_then(OsStr::to_str)
+        .map(|s| s.to_string())
+        .or_else(|| {
+            let usb = dev
+                .parent_with_subsystem_devtype("usb", "usb_device")
+                .ok()
+                .flatten()?;
+            usb.attribute_value("bcdDevice")?
+                .to_str()
+                .map(|s| s.to_string())
+        })?;
+    parse_hex(&revision)
+}
+
+/// The speed attribute of the USB device, which the kernel reports in Mbps
+fn read_usb_speed(dev: &Device) -> Option<UsbSpeed> {
+    let usb = dev
+        .parent_with_subsystem_devtype("usb", "usb_device")
+        .ok()
+        .flatten()?;
+    UsbSpeed::from_sysfs(usb.attribute_value("speed")?.to_str()?)
+}
+
+/// The class codes of the USB interface, which is the parent of the tty (cdc_acm) or of its
+/// usb-serial port (ftdi_sio)
+fn read_interface_class(dev: &Device) -> Option<UsbClass> {
+    let interface = dev
+        .parent_with_subsystem_devtype("usb", "usb_interface")
+        .ok()
+        .flatten()?;
+    let code = |attr| interface.attribute_value(attr).and_then(OsStr::to_str);
+    UsbClass::from_hex(
+        code("bInterfaceClass")?,
+        code("bInterfaceSubClass")?,
+        code("bInterfaceProtocol")?,
+    )
+}
+
+/// The driver of the nearest ancestor which has one. The tty itself is never bound, its parent is
+/// the USB interface (cdc_acm) or the usb-serial port (ftdi_sio)
+fn read_driver(dev: &Device) -> Option<String> {
+    if let Some(driver) = dev.property_value("ID_USB_DRIVER").and_then(OsStr::to_str) {
+        return Some(driver.to_string());
+    }
+    let mut parent = dev.parent();
+    while let Some(dev) = parent {
+        if let Some(driver) = dev.driver().and_then(OsStr::to_str) {
+            return Some(driver.to_string());
+        }
+        parent = dev.parent();
     }
+    None
 }
 
-/// The AbortHandle will cause the [`EventIter`] to stop emitting events when dropped
+#[cfg(feature = "udev-properties")]
+fn read_properties(dev: &Device) -> HashMap<String, String> {
+    dev.properties()
+        .filter_map(|entry| {
+            let name = entry.name().to_str()?;
+            let value = entry.value().to_str()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "udev-properties"))]
+fn read_properties(_dev: &Device) -> HashMap<String, String> {
+    HashMap::new()
+}
+
+/// The AbortHandle will cause the [`crate::EventIter`] to stop emitting events when dropped
 #[derive(Debug)]
 pub struct AbortHandle {
-    evfd: EventFd,
-    join_handle: Option<JoinHandle<()>>,
+    inner: Listener,
+}
+
+#[derive(Debug)]
+enum Listener {
+    Udev {
+        evfd: EventFd,
+        join_handle: Option<JoinHandle<()>>,
+    },
+    Sysfs(sysfs::AbortHandle),
 }
 
 impl AbortHandle {
-    /// Cancel [`EventIter`] and no longer listen to Device Connect and Disconnect events
-    pub fn abort(self) {}
+    /// False once the listener thread has exited
+    pub(crate) fn is_alive(&self) -> bool {
+        match &self.inner {
+            Listener::Udev { join_handle, .. } => {
+                join_handle.as_ref().is_some_and(|jh| !jh.is_finished())
+            }
+            Listener::Sysfs(handle) => handle.is_alive(),
+        }
+    }
+
+    pub(crate) fn mode(&self) -> ListenMode {
+        match self.inner {
+            Listener::Udev { .. } => ListenMode::Native,
+            Listener::Sysfs(_) => ListenMode::Sysfs,
+        }
+    }
 }
 
 impl Drop for AbortHandle {
-    // We signal the remote thread to break its loop with the eventfd, and then we join
+    // We signal the remote thread to break its loop with the eventfd, and then we join. The sysfs
+    // listener does the same when dropped
     fn drop(&mut self) {
+        let Listener::Udev { evfd, join_handle } = &mut self.inner else {
+            return;
+        };
         trace!("dropping event iter");
-        if let Some(jh) = self.join_handle.take() {
-            match self.evfd.write(1) {
+        if let Some(jh) = join_handle.take() {
+            match evfd.write(1) {
                 Err(error) => error!(?error, "failed to write evfd"),
                 Ok(_) => {
                     if let Err(error) = jh.join() {
@@ -0,0 +1,68 @@
+// ready.rs
+//
+// A tty which was just added is often not usable yet: udev is still applying its permissions, or
+// ModemManager is probing it. When asked to, we hold back the Add until the current user can open
+// the device node
+
+use crate::{
+    access,
+    detect::{EventInfo, EventType, Queue},
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{trace, warn};
+
+/// How often we check a device node which is not ready
+const INTERVAL: Duration = Duration::from_millis(50);
+
+/// Push an event, holding back an Add until its device node is ready. See
+/// [`crate::ListenBuilder::ready`]
+pub(crate) fn push(queue: &Arc<Queue>, event: EventInfo) {
+    let grace = match (queue.ready(), event.event) {
+        (Some(grace), EventType::Add) if queue.admits(&event) && !accessible(&event) => grace,
+        _ => return queue.push(Ok(event)),
+    };
+    let id = queue.hold(&event.device.port);
+    // The Add is pushed at once if we cannot wait for it, rather than being lost with the thread
+    let fallback = event.clone();
+    let waiting = Arc::clone(queue);
+    // Traced in the span of the listener which held back the Add
+    let span = tracing::Span::current();
+    let spawned = std::thread::Builder::new()
+        .name("serialport-detect-ready".into())
+        .spawn(move || {
+            let _span = span.entered();
+            let port = event.device.port.clone();
+            let deadline = Instant::now() + grace;
+            loop {
+                std::thread::sleep(INTERVAL);
+                if !waiting.is_held(&port, id) {
+                    trace!(port, "device removed before it was ready");
+                    return;
+                }
+                if accessible(&event) {
+                    trace!(port, "device ready");
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    warn!(port, ?grace, "device not ready in time");
+                    break;
+                }
+            }
+            waiting.release(id, event);
+        });
+    if let Err(error) = spawned {
+        warn!(
+            ?error,
+            "failed to spawn the readiness thread, not waiting for the device"
+        );
+        queue.release(id, fallback);
+    }
+}
+
+/// Whether the current user may read and write the device node
+fn accessible(event: &EventInfo) -> bool {
+    access::accessible(&event.device.port) == Some(true)
+}
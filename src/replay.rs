@@ -0,0 +1,174 @@
+// replay.rs
+//
+// Record the events of a listener to a file, and play them back through an EventIter to reproduce
+// a hotplug sequence without the hardware
+
+use crate::{
+    detect::{listener_span, EventInfo, Queue},
+    error::{Error, Result},
+};
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+use tracing::trace;
+
+/// Writes events to a file, one JSON object per line, to be played back with
+/// [`crate::ListenBuilder::replay`]. Enabled with the `replay` feature
+///
+/// ```no_run
+/// use futures::StreamExt;
+/// use serialport_detect::Recorder;
+///
+/// # async fn run() -> serialport_detect::Result<()> {
+/// let mut recorder = Recorder::create("hotplug.jsonl")?;
+/// let (abort, mut events) = serialport_detect::listen()?;
+/// while let Some(event) = events.next().await {
+///     recorder.record(&event?)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    /// Record to `path`, replacing the file if it exists
+    pub fn create(path: impl AsRef<Path>) -> Result<Recorder> {
+        let writer = BufWriter::new(File::create(path)?);
+        Ok(Recorder { writer })
+    }
+
+    /// Append an event. Each event is flushed, so the recording survives a crash
+    pub fn record(&mut self, event: &EventInfo) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, event).map_err(io::Error::from)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Read a recording made by a [`Recorder`]
+pub(crate) fn load(path: &Path) -> Result<Vec<EventInfo>> {
+    let contents = std::fs::read_to_string(path)?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            serde_json::from_str(line).map_err(|error| {
+                Error::Decode(format!("line {} of {}: {error}", n + 1, path.display()))
+            })
+        })
+        .collect()
+}
+
+/// Push `events` into the queue, waiting between them as long as they were apart when recorded
+/// divided by `speed`. The stream ends after the last event
+pub(crate) fn listen(events: Vec<EventInfo>, speed: f64, queue: Arc<Queue>) -> AbortHandle {
+    let (tx, rx) = mpsc::channel::<()>();
+    let span = listener_span("replay");
+    let join_handle = std::thread::spawn(move || {
+        let _span = span.entered();
+        trace!(events = events.len(), speed, "replaying");
+        let mut prev = None;
+        for event in events {
+            let delay = prev
+                .and_then(|prev| event.timestamp.system.duration_since(prev).ok())
+                .map_or(Duration::ZERO, |delay: Duration| delay.div_f64(speed));
+            prev = Some(event.timestamp.system);
+            // We are signalled to stop when the AbortHandle drops its sender
+            if delay > Duration::ZERO {
+                if let Ok(()) | Err(RecvTimeoutError::Disconnected) = rx.recv_timeout(delay) {
+                    break;
+                }
+            }
+            queue.push(Ok(event));
+        }
+        trace!("replay finished");
+        queue.done();
+    });
+    AbortHandle {
+        tx: Some(tx),
+        join_handle: Some(join_handle),
+    }
+}
+
+/// The AbortHandle will cause the [`crate::EventIter`] to stop emitting events when dropped
+#[derive(Debug)]
+pub(crate) struct AbortHandle {
+    tx: Option<mpsc::Sender<()>>,
+    join_handle: Option<JoinHandle<()>>,
+}
+
+impl AbortHandle {
+    /// False once the replay thread has exited
+    pub(crate) fn is_alive(&self) -> bool {
+        self.join_handle
+            .as_ref()
+            .is_some_and(|jh| !jh.is_finished())
+    }
+}
+
+impl Drop for AbortHandle {
+    // Dropping the sender wakes the replay thread, and then we join
+    fn drop(&mut self) {
+        drop(self.tx.take());
+        if let Some(jh) = self.join_handle.take() {
+            if let Err(error) = jh.join() {
+                tracing::error!(?error, "replay join error");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DeviceInfo, EventType};
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn test_record_replay() {
+        let path = std::env::temp_dir().join(format!("replay-{}.jsonl", std::process::id()));
+        let event = |port: &str, event, millis| {
+            let mut event = EventInfo::new(
+                DeviceInfo {
+                    port: port.to_string(),
+                    ..Default::default()
+                },
+                event,
+            );
+            event.timestamp.system = UNIX_EPOCH + Duration::from_millis(millis);
+            event
+        };
+        let mut recorder = Recorder::create(&path).unwrap();
+        recorder
+            .record(&event("COM3", EventType::Add, 1000))
+            .unwrap();
+        recorder
+            .record(&event("COM3", EventType::Remove, 1200))
+            .unwrap();
+        let (_abort, events) = crate::builder().replay(&path, f64::INFINITY).unwrap();
+        let events = events
+            .into_iter()
+            .map(|event| event.unwrap())
+            .map(|event| (event.device.port, event.event, event.seq))
+            .collect::<Vec<_>>();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            events.as_slice(),
+            [(_, EventType::Add, 0), (_, EventType::Remove, 1)]
+        ));
+        assert!(events.iter().all(|(port, _, _)| port == "COM3"));
+    }
+}
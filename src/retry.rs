@@ -0,0 +1,146 @@
+// retry.rs
+use std::time::Duration;
+
+/// How a listener recovers when the OS notification mechanism fails. See
+/// [`crate::ListenBuilder::retry`]
+///
+/// The listener waits, re-initializes itself and carries on emitting events. The wait doubles
+/// after each failed attempt, up to a maximum. Without a retry policy the stream ends on the first
+/// failure. Only the Linux and Android listeners restart, the others never fail once started
+///
+/// ```no_run
+/// use serialport_detect::Retry;
+/// use std::time::Duration;
+///
+/// let retry = Retry::new()
+///     .max_attempts(5)
+///     .backoff(Duration::from_millis(250), Duration::from_secs(10));
+/// let (abort, events) = serialport_detect::builder().retry(retry).listen()?;
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Retry {
+    max_attempts: Option<u32>,
+    initial: Duration,
+    max: Duration,
+}
+
+impl Default for Retry {
+    fn default() -> Retry {
+        Retry {
+            max_attempts: None,
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Retry {
+    /// Retry forever, waiting from 100ms up to 30s between attempts
+    pub fn new() -> Retry {
+        Retry::default()
+    }
+
+    /// Give up and end the stream after `attempts` consecutive failed restarts
+    pub fn max_attempts(mut self, attempts: u32) -> Self {
+        self.max_attempts = Some(attempts);
+        self
+    }
+
+    /// Wait `initial` before the first attempt, doubling up to `max`
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial = initial;
+        self.max = max;
+        self
+    }
+
+    /// How long to wait before `attempt`, counting from 0. None when we should give up
+    #[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+    pub(crate) fn delay(&self, attempt: u32) -> Option<Duration> {
+        if self.max_attempts.is_some_and(|max| attempt >= max) {
+            return None;
+        }
+        let factor = 2u32.checked_pow(attempt).unwrap_or(u32::MAX);
+        Some(self.initial.saturating_mul(factor).min(self.max))
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) use restart::restart;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod restart {
+    use crate::{detect::Queue, error::Result};
+    use nix::{
+        poll::{poll, PollFd, PollFlags, PollTimeout},
+        unistd,
+    };
+    use std::{os::fd::BorrowedFd, time::Duration};
+    use tracing::{debug, error, trace};
+
+    /// Wait out the backoff and re-initialize a failed listener with `init`, until it succeeds or
+    /// the retry policy gives up. `attempt` counts the consecutive failures.
+    ///
+    /// Returns None when we gave up, or were aborted with `evfd` while waiting. The stream is
+    /// finished in both cases
+    pub(crate) fn restart<T>(
+        queue: &Queue,
+        evfd: BorrowedFd<'_>,
+        attempt: &mut u32,
+        mut init: impl FnMut() -> Result<T>,
+    ) -> Option<T> {
+        loop {
+            let Some(delay) = queue.retry().and_then(|retry| retry.delay(*attempt)) else {
+                trace!("giving up on listener");
+                queue.done();
+                return None;
+            };
+            *attempt += 1;
+            debug!(?delay, attempt, "restarting listener");
+            if aborted(evfd, delay) {
+                trace!("closing listener");
+                queue.done();
+                return None;
+            }
+            match init() {
+                Ok(listener) => return Some(listener),
+                Err(error) => {
+                    error!(?error, "failed to restart listener");
+                    queue.error(error);
+                }
+            }
+        }
+    }
+
+    /// Sleep for `delay`, returning early with true if the AbortHandle was dropped
+    fn aborted(evfd: BorrowedFd<'_>, delay: Duration) -> bool {
+        let timeout = PollTimeout::try_from(delay).unwrap_or(PollTimeout::MAX);
+        let mut fds = [PollFd::new(evfd, PollFlags::POLLIN)];
+        match poll(&mut fds, timeout) {
+            Ok(n) if n > 0 => {
+                let mut arr = [0; std::mem::size_of::<u64>()];
+                let _ = unistd::read(evfd, &mut arr);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let retry = Retry::new()
+            .max_attempts(4)
+            .backoff(Duration::from_millis(100), Duration::from_millis(500));
+        let delays = (0..5)
+            .map(|attempt| retry.delay(attempt))
+            .collect::<Vec<_>>();
+        let ms = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(delays, vec![ms(100), ms(200), ms(400), ms(500), None]);
+        assert_eq!(Retry::new().delay(64), Some(Duration::from_secs(30)));
+    }
+}
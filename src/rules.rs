@@ -0,0 +1,246 @@
+// rules.rs
+use crate::{detect::DeviceInfo, filter::Filter};
+
+/// Which devices to report, IE: to allow only the approved adapters of a fleet and ignore the
+/// built in modems. A device is reported when it matches one of the allow rules, or there are none,
+/// and none of the deny rules
+///
+/// Apply the rules with [`crate::ListenBuilder::rules`], to both scanning and listening. With the
+/// `config` feature they are loaded from a file, see [`Rules::load`]
+///
+/// ```
+/// use serialport_detect::{DeviceInfo, Filter, Match, Rules};
+///
+/// let rules = Rules::new()
+///     .allow(Filter::new().vid(0x0403))
+///     .deny(Filter::new().serial(Match::prefix("MODEM-")));
+/// let device = DeviceInfo {
+///     vid_u16: Some(0x0403),
+///     serial: Some("FIX-0042".to_string()),
+///     ..Default::default()
+/// };
+/// assert!(rules.admits(&device));
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Rules {
+    allow: Vec<Filter>,
+    deny: Vec<Filter>,
+}
+
+impl Rules {
+    /// Rules which admit every device
+    pub fn new() -> Rules {
+        Rules::default()
+    }
+
+    /// Admit the devices matching `filter`. Once there is an allow rule, the devices which match
+    /// none of them are not reported
+    pub fn allow(mut self, filter: Filter) -> Self {
+        self.allow.push(filter);
+        self
+    }
+
+    /// Never report the devices matching `filter`, even when an allow rule matches them
+    pub fn deny(mut self, filter: Filter) -> Self {
+        self.deny.push(filter);
+        self
+    }
+
+    /// Whether the device is reported
+    pub fn admits(&self, device: &DeviceInfo) -> bool {
+        let allowed =
+            self.allow.is_empty() || self.allow.iter().any(|filter| filter.matches(device));
+        allowed && !self.deny.iter().any(|filter| filter.matches(device))
+    }
+}
+
+#[cfg(feature = "config")]
+mod config {
+    use super::Rules;
+    use crate::{
+        error::{Error, Result},
+        filter::{Filter, Match},
+        id::{Pid, Vid},
+    };
+    use serde::Deserialize;
+    use std::path::Path;
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct File {
+        #[serde(default)]
+        allow: Vec<Rule>,
+        #[serde(default)]
+        deny: Vec<Rule>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Rule {
+        vid: Option<Id>,
+        pid: Option<Id>,
+        serial: Option<String>,
+    }
+
+    /// A USB id as a number, or as a hex string like the ones printed by the cli
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Number(u16),
+        Hex(String),
+    }
+
+    impl Id {
+        fn parse<T: std::str::FromStr<Err = Error> + Into<u16>>(self) -> Result<u16> {
+            match self {
+                Id::Number(id) => Ok(id),
+                Id::Hex(hex) => hex.parse::<T>().map(Into::into),
+            }
+        }
+    }
+
+    impl Rule {
+        fn into_filter(self) -> Result<Filter> {
+            let mut filter = Filter::new();
+            if let Some(vid) = self.vid {
+                filter = filter.vid(vid.parse::<Vid>()?);
+            }
+            if let Some(pid) = self.pid {
+                filter = filter.pid(pid.parse::<Pid>()?);
+            }
+            if let Some(serial) = self.serial {
+                filter = filter.serial(match serial.strip_suffix('*') {
+                    Some(prefix) => Match::prefix(prefix),
+                    None => Match::Exact(serial),
+                });
+            }
+            Ok(filter)
+        }
+    }
+
+    impl File {
+        fn into_rules(self) -> Result<Rules> {
+            let filters = |rules: Vec<Rule>| -> Result<Vec<Filter>> {
+                rules.into_iter().map(Rule::into_filter).collect()
+            };
+            Ok(Rules {
+                allow: filters(self.allow)?,
+                deny: filters(self.deny)?,
+            })
+        }
+    }
+
+    impl Rules {
+        /// Load the rules from a TOML file, or a JSON file when its extension is `.json`.
+        /// Enabled with the `config` feature
+        ///
+        /// Each rule matches a device by `vid`, `pid` and `serial`, all of them optional. The ids
+        /// are numbers or hex strings, and a serial ending with `*` matches its prefix
+        ///
+        /// ```toml
+        /// [[allow]]
+        /// vid = "0403"
+        /// pid = "6001"
+        ///
+        /// [[deny]]
+        /// vid = "1519"
+        /// serial = "MODEM-*"
+        /// ```
+        pub fn load(path: impl AsRef<Path>) -> Result<Rules> {
+            let path = path.as_ref();
+            let contents = std::fs::read_to_string(path)?;
+            let rules = match path.extension().is_some_and(|ext| ext == "json") {
+                true => Rules::from_json(&contents),
+                false => Rules::from_toml(&contents),
+            };
+            rules.map_err(|error| match error {
+                Error::Decode(what) => Error::Decode(format!("{what} of {}", path.display())),
+                error => error,
+            })
+        }
+
+        /// Parse the rules from TOML. See [`Rules::load`]
+        pub fn from_toml(contents: &str) -> Result<Rules> {
+            toml::from_str::<File>(contents)
+                .map_err(|error| Error::Decode(format!("device rules: {error}")))?
+                .into_rules()
+        }
+
+        /// Parse the rules from JSON. See [`Rules::load`]
+        pub fn from_json(contents: &str) -> Result<Rules> {
+            serde_json::from_str::<File>(contents)
+                .map_err(|error| Error::Decode(format!("device rules: {error}")))?
+                .into_rules()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn device(vid: u16, serial: &str) -> DeviceInfo {
+        DeviceInfo {
+            port: "/dev/ttyUSB0".to_string(),
+            vid_u16: Some(vid),
+            serial: Some(serial.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rules_admits() {
+        assert!(Rules::new().admits(&device(0x1519, "1")));
+        let rules = Rules::new()
+            .allow(Filter::new().vid(0x0403))
+            .allow(Filter::new().vid(0x1a86))
+            .deny(Filter::new().serial("2"));
+        assert!(rules.admits(&device(0x0403, "1")));
+        assert!(rules.admits(&device(0x1a86, "1")));
+        assert!(!rules.admits(&device(0x0403, "2")));
+        assert!(!rules.admits(&device(0x1519, "1")));
+        let deny = Rules::new().deny(Filter::new().vid(0x1519));
+        assert!(deny.admits(&device(0x0403, "1")));
+        assert!(!deny.admits(&device(0x1519, "1")));
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn test_rules_parse() {
+        let toml = Rules::from_toml(
+            r#"
+            [[allow]]
+            vid = "0403"
+            [[allow]]
+            vid = 0x1a86
+            pid = "7523"
+            [[deny]]
+            serial = "MODEM-*"
+            "#,
+        )
+        .unwrap();
+        let json = Rules::from_json(
+            r#"{
+                "allow": [{ "vid": "0403" }, { "vid": 6790, "pid": "7523" }],
+                "deny": [{ "serial": "MODEM-*" }]
+            }"#,
+        )
+        .unwrap();
+        for rules in [toml, json] {
+            assert!(rules.admits(&device(0x0403, "1")));
+            assert!(!rules.admits(&device(0x0403, "MODEM-1")));
+            assert!(!rules.admits(&device(0x1a86, "1")));
+            let mut ch340 = device(0x1a86, "1");
+            ch340.pid_u16 = Some(0x7523);
+            assert!(rules.admits(&ch340));
+        }
+        assert!(matches!(
+            Rules::from_toml("[[allow]]\nvid = \"xyz\""),
+            Err(crate::Error::Decode(_))
+        ));
+        assert!(matches!(
+            Rules::from_json(r#"{"allows": []}"#),
+            Err(crate::Error::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,55 @@
+// seat.rs
+//
+// The systemd-logind seat of our session, so that each session of a multi-seat machine only sees
+// the devices plugged into its own seat
+
+/// Where logind keeps the state of each session
+#[cfg(target_os = "linux")]
+const SESSIONS: &str = "/run/systemd/sessions";
+
+/// The seat of our session. logind sets XDG_SEAT in the environment of a graphical session,
+/// otherwise we look the session up in the logind state. None when it has no seat, IE: over ssh
+#[cfg(target_os = "linux")]
+pub(crate) fn current() -> Option<String> {
+    if let Some(seat) = std::env::var("XDG_SEAT")
+        .ok()
+        .filter(|seat| !seat.is_empty())
+    {
+        return Some(seat);
+    }
+    // The audit session id of the kernel is the id logind gives the session
+    let session = std::env::var("XDG_SESSION_ID")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/self/sessionid").ok())?;
+    let state = std::fs::read_to_string(format!("{SESSIONS}/{}", session.trim())).ok()?;
+    session_seat(&state)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn current() -> Option<String> {
+    None
+}
+
+/// The SEAT of the state file of a session, which is in the environment file format
+#[cfg(target_os = "linux")]
+fn session_seat(state: &str) -> Option<String> {
+    state
+        .lines()
+        .find_map(|line| line.strip_prefix("SEAT="))
+        .filter(|seat| !seat.is_empty())
+        .map(|seat| seat.to_string())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_seat() {
+        let state = "# This is private data. Do not parse.\nUID=1000\nUSER=kiosk\nACTIVE=1\n\
+                     SEAT=seat1\nTTY=tty2\n";
+        assert_eq!(session_seat(state).as_deref(), Some("seat1"));
+        assert_eq!(session_seat("UID=1000\nREMOTE=1\n"), None);
+        assert_eq!(session_seat("SEAT=\n"), None);
+    }
+}
@@ -0,0 +1,327 @@
+// server.rs
+//
+// The devices and the events of one listener over HTTP, for the machines which watch the serial
+// ports of another: a JSON snapshot, and a stream of server-sent events
+
+use crate::{
+    error::Result,
+    hub::{Hub, Publisher, Response},
+    AbortHandle, ListenBuilder,
+};
+use crossbeam::channel::RecvTimeoutError;
+use parking_lot::Mutex;
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use tracing::{debug, trace, warn};
+
+/// How long a client may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The largest request line and headers we read
+const MAX_HEAD: u64 = 8 * 1024;
+
+/// How often an idle event stream sends a comment, so that proxies keep it open and we notice a
+/// client which is gone
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// How many clients we serve at once, each with a thread of its own. The others are turned away
+const MAX_CLIENTS: usize = 64;
+
+impl ListenBuilder {
+    /// Listen, and serve the devices and the events over HTTP to the clients of `listener`,
+    /// instead of returning an [`EventIter`]. Enabled with the `server` feature
+    ///
+    /// - `GET /scan` returns the connected devices as a JSON array of [`DeviceInfo`]
+    /// - `GET /listen` streams the events as server-sent events until the client hangs up, each
+    ///   an [`EventInfo`] as JSON in a `data` field. `GET /listen?initial=true` starts with an Add
+    ///   event for every connected device. Errors of the listener are sent as `listener-error`
+    ///   events, with the message as a JSON string
+    ///
+    /// At most 64 clients are served at once, the others are answered `503 Service Unavailable`.
+    /// A client which falls 1024 events behind is hung up on. The listener reports the connected
+    /// devices when it starts, so [`ListenBuilder::initial`] is always on. There is neither
+    /// authentication nor TLS, so bind to an address of a trusted network. The server stops when
+    /// the [`AbortHandle`] is dropped
+    ///
+    /// ```no_run
+    /// use std::net::TcpListener;
+    ///
+    /// let listener = TcpListener::bind("0.0.0.0:7878")?;
+    /// let abort = serialport_detect::builder().serve_http(listener)?;
+    /// // curl http://localhost:7878/scan
+    /// // curl -N http://localhost:7878/listen?initial=true
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    ///
+    /// [`EventIter`]: crate::EventIter
+    /// [`DeviceInfo`]: crate::DeviceInfo
+    /// [`EventInfo`]: crate::EventInfo
+    pub fn serve_http(self, listener: TcpListener) -> Result<AbortHandle> {
+        let addr = listener.local_addr()?;
+        let hub = Arc::new(Mutex::new(Hub::default()));
+        let closed = Arc::new(AtomicBool::new(false));
+        let wake = loopback(addr);
+        let publisher = Publisher::new(&hub, &closed, move || {
+            let _ = TcpStream::connect(wake);
+        });
+        let abort = self.initial(true).listen_into(publisher)?;
+        debug!(%addr, "serving over HTTP");
+        thread::Builder::new()
+            .name("serialport-detect-server".into())
+            .spawn(move || accept(listener, &hub, &closed, MAX_CLIENTS))?;
+        Ok(abort)
+    }
+}
+
+/// The address to connect to for reaching a listener bound to `addr`, which may be unspecified
+/// (IE: 0.0.0.0)
+fn loopback(mut addr: SocketAddr) -> SocketAddr {
+    if addr.ip().is_unspecified() {
+        match addr {
+            SocketAddr::V4(_) => addr.set_ip(Ipv4Addr::LOCALHOST.into()),
+            SocketAddr::V6(_) => addr.set_ip(Ipv6Addr::LOCALHOST.into()),
+        }
+    }
+    addr
+}
+
+/// Serve every client with a thread of its own, until the listener is gone. Over `max_clients` a
+/// client is turned away
+fn accept(listener: TcpListener, hub: &Arc<Mutex<Hub>>, closed: &AtomicBool, max_clients: usize) {
+    let clients = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        if closed.load(Ordering::Acquire) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!(?error, "failed to accept client");
+                continue;
+            }
+        };
+        let client = Client::new(&clients);
+        if client.count > max_clients {
+            debug!(max_clients, "too many clients, turning one away");
+            let _ = reject(stream);
+            continue;
+        }
+        let hub = Arc::clone(hub);
+        let spawned = thread::Builder::new()
+            .name("serialport-detect-client".into())
+            .spawn(move || {
+                if let Err(error) = serve(stream, &hub) {
+                    trace!(?error, "client gone");
+                }
+                drop(client);
+            });
+        if let Err(error) = spawned {
+            warn!(?error, "failed to serve client");
+        }
+    }
+    trace!("closing HTTP server");
+}
+
+/// Answer a client we turn away without waiting for its request, which would hold up accepting
+/// others. We end our side first and take what already arrived of the request, so that the close
+/// is less likely to reset the connection before the client reads the answer
+fn reject(mut stream: TcpStream) -> io::Result<()> {
+    respond(
+        &mut stream,
+        "503 Service Unavailable",
+        "text/plain",
+        b"too many clients\n",
+    )?;
+    stream.shutdown(Shutdown::Write)?;
+    stream.set_nonblocking(true)?;
+    // Ends with WouldBlock once we read all that arrived
+    let _ = io::copy(&mut stream, &mut io::sink());
+    Ok(())
+}
+
+/// Counts a client being served, until it is dropped
+struct Client {
+    clients: Arc<AtomicUsize>,
+    /// How many clients are served, counting this one
+    count: usize,
+}
+
+impl Client {
+    fn new(clients: &Arc<AtomicUsize>) -> Client {
+        Client {
+            clients: Arc::clone(clients),
+            count: clients.fetch_add(1, Ordering::AcqRel) + 1,
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        self.clients.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Answer the request of a client. The connection is closed after the response
+fn serve(mut stream: TcpStream, hub: &Mutex<Hub>) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let Some((method, target)) = read_request(&stream)? else {
+        return respond(
+            &mut stream,
+            "400 Bad Request",
+            "text/plain",
+            b"bad request\n",
+        );
+    };
+    trace!(method, target, "request");
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+    if !matches!(path, "/scan" | "/listen") {
+        return respond(&mut stream, "404 Not Found", "text/plain", b"not found\n");
+    }
+    if method != "GET" {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"GET only\n",
+        );
+    }
+    if path == "/scan" {
+        let devices = hub.lock().scan();
+        let json = serde_json::to_vec(&devices).map_err(io::Error::from)?;
+        return respond(&mut stream, "200 OK", "application/json", &json);
+    }
+    let initial = query
+        .split('&')
+        .any(|param| matches!(param, "initial" | "initial=true" | "initial=1"));
+    let events = hub.lock().subscribe(initial);
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\n\
+          Content-Type: text/event-stream\r\n\
+          Cache-Control: no-cache\r\n\
+          Connection: close\r\n\r\n",
+    )?;
+    loop {
+        let message = match events.recv_timeout(KEEPALIVE) {
+            Ok(Response::Event(event)) => {
+                let json = serde_json::to_string(&event).map_err(io::Error::from)?;
+                format!("data: {json}\n\n")
+            }
+            Ok(Response::Error(error)) => {
+                let json = serde_json::to_string(&error).map_err(io::Error::from)?;
+                format!("event: listener-error\ndata: {json}\n\n")
+            }
+            Ok(Response::Devices(_)) => continue,
+            Err(RecvTimeoutError::Timeout) => ":\n\n".to_string(),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        stream.write_all(message.as_bytes())?;
+    }
+}
+
+/// The method and the target of a request. Its headers are read and ignored. None when it is
+/// not HTTP
+fn read_request(stream: &TcpStream) -> io::Result<Option<(String, String)>> {
+    let mut reader = BufReader::new(stream.take(MAX_HEAD));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let request = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => {
+            Some((method.to_string(), target.to_string()))
+        }
+        _ => return Ok(None),
+    };
+    loop {
+        line.clear();
+        // The headers end with an empty line, or with the limit
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            return Ok(request);
+        }
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: {content_type}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DeviceInfo, EventInfo, EventType};
+
+    #[test]
+    fn test_server() {
+        let hub = Arc::new(Mutex::new(Hub::default()));
+        let device = DeviceInfo {
+            port: "/dev/ttyUSB0".to_string(),
+            ..Default::default()
+        };
+        hub.lock()
+            .publish(Ok(EventInfo::new(device.clone(), EventType::Add)));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Arc::clone(&hub);
+        thread::spawn(move || accept(listener, &server, &AtomicBool::new(false), 2));
+        let request = |request: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            stream
+        };
+        let mut response = String::new();
+        request("GET /scan HTTP/1.1\r\nHost: lab\r\n\r\n")
+            .read_to_string(&mut response)
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        let devices = serde_json::from_str::<Vec<DeviceInfo>>(body).unwrap();
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].port, "/dev/ttyUSB0");
+        let mut response = String::new();
+        request("GET /nope HTTP/1.1\r\n\r\n")
+            .read_to_string(&mut response)
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        let stream = request("GET /listen?initial=true HTTP/1.1\r\n\r\n");
+        let mut lines = BufReader::new(stream).lines().map(|line| line.unwrap());
+        assert_eq!(lines.next().unwrap(), "HTTP/1.1 200 OK");
+        let mut data = lines.filter_map(|line| line.strip_prefix("data: ").map(str::to_string));
+        let event = |json: String| serde_json::from_str::<EventInfo>(&json).unwrap();
+        let initial = event(data.next().unwrap());
+        assert!(matches!(initial.event, EventType::Add));
+        hub.lock()
+            .publish(Ok(EventInfo::new(device, EventType::Remove)));
+        let removed = event(data.next().unwrap());
+        assert!(matches!(removed.event, EventType::Remove));
+        assert_eq!(removed.device.port, "/dev/ttyUSB0");
+
+        // Over the limit of clients, with the stream above and another
+        let _listening = request("GET /listen HTTP/1.1\r\n\r\n");
+        let mut response = String::new();
+        request("GET /scan HTTP/1.1\r\n\r\n")
+            .read_to_string(&mut response)
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+    }
+}
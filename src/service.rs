@@ -0,0 +1,306 @@
+// service.rs
+//
+// Publishes the events of a listener on D-Bus, so that applications in any language follow the
+// serial ports without linking us: a signal for each event, and a Scan method for a snapshot
+
+use crate::{
+    dbus::{Connection, Message, MessageType, Writer},
+    detect::{DeviceInfo, EventInfo, EventType},
+    error::Result,
+    forward::EventSender,
+    AbortHandle, ListenBuilder,
+};
+use mio::{unix::SourceFd, Events, Interest, Poll, Token, Waker};
+use parking_lot::Mutex;
+use std::{io, os::fd::AsRawFd, sync::Arc, thread};
+use tracing::{debug, trace, warn};
+
+/// The bus name we own by default
+const NAME: &str = "io.github.tomzbench.SerialportDetect";
+
+/// The object which emits the signals and answers Scan
+const PATH: &str = "/io/github/tomzbench/SerialportDetect";
+
+/// The interface of the object
+const INTERFACE: &str = "io.github.tomzbench.SerialportDetect";
+
+const INTROSPECTABLE: &str = "org.freedesktop.DBus.Introspectable";
+const PEER: &str = "org.freedesktop.DBus.Peer";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC
+ "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="io.github.tomzbench.SerialportDetect">
+    <method name="Scan">
+      <arg name="devices" type="aa{ss}" direction="out"/>
+    </method>
+    <signal name="DeviceAdded">
+      <arg name="device" type="a{ss}"/>
+    </signal>
+    <signal name="DeviceRemoved">
+      <arg name="device" type="a{ss}"/>
+    </signal>
+    <signal name="DeviceChanged">
+      <arg name="device" type="a{ss}"/>
+    </signal>
+    <signal name="DeviceMoved">
+      <arg name="device" type="a{ss}"/>
+      <arg name="old_port" type="s"/>
+    </signal>
+    <signal name="Error">
+      <arg name="message" type="s"/>
+    </signal>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg name="xml" type="s" direction="out"/>
+    </method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+  </interface>
+</node>
+"#;
+
+/// Messages from the bus
+const BUS: Token = Token(0);
+
+/// The listener is gone
+const STOP: Token = Token(1);
+
+/// Where [`ListenBuilder::serve_dbus`] publishes the events, on Linux with the `dbus` feature
+///
+/// The object `/io/github/tomzbench/SerialportDetect` implements the interface
+/// `io.github.tomzbench.SerialportDetect`. It emits the signals `DeviceAdded`, `DeviceRemoved`,
+/// `DeviceChanged` and `DeviceMoved` (with the old port) for the events of the listener, and
+/// `Error` for its errors. Its method `Scan` returns the matching devices. A device is a
+/// dictionary of strings, with the keys `port`, `vid`, `pid`, `serial`, `manufacturer`,
+/// `product`, `stable_id`, `location`, `seat`, `driver` and `syspath` of the properties it has
+///
+/// ```sh
+/// busctl --user call io.github.tomzbench.SerialportDetect /io/github/tomzbench/SerialportDetect \
+///     io.github.tomzbench.SerialportDetect Scan
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DbusService {
+    system: bool,
+    name: String,
+}
+
+impl DbusService {
+    /// Publish on the session bus of the user
+    pub fn session() -> DbusService {
+        DbusService {
+            system: false,
+            name: NAME.to_string(),
+        }
+    }
+
+    /// Publish on the system bus. The policy of the bus must allow us to own the name
+    pub fn system() -> DbusService {
+        DbusService {
+            system: true,
+            ..DbusService::session()
+        }
+    }
+
+    /// Own this bus name instead of `io.github.tomzbench.SerialportDetect`, IE: to run a service
+    /// per filter
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    fn connect(&self) -> io::Result<Connection> {
+        let mut bus = match self.system {
+            true => Connection::system()?,
+            false => Connection::session()?,
+        };
+        bus.request_name(&self.name)?;
+        debug!(name = self.name, system = self.system, "serving on D-Bus");
+        Ok(bus)
+    }
+}
+
+impl ListenBuilder {
+    /// Listen, and publish the events on D-Bus instead of returning an [`EventIter`]. See
+    /// [`DbusService`]
+    ///
+    /// Fails when we cannot connect to the bus or own the name, IE: when another service owns it.
+    /// The service stops when the [`AbortHandle`] is dropped
+    ///
+    /// ```no_run
+    /// use serialport_detect::DbusService;
+    ///
+    /// let abort = serialport_detect::builder()
+    ///     .vid(0x0403)
+    ///     .serve_dbus(DbusService::session())?;
+    /// # Ok::<(), serialport_detect::Error>(())
+    /// ```
+    ///
+    /// [`EventIter`]: crate::EventIter
+    pub fn serve_dbus(self, service: DbusService) -> Result<AbortHandle> {
+        let bus = service.connect()?;
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), STOP)?);
+        let fd = bus.as_raw_fd();
+        poll.registry()
+            .register(&mut SourceFd(&fd), BUS, Interest::READABLE)?;
+        let bus = Arc::new(Mutex::new(bus));
+        let scan = self.clone();
+        let signals = Signals {
+            bus: Arc::clone(&bus),
+            waker: Arc::clone(&waker),
+        };
+        let abort = self.listen_into(signals)?;
+        thread::Builder::new()
+            .name("serialport-detect-dbus".into())
+            .spawn(move || serve(poll, &bus, &scan, waker))?;
+        Ok(abort)
+    }
+}
+
+/// Emits a signal for every event. Wakes the service up to stop once the listener is gone
+struct Signals {
+    bus: Arc<Mutex<Connection>>,
+    waker: Arc<Waker>,
+}
+
+impl EventSender for Signals {
+    fn send_event(&self, event: Result<EventInfo>) -> bool {
+        let mut body = Writer::new();
+        let (member, signature) = match event {
+            Ok(event) => {
+                device(&mut body, &event.device);
+                match event.event {
+                    EventType::Add => ("DeviceAdded", "a{ss}"),
+                    EventType::Remove => ("DeviceRemoved", "a{ss}"),
+                    EventType::Change => ("DeviceChanged", "a{ss}"),
+                    EventType::Moved => {
+                        body.string(event.old_port.as_deref().unwrap_or_default());
+                        ("DeviceMoved", "a{ss}s")
+                    }
+                }
+            }
+            Err(error) => {
+                body.string(&error.to_string());
+                ("Error", "s")
+            }
+        };
+        trace!(member, "emitting signal");
+        let sent = self
+            .bus
+            .lock()
+            .signal(PATH, INTERFACE, member, signature, &body.into_inner());
+        match sent {
+            Ok(()) => true,
+            Err(error) => {
+                warn!(?error, "lost the bus, not emitting signals");
+                false
+            }
+        }
+    }
+}
+
+impl Drop for Signals {
+    fn drop(&mut self) {
+        let _ = self.waker.wake();
+    }
+}
+
+/// Answer the method calls until the listener is gone or we lose the bus. The waker lives as long
+/// as we poll it
+fn serve(mut poll: Poll, bus: &Mutex<Connection>, builder: &ListenBuilder, _waker: Arc<Waker>) {
+    let mut events = Events::with_capacity(4);
+    loop {
+        // The calls received while we owned the name are pending already, so we read first
+        let received = bus.lock().receive();
+        let answered = received.and_then(|messages| {
+            messages
+                .iter()
+                .try_for_each(|message| answer(bus, message, builder))
+        });
+        if let Err(error) = answered {
+            warn!(?error, "lost the bus, not answering method calls");
+            return;
+        }
+        match poll.poll(&mut events, None) {
+            Ok(()) if events.iter().any(|event| event.token() == STOP) => {
+                trace!("closing D-Bus service");
+                return;
+            }
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => {
+                warn!(?error, "failed to poll the bus");
+                return;
+            }
+        }
+    }
+}
+
+/// Answer a method call of the bus. The other messages are ignored, IE: NameAcquired
+fn answer(bus: &Mutex<Connection>, call: &Message, builder: &ListenBuilder) -> io::Result<()> {
+    if call.kind != MessageType::MethodCall {
+        return Ok(());
+    }
+    trace!(member = call.member, "method call");
+    if call.path.as_deref() != Some(PATH) {
+        let error = "org.freedesktop.DBus.Error.UnknownObject";
+        return bus.lock().error(call, error, "no such object");
+    }
+    if call.is_call(INTERFACE, "Scan") {
+        // Scan without holding the bus, the listener emits signals meanwhile
+        return match builder.scan_sorted() {
+            Ok(devices) => {
+                let mut body = Writer::new();
+                body.array(4, |body| {
+                    devices.iter().for_each(|info| device(body, info));
+                });
+                bus.lock().reply(call, "aa{ss}", &body.into_inner())
+            }
+            Err(error) => {
+                let name = "org.freedesktop.DBus.Error.Failed";
+                bus.lock().error(call, name, &error.to_string())
+            }
+        };
+    }
+    if call.is_call(INTROSPECTABLE, "Introspect") {
+        let mut body = Writer::new();
+        body.string(INTROSPECTION);
+        return bus.lock().reply(call, "s", &body.into_inner());
+    }
+    if call.is_call(PEER, "Ping") {
+        return bus.lock().reply(call, "", &[]);
+    }
+    let error = "org.freedesktop.DBus.Error.UnknownMethod";
+    bus.lock().error(call, error, "no such method")
+}
+
+/// Write a device as a dictionary of the properties it has
+fn device(body: &mut Writer, device: &DeviceInfo) {
+    let properties = [
+        ("port", Some(device.port.as_str())),
+        ("vid", device.vid.as_deref()),
+        ("pid", device.pid.as_deref()),
+        ("serial", device.serial.as_deref()),
+        ("manufacturer", device.manufacturer.as_deref()),
+        ("product", device.product.as_deref()),
+        ("stable_id", device.stable_id.as_deref()),
+        ("location", device.location.as_deref()),
+        ("seat", device.seat.as_deref()),
+        ("driver", device.driver.as_deref()),
+        ("syspath", device.syspath.as_deref()),
+    ];
+    body.array(8, |body| {
+        for (key, value) in properties {
+            let Some(value) = value else {
+                continue;
+            };
+            body.align(8);
+            body.string(key);
+            body.string(value);
+        }
+    });
+}
@@ -0,0 +1,309 @@
+// Sysfs support
+//
+// Used where there is no libudev (IE: Android, or Linux when udev is not running). We watch /dev
+// with inotify for tty nodes being created and removed, and read the USB descriptors of the device
+// from sysfs
+
+use crate::{
+    detect::{
+        listener_span, usb_stable_id, DeviceInfo, EventInfo, EventType, PortType, Queue, UsbClass,
+        UsbSpeed,
+    },
+    error::{Error, Result},
+    id::{parse_hex, Pid, Vid},
+    poll, ready, retry,
+};
+use mio::{unix::SourceFd, Events, Interest, Token};
+use nix::{
+    errno::Errno,
+    sys::{
+        eventfd::{EfdFlags, EventFd},
+        inotify::{AddWatchFlags, InitFlags, Inotify},
+    },
+    unistd,
+};
+use std::{
+    collections::HashMap,
+    fs, io,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc},
+    thread::JoinHandle,
+};
+use tracing::{error, trace};
+
+/// Every tty is listed here, including the virtual consoles which have no device link
+const SYSFS_TTY: &str = "/sys/class/tty";
+
+#[derive(Debug)]
+struct ListenerOptions {
+    capacity: usize,
+    evfd: RawFd,
+    ready: mpsc::Sender<Result<()>>,
+}
+
+/// Scan for connected devices
+pub fn scan() -> io::Result<HashMap<String, DeviceInfo>> {
+    let mut items = HashMap::new();
+    for entry in fs::read_dir(SYSFS_TTY)? {
+        let name = entry?.file_name();
+        if let Some(device) = name.to_str().and_then(read_device_info) {
+            items.insert(device.port.clone(), device);
+        }
+    }
+    Ok(items)
+}
+
+/// Look up the device at `port`, following links to it
+pub fn port_info(port: &str) -> io::Result<Option<DeviceInfo>> {
+    let path = match fs::canonicalize(port) {
+        Ok(path) => path,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+    let name = path
+        .strip_prefix("/dev")
+        .ok()
+        .and_then(|name| name.to_str());
+    Ok(name.and_then(read_device_info))
+}
+
+/// Listen for connected devices
+pub fn listen(queue: Arc<Queue>) -> Result<AbortHandle> {
+    let evfd = EventFd::from_value_and_flags(0, EfdFlags::EFD_NONBLOCK | EfdFlags::EFD_SEMAPHORE)
+        .map_err(io::Error::from)?;
+    let (ready, rx) = mpsc::channel();
+    let opts = ListenerOptions {
+        capacity: 1024,
+        evfd: evfd.as_raw_fd(),
+        ready,
+    };
+    let span = listener_span("sysfs");
+    let join_handle = std::thread::spawn(move || span.in_scope(|| listener(queue, opts)));
+    // Wait for the listener so that setup errors are returned to the caller
+    match rx.recv() {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            let _ = join_handle.join();
+            return Err(error);
+        }
+        Err(_) => {
+            let error = io::Error::other("listener exited during setup");
+            return Err(Error::Backend(error));
+        }
+    }
+    let join_handle = Some(join_handle);
+    Ok(AbortHandle { evfd, join_handle })
+}
+
+fn listener(queue: Arc<Queue>, opts: ListenerOptions) {
+    trace!(capacity = opts.capacity, "listening");
+    // Safety: EventFd is private and when dropped we close, and remains open until join is called.
+    // See AbortHandle drop
+    let evfd = unsafe { BorrowedFd::borrow_raw(opts.evfd) };
+    let (mut inotify, mut poller) = match init_listener(evfd.as_fd()) {
+        Ok(result) => result,
+        Err(error) => {
+            error!(?error, "failed to setup listener");
+            let _ = opts.ready.send(Err(error));
+            return;
+        }
+    };
+    let _ = opts.ready.send(Ok(()));
+    // The sysfs entry is gone by the time the node is deleted, so we remember what we learned
+    // about each device when it was created
+    let mut cache = scan().unwrap_or_default();
+    let mut events = Events::with_capacity(opts.capacity);
+    let mut attempt = 0;
+    'main: loop {
+        let mut failed = poller.poll(&mut events, None).err();
+        'events: for event in events.iter().filter(|_| failed.is_none()) {
+            if event.token() == Token(0) && event.is_readable() {
+                trace!("closing listener");
+                let mut arr = [0; std::mem::size_of::<u64>()];
+                let _ = unistd::read(evfd.as_fd(), &mut arr);
+                queue.done();
+                break 'main;
+            } else if event.token() == Token(1) && event.is_readable() {
+                // The inotify fd is edge triggered, and a storm under /dev may not fit one read, so
+                // we read until it is drained
+                loop {
+                    let items = match inotify.read_events() {
+                        Ok(items) => items,
+                        Err(Errno::EAGAIN) => break,
+                        Err(error) => {
+                            failed = Some(error.into());
+                            break 'events;
+                        }
+                    };
+                    for item in items {
+                        let name = match item.name.as_ref().and_then(|name| name.to_str()) {
+                            Some(name) if name.starts_with("tty") => name,
+                            _ => continue,
+                        };
+                        trace!(name, mask = ?item.mask, "device event");
+                        if item.mask.contains(AddWatchFlags::IN_CREATE) {
+                            if let Some(device) = read_device_info(name) {
+                                cache.insert(device.port.clone(), device.clone());
+                                ready::push(&queue, EventInfo::new(device, EventType::Add));
+                            }
+                        } else if item.mask.contains(AddWatchFlags::IN_DELETE) {
+                            if let Some(device) = cache.remove(&format!("/dev/{name}")) {
+                                queue.push(Ok(EventInfo::new(device, EventType::Remove)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let Some(error) = failed else {
+            attempt = 0;
+            continue;
+        };
+        error!(?error, "failed to watch /dev");
+        queue.push(Err(error));
+        match retry::restart(&queue, evfd, &mut attempt, || init_listener(evfd)) {
+            Some(listener) => (inotify, poller) = listener,
+            None => return,
+        }
+        // Catch up with whatever happened while we were not watching
+        let next = scan().unwrap_or_default();
+        for event in poll::diff(&cache, &next) {
+            ready::push(&queue, event);
+        }
+        cache = next;
+    }
+    trace!("listener finished");
+}
+
+#[inline]
+fn init_listener(evfd: BorrowedFd<'_>) -> Result<(Inotify, mio::Poll)> {
+    let inotify =
+        Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC).map_err(io::Error::from)?;
+    inotify
+        .add_watch("/dev", AddWatchFlags::IN_CREATE | AddWatchFlags::IN_DELETE)
+        .map_err(|errno| Error::Registration(errno.into()))?;
+    let poll = mio::Poll::new()?;
+    poll.registry().register(
+        &mut SourceFd(&evfd.as_raw_fd()),
+        Token(0),
+        Interest::READABLE,
+    )?;
+    poll.registry().register(
+        &mut SourceFd(&inotify.as_fd().as_raw_fd()),
+        Token(1),
+        Interest::READABLE,
+    )?;
+    Ok((inotify, poll))
+}
+
+/// Read the USB descriptors of a tty (IE: ttyUSB0). Returns None if the tty is not a USB device
+pub(crate) fn read_device_info(name: &str) -> Option<DeviceInfo> {
+    let device = fs::canonicalize(Path::new(SYSFS_TTY).join(name).join("device")).ok()?;
+    // The tty's device is the USB interface (cdc_acm) or a usb-serial port below it
+    let interface_dir = match device.join("bInterfaceNumber").is_file() {
+        true => Some(device.as_path()),
+        false => device.parent(),
+    };
+    let interface = interface_dir.and_then(|dir| read_attr(dir, "bInterfaceNumber"));
+    let interface_class = interface_dir.and_then(|dir| {
+        let code = |attr| read_attr(dir, attr);
+        UsbClass::from_hex(
+            &code("bInterfaceClass")?,
+            &code("bInterfaceSubClass")?,
+            &code("bInterfaceProtocol")?,
+        )
+    });
+    let driver = read_driver(&device).or_else(|| read_driver(device.parent()?));
+    let usb = usb_device(device)?;
+    let attr = |attr| read_attr(&usb, attr);
+    let vid = attr("idVendor").and_then(|s| s.parse::<Vid>().ok());
+    let pid = attr("idProduct").and_then(|s| s.parse::<Pid>().ok());
+    let (vid_u16, pid_u16) = (vid.map(u16::from), pid.map(u16::from));
+    let serial = attr("serial");
+    // The USB device is named after its bus number and hub ports (IE: 1-3.2)
+    let location = usb
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(|s| s.to_string());
+    Some(DeviceInfo {
+        port: format!("/dev/{name}"),
+        vid_u16,
+        pid_u16,
+        bcd_device: attr("bcdDevice").and_then(|bcd| parse_hex(&bcd)),
+        vid: vid.map(|vid| vid.to_string()),
+        pid: pid.map(|pid| pid.to_string()),
+        stable_id: usb_stable_id(vid_u16, pid_u16, serial.as_deref(), interface.as_deref()),
+        serial,
+        manufacturer: attr("manufacturer"),
+        product: attr("product"),
+        port_type: PortType::Usb,
+        usb_speed: attr("speed").and_then(|speed| UsbSpeed::from_sysfs(&speed)),
+        interface_class,
+        location,
+        driver,
+        syspath: fs::canonicalize(Path::new(SYSFS_TTY).join(name))
+            .ok()
+            .and_then(|path| path.to_str().map(|s| s.to_string())),
+        ..Default::default()
+    })
+}
+
+fn read_attr(path: &Path, attr: &str) -> Option<String> {
+    fs::read_to_string(path.join(attr))
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+/// The name of the driver bound to a sysfs device, which is the target of its driver link
+fn read_driver(path: &Path) -> Option<String> {
+    let driver = fs::read_link(path.join("driver")).ok()?;
+    driver.file_name()?.to_str().map(|s| s.to_string())
+}
+
+/// Walk up from the tty's device (a USB interface or a usb-serial port) to the USB device, which
+/// is the first ancestor with an idVendor attribute
+fn usb_device(mut path: PathBuf) -> Option<PathBuf> {
+    loop {
+        if path.join("idVendor").is_file() {
+            break Some(path);
+        }
+        if !path.pop() || path == Path::new("/sys/devices") {
+            break None;
+        }
+    }
+}
+
+/// The AbortHandle will cause the [`crate::EventIter`] to stop emitting events when dropped
+#[derive(Debug)]
+pub struct AbortHandle {
+    evfd: EventFd,
+    join_handle: Option<JoinHandle<()>>,
+}
+
+impl AbortHandle {
+    /// False once the listener thread has exited
+    pub(crate) fn is_alive(&self) -> bool {
+        self.join_handle
+            .as_ref()
+            .is_some_and(|jh| !jh.is_finished())
+    }
+}
+
+impl Drop for AbortHandle {
+    // We signal the remote thread to break its loop with the eventfd, and then we join
+    fn drop(&mut self) {
+        trace!("dropping event iter");
+        if let Some(jh) = self.join_handle.take() {
+            match self.evfd.write(1) {
+                Err(error) => error!(?error, "failed to write evfd"),
+                Ok(_) => {
+                    if let Err(error) = jh.join() {
+                        error!(?error, "event iter join error");
+                    }
+                }
+            }
+        }
+    }
+}
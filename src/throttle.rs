@@ -0,0 +1,145 @@
+// throttle.rs
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Limits on the rate of events, so that a device caught in a connect and disconnect loop does not
+/// flood the consumer. See [`crate::ListenBuilder::rate_limit`]
+///
+/// The events over a limit are dropped, and the stream yields an [`crate::Error::Throttled`] which
+/// counts them. As the events of a port may have been dropped, a consumer which follows the state
+/// of the devices should take a snapshot again. Events of filtered out devices are not counted
+///
+/// ```no_run
+/// use serialport_detect::RateLimit;
+/// use std::time::Duration;
+///
+/// let limit = RateLimit::new()
+///     .per_port(10, Duration::from_secs(1))
+///     .global(100, Duration::from_secs(1));
+/// let (abort, events) = serialport_detect::builder().rate_limit(limit).listen()?;
+/// # Ok::<(), serialport_detect::Error>(())
+/// ```
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RateLimit {
+    per_port: Option<Limit>,
+    global: Option<Limit>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Limit {
+    events: u32,
+    window: Duration,
+}
+
+impl RateLimit {
+    /// No limit, until one is set
+    pub fn new() -> RateLimit {
+        RateLimit::default()
+    }
+
+    /// Report at most `events` of each port every `window`
+    pub fn per_port(mut self, events: u32, window: Duration) -> Self {
+        self.per_port = Some(Limit { events, window });
+        self
+    }
+
+    /// Report at most `events` of all the ports together every `window`
+    pub fn global(mut self, events: u32, window: Duration) -> Self {
+        self.global = Some(Limit { events, window });
+        self
+    }
+}
+
+/// The events counted in the current window of a limit
+#[derive(Debug, Default)]
+struct Window {
+    start: Option<Instant>,
+    count: u32,
+}
+
+impl Window {
+    /// Whether the limit is reached, starting a new window once the current one is over
+    fn full(&mut self, limit: Limit, now: Instant) -> bool {
+        if self
+            .start
+            .is_none_or(|start| now.duration_since(start) >= limit.window)
+        {
+            self.start = Some(now);
+            self.count = 0;
+        }
+        self.count >= limit.events
+    }
+}
+
+/// Counts the events of each port and of the listener against a [`RateLimit`]
+#[derive(Debug, Default)]
+pub(crate) struct Throttle {
+    limit: RateLimit,
+    ports: HashMap<String, Window>,
+    global: Window,
+}
+
+impl Throttle {
+    pub(crate) fn new(limit: RateLimit) -> Throttle {
+        Throttle {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// Returns false if an event of `port` is over a limit. The events we drop are not counted
+    pub(crate) fn admit(&mut self, port: &str, now: Instant) -> bool {
+        if let Some(limit) = self.limit.per_port {
+            // Forget the ports which have been quiet for a window
+            self.ports.retain(|_, window| {
+                window
+                    .start
+                    .is_some_and(|start| now.duration_since(start) < limit.window)
+            });
+            if self
+                .ports
+                .entry(port.to_string())
+                .or_default()
+                .full(limit, now)
+            {
+                return false;
+            }
+        }
+        if let Some(limit) = self.limit.global {
+            if self.global.full(limit, now) {
+                return false;
+            }
+            self.global.count += 1;
+        }
+        if let Some(window) = self.ports.get_mut(port) {
+            window.count += 1;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_throttle() {
+        let second = Duration::from_secs(1);
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut throttle = Throttle::new(RateLimit::new().per_port(2, second).global(3, second));
+        assert!(throttle.admit("a", at(0)));
+        assert!(throttle.admit("a", at(10)));
+        assert!(!throttle.admit("a", at(20)));
+        // The event dropped by the limit of its port does not count against the global one
+        assert!(throttle.admit("b", at(30)));
+        assert!(!throttle.admit("c", at(40)));
+        // Both windows start over
+        assert!(throttle.admit("a", at(1000)));
+        assert!(throttle.admit("c", at(1010)));
+        let mut unlimited = Throttle::new(RateLimit::new());
+        assert!((0..100).all(|millis| unlimited.admit("a", at(millis))));
+    }
+}
@@ -0,0 +1,92 @@
+// timer.rs
+use parking_lot::Mutex;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+/// A future which resolves after a delay, without depending on an async runtime. The delay is
+/// waited out by a thread of our own, which exits early when the timer is dropped
+#[derive(Debug)]
+pub(crate) struct Timer {
+    rx: Notified,
+    _cancel: mpsc::Sender<()>,
+}
+
+impl Timer {
+    pub(crate) fn after(delay: Duration) -> Timer {
+        let (tx, rx) = notify();
+        let (cancel, cancelled) = mpsc::channel::<()>();
+        let spawned = std::thread::Builder::new()
+            .name("serialport-detect-timer".into())
+            .spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(delay) {
+                    drop(tx);
+                }
+            });
+        // Without a thread the notifier is dropped, and the timer fires immediately
+        if let Err(error) = spawned {
+            tracing::error!(?error, "failed to spawn the timer thread");
+        }
+        Timer {
+            rx,
+            _cancel: cancel,
+        }
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        Pin::new(&mut self.rx).poll(cx)
+    }
+}
+
+/// A oneshot channel without a value. The [`Notified`] future resolves once its [`Notifier`] is
+/// dropped
+pub(crate) fn notify() -> (Notifier, Notified) {
+    let shared = Arc::new(Mutex::new(Notification::default()));
+    (Notifier(Arc::clone(&shared)), Notified(shared))
+}
+
+#[derive(Debug, Default)]
+struct Notification {
+    notified: bool,
+    waker: Option<Waker>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Notifier(Arc<Mutex<Notification>>);
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        let mut shared = self.0.lock();
+        shared.notified = true;
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Notified(Arc<Mutex<Notification>>);
+
+impl Future for Notified {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut shared = self.0.lock();
+        match shared.notified {
+            true => Poll::Ready(()),
+            false => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
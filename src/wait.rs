@@ -0,0 +1,69 @@
+// wait.rs
+use crate::{
+    detect::{DeviceInfo, EventType},
+    error::{Error, Result},
+    timer::Timer,
+    ListenBuilder,
+};
+use futures::{
+    future::{self, Either},
+    StreamExt,
+};
+use std::{io, time::Duration};
+use tracing::debug;
+
+/// Resolve with the first device matching `filter`, as soon as it is present
+///
+/// A device which is already connected resolves immediately, otherwise we listen until one is
+/// plugged in. Fails with [`Error::Timeout`] when none shows up within `timeout`. Errors of the
+/// listener are skipped, unless it stops for good
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// # async fn run() -> serialport_detect::Result<()> {
+/// let filter = serialport_detect::builder().vid(0x0403).pid(0x6001);
+/// let device = serialport_detect::wait_for_device(filter, Duration::from_secs(10)).await?;
+/// println!("found {}", device.port);
+/// # Ok(())
+/// # }
+/// ```
+pub async fn wait_for_device(filter: ListenBuilder, timeout: Duration) -> Result<DeviceInfo> {
+    first_device(filter, Some(timeout)).await
+}
+
+/// See [`wait_for_device`]. Waits forever without a `timeout`
+pub(crate) async fn first_device(
+    filter: ListenBuilder,
+    timeout: Option<Duration>,
+) -> Result<DeviceInfo> {
+    // The initial events cover the devices which are already connected, without a window in
+    // which a device could be missed
+    let (_abort, mut events) = filter.initial(true).listen()?;
+    let found = Box::pin(async move {
+        let mut last = None;
+        while let Some(event) = events.next().await {
+            match event {
+                Ok(event) if matches!(event.event, EventType::Add) => return Ok(event.device),
+                Ok(_) => {}
+                Err(error) => {
+                    debug!(?error, "error while waiting for device");
+                    last = Some(error);
+                }
+            }
+        }
+        Err(last.unwrap_or_else(|| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "device listener stopped",
+            ))
+        }))
+    });
+    let Some(timeout) = timeout else {
+        return found.await;
+    };
+    match future::select(found, Timer::after(timeout)).await {
+        Either::Left((result, _)) => result,
+        Either::Right(((), _)) => Err(Error::Timeout),
+    }
+}
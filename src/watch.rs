@@ -0,0 +1,58 @@
+// watch.rs
+use crate::{detect::DeviceInfo, error::Result, EventIter, EventType};
+use futures::StreamExt;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::watch;
+use tracing::{error, trace};
+
+/// A shared snapshot of the currently connected devices, keyed by port name
+pub type DeviceMap = Arc<HashMap<String, DeviceInfo>>;
+
+impl EventIter {
+    /// Consume the event stream into a [`watch::Receiver`] holding the currently connected devices
+    ///
+    /// The map is seeded with the [`EventIter::snapshot`] of the stream, so a mock, a replay or a
+    /// polling listener is followed from its own devices, and kept up to date by a task spawned on
+    /// the current tokio runtime. Because the snapshot is taken after the listener has started, no
+    /// events are missed in between. The task finishes when the [`crate::AbortHandle`] is dropped
+    /// or when every receiver has been dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a tokio runtime
+    pub fn into_watch(self) -> Result<watch::Receiver<DeviceMap>> {
+        let devices = self.snapshot()?;
+        let (tx, rx) = watch::channel(Arc::new(devices));
+        let mut stream = self;
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = tx.closed() => break,
+                    next = stream.next() => match next {
+                        None => break,
+                        Some(Err(error)) => error!(?error, "device watch error"),
+                        Some(Ok(event)) => tx.send_modify(|devices| {
+                            let devices = Arc::make_mut(devices);
+                            match event.event {
+                                EventType::Add | EventType::Change => {
+                                    devices.insert(event.device.port.clone(), event.device);
+                                }
+                                EventType::Remove => {
+                                    devices.remove(&event.device.port);
+                                }
+                                EventType::Moved => {
+                                    if let Some(old_port) = &event.old_port {
+                                        devices.remove(old_port);
+                                    }
+                                    devices.insert(event.device.port.clone(), event.device);
+                                }
+                            }
+                        }),
+                    }
+                }
+            }
+            trace!("device watch finished");
+        });
+        Ok(rx)
+    }
+}
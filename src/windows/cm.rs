@@ -0,0 +1,180 @@
+//! cm
+//!
+//! Device interface notifications of the configuration manager. Unlike the window backend there is
+//! no window or message loop, so it works from services and non-interactive sessions
+
+use crate::{
+    detect::{listener_span, EventType},
+    error::{Error, Result},
+    guid,
+    windows::{IterState, RECONCILE_INTERVAL},
+};
+use parking_lot::Mutex;
+use std::{
+    ffi::c_void,
+    io, ptr,
+    sync::{mpsc, Arc},
+    thread::JoinHandle,
+};
+use tracing::{error, warn};
+use windows_sys::{
+    core::GUID,
+    Win32::{
+        Devices::DeviceAndDriverInstallation::{
+            CM_MapCrToWin32Err, CM_Register_Notification, CM_Unregister_Notification,
+            CM_NOTIFY_ACTION, CM_NOTIFY_ACTION_DEVICEINTERFACEARRIVAL,
+            CM_NOTIFY_ACTION_DEVICEINTERFACEREMOVAL, CM_NOTIFY_EVENT_DATA, CM_NOTIFY_FILTER,
+            CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE, CR_SUCCESS, HCMNOTIFICATION,
+        },
+        Foundation::{ERROR_GEN_FAILURE, ERROR_SUCCESS},
+    },
+};
+
+/// Our notification registrations, unregistered when dropped
+#[derive(Debug)]
+pub(crate) struct Registration {
+    handles: Vec<HCMNOTIFICATION>,
+    context: *const Context,
+    /// The thread which rescans every [`RECONCILE_INTERVAL`], stopped when the sender is dropped
+    reconcile: Option<(mpsc::Sender<()>, JoinHandle<()>)>,
+}
+
+// Safety: the handles and the context are only used to unregister, and the context is shared with
+// the callbacks through its mutex
+unsafe impl Send for Registration {}
+unsafe impl Sync for Registration {}
+
+struct Context {
+    state: Arc<IterState>,
+    /// Callbacks run on the thread pool and may run at once. We handle one at a time, so that they
+    /// do not race to diff the cache, nor with a rescan
+    busy: Arc<Mutex<()>>,
+    /// Entered by each callback, as there is no listener thread of our own
+    span: tracing::Span,
+}
+
+/// Register for the arrival and removal of COM port and USB device interfaces
+pub(crate) fn listen(state: Arc<IterState>) -> Result<Registration> {
+    const COMPORT: GUID =
+        guid!(0x86e0d1e0, 0x8089, 0x11d0, 0x9c, 0xe4, 0x08, 0x00, 0x3e, 0x30, 0x1f, 0x73);
+    const USBDEVICE: GUID =
+        guid!(0xA5DCBF10, 0x6530, 0x11D2, 0x90, 0x1F, 0x00, 0xC0, 0x4F, 0xB9, 0x51, 0xED);
+    let context = Box::into_raw(Box::new(Context {
+        state,
+        busy: Arc::new(Mutex::new(())),
+        span: listener_span("config-manager"),
+    }));
+    // Dropped on error, which unregisters what we registered so far
+    let mut registration = Registration {
+        handles: Vec::new(),
+        context,
+        reconcile: None,
+    };
+    for guid in [COMPORT, USBDEVICE] {
+        let mut filter = CM_NOTIFY_FILTER {
+            cbSize: std::mem::size_of::<CM_NOTIFY_FILTER>() as u32,
+            FilterType: CM_NOTIFY_FILTER_TYPE_DEVICEINTERFACE,
+            ..Default::default()
+        };
+        filter.u.DeviceInterface.ClassGuid = guid;
+        let mut handle = ptr::null_mut();
+        // Safety: the context outlives the registration, see Drop
+        let rc =
+            unsafe { CM_Register_Notification(&filter, context as _, Some(callback), &mut handle) };
+        if rc != CR_SUCCESS {
+            // Safety: only maps an error code
+            let code = unsafe { CM_MapCrToWin32Err(rc, ERROR_GEN_FAILURE) };
+            let error = io::Error::from_raw_os_error(code as i32);
+            return Err(Error::Registration(error));
+        }
+        registration.handles.push(handle);
+    }
+    // Safety: the context lives until the registration is dropped
+    registration.reconcile = reconcile(unsafe { &*context });
+    Ok(registration)
+}
+
+impl Registration {
+    /// False once the notifications are unregistered, or once the reconcile thread has exited,
+    /// IE: it panicked while rescanning
+    pub(crate) fn is_alive(&self) -> bool {
+        !self.handles.is_empty()
+            && self
+                .reconcile
+                .as_ref()
+                .is_none_or(|(_, jh)| !jh.is_finished())
+    }
+}
+
+/// Rescan every [`RECONCILE_INTERVAL`], as Windows can give a device another COM number without a
+/// notification. None if the thread could not be spawned, and we do without
+fn reconcile(context: &Context) -> Option<(mpsc::Sender<()>, JoinHandle<()>)> {
+    let (tx, rx) = mpsc::channel::<()>();
+    let state = Arc::clone(&context.state);
+    let busy = Arc::clone(&context.busy);
+    let span = context.span.clone();
+    let spawned = std::thread::Builder::new()
+        .name("serialport-detect-reconcile".into())
+        .spawn(move || {
+            let _span = span.entered();
+            while let Err(mpsc::RecvTimeoutError::Timeout) = rx.recv_timeout(RECONCILE_INTERVAL) {
+                let _busy = busy.lock();
+                state.resync();
+            }
+        });
+    match spawned {
+        Ok(jh) => Some((tx, jh)),
+        Err(error) => {
+            warn!(?error, "failed to spawn the reconcile thread");
+            None
+        }
+    }
+}
+
+/// Interface notifications do not name the port, so we diff the present ports with our cache
+unsafe extern "system" fn callback(
+    _notify: HCMNOTIFICATION,
+    context: *const c_void,
+    action: CM_NOTIFY_ACTION,
+    _data: *const CM_NOTIFY_EVENT_DATA,
+    _size: u32,
+) -> u32 {
+    // Safety: the context lives until every registration is unregistered
+    let context = &*(context as *const Context);
+    let _busy = context.busy.lock();
+    let _span = context.span.enter();
+    let state = &context.state;
+    match action {
+        CM_NOTIFY_ACTION_DEVICEINTERFACEARRIVAL => {
+            state.arrival(&state.changed_ports(EventType::Add))
+        }
+        CM_NOTIFY_ACTION_DEVICEINTERFACEREMOVAL => {
+            state.removal(&state.changed_ports(EventType::Remove))
+        }
+        _ => {}
+    }
+    ERROR_SUCCESS
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if let Some((tx, jh)) = self.reconcile.take() {
+            drop(tx);
+            let _ = jh.join();
+        }
+        let mut unregistered = true;
+        for handle in self.handles.drain(..) {
+            // Safety: waits for the callbacks in flight, and none run once it returns
+            if unsafe { CM_Unregister_Notification(handle) } != CR_SUCCESS {
+                error!("failed to unregister device notification");
+                unregistered = false;
+            }
+        }
+        // Safety: the context is freed only once no callback can use it, and leaked otherwise
+        let context = unsafe { &*self.context };
+        context.state.queue.done();
+        if unregistered {
+            drop(unsafe { Box::from_raw(self.context as *mut Context) });
+        }
+    }
+}
@@ -1,136 +1,424 @@
+mod cm;
 mod guid;
+mod setupapi;
 mod wide;
 mod wm;
 
 use crate::{
-    detect::{DeviceInfo, Queue},
-    EventInfo,
+    detect::{listener_span, DeviceInfo, EventInfo, EventType, PortType, Queue, WindowsBackend},
+    error::{Error, Result},
+    id::{Pid, Vid},
+    poll,
 };
-use futures::Stream;
 use parking_lot::Mutex;
 use serialport::SerialPortType;
+use setupapi::{PortProperties, UsbIds};
 use std::{
-    collections::HashMap,
+    collections::{hash_map::Entry, HashMap},
     ffi::OsString,
-    fmt::{self, Debug},
     io,
-    pin::Pin,
-    sync::Arc,
-    task::{Context, Poll},
+    mem::Discriminant,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc,
+    },
     thread::JoinHandle,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant},
 };
 use tracing::{error, trace};
 use wide::to_wide;
-use windows_sys::Win32::UI::WindowsAndMessaging::{FindWindowW, PostMessageW, WM_CLOSE};
+use windows_sys::Win32::Foundation::{ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION, WIN32_ERROR};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    FindWindowExW, PostMessageW, HWND_MESSAGE, WM_CLOSE,
+};
 
-/// The AbortHandle will cause the [`EventIter`] to stop emitting events when dropped
+/// The AbortHandle will cause the [`crate::EventIter`] to stop emitting events when dropped
 #[derive(Debug)]
 pub struct AbortHandle {
-    window: OsString,
-    join_handle: Option<JoinHandle<io::Result<()>>>,
+    inner: Listener,
+}
+
+#[derive(Debug)]
+enum Listener {
+    /// A message-only window, and the thread pumping its messages
+    Window {
+        window: OsString,
+        join_handle: Option<JoinHandle<io::Result<()>>>,
+    },
+    /// Configuration manager notifications, delivered on the thread pool
+    Notification(cm::Registration),
 }
 
 impl AbortHandle {
-    /// Cancel [`EventIter`] and no longer listen to Device Connect and Disconnect events
-    pub fn abort(self) {}
+    /// False once the listener thread has exited
+    pub(crate) fn is_alive(&self) -> bool {
+        match &self.inner {
+            Listener::Window { join_handle, .. } => {
+                join_handle.as_ref().is_some_and(|jh| !jh.is_finished())
+            }
+            Listener::Notification(registration) => registration.is_alive(),
+        }
+    }
 }
 
 impl Drop for AbortHandle {
+    // The notification registration cleans up after itself
     fn drop(&mut self) {
-        let wide = to_wide(&self.window);
-        let hwnd = unsafe {
-            let result = FindWindowW(wm::WINDOW_CLASS_NAME, wide.as_ptr());
-            match result.is_null() {
-                false => result,
-                _ => {
-                    error!(error = ?io::Error::last_os_error(), "failed to abort");
-                    return;
-                }
+        if let Listener::Window {
+            window,
+            join_handle,
+        } = &mut self.inner
+        {
+            close_window(window, join_handle);
+        }
+    }
+}
+
+fn close_window(window: &OsString, join_handle: &mut Option<JoinHandle<io::Result<()>>>) {
+    let wide = to_wide(window);
+    let hwnd = unsafe {
+        // A message-only window is only found by searching the children of HWND_MESSAGE
+        let result = FindWindowExW(
+            HWND_MESSAGE,
+            std::ptr::null_mut(),
+            wm::WINDOW_CLASS_NAME,
+            wide.as_ptr(),
+        );
+        match result.is_null() {
+            false => result,
+            _ => {
+                error!(error = ?io::Error::last_os_error(), "failed to abort");
+                return;
             }
-        };
-        match unsafe { PostMessageW(hwnd as _, WM_CLOSE, 0, 0) } {
-            0 => error!(error = ?io::Error::last_os_error()),
-            _ => match self.join_handle.take() {
-                None => unreachable!(),
-                Some(jh) => match jh.join() {
-                    Ok(_) => trace!("device detection closed"),
-                    Err(error) => error!(?error, "device detection close error"),
-                },
-            },
         }
+    };
+    match unsafe { PostMessageW(hwnd as _, WM_CLOSE, 0, 0) } {
+        0 => error!(error = ?io::Error::last_os_error()),
+        _ => match join_handle.take() {
+            None => unreachable!(),
+            Some(jh) => match jh.join() {
+                Ok(_) => trace!("device detection closed"),
+                Err(error) => error!(?error, "device detection close error"),
+            },
+        },
     }
 }
 
 pub(crate) struct IterState {
     pub(crate) cache: Mutex<HashMap<String, DeviceInfo>>,
-    pub(crate) queue: Queue,
+    pub(crate) dedup: Mutex<Dedup>,
+    pub(crate) queue: Arc<Queue>,
 }
 
-/// An event emitter to listen for Usb Add Remove events
-pub struct EventIter {
-    pub(crate) state: Arc<IterState>,
-}
+impl IterState {
+    /// Rescan and report what changed since we last looked, IE: across a suspend, or when Windows
+    /// gave a device another COM number without telling us
+    pub(crate) fn resync(&self) {
+        let next = match self.queue.all_ports() {
+            true => scan_all(),
+            false => scan(),
+        };
+        match next {
+            Ok(next) => {
+                let mut cache = self.cache.lock();
+                for event in poll::diff(&cache, &next) {
+                    trace!(port = event.device.port, event = ?event.event, "cache out of date");
+                    self.queue.push(Ok(event));
+                }
+                *cache = next;
+            }
+            Err(error) => {
+                error!(?error, "failed to rescan devices");
+                self.queue.push(Err(error));
+            }
+        }
+    }
 
-impl Debug for EventIter {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("EventIter").finish()
+    /// Report the devices which arrived at `ports`
+    pub(crate) fn arrival(&self, ports: &[String]) {
+        for event in ports
+            .iter()
+            .filter_map(|port| arrived(port, self.queue.all_ports()))
+            .map(|device| EventInfo::new(device, EventType::Add))
+            .filter(|event| self.dedup.lock().admit(event, Instant::now()))
+        {
+            self.cache
+                .lock()
+                .insert(event.device.port.clone(), event.device.clone());
+            self.queue.push(Ok(event));
+        }
     }
-}
 
-impl Stream for EventIter {
-    type Item = io::Result<EventInfo>;
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.state.queue.poll_next(cx)
+    /// Report the devices which were removed from `ports`
+    pub(crate) fn removal(&self, ports: &[String]) {
+        for event in ports
+            .iter()
+            .filter_map(|port| self.cache.lock().remove(port))
+            .map(|device| EventInfo::new(device, EventType::Remove))
+            .filter(|event| self.dedup.lock().admit(event, Instant::now()))
+        {
+            self.queue.push(Ok(event))
+        }
+    }
+
+    /// The ports which appeared (Add) or went away (Remove) since we last looked. Device interface
+    /// notifications do not name the port, so we compare the present ports with our cache
+    pub(crate) fn changed_ports(&self, event: EventType) -> Vec<String> {
+        let mut present = setupapi::port_names();
+        if self.queue.all_ports() {
+            present.extend(setupapi::registry_ports());
+        }
+        let cache = self.cache.lock();
+        match event {
+            EventType::Add => present
+                .into_iter()
+                .filter(|port| !cache.contains_key(port))
+                .collect(),
+            EventType::Remove | EventType::Change | EventType::Moved => cache
+                .keys()
+                .filter(|port| !present.contains(*port))
+                .cloned()
+                .collect(),
+        }
     }
 }
 
-pub(crate) fn listen() -> io::Result<(AbortHandle, EventIter)> {
-    // We generate a random window name for our window manager device port listener
-    let nanos = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|dur| dur.subsec_nanos())
-        .unwrap_or(18825437)
-        .to_string();
-    let window = OsString::from(format!("SERIALPORT_DETECT{nanos}"));
-    let name = window.clone();
+/// How often we rescan to catch what the notifications missed. A driver update or a hub change can
+/// give a device another COM number without a removal and an arrival of its port
+pub(crate) const RECONCILE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long a repeat of the same event of a port is considered part of the same plug
+const DEDUP_WINDOW: Duration = Duration::from_millis(500);
 
+/// We register for several device interface classes, and one plug can broadcast the same port
+/// arrival more than once. Drops an event which repeats the last one of its port within a window
+#[derive(Debug, Default)]
+pub(crate) struct Dedup {
+    last: HashMap<String, (Discriminant<EventType>, Instant)>,
+}
+
+impl Dedup {
+    /// Returns false if the event is a duplicate
+    pub(crate) fn admit(&mut self, event: &EventInfo, now: Instant) -> bool {
+        let kind = std::mem::discriminant(&event.event);
+        let port = &event.device.port;
+        let duplicate = self
+            .last
+            .get(port)
+            .is_some_and(|(last, at)| *last == kind && now.duration_since(*at) < DEDUP_WINDOW);
+        if !duplicate {
+            self.last.insert(port.clone(), (kind, now));
+        }
+        !duplicate
+    }
+}
+
+pub(crate) fn listen(queue: Arc<Queue>) -> Result<AbortHandle> {
+    let backend = queue.windows_backend();
     // Create polling context
     let state = Arc::new(IterState {
-        cache: Mutex::new(scan()?),
-        queue: Queue::new(),
-    });
-    let theirs = Arc::clone(&state);
-    let jh = std::thread::spawn(move || unsafe {
-        wm::window_dispatcher(name, Arc::into_raw(theirs) as _)
+        cache: Mutex::new(match queue.all_ports() {
+            true => scan_all()?,
+            false => scan()?,
+        }),
+        dedup: Mutex::new(Dedup::default()),
+        queue,
     });
+    let inner = match backend {
+        WindowsBackend::Window => listen_window(state)?,
+        WindowsBackend::ConfigManager => Listener::Notification(cm::listen(state)?),
+    };
+    Ok(AbortHandle { inner })
+}
 
-    // Return an abort handle and a stream
-    let abort_handle = AbortHandle {
+fn listen_window(state: Arc<IterState>) -> Result<Listener> {
+    // Each listener has a window of its own, found by name when we abort. Message-only windows
+    // of other processes are searched as well, so the name includes our process id
+    static LISTENERS: AtomicU64 = AtomicU64::new(0);
+    let id = LISTENERS.fetch_add(1, Ordering::Relaxed);
+    let window = OsString::from(format!("SERIALPORT_DETECT{}_{id}", std::process::id()));
+    let name = window.clone();
+    let (ready, rx) = mpsc::channel();
+    let span = listener_span("window");
+    let jh = std::thread::spawn(move || {
+        // Safety: the dispatcher takes back the state when the window is destroyed
+        span.in_scope(|| unsafe { wm::window_dispatcher(name, Arc::into_raw(state) as _, ready) })
+    });
+    // Wait for the window so that setup errors are returned to the caller
+    match rx.recv() {
+        Ok(Ok(())) => {}
+        Ok(Err(error)) => {
+            let _ = jh.join();
+            return Err(error);
+        }
+        Err(_) => {
+            let error = io::Error::other("listener exited during setup");
+            return Err(Error::Backend(error));
+        }
+    }
+    Ok(Listener::Window {
         window,
         join_handle: Some(jh),
-    };
-    Ok((abort_handle, EventIter { state }))
+    })
 }
 
 pub(crate) fn scan() -> io::Result<HashMap<String, DeviceInfo>> {
+    let mut properties = setupapi::port_properties();
     let devices = serialport::available_ports()?
         .into_iter()
         .filter_map(|info| match info.port_type {
             SerialPortType::UsbPort(usb) => {
                 let port = info.port_name;
-                let info = DeviceInfo {
-                    port: port.clone(),
-                    vid: Some(format!("{:X}", usb.vid)),
-                    pid: Some(format!("{:X}", usb.pid)),
+                let properties = properties.remove(&port).unwrap_or_default();
+                let usb = UsbIds {
+                    vid: usb.vid,
+                    pid: usb.pid,
                     serial: usb.serial_number,
                     manufacturer: usb.manufacturer,
                     product: usb.product,
                 };
-                Some((port, info))
+                Some((port.clone(), device_info(port, usb, properties)))
             }
             _ => None,
         })
         .collect::<HashMap<String, _>>();
     Ok(devices)
 }
+
+/// Scan the serial ports of every kind. The ports which are not USB devices are read from the
+/// device tree, or only named when their driver is outside the Ports class
+pub(crate) fn scan_all() -> io::Result<HashMap<String, DeviceInfo>> {
+    let mut devices = scan()?;
+    let mut ports = setupapi::port_names();
+    ports.extend(setupapi::registry_ports());
+    for port in ports {
+        if let Entry::Vacant(entry) = devices.entry(port) {
+            let device = other_port(entry.key());
+            entry.insert(device);
+        }
+    }
+    Ok(devices)
+}
+
+/// Look up the device at `port`, without enumerating every port
+pub(crate) fn port_info(port: &str) -> io::Result<Option<DeviceInfo>> {
+    if let Some((usb, properties)) = setupapi::usb_port(port) {
+        return Ok(Some(device_info(port.to_string(), usb, properties)));
+    }
+    // Every COM port is listed in the registry, including the ones outside the Ports class
+    Ok(setupapi::registry_ports()
+        .contains(port)
+        .then(|| other_port(port)))
+}
+
+/// Read the device which just arrived at `port`, without enumerating every port. Ports which are
+/// not USB devices are reported when `all_ports` is set
+pub(crate) fn arrived(port: &str, all_ports: bool) -> Option<DeviceInfo> {
+    match setupapi::usb_port(port) {
+        Some((usb, properties)) => Some(device_info(port.to_string(), usb, properties)),
+        None if all_ports => Some(other_port(port)),
+        None => None,
+    }
+}
+
+/// Whether the current user may open the port, and whether another process has it open, from one
+/// attempt to open it. Windows denies opening a COM port which is already open the same as it does
+/// when we are not allowed to, so a port denied to us is in use as well, and a port in use is not
+/// accessible. None when the check fails otherwise
+pub(crate) fn probe(port: &str) -> (Option<bool>, Option<bool>) {
+    match query_open(port) {
+        Ok(()) => (Some(true), Some(false)),
+        Err(ERROR_ACCESS_DENIED) => (Some(false), Some(true)),
+        Err(ERROR_SHARING_VIOLATION) => (None, Some(true)),
+        Err(_) => (None, None),
+    }
+}
+
+/// Whether the current user may open the port. See [`probe`]
+pub(crate) fn accessible(port: &str) -> Option<bool> {
+    probe(port).0
+}
+
+/// Open the port without asking for read or write access, which only checks that we could, and
+/// close it again
+fn query_open(port: &str) -> std::result::Result<(), WIN32_ERROR> {
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, GetLastError, INVALID_HANDLE_VALUE},
+        Storage::FileSystem::{CreateFileW, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING},
+    };
+    let path = to_wide(format!(r"\\.\{port}"));
+    // SAFETY: path is nul terminated, and the handle is closed once we know it opened
+    let handle = unsafe {
+        CreateFileW(
+            path.as_ptr(),
+            0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE,
+            std::ptr::null(),
+            OPEN_EXISTING,
+            0,
+            std::ptr::null_mut(),
+        )
+    };
+    if handle == INVALID_HANDLE_VALUE {
+        return Err(unsafe { GetLastError() });
+    }
+    unsafe { CloseHandle(handle) };
+    Ok(())
+}
+
+fn other_port(port: &str) -> DeviceInfo {
+    let (port_type, properties) = setupapi::other_port(port).unwrap_or_default();
+    DeviceInfo {
+        port: port.to_string(),
+        port_type,
+        driver: properties.driver,
+        friendly_name: properties.friendly_name,
+        instance_id: properties.instance_id,
+        ..Default::default()
+    }
+}
+
+fn device_info(port: String, usb: UsbIds, properties: PortProperties) -> DeviceInfo {
+    DeviceInfo {
+        port,
+        vid: Some(Vid(usb.vid).to_string()),
+        pid: Some(Pid(usb.pid).to_string()),
+        vid_u16: Some(usb.vid),
+        pid_u16: Some(usb.pid),
+        stable_id: usb
+            .serial
+            .as_ref()
+            .map(|serial| format!("USB\\VID_{:04X}&PID_{:04X}\\{serial}", usb.vid, usb.pid)),
+        serial: usb.serial,
+        manufacturer: usb.manufacturer,
+        product: usb.product,
+        port_type: PortType::Usb,
+        location: properties.location,
+        driver: properties.driver,
+        friendly_name: properties.friendly_name,
+        instance_id: properties.instance_id,
+        usb_speed: properties.usb_speed,
+        bcd_device: properties.bcd_device,
+        interface_class: properties.interface_class,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::detect::test::event;
+
+    #[test]
+    fn test_dedup() {
+        let mut dedup = Dedup::default();
+        let now = Instant::now();
+        assert!(dedup.admit(&event("COM3", EventType::Add), now));
+        assert!(!dedup.admit(&event("COM3", EventType::Add), now));
+        assert!(dedup.admit(&event("COM4", EventType::Add), now));
+        assert!(dedup.admit(&event("COM3", EventType::Remove), now));
+        assert!(dedup.admit(&event("COM3", EventType::Add), now));
+        let later = now + DEDUP_WINDOW;
+        assert!(dedup.admit(&event("COM3", EventType::Add), later));
+    }
+}
@@ -0,0 +1,681 @@
+//! setupapi
+//!
+//! serialport only reports the USB descriptors of a port. We read everything else from the device
+//! tree: each port in the Ports class is a device node, and the USB device is one of its ancestors
+
+use crate::{
+    detect::{PortType, UsbClass, UsbSpeed},
+    id::parse_hex,
+    windows::wide::{from_wide, to_wide},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::c_void,
+    ptr,
+};
+use windows_sys::Win32::{
+    Devices::{
+        DeviceAndDriverInstallation::{
+            CM_Get_DevNode_Registry_PropertyW, CM_Get_Device_IDW, CM_Get_Device_Interface_ListW,
+            CM_Get_Device_Interface_List_SizeW, CM_Get_Parent, SetupDiDestroyDeviceInfoList,
+            SetupDiEnumDeviceInfo, SetupDiGetClassDevsW, SetupDiOpenDevRegKey, CM_DRP_ADDRESS,
+            CM_DRP_COMPATIBLEIDS, CM_DRP_FRIENDLYNAME, CM_DRP_HARDWAREID,
+            CM_DRP_LOCATION_INFORMATION, CM_DRP_LOCATION_PATHS, CM_DRP_MFG, CM_DRP_SERVICE,
+            CM_GET_DEVICE_INTERFACE_LIST_PRESENT, CR_SUCCESS, DICS_FLAG_GLOBAL, DIGCF_PRESENT,
+            DIREG_DEV, GUID_DEVCLASS_PORTS, HDEVINFO, MAX_DEVICE_ID_LEN, SP_DEVINFO_DATA,
+        },
+        Usb::{
+            UsbFullSpeed, UsbHighSpeed, UsbLowSpeed, UsbSuperSpeed, GUID_DEVINTERFACE_USB_HUB,
+            IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX,
+            IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX_V2, USB_NODE_CONNECTION_INFORMATION_EX,
+            USB_NODE_CONNECTION_INFORMATION_EX_V2, USB_PIPE_INFO, USB_PROTOCOLS,
+        },
+    },
+    Foundation::{CloseHandle, ERROR_SUCCESS, GENERIC_WRITE, HANDLE, INVALID_HANDLE_VALUE},
+    Storage::FileSystem::{CreateFileW, FILE_SHARE_WRITE, OPEN_EXISTING},
+    System::{
+        Registry::{
+            RegCloseKey, RegEnumValueW, RegOpenKeyExW, RegQueryValueExW, HKEY_LOCAL_MACHINE,
+            KEY_READ, REG_SZ,
+        },
+        IO::DeviceIoControl,
+    },
+};
+
+/// What we learn about a port from the device tree
+#[derive(Debug, Default, Clone)]
+pub(crate) struct PortProperties {
+    /// See [`crate::DeviceInfo::location`]
+    pub(crate) location: Option<String>,
+    /// See [`crate::DeviceInfo::driver`]
+    pub(crate) driver: Option<String>,
+    /// See [`crate::DeviceInfo::friendly_name`]
+    pub(crate) friendly_name: Option<String>,
+    /// See [`crate::DeviceInfo::instance_id`]
+    pub(crate) instance_id: Option<String>,
+    /// See [`crate::DeviceInfo::usb_speed`]
+    pub(crate) usb_speed: Option<UsbSpeed>,
+    /// See [`crate::DeviceInfo::bcd_device`]
+    pub(crate) bcd_device: Option<u16>,
+    /// See [`crate::DeviceInfo::interface_class`]
+    pub(crate) interface_class: Option<UsbClass>,
+}
+
+/// The USB descriptors of a port, as serialport reports them
+#[derive(Debug, Default, Clone)]
+pub(crate) struct UsbIds {
+    pub(crate) vid: u16,
+    pub(crate) pid: u16,
+    pub(crate) serial: Option<String>,
+    pub(crate) manufacturer: Option<String>,
+    pub(crate) product: Option<String>,
+}
+
+/// Read the properties of every present port, keyed by port name (IE: COM3)
+pub(crate) fn port_properties() -> HashMap<String, PortProperties> {
+    let mut ports = HashMap::new();
+    for_each_port(|name, devinst| {
+        ports.insert(name, properties(devinst));
+        true
+    });
+    ports
+}
+
+/// The names of the present ports, without reading anything else
+pub(crate) fn port_names() -> HashSet<String> {
+    let mut names = HashSet::new();
+    for_each_port(|name, _| {
+        names.insert(name);
+        true
+    });
+    names
+}
+
+/// The names of every COM port published by a serial driver, including the ports of drivers
+/// outside of the Ports class (IE: com0com). Read from HKLM\HARDWARE\DEVICEMAP\SERIALCOMM
+pub(crate) fn registry_ports() -> HashSet<String> {
+    let mut ports = HashSet::new();
+    let path = to_wide("HARDWARE\\DEVICEMAP\\SERIALCOMM");
+    let mut key = ptr::null_mut();
+    // Safety: the key is closed before we return, and we leave room for a null terminator past
+    // the reported length of each value
+    unsafe {
+        if RegOpenKeyExW(HKEY_LOCAL_MACHINE, path.as_ptr(), 0, KEY_READ, &mut key) != ERROR_SUCCESS
+        {
+            return ports;
+        }
+        let mut index = 0;
+        loop {
+            let mut name = [0u16; 256];
+            let mut name_len = name.len() as u32;
+            let mut buffer = [0u16; 64];
+            let mut len = std::mem::size_of_val(&buffer) as u32 - 2;
+            let mut kind = 0;
+            let rc = RegEnumValueW(
+                key,
+                index,
+                name.as_mut_ptr(),
+                &mut name_len,
+                ptr::null(),
+                &mut kind,
+                buffer.as_mut_ptr() as _,
+                &mut len,
+            );
+            if rc != ERROR_SUCCESS {
+                break;
+            }
+            index += 1;
+            if kind == REG_SZ {
+                let chars = (len as usize / 2).min(buffer.len() - 1);
+                buffer[chars] = 0;
+                if let Ok(port) = from_wide(buffer.as_ptr()).into_string() {
+                    ports.insert(port);
+                }
+            }
+        }
+        RegCloseKey(key);
+    }
+    ports
+}
+
+/// Read a port which is not a USB device. Returns None if the port is not in the Ports class
+pub(crate) fn other_port(port: &str) -> Option<(PortType, PortProperties)> {
+    let devinst = find_port(port)?;
+    let id = device_id(devinst)?;
+    let mut parent = 0;
+    // Safety: parent is a valid out pointer
+    let parent = match unsafe { CM_Get_Parent(&mut parent, devinst, 0) } {
+        CR_SUCCESS => device_id(parent),
+        _ => None,
+    };
+    Some((port_type(&id, parent.as_deref()), properties(devinst)))
+}
+
+/// Read the USB port named `port` (IE: COM3) alone, instead of enumerating every port as
+/// serialport does. Returns None if the port is not present or not a USB device
+pub(crate) fn usb_port(port: &str) -> Option<(UsbIds, PortProperties)> {
+    let devinst = find_port(port)?;
+    let id = device_id(devinst)?;
+    let mut ids = parse_instance_id(&id)?;
+    if ids.interface {
+        // The serial number of a composite device is in the instance ID of its parent
+        let mut parent = 0;
+        // Safety: parent is a valid out pointer
+        if unsafe { CM_Get_Parent(&mut parent, devinst, 0) } != CR_SUCCESS {
+            return None;
+        }
+        ids = parse_instance_id(&device_id(parent)?)?;
+    }
+    // The manufacturer is prefixed with the driver package (IE: FTDI5.inf,%ftdi%;FTDI)
+    let manufacturer = string_property(devinst, CM_DRP_MFG)
+        .and_then(|mfg| mfg.rsplit(';').next().map(str::to_string));
+    let usb = UsbIds {
+        vid: ids.vid,
+        pid: ids.pid,
+        serial: ids.serial,
+        manufacturer,
+        product: string_property(devinst, CM_DRP_FRIENDLYNAME),
+    };
+    Some((usb, properties(devinst)))
+}
+
+/// The device node of the port named `port` in the Ports class
+fn find_port(port: &str) -> Option<u32> {
+    let mut found = None;
+    for_each_port(|name, devinst| match name == port {
+        true => {
+            found = Some(devinst);
+            false
+        }
+        false => true,
+    });
+    found
+}
+
+/// Call `f` with the name and device node of each present port in the Ports class, until it
+/// returns false
+fn for_each_port(mut f: impl FnMut(String, u32) -> bool) {
+    // Safety: the device information set is destroyed before we return
+    unsafe {
+        let set = SetupDiGetClassDevsW(
+            &GUID_DEVCLASS_PORTS,
+            ptr::null(),
+            ptr::null_mut(),
+            DIGCF_PRESENT,
+        );
+        if set == INVALID_HANDLE_VALUE as HDEVINFO {
+            return;
+        }
+        let mut data: SP_DEVINFO_DATA = std::mem::zeroed();
+        data.cbSize = std::mem::size_of::<SP_DEVINFO_DATA>() as u32;
+        let mut index = 0;
+        while SetupDiEnumDeviceInfo(set, index, &mut data) != 0 {
+            index += 1;
+            let Some(name) = port_name(set, &data) else {
+                continue;
+            };
+            if !f(name, data.DevInst) {
+                break;
+            }
+        }
+        SetupDiDestroyDeviceInfoList(set);
+    }
+}
+
+/// Read the properties of the port at `devinst`
+fn properties(devinst: u32) -> PortProperties {
+    let usb = usb_device(devinst);
+    let location = usb.and_then(|usb| {
+        string_property(usb, CM_DRP_LOCATION_PATHS)
+            .and_then(|paths| usb_location(&paths))
+            // Without a location path we fall back to the hub and port (IE: Port_#0002.Hub_#0003)
+            .or_else(|| {
+                string_property(usb, CM_DRP_LOCATION_INFORMATION)
+                    .filter(|info| info.starts_with("Port_#"))
+            })
+    });
+    // The port is bound to the function driver, which is named after its service
+    let driver = string_property(devinst, CM_DRP_SERVICE);
+    PortProperties {
+        location,
+        driver,
+        friendly_name: string_property(devinst, CM_DRP_FRIENDLYNAME),
+        instance_id: device_id(devinst),
+        usb_speed: usb.and_then(usb_speed),
+        bcd_device: usb
+            .and_then(|usb| string_property(usb, CM_DRP_HARDWAREID))
+            .and_then(|id| parse_revision(&id)),
+        interface_class: usb_function(devinst)
+            .and_then(|usb| string_property(usb, CM_DRP_COMPATIBLEIDS))
+            .and_then(|id| parse_class(&id)),
+    }
+}
+
+/// The connection information the hub returns for one of its ports, with room for the pipes of
+/// the device past the first as USBView leaves
+#[repr(C, packed(1))]
+#[derive(Default)]
+struct Connection {
+    info: USB_NODE_CONNECTION_INFORMATION_EX,
+    _pipes: [USB_PIPE_INFO; 30],
+}
+
+/// The speed the USB device at `usb` negotiated with its hub. Only the hub driver knows of it, so
+/// we ask the parent hub about the port the device is plugged into
+fn usb_speed(usb: u32) -> Option<UsbSpeed> {
+    // The address of a USB device node is the number of its hub port
+    let port = u32_property(usb, CM_DRP_ADDRESS)?;
+    let mut hub = 0;
+    // Safety: hub is a valid out pointer
+    if unsafe { CM_Get_Parent(&mut hub, usb, 0) } != CR_SUCCESS {
+        return None;
+    }
+    let hub = Hub::open(&device_id(hub)?)?;
+    let mut connection = Connection::default();
+    connection.info.ConnectionIndex = port;
+    hub.ioctl(
+        IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX,
+        &mut connection,
+    )?;
+    match i32::from(connection.info.Speed) {
+        UsbLowSpeed => Some(UsbSpeed::Low),
+        UsbFullSpeed => Some(UsbSpeed::Full),
+        UsbHighSpeed => Some(UsbSpeed::High),
+        // Only the second version of the request tells 5 Gbps from faster
+        UsbSuperSpeed => {
+            let mut info = USB_NODE_CONNECTION_INFORMATION_EX_V2 {
+                ConnectionIndex: port,
+                Length: std::mem::size_of::<USB_NODE_CONNECTION_INFORMATION_EX_V2>() as u32,
+                // USB 1.1, 2.0 and 3.0
+                SupportedUsbProtocols: USB_PROTOCOLS { ul: 0b111 },
+                ..Default::default()
+            };
+            let answered = hub
+                .ioctl(IOCTL_USB_GET_NODE_CONNECTION_INFORMATION_EX_V2, &mut info)
+                .is_some();
+            // Safety: the flags are a bitfield, and bit 2 is
+            // DeviceIsOperatingAtSuperSpeedPlusOrHigher
+            match answered && unsafe { info.Flags.ul } & 0b100 != 0 {
+                true => Some(UsbSpeed::SuperPlus),
+                false => Some(UsbSpeed::Super),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// A handle to the device interface of a USB hub, closed when dropped
+struct Hub(HANDLE);
+
+impl Hub {
+    /// Open the hub interface of the hub device node `id`
+    fn open(id: &str) -> Option<Hub> {
+        let id = to_wide(id);
+        let mut len = 0;
+        // Safety: the buffer is as long as the size we are told, and the list ends with an empty
+        // string
+        let path = unsafe {
+            let rc = CM_Get_Device_Interface_List_SizeW(
+                &mut len,
+                &GUID_DEVINTERFACE_USB_HUB,
+                id.as_ptr(),
+                CM_GET_DEVICE_INTERFACE_LIST_PRESENT,
+            );
+            if rc != CR_SUCCESS || len <= 1 {
+                return None;
+            }
+            let mut list = vec![0u16; len as usize];
+            let rc = CM_Get_Device_Interface_ListW(
+                &GUID_DEVINTERFACE_USB_HUB,
+                id.as_ptr(),
+                list.as_mut_ptr(),
+                len,
+                CM_GET_DEVICE_INTERFACE_LIST_PRESENT,
+            );
+            if rc != CR_SUCCESS {
+                return None;
+            }
+            list
+        };
+        // Safety: path is null terminated, the first interface of the list
+        let handle = unsafe {
+            CreateFileW(
+                path.as_ptr(),
+                GENERIC_WRITE,
+                FILE_SHARE_WRITE,
+                ptr::null(),
+                OPEN_EXISTING,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        match handle == INVALID_HANDLE_VALUE {
+            true => None,
+            false => Some(Hub(handle)),
+        }
+    }
+
+    /// Send a request about one of the ports of the hub, which reads and writes `buffer`
+    fn ioctl<T>(&self, code: u32, buffer: &mut T) -> Option<()> {
+        let size = std::mem::size_of::<T>() as u32;
+        let buffer = buffer as *mut T as *mut c_void;
+        let mut returned = 0;
+        // Safety: the buffer is valid for its size, both ways
+        let ok = unsafe {
+            DeviceIoControl(
+                self.0,
+                code,
+                buffer,
+                size,
+                buffer,
+                size,
+                &mut returned,
+                ptr::null_mut(),
+            )
+        };
+        (ok != 0).then_some(())
+    }
+}
+
+impl Drop for Hub {
+    fn drop(&mut self) {
+        // Safety: we own the handle
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+/// The kind of a port, from the enumerator of its device instance ID (IE: BTHENUM\...) and the
+/// one of its parent
+fn port_type(id: &str, parent: Option<&str>) -> PortType {
+    let enumerator = |id: &str| {
+        id.split('\\')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_uppercase()
+    };
+    match enumerator(id).as_str() {
+        "USB" | "FTDIBUS" => PortType::Usb,
+        "PCI" => PortType::Pci,
+        "BTHENUM" => PortType::Bluetooth,
+        // The ports of a multifunction PCI card are children of the card
+        "MF" if parent.is_some_and(|parent| enumerator(parent) == "PCI") => PortType::Pci,
+        // Built in UARTs (ACPI\PNP0501), and virtual ports
+        _ => PortType::Unknown,
+    }
+}
+
+/// What a device instance ID tells us of a USB device
+#[derive(Debug, PartialEq)]
+struct InstanceId {
+    vid: u16,
+    pid: u16,
+    /// The instance is an interface of a composite device
+    interface: bool,
+    serial: Option<String>,
+}
+
+/// Parse the USB ids of a device instance ID the way serialport does, so that both agree on the
+/// serial number. IE: USB\VID_0403&PID_6001\A50285BI, FTDIBUS\VID_0403+PID_6001+A50285BI\0000
+/// or USB\VID_1D50&PID_6018&MI_02\6&A694CA9&0&0002
+fn parse_instance_id(id: &str) -> Option<InstanceId> {
+    let tail = &id[id.find("VID_")? + 4..];
+    let vid = u16::from_str_radix(tail.get(..4)?, 16).ok()?;
+    let tail = tail.get(4..)?;
+    let tail = tail
+        .strip_prefix("&PID_")
+        .or_else(|| tail.strip_prefix("+PID_"))?;
+    let pid = u16::from_str_radix(tail.get(..4)?, 16).ok()?;
+    let mut tail = tail.get(4..)?;
+    let interface = match tail
+        .strip_prefix("&MI_")
+        .or_else(|| tail.strip_prefix("+MI_"))
+    {
+        Some(rest) => {
+            tail = rest.get(2..).unwrap_or(rest);
+            true
+        }
+        None => false,
+    };
+    let serial = tail
+        .strip_prefix('\\')
+        .or_else(|| tail.strip_prefix('+'))
+        .map(|rest| {
+            let end = rest
+                .find(|c: char| !(c.is_alphanumeric() || matches!(c, '_' | '\u{ff3f}')))
+                .unwrap_or(rest.len());
+            rest[..end].to_string()
+        });
+    Some(InstanceId {
+        vid,
+        pid,
+        interface,
+        serial,
+    })
+}
+
+/// The bcdDevice in the first hardware ID of a USB device, IE: USB\VID_0403&PID_6001&REV_0600
+fn parse_revision(id: &str) -> Option<u16> {
+    let tail = &id[id.find("&REV_")? + 5..];
+    parse_hex(tail.get(..4)?)
+}
+
+/// The class codes in the first compatible ID of a USB interface, or of a device with a single
+/// interface. IE: USB\Class_02&SubClass_02&Prot_01
+fn parse_class(id: &str) -> Option<UsbClass> {
+    let tail = &id[id.find("\\Class_")? + 7..];
+    let (class, tail) = tail.split_once("&SubClass_")?;
+    let (subclass, tail) = tail.split_once("&Prot_")?;
+    UsbClass::from_hex(class, subclass, tail.get(..2)?)
+}
+
+/// The PortName value of the device's hardware key
+///
+/// Safety: data must belong to set
+unsafe fn port_name(set: HDEVINFO, data: &SP_DEVINFO_DATA) -> Option<String> {
+    let key = SetupDiOpenDevRegKey(set, data, DICS_FLAG_GLOBAL, 0, DIREG_DEV, KEY_READ);
+    if key == INVALID_HANDLE_VALUE as _ {
+        return None;
+    }
+    let value = to_wide("PortName");
+    let mut buffer = [0u16; 64];
+    let mut len = std::mem::size_of_val(&buffer) as u32;
+    let mut kind = 0;
+    let rc = RegQueryValueExW(
+        key,
+        value.as_ptr(),
+        ptr::null(),
+        &mut kind,
+        buffer.as_mut_ptr() as _,
+        &mut len,
+    );
+    RegCloseKey(key);
+    if rc != ERROR_SUCCESS || kind != REG_SZ {
+        return None;
+    }
+    // The value is not always null terminated
+    let chars = (len as usize / 2).min(buffer.len() - 1);
+    buffer[chars] = 0;
+    from_wide(buffer.as_ptr()).into_string().ok()
+}
+
+/// Walk up from the port to the USB device. The interfaces of a composite device
+/// (IE: USB\VID_0403&PID_6010&MI_00) are skipped
+fn usb_device(mut devinst: u32) -> Option<u32> {
+    loop {
+        let id = device_id(devinst)?.to_ascii_uppercase();
+        if id.starts_with("USB\\") && !id.contains("&MI_") {
+            break Some(devinst);
+        }
+        let mut parent = 0;
+        // Safety: parent is a valid out pointer
+        if unsafe { CM_Get_Parent(&mut parent, devinst, 0) } != CR_SUCCESS {
+            break None;
+        }
+        devinst = parent;
+    }
+}
+
+/// Walk up from the port to the USB function it belongs to, which is the interface of a composite
+/// device (IE: USB\VID_0403&PID_6010&MI_00) or else the USB device
+fn usb_function(mut devinst: u32) -> Option<u32> {
+    loop {
+        if device_id(devinst)?
+            .to_ascii_uppercase()
+            .starts_with("USB\\")
+        {
+            break Some(devinst);
+        }
+        let mut parent = 0;
+        // Safety: parent is a valid out pointer
+        if unsafe { CM_Get_Parent(&mut parent, devinst, 0) } != CR_SUCCESS {
+            break None;
+        }
+        devinst = parent;
+    }
+}
+
+/// The device instance ID (IE: USB\VID_0403&PID_6001\A50285BI)
+fn device_id(devinst: u32) -> Option<String> {
+    let mut buffer = [0u16; MAX_DEVICE_ID_LEN as usize + 1];
+    // Safety: the buffer has room for the null terminator
+    unsafe {
+        match CM_Get_Device_IDW(devinst, buffer.as_mut_ptr(), MAX_DEVICE_ID_LEN, 0) {
+            CR_SUCCESS => from_wide(buffer.as_ptr()).into_string().ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Read a string property of a device node. For a list of strings (REG_MULTI_SZ) the first
+/// string is returned
+fn string_property(devinst: u32, property: u32) -> Option<String> {
+    let mut buffer = [0u16; 512];
+    let mut len = std::mem::size_of_val(&buffer) as u32 - 2;
+    let mut kind = 0;
+    // Safety: we leave room for a null terminator past the reported length
+    unsafe {
+        let rc = CM_Get_DevNode_Registry_PropertyW(
+            devinst,
+            property,
+            &mut kind,
+            buffer.as_mut_ptr() as *mut c_void,
+            &mut len,
+            0,
+        );
+        match rc {
+            CR_SUCCESS => from_wide(buffer.as_ptr())
+                .into_string()
+                .ok()
+                .filter(|s| !s.is_empty()),
+            _ => None,
+        }
+    }
+}
+
+/// Read a number property of a device node
+fn u32_property(devinst: u32, property: u32) -> Option<u32> {
+    let mut value = 0u32;
+    let mut len = std::mem::size_of::<u32>() as u32;
+    let mut kind = 0;
+    // Safety: the buffer is as long as we say
+    let rc = unsafe {
+        CM_Get_DevNode_Registry_PropertyW(
+            devinst,
+            property,
+            &mut kind,
+            &mut value as *mut u32 as *mut c_void,
+            &mut len,
+            0,
+        )
+    };
+    (rc == CR_SUCCESS).then_some(value)
+}
+
+/// Convert a location path (IE: PCIROOT(0)#PCI(1400)#USBROOT(0)#USB(3)#USB(2)) into the bus
+/// number and hub ports (IE: 0-3.2)
+fn usb_location(path: &str) -> Option<String> {
+    let mut bus = None;
+    let mut ports = Vec::new();
+    for node in path.split('#') {
+        let (kind, index) = node.strip_suffix(')')?.split_once('(')?;
+        match kind {
+            "USBROOT" => {
+                bus = Some(index);
+                ports.clear();
+            }
+            "USB" => ports.push(index),
+            _ => {}
+        }
+    }
+    match (bus, ports.is_empty()) {
+        (Some(bus), false) => Some(format!("{bus}-{}", ports.join("."))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_class, parse_instance_id, parse_revision, port_type, usb_location};
+    use crate::detect::PortType;
+
+    #[test]
+    fn test_port_type() {
+        let usb = "USB\\VID_2341&PID_0043\\75830303934351F0E1F1";
+        assert_eq!(port_type(usb, None), PortType::Usb);
+        assert_eq!(
+            port_type("BTHENUM\\{00001101-0000}_LOCALMFG&0002\\7&1", None),
+            PortType::Bluetooth
+        );
+        assert_eq!(
+            port_type("ACPI\\PNP0501\\1", Some("PCI\\VEN_8086&DEV_A30E")),
+            PortType::Unknown
+        );
+        let card = Some("PCI\\VEN_9710&DEV_9865\\4&1");
+        assert_eq!(
+            port_type("MF\\PCI9710_COM&VEN_9710\\5&0", card),
+            PortType::Pci
+        );
+        assert_eq!(port_type("ROOT\\PORTS\\0000", None), PortType::Unknown);
+    }
+
+    #[test]
+    fn test_parse_instance_id() {
+        let id = parse_instance_id("USB\\VID_F055&PID_9802\\385435603432").unwrap();
+        assert_eq!((id.vid, id.pid, id.interface), (0xf055, 0x9802, false));
+        assert_eq!(id.serial.as_deref(), Some("385435603432"));
+        let id = parse_instance_id("FTDIBUS\\VID_0403+PID_6001+A702TB52A\\0000").unwrap();
+        assert_eq!((id.vid, id.pid), (0x0403, 0x6001));
+        assert_eq!(id.serial.as_deref(), Some("A702TB52A"));
+        let id = parse_instance_id("USB\\VID_1D50&PID_6018&MI_02\\6&A694CA9&0&0002").unwrap();
+        assert!(id.interface);
+        assert_eq!(parse_instance_id("ACPI\\PNP0501\\1"), None);
+    }
+
+    #[test]
+    fn test_usb_location() {
+        let path = "PCIROOT(0)#PCI(1400)#USBROOT(0)#USB(3)#USB(2)";
+        assert_eq!(usb_location(path).as_deref(), Some("0-3.2"));
+        let path = "PCIROOT(0)#PCI(1400)#USBROOT(1)#USB(4)#USBMI(0)";
+        assert_eq!(usb_location(path).as_deref(), Some("1-4"));
+        assert_eq!(usb_location("PCIROOT(0)#PCI(1C00)#PCI(0000)"), None);
+        assert_eq!(usb_location("ACPI(_SB_)#ACPI(PCI0)"), None);
+    }
+
+    #[test]
+    fn test_parse_revision() {
+        let id = "USB\\VID_0403&PID_6001&REV_0600";
+        assert_eq!(parse_revision(id), Some(0x0600));
+        assert_eq!(parse_revision("USB\\VID_0403&PID_6001"), None);
+        assert_eq!(parse_revision("USB\\VID_0403&PID_6001&REV_06"), None);
+    }
+
+    #[test]
+    fn test_parse_class() {
+        let acm = parse_class("USB\\Class_02&SubClass_02&Prot_01").unwrap();
+        assert_eq!((acm.class, acm.subclass, acm.protocol), (0x02, 0x02, 0x01));
+        let vendor = parse_class("USB\\Class_ff&SubClass_ff&Prot_ff").unwrap();
+        assert!(vendor.is_vendor_specific());
+        assert_eq!(parse_class("USB\\DevClass_00&SubClass_00&Prot_00"), None);
+        assert_eq!(parse_class("USB\\COMPOSITE"), None);
+    }
+}
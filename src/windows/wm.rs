@@ -1,18 +1,29 @@
 use crate::{
-    detect::{EventInfo, EventType},
+    detect::EventType,
+    error::{Error, Result},
     guid,
-    windows::{wide::*, IterState},
+    windows::{wide::*, IterState, RECONCILE_INTERVAL},
 };
 use std::{
     ffi::{c_void, OsString},
     io,
-    sync::Arc,
+    sync::{mpsc, Arc},
 };
+use tracing::warn;
 use windows_sys::{
     core::GUID,
     Win32::{
-        Foundation::{GetLastError, SetLastError, HMODULE, HWND, LPARAM, LRESULT, WPARAM},
-        System::LibraryLoader::GetModuleHandleW,
+        Foundation::{
+            GetLastError, SetLastError, ERROR_CLASS_ALREADY_EXISTS, HMODULE, HWND, LPARAM, LRESULT,
+            TRUE, WPARAM,
+        },
+        System::{
+            LibraryLoader::GetModuleHandleW,
+            Power::{
+                RegisterSuspendResumeNotification, UnregisterSuspendResumeNotification,
+                HPOWERNOTIFY,
+            },
+        },
         UI::WindowsAndMessaging::*,
     },
 };
@@ -21,20 +32,23 @@ use windows_sys::{
 /// [See also](https://learn.microsoft.com/en-us/windows/win32/winmsg/about-window-classes)
 pub(crate) const WINDOW_CLASS_NAME: *const u16 = windows_sys::w!("DeviceNotifier");
 
+/// The id of the timer which has us rescan, see [`RECONCILE_INTERVAL`]
+const RECONCILE_TIMER: usize = 1;
+
 /// Create an instance of a DeviceNotifier window.
 ///
 /// Safety: name must be a null terminated Wide string, and user_data must be a pointer to an
 unsafe fn create_window(name: *const u16, user_data: isize) -> io::Result<HWND> {
     let handle = CreateWindowExW(
-        WS_EX_APPWINDOW,      // styleEx
+        0,                    // styleEx
         WINDOW_CLASS_NAME,    // class name
         name,                 // window name
-        WS_MINIMIZE,          // style
+        0,                    // style
         0,                    // x
         0,                    // y
-        CW_USEDEFAULT,        // width
-        CW_USEDEFAULT,        // hight
-        std::ptr::null_mut(), // parent
+        0,                    // width
+        0,                    // hight
+        HWND_MESSAGE,         // parent, a message-only window
         std::ptr::null_mut(), // menu
         hinstance(),          // instance
         std::ptr::null(),     // data
@@ -52,7 +66,11 @@ unsafe fn create_window(name: *const u16, user_data: isize) -> io::Result<HWND>
             match prev {
                 0 => match unsafe { GetLastError() } as _ {
                     0 => Ok(handle),
-                    raw => Err(io::Error::from_raw_os_error(raw)),
+                    raw => {
+                        // Safety: the window has no user data so the procedure ignores WM_DESTROY
+                        unsafe { DestroyWindow(handle) };
+                        Err(io::Error::from_raw_os_error(raw))
+                    }
                 },
                 _ => Ok(handle),
             }
@@ -74,45 +92,35 @@ unsafe extern "system" fn window_proceedure(
             WM_DEVICECHANGE => {
                 match parse_event(wparam) {
                     Some(EventType::Add) => {
-                        if let Some(event) = crate::scan().ok().and_then(|scan| {
-                            scan.into_iter().find_map(|(port, device)| {
-                                // Safety: data is a DEV_BROADCAST_HDR when wparam is DBT_DEVICEARRIVAL
-                                match unsafe { maybe_serialport(lparam as _) }? == port {
-                                    false => None,
-                                    true => Some(EventInfo {
-                                        device,
-                                        event: EventType::Add,
-                                    }),
-                                }
-                            })
-                        }) {
-                            state
-                                .cache
-                                .lock()
-                                .insert(event.device.port.clone(), event.device.clone());
-                            state.queue.push(Ok(event));
-                        }
+                        // Safety: data is a DEV_BROADCAST_HDR when wparam is DBT_DEVICEARRIVAL
+                        let ports = unsafe { broadcast_ports(state, lparam as _, EventType::Add) };
+                        state.arrival(&ports);
                         0
                     }
                     Some(EventType::Remove) => {
-                        // Safety: data is a DEV_BROADCAST_HDR when wparam is DBT_DEVICEARRIVAL
-                        if let Some(event) = unsafe { maybe_serialport(lparam as _) }
-                            .and_then(|want| state.cache.lock().remove(&want))
-                            .map(|device| EventInfo {
-                                device,
-                                event: EventType::Remove,
-                            })
-                        {
-                            state.queue.push(Ok(event))
-                        };
+                        // Safety: data is a DEV_BROADCAST_HDR for DBT_DEVICEREMOVECOMPLETE
+                        let ports =
+                            unsafe { broadcast_ports(state, lparam as _, EventType::Remove) };
+                        state.removal(&ports);
                         0
                     }
-                    None => {
+                    Some(EventType::Change | EventType::Moved) | None => {
                         // Just ignore the event
                         0
                     }
                 }
             }
+            WM_POWERBROADCAST => {
+                // Devices come and go during a suspend without a notification
+                if wparam as u32 == PBT_APMRESUMEAUTOMATIC {
+                    state.resync();
+                }
+                TRUE as _
+            }
+            WM_TIMER if wparam == RECONCILE_TIMER => {
+                state.resync();
+                0
+            }
             WM_DESTROY => {
                 // NOTE we only reconstruct our arc on destroy
                 let arc = Arc::from_raw(ptr);
@@ -134,34 +142,84 @@ fn parse_event(wparam: WPARAM) -> Option<EventType> {
     }
 }
 
+/// The ports a device broadcast is about. A port broadcast names its port. Our window is
+/// message-only, so those are rare and we mostly hear of device interfaces, which do not
+///
 /// Safety: data must be a DEV_BROADCAST_HDR
-unsafe fn maybe_serialport(data: *mut c_void) -> Option<String> {
-    let broadcast = &mut *(data as *mut DEV_BROADCAST_HDR);
+unsafe fn broadcast_ports(state: &IterState, data: *mut c_void, event: EventType) -> Vec<String> {
+    let broadcast = &*(data as *const DEV_BROADCAST_HDR);
     match broadcast.dbch_devicetype {
         DBT_DEVTYP_PORT => {
             let data = &*(data as *const DEV_BROADCAST_PORT_W);
             from_wide(data.dbcp_name.as_ptr())
-                .to_str()
-                .map(|port| port.to_string())
+                .into_string()
+                .into_iter()
+                .collect()
         }
-        _ => None,
+        DBT_DEVTYP_DEVICEINTERFACE => state.changed_ports(event),
+        _ => Vec::new(),
     }
 }
 
 /// Dispatch window messages
 ///
-/// We receive a "name", a list of GUID registrations, and some "user_data" which is an arc.
+/// We receive a "name", a list of GUID registrations, and some "user_data" which is an arc. The
+/// result of creating the window is sent to `ready` before we start dispatching.
 ///
 /// Safety: user_data must outlive window procedure
 ///
-/// This method will rebuild the Arc and pass it to the window procedure...
-pub unsafe fn window_dispatcher(name: OsString, user_data: isize) -> io::Result<()> {
+/// The raw Arc is handed to the window procedure, which rebuilds and drops it on WM_DESTROY
+pub unsafe fn window_dispatcher(
+    name: OsString,
+    user_data: isize,
+    ready: mpsc::Sender<Result<()>>,
+) -> io::Result<()> {
+    let _registery = match init_window(name, user_data) {
+        Ok(registery) => {
+            let _ = ready.send(Ok(()));
+            registery
+        }
+        Err(error) => {
+            let _ = ready.send(Err(error));
+            return Ok(());
+        }
+    };
+
+    let mut msg: MSG = std::mem::zeroed();
+    loop {
+        match GetMessageW(&mut msg as *mut _, std::ptr::null_mut(), 0, 0) {
+            0 => {
+                break Ok(());
+            }
+            -1 => {
+                let error = Err(io::Error::last_os_error());
+                break error;
+            }
+            _ if msg.message == WM_CLOSE => {
+                TranslateMessage(&msg as *const _);
+                DispatchMessageW(&msg as *const _);
+                break Ok(());
+            }
+            _ => {
+                TranslateMessage(&msg as *const _);
+                DispatchMessageW(&msg as *const _);
+            }
+        }
+    }
+}
+
+/// Register our window class, create the window and register it for device notifications
+///
+/// Safety: user_data must be a raw Arc<IterState>. It is released if we fail
+unsafe fn init_window(name: OsString, user_data: isize) -> Result<Registery> {
     const WCEUSBS: GUID =
         guid!(0x25dbce51, 0x6c8f, 0x4a72, 0x8a, 0x6d, 0xb5, 0x4c, 0x2b, 0x4f, 0xc8, 0x35);
     const USBDEVICE: GUID =
         guid!(0x88BAE032, 0x5A81, 0x49f0, 0xBC, 0x3D, 0xA4, 0xFF, 0x13, 0x82, 0x16, 0xD6);
     const PORTS: GUID =
         guid!(0x4d36e978, 0xe325, 0x11ce, 0xbf, 0xc1, 0x08, 0x00, 0x2b, 0xe1, 0x03, 0x18);
+    const COMPORT: GUID =
+        guid!(0x86e0d1e0, 0x8089, 0x11d0, 0x9c, 0xe4, 0x08, 0x00, 0x3e, 0x30, 0x1f, 0x73);
     let class = WNDCLASSEXW {
         style: 0,
         hIcon: std::ptr::null_mut(),
@@ -176,15 +234,23 @@ pub unsafe fn window_dispatcher(name: OsString, user_data: isize) -> io::Result<
         lpfnWndProc: Some(window_proceedure),
         hbrBackground: std::ptr::null_mut(),
     };
-    let _atom = match unsafe { RegisterClassExW(&class as *const _) } {
-        0 => panic!("{:?}", io::Error::last_os_error()),
-        atom => atom,
-    };
+    // The class is registered by the first listener of the process and shared by the others
+    if RegisterClassExW(&class as *const _) == 0 && GetLastError() != ERROR_CLASS_ALREADY_EXISTS {
+        let error = io::Error::last_os_error();
+        // The window procedure never saw our arc, so we still own it
+        drop(Arc::from_raw(user_data as *const IterState));
+        return Err(Error::Backend(error));
+    }
 
-    let unsafe_name = to_wide(name.clone());
-    let arc = Arc::from_raw(user_data as *const Arc<IterState>);
-    let hwnd = create_window(unsafe_name.as_ptr(), Arc::as_ptr(&arc) as _)?;
-    let _registery = [WCEUSBS, USBDEVICE, PORTS]
+    let unsafe_name = to_wide(name);
+    let hwnd = match create_window(unsafe_name.as_ptr(), user_data) {
+        Ok(hwnd) => hwnd,
+        Err(error) => {
+            drop(Arc::from_raw(user_data as *const IterState));
+            return Err(Error::Backend(error));
+        }
+    };
+    let devices = [WCEUSBS, USBDEVICE, PORTS, COMPORT]
         .into_iter()
         .map(|guid| {
             let handle = unsafe {
@@ -200,30 +266,42 @@ pub unsafe fn window_dispatcher(name: OsString, user_data: isize) -> io::Result<
             };
             match handle.is_null() {
                 false => Ok(handle),
-                true => Err(io::Error::last_os_error()),
+                true => Err(Error::Registration(io::Error::last_os_error())),
             }
         })
-        .collect::<io::Result<Vec<_>>>()?;
+        .collect::<Result<Vec<_>>>()
+        .inspect_err(|_| {
+            // The window procedure releases our arc on WM_DESTROY
+            DestroyWindow(hwnd);
+        })?;
+    // Without the timer we only miss a COM number which Windows reassigned without telling us
+    let interval = RECONCILE_INTERVAL.as_millis() as u32;
+    if SetTimer(hwnd, RECONCILE_TIMER, interval, None) == 0 {
+        warn!(error = ?io::Error::last_os_error(), "failed to set the reconcile timer");
+    }
+    // A message-only window is only sent the power broadcasts it registers for. We can do without
+    let power = RegisterSuspendResumeNotification(hwnd as _, DEVICE_NOTIFY_WINDOW_HANDLE);
+    if power == 0 {
+        warn!(error = ?io::Error::last_os_error(), "failed to register for resume notifications");
+    }
+    Ok(Registery {
+        _devices: devices,
+        power,
+    })
+}
 
-    let mut msg: MSG = std::mem::zeroed();
-    loop {
-        match GetMessageW(&mut msg as *mut _, std::ptr::null_mut(), 0, 0) {
-            0 => {
-                break Ok(());
-            }
-            -1 => {
-                let error = Err(io::Error::last_os_error());
-                break error;
-            }
-            _ if msg.message == WM_CLOSE => {
-                TranslateMessage(&msg as *const _);
-                DispatchMessageW(&msg as *const _);
-                break Ok(());
-            }
-            _ => {
-                TranslateMessage(&msg as *const _);
-                DispatchMessageW(&msg as *const _);
-            }
+/// The notifications our window registered for. The device notifications go with the window, the
+/// power notification is unregistered when dropped
+struct Registery {
+    _devices: Vec<HDEVNOTIFY>,
+    power: HPOWERNOTIFY,
+}
+
+impl Drop for Registery {
+    fn drop(&mut self) {
+        if self.power != 0 {
+            // Safety: the handle was returned by RegisterSuspendResumeNotification
+            unsafe { UnregisterSuspendResumeNotification(self.power) };
         }
     }
 }